@@ -0,0 +1,31 @@
+//! How long each filter takes to fit a background image to the screen.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use ndlm::scale::{resize, Filter};
+
+/// A photo's worth of pixels, resized to a 1080p screen.
+const FROM: (u32, u32) = (2560, 1600);
+const TO: (u32, u32) = (1920, 1080);
+
+fn gradient((width, height): (u32, u32)) -> Vec<u8> {
+    (0..height)
+        .flat_map(|y| (0..width).flat_map(move |x| [x as u8, y as u8, (x ^ y) as u8, 0xFF]))
+        .collect()
+}
+
+fn scale(c: &mut Criterion) {
+    let pixels = gradient(FROM);
+    let mut group = c.benchmark_group("resize 2560x1600 to 1920x1080");
+    group.sample_size(10);
+    for filter in [Filter::Nearest, Filter::Bilinear, Filter::Lanczos] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(filter),
+            &filter,
+            |b, &filter| b.iter(|| resize(&pixels, FROM, TO, filter)),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, scale);
+criterion_main!(benches);
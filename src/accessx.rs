@@ -0,0 +1,210 @@
+use std::time::{Duration, Instant};
+
+/// How long slow keys wait when turned on without `--slow-keys`.
+pub const DEFAULT_SLOW_KEYS: Duration = Duration::from_millis(300);
+/// Pressing Shift this many times in a row turns sticky keys on or off.
+const STICKY_PRESSES: u8 = 5;
+/// Holding Shift this long turns slow keys on or off.
+const SLOW_HOLD: Duration = Duration::from_secs(8);
+
+/// A modifier sticky keys can latch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Modifier {
+    Shift,
+    Ctrl,
+    AltGr,
+}
+
+/// Whether a modifier is latched for the next key or locked until it's
+/// pressed again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stick {
+    Latched,
+    Locked,
+}
+
+/// What becomes of a key event once slow keys had a look at it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filtered {
+    Press,
+    Repeat,
+    Release,
+    Ignore,
+}
+
+/// Sticky keys and slow keys, as the X accessibility extension has them, for
+/// users who can't hold several keys at once or press keys by accident.
+#[derive(Debug)]
+pub struct AccessX {
+    sticky: bool,
+    slow: Option<Duration>,
+    slow_delay: Duration,
+    stuck: Vec<(Modifier, Stick)>,
+    // the modifier pressed with no other key since, which sticks on release
+    pending: Option<Modifier>,
+    // keys pressed with slow keys on, and whether they were accepted
+    held: Vec<(u16, Instant, bool)>,
+    shift_presses: u8,
+    shift_down: Option<Instant>,
+    notice: Option<&'static str>,
+}
+
+impl AccessX {
+    /// Starts with sticky keys on if `sticky`, and slow keys on with the
+    /// `slow` delay if given.
+    pub fn new(sticky: bool, slow: Option<Duration>) -> Self {
+        Self {
+            sticky,
+            slow,
+            slow_delay: slow.unwrap_or(DEFAULT_SLOW_KEYS),
+            stuck: Vec::new(),
+            pending: None,
+            held: Vec::new(),
+            shift_presses: 0,
+            shift_down: None,
+            notice: None,
+        }
+    }
+
+    /// Follows a press (1), repeat (2) or release (0) of `modifier`, watching
+    /// for the gestures and sticking it when pressed on its own.
+    pub fn modifier(&mut self, modifier: Modifier, value: i32, now: Instant) {
+        let toggled = if modifier == Modifier::Shift {
+            self.shift_gestures(value, now)
+        } else {
+            if value == 1 {
+                self.shift_presses = 0;
+            }
+            false
+        };
+        match value {
+            // the press toggling sticky keys doesn't stick Shift as well
+            1 => self.pending = (!toggled).then_some(modifier),
+            0 if self.pending == Some(modifier) => {
+                self.pending = None;
+                if self.sticky {
+                    self.stick(modifier);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Slow keys: accepts a key only once it has been held down for the
+    /// delay, and drops it if released sooner.
+    pub fn filter(&mut self, code: u16, value: i32, now: Instant) -> Filtered {
+        if value == 1 {
+            self.pending = None;
+            self.shift_presses = 0;
+        }
+        let delay = match self.slow {
+            Some(delay) => delay,
+            None => {
+                return match value {
+                    0 => Filtered::Release,
+                    1 => Filtered::Press,
+                    _ => Filtered::Repeat,
+                }
+            }
+        };
+        let i = self.held.iter().position(|(held, _, _)| *held == code);
+        match (value, i) {
+            (1, _) => {
+                self.held.retain(|(held, _, _)| *held != code);
+                self.held.push((code, now, false));
+                Filtered::Ignore
+            }
+            (_, None) => Filtered::Ignore,
+            (0, Some(i)) => {
+                let (_, since, accepted) = self.held.remove(i);
+                match accepted {
+                    true => Filtered::Release,
+                    false if now - since >= delay => Filtered::Press,
+                    false => Filtered::Ignore,
+                }
+            }
+            (_, Some(i)) => {
+                let (_, since, accepted) = &mut self.held[i];
+                match *accepted {
+                    true => Filtered::Repeat,
+                    false if now - *since >= delay => {
+                        *accepted = true;
+                        Filtered::Press
+                    }
+                    false => Filtered::Ignore,
+                }
+            }
+        }
+    }
+
+    /// The modifiers stuck for the next key.
+    pub fn stuck(&self) -> impl Iterator<Item = Modifier> + '_ {
+        self.stuck.iter().map(|(modifier, _)| *modifier)
+    }
+
+    /// Releases the modifiers only latched, once a key was typed with them.
+    pub fn unlatch(&mut self) {
+        self.stuck.retain(|(_, stick)| *stick == Stick::Locked);
+    }
+
+    /// What was turned on or off since last asked, to tell the user.
+    pub fn take_notice(&mut self) -> Option<&'static str> {
+        self.notice.take()
+    }
+
+    /// Latches `modifier`, locks it if it was latched, and releases it if it
+    /// was locked.
+    fn stick(&mut self, modifier: Modifier) {
+        match self.stuck.iter().position(|(m, _)| *m == modifier) {
+            None => self.stuck.push((modifier, Stick::Latched)),
+            Some(i) if self.stuck[i].1 == Stick::Latched => self.stuck[i].1 = Stick::Locked,
+            Some(i) => {
+                self.stuck.remove(i);
+            }
+        }
+    }
+
+    /// Five presses of Shift in a row toggle sticky keys, and holding it
+    /// for eight seconds toggles slow keys. Returns whether either was.
+    fn shift_gestures(&mut self, value: i32, now: Instant) -> bool {
+        match value {
+            1 => {
+                self.shift_down = Some(now);
+                self.shift_presses += 1;
+                if self.shift_presses == STICKY_PRESSES {
+                    self.shift_presses = 0;
+                    self.sticky = !self.sticky;
+                    self.stuck.clear();
+                    self.notice = Some(if self.sticky {
+                        "Sticky keys on"
+                    } else {
+                        "Sticky keys off"
+                    });
+                    return true;
+                }
+                false
+            }
+            _ => {
+                let held = self.shift_down.is_some_and(|down| now - down >= SLOW_HOLD);
+                if held {
+                    self.shift_down = None;
+                    self.shift_presses = 0;
+                    self.slow = match self.slow {
+                        Some(_) => None,
+                        None => Some(self.slow_delay),
+                    };
+                    self.held.clear();
+                    self.notice = Some(if self.slow.is_some() {
+                        "Slow keys on"
+                    } else {
+                        "Slow keys off"
+                    });
+                }
+                if value == 0 {
+                    self.shift_down = None;
+                }
+                held
+            }
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::time::{Duration, Instant};
+
+pub const INPUT_DIR: &str = "/dev/input";
+
+// enough for a burst of evdev events, 24 bytes each on 64 bit
+const READ_CHUNK: usize = 24 * 64;
+
+/// When the user last did something, on the console or any input device, so
+/// moving the mouse or touching the screen keeps the display from blanking
+/// as typing does.
+pub struct Activity {
+    devices: Vec<File>,
+    // unplugged devices, to stop waiting on before they're closed
+    lost: Vec<File>,
+    last: Instant,
+}
+
+impl Activity {
+    /// Watches the `event*` devices in `dir` that can be opened, which
+    /// usually takes being in the `input` group.
+    pub fn open(dir: &str) -> Self {
+        let mut paths = match fs::read_dir(dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| {
+                    path.file_name()
+                        .is_some_and(|name| name.to_string_lossy().starts_with("event"))
+                })
+                .collect::<Vec<_>>(),
+            Err(_) => Vec::new(),
+        };
+        paths.sort();
+        let mut devices = Vec::new();
+        let mut denied = 0;
+        for path in &paths {
+            match OpenOptions::new()
+                .read(true)
+                .custom_flags(nix::libc::O_NONBLOCK)
+                .open(path)
+            {
+                Ok(device) => devices.push(device),
+                Err(err) if err.kind() == io::ErrorKind::PermissionDenied => denied += 1,
+                Err(err) => eprintln!("unable to watch {}: {err}", path.display()),
+            }
+        }
+        if denied > 0 {
+            eprintln!("not allowed to watch {denied} input devices for activity, only the console");
+        }
+        Self {
+            devices,
+            lost: Vec::new(),
+            last: Instant::now(),
+        }
+    }
+
+    /// The devices to wait on along with the console.
+    pub fn fds(&self) -> impl Iterator<Item = RawFd> + '_ {
+        self.devices.iter().map(File::as_raw_fd)
+    }
+
+    /// Records activity seen elsewhere, like keys typed into the console.
+    pub fn touch(&mut self) {
+        self.last = Instant::now();
+    }
+
+    /// Drains the devices' pending events, recording activity if there were
+    /// any. Returns whether there were.
+    pub fn update(&mut self) -> bool {
+        let mut active = false;
+        let mut chunk = [0u8; READ_CHUNK];
+        let mut i = 0;
+        while i < self.devices.len() {
+            let err: io::Error = loop {
+                match self.devices[i].read(&mut chunk) {
+                    Ok(0) => break io::ErrorKind::UnexpectedEof.into(),
+                    Ok(_) => active = true,
+                    Err(err) => break err,
+                }
+            };
+            if err.kind() == io::ErrorKind::WouldBlock {
+                i += 1;
+            } else {
+                self.lost.push(self.devices.remove(i));
+            }
+        }
+        if active {
+            self.touch();
+        }
+        active
+    }
+
+    /// Takes the devices that went away since the last call, which are closed
+    /// when dropped.
+    pub fn take_lost(&mut self) -> Vec<File> {
+        std::mem::take(&mut self.lost)
+    }
+
+    pub fn last(&self) -> Instant {
+        self.last
+    }
+
+    pub fn idle(&self) -> Duration {
+        self.last.elapsed()
+    }
+}
@@ -0,0 +1,77 @@
+use std::process::Command;
+
+use crate::dbus::{Arg, Bus};
+
+/// How many lines of greetd's log the palette shows.
+const LOG_LINES: usize = 15;
+
+const SYSTEMD: &str = "org.freedesktop.systemd1";
+const SYSTEMD_PATH: &str = "/org/freedesktop/systemd1";
+const SYSTEMD_MANAGER: &str = "org.freedesktop.systemd1.Manager";
+
+/// What an admin can do from the palette.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdminAction {
+    RestartGreetd,
+    ReloadTheme,
+    ShowLogs,
+    LogViewer,
+}
+
+impl AdminAction {
+    pub const ALL: [AdminAction; 4] = [
+        AdminAction::RestartGreetd,
+        AdminAction::ReloadTheme,
+        AdminAction::ShowLogs,
+        AdminAction::LogViewer,
+    ];
+
+    /// How the palette shows it.
+    pub fn label(&self) -> &'static str {
+        match self {
+            AdminAction::RestartGreetd => "Restart greetd",
+            AdminAction::ReloadTheme => "Reload theme",
+            AdminAction::ShowLogs => "Show greetd's log",
+            AdminAction::LogViewer => "Show the greeter's log",
+        }
+    }
+}
+
+/// Where the palette is at: offering the actions, or showing the log.
+#[derive(Debug)]
+pub enum Palette {
+    Menu(usize),
+    Logs(Vec<String>),
+}
+
+/// Has systemd restart greetd, which ends the greeter too. It's up to polkit
+/// whether the greeter's user may.
+pub fn restart_greetd() -> Result<(), String> {
+    let mut bus = Bus::system().map_err(|err| format!("Unable to restart greetd: {err}"))?;
+    bus.call(
+        SYSTEMD,
+        SYSTEMD_PATH,
+        SYSTEMD_MANAGER,
+        "RestartUnit",
+        &[Arg::Str("greetd.service"), Arg::Str("replace")],
+    )
+    .map(|_| ())
+    .map_err(|err| format!("Unable to restart greetd: {err}"))
+}
+
+/// The last lines of greetd's log in the journal.
+pub fn logs() -> Result<Vec<String>, String> {
+    let output = Command::new("journalctl")
+        .args(["--unit", "greetd.service", "--no-pager", "--output", "cat"])
+        .arg(format!("--lines={LOG_LINES}"))
+        .output()
+        .map_err(|err| format!("Unable to run journalctl: {err}"))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("journalctl failed: {}", stderr.trim_end()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::to_string)
+        .collect())
+}
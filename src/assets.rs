@@ -0,0 +1,20 @@
+use std::path::Path;
+
+use crate::{draw, Theme};
+
+/// Loads everything the theme refers to up front, returning a description of
+/// each asset that is missing, prefixed with the theme key naming it.
+pub fn preload(theme: &Theme) -> Vec<String> {
+    let mut missing = Vec::new();
+    if let Err(err) = draw::preload() {
+        missing.push(format!("Font: {err}"));
+    }
+    let mut dirs = vec![&theme.dark.image_dir, &theme.light.image_dir];
+    dirs.dedup();
+    for dir in dirs {
+        if !dir.is_empty() && !Path::new(dir).is_dir() {
+            missing.push(format!("ImageDir: {dir} is not a directory"));
+        }
+    }
+    missing
+}
@@ -0,0 +1,358 @@
+#[cfg(feature = "background-command")]
+use std::fs;
+#[cfg(feature = "background-command")]
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+#[cfg(feature = "background-command")]
+use std::process::{Child, Command, Stdio};
+#[cfg(feature = "background-command")]
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "background-command")]
+use nix::sys::signal::{killpg, Signal};
+#[cfg(feature = "background-command")]
+use nix::unistd::Pid;
+
+use crate::buffer::{Buffer, Rect, Vect};
+use crate::color::Color;
+use crate::image;
+use crate::scale::{self, Filter};
+
+// looked for in the theme's ImageDir, the first one found is used
+const BACKGROUND_IMAGES: &[&str] = &[
+    "background.png",
+    "background.jpg",
+    "background.jpeg",
+    "background-tile.png",
+];
+const WATERMARK_IMAGE: &str = "watermark.png";
+// how long the command may run before it's killed
+#[cfg(feature = "background-command")]
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+// how often a running command is checked on
+const COMMAND_POLL: Duration = Duration::from_millis(100);
+
+/// Background produced by an external command, re-run every `interval` if set,
+/// or else the theme's background image, with its watermark on top.
+///
+/// The command is run through `sh -c` with `DDLM_WIDTH`/`DDLM_HEIGHT` set to the
+/// screen size and must print either raw RGBA pixels of exactly that size or
+/// the path of a file containing them. The path may be followed by the
+/// image's size as `WIDTHxHEIGHT` if it differs, to have it scaled to fit.
+/// It runs in the background, the greeter drawn as it was meanwhile, and is
+/// killed if it takes longer than 30 seconds.
+pub struct Background {
+    command: Option<String>,
+    interval: Option<Duration>,
+    filter: Filter,
+    last_run: Option<Instant>,
+    #[cfg(feature = "background-command")]
+    job: Option<Job>,
+    dimensions: Vect,
+    pixels: Option<Vec<u8>>,
+    // from the theme's ImageDir
+    image: Option<Vec<u8>>,
+    watermark: Option<(Vec<u8>, Rect)>,
+}
+
+impl Background {
+    pub fn new(
+        command: Option<String>,
+        interval: Option<Duration>,
+        filter: Filter,
+        dimensions: Vect,
+    ) -> Self {
+        Self {
+            command,
+            interval,
+            filter,
+            last_run: None,
+            #[cfg(feature = "background-command")]
+            job: None,
+            dimensions,
+            pixels: None,
+            image: None,
+            watermark: None,
+        }
+    }
+
+    /// Loads the background and watermark images of a Plymouth style theme
+    /// from `image_dir`, the watermark placed at `alignment` as Plymouth
+    /// does. The background is scaled to cover the screen.
+    pub fn load_theme(&mut self, image_dir: &str, alignment: (f32, f32)) {
+        self.image = None;
+        self.watermark = None;
+        if image_dir.is_empty() {
+            return;
+        }
+        let dir = Path::new(image_dir);
+        let background = BACKGROUND_IMAGES
+            .iter()
+            .map(|name| dir.join(name))
+            .find(|path| path.is_file());
+        if let Some((pixels, size)) = background.and_then(|path| load(&path)) {
+            self.image = Some(cover(&pixels, size, self.dimensions, self.filter));
+        }
+        let watermark = dir.join(WATERMARK_IMAGE);
+        if let Some((pixels, size)) = watermark.is_file().then(|| load(&watermark)).flatten() {
+            // shrunk if it doesn't fit
+            let ratio = (self.dimensions.0 as f32 / size.0 as f32)
+                .min(self.dimensions.1 as f32 / size.1 as f32)
+                .min(1.0);
+            let fit = (
+                ((size.0 as f32 * ratio) as u32).max(1),
+                ((size.1 as f32 * ratio) as u32).max(1),
+            );
+            let pixels = scale::resize(&pixels, size, fit, self.filter);
+            let x = ((self.dimensions.0 - fit.0) as f32 * alignment.0) as u32;
+            let y = ((self.dimensions.1 - fit.1) as f32 * alignment.1) as u32;
+            self.watermark = Some((pixels, (x, y, fit.0, fit.1)));
+        }
+    }
+
+    /// Starts the command if it never ran or its interval elapsed, returning
+    /// whether the background changed as a command started before finished.
+    pub fn update(&mut self) -> bool {
+        if let Some(changed) = self.poll() {
+            return changed;
+        }
+        let command = match &self.command {
+            Some(command) => command.clone(),
+            None => return false,
+        };
+        let due = match (self.last_run, self.interval) {
+            (None, _) => true,
+            (Some(last_run), Some(interval)) => last_run.elapsed() >= interval,
+            (Some(_), None) => false,
+        };
+        if !due {
+            return false;
+        }
+        self.last_run = Some(Instant::now());
+        self.start(&command);
+        false
+    }
+
+    /// Like `update`, but waits for the command to finish, for when nothing
+    /// is shown meanwhile.
+    pub fn update_now(&mut self) -> bool {
+        let mut changed = self.update();
+        while self.running() {
+            thread::sleep(COMMAND_POLL);
+            changed |= self.update();
+        }
+        changed
+    }
+
+    #[cfg(feature = "background-command")]
+    fn start(&mut self, command: &str) {
+        let child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .env("DDLM_WIDTH", self.dimensions.0.to_string())
+            .env("DDLM_HEIGHT", self.dimensions.1.to_string())
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .process_group(0)
+            .spawn();
+        let child = match child {
+            Ok(child) => child,
+            Err(err) => {
+                eprintln!("unable to run background command: {err}");
+                return;
+            }
+        };
+        let pid = child.id();
+        let (dimensions, filter) = (self.dimensions, self.filter);
+        let (sender, result) = mpsc::channel();
+        thread::spawn(move || {
+            let pixels = read_output(child, dimensions)
+                .map(|(pixels, size)| scale::resize(&pixels, size, dimensions, filter));
+            let _ = sender.send(pixels);
+        });
+        self.job = Some(Job {
+            pid,
+            started: Instant::now(),
+            result,
+        });
+    }
+
+    #[cfg(not(feature = "background-command"))]
+    fn start(&mut self, _command: &str) {
+        eprintln!("built without the background-command feature, ignoring background command");
+    }
+
+    // whether the command finished changing the background, if it ran, giving
+    // up on it once it took too long
+    #[cfg(feature = "background-command")]
+    fn poll(&mut self) -> Option<bool> {
+        let job = self.job.as_ref()?;
+        let pixels = match job.result.try_recv() {
+            Ok(pixels) => pixels,
+            Err(TryRecvError::Empty) if job.started.elapsed() < COMMAND_TIMEOUT => {
+                return Some(false)
+            }
+            Err(TryRecvError::Empty) => {
+                eprintln!(
+                    "background command still running after {}s, killing it",
+                    COMMAND_TIMEOUT.as_secs()
+                );
+                let _ = killpg(Pid::from_raw(job.pid as i32), Signal::SIGKILL);
+                None
+            }
+            Err(TryRecvError::Disconnected) => None,
+        };
+        self.job = None;
+        Some(match pixels {
+            Some(pixels) => {
+                self.pixels = Some(pixels);
+                true
+            }
+            None => false,
+        })
+    }
+
+    #[cfg(not(feature = "background-command"))]
+    fn poll(&mut self) -> Option<bool> {
+        None
+    }
+
+    #[cfg(feature = "background-command")]
+    fn running(&self) -> bool {
+        self.job.is_some()
+    }
+
+    #[cfg(not(feature = "background-command"))]
+    fn running(&self) -> bool {
+        false
+    }
+
+    /// When the command is due to run again, or to be checked on while it
+    /// runs.
+    pub fn next_update(&self) -> Option<Instant> {
+        if self.running() {
+            return Some(Instant::now() + COMMAND_POLL);
+        }
+        self.command.as_ref()?;
+        Some(self.last_run? + self.interval?)
+    }
+
+    /// Whether there is an image to draw, rather than only the theme's color.
+    pub fn has_image(&self) -> bool {
+        self.pixels.is_some() || self.image.is_some() || self.watermark.is_some()
+    }
+
+    /// Draws the whole background, a gradient from `start` at the top to
+    /// `end` at the bottom where there is no image.
+    pub fn draw(&self, buf: &mut Buffer<'_>, start: &Color, end: &Color) {
+        let base = self.pixels.as_ref().or(self.image.as_ref());
+        match base {
+            Some(pixels) => buf.blit_rgba(pixels, self.dimensions, buf.get_bounds()),
+            None if start == end => buf.memset(start),
+            None => buf.gradient(start, end),
+        }
+        let (pixels, rect) = match &self.watermark {
+            Some(watermark) => watermark,
+            None => return,
+        };
+        for (i, p) in pixels.chunks_exact(4).enumerate() {
+            let pos = (rect.0 + i as u32 % rect.2, rect.1 + i as u32 / rect.2);
+            let under = match base {
+                Some(base) => {
+                    let j = 4 * (pos.0 + pos.1 * self.dimensions.0) as usize;
+                    Color::from_rgba8([base[j], base[j + 1], base[j + 2], 0xFF])
+                }
+                None => {
+                    let last = self.dimensions.1.saturating_sub(1).max(1) as f32;
+                    start.blend(end, pos.1 as f32 / last)
+                }
+            };
+            let c = Color::from_rgba8([p[0], p[1], p[2], 0xFF]);
+            let _ = buf.put(pos, &under.blend(&c, p[3] as f32 / 255.0));
+        }
+    }
+}
+
+/// The background command started in the background.
+#[cfg(feature = "background-command")]
+struct Job {
+    // also the id of its process group
+    pid: u32,
+    started: Instant,
+    result: Receiver<Option<Vec<u8>>>,
+}
+
+/// Waits for the background command and reads the pixels it printed or
+/// the file it named.
+#[cfg(feature = "background-command")]
+fn read_output(child: Child, dimensions: Vect) -> Option<(Vec<u8>, Vect)> {
+    let stdout = match child.wait_with_output() {
+        Ok(output) if output.status.success() => output.stdout,
+        Ok(output) => {
+            eprintln!("background command exited with {}", output.status);
+            return None;
+        }
+        Err(err) => {
+            eprintln!("unable to run background command: {err}");
+            return None;
+        }
+    };
+
+    if stdout.len() == (dimensions.0 * dimensions.1 * 4) as usize {
+        return Some((stdout, dimensions));
+    }
+    let output = String::from_utf8_lossy(&stdout).trim().to_string();
+    let (path, size) = match output.rsplit_once(' ').map(|(p, s)| (p, parse_size(s))) {
+        Some((path, Some(size))) => (path.trim_end().to_string(), size),
+        _ => (output, dimensions),
+    };
+    let pixels = match fs::read(&path) {
+        Ok(pixels) if pixels.len() == (size.0 * size.1 * 4) as usize => pixels,
+        Ok(_) => {
+            eprintln!("background {path} is not raw RGBA of {}x{}", size.0, size.1);
+            return None;
+        }
+        Err(err) => {
+            eprintln!("unable to read background {path}: {err}");
+            return None;
+        }
+    };
+    Some((pixels, size))
+}
+
+fn load(path: &Path) -> Option<(Vec<u8>, Vect)> {
+    match image::decode(path) {
+        Ok(image) => Some(image),
+        Err(err) => {
+            eprintln!("unable to load theme image {}: {err}", path.display());
+            None
+        }
+    }
+}
+
+/// Scales RGBA `pixels` of `size` to cover `screen`, keeping the aspect
+/// ratio and cropping what overflows evenly on both sides.
+fn cover(pixels: &[u8], size: Vect, screen: Vect, filter: Filter) -> Vec<u8> {
+    let ratio = (screen.0 as f32 / size.0 as f32).max(screen.1 as f32 / size.1 as f32);
+    let scaled = (
+        ((size.0 as f32 * ratio).ceil() as u32).max(screen.0),
+        ((size.1 as f32 * ratio).ceil() as u32).max(screen.1),
+    );
+    let pixels = scale::resize(pixels, size, scaled, filter);
+    let (x0, y0) = ((scaled.0 - screen.0) / 2, (scaled.1 - screen.1) / 2);
+    let mut out = Vec::with_capacity((screen.0 * screen.1 * 4) as usize);
+    for y in y0..y0 + screen.1 {
+        let row = 4 * (y * scaled.0 + x0) as usize;
+        out.extend_from_slice(&pixels[row..row + 4 * screen.0 as usize]);
+    }
+    out
+}
+
+/// Parses an image size written as `WIDTHxHEIGHT`.
+pub fn parse_size(s: &str) -> Option<Vect> {
+    let (width, height) = s.split_once('x')?;
+    let size = (width.parse().ok()?, height.parse().ok()?);
+    (size.0 > 0 && size.1 > 0).then_some(size)
+}
@@ -1,6 +1,7 @@
 use thiserror::Error;
 
 use crate::color::Color;
+use crate::format::PixelFormat;
 
 pub type Vect = (u32, u32);
 pub type Rect = (u32, u32, u32, u32);
@@ -21,18 +22,36 @@ pub enum BufferError {
 pub struct Buffer<'a> {
     buf: &'a mut [u8],
     dimensions: Vect,
+    // bytes from one row to the next, at least a row of pixels
+    stride: u32,
     subdimensions: Option<Rect>,
+    format: PixelFormat,
+    // where views of the buffer record what they drew to, if anywhere
+    damage: Option<&'a mut Damage>,
+    // which of the damage's rectangles is what this view drew to
+    drawn: Option<usize>,
 }
 
 impl<'a> Buffer<'a> {
-    pub fn new(buf: &'a mut [u8], dimensions: Vect) -> Self {
+    pub fn new(buf: &'a mut [u8], dimensions: Vect, stride: u32, format: PixelFormat) -> Self {
         Self {
             buf,
             dimensions,
+            stride,
             subdimensions: None,
+            format,
+            damage: None,
+            drawn: None,
         }
     }
 
+    /// Records the rectangles drawn to through this buffer and its views in
+    /// `damage`, one for each that drew anything.
+    pub fn track(mut self, damage: &'a mut Damage) -> Self {
+        self.damage = Some(damage);
+        self
+    }
+
     pub fn get_bounds(&self) -> Rect {
         if let Some(subdim) = self.subdimensions {
             subdim
@@ -50,6 +69,10 @@ impl<'a> Buffer<'a> {
         Ok(Buffer {
             buf: self.buf,
             dimensions: self.dimensions,
+            stride: self.stride,
+            format: self.format,
+            damage: self.damage.as_deref_mut(),
+            drawn: None,
             subdimensions: Some((
                 offset.0 + bounds.0,
                 offset.1 + bounds.1,
@@ -59,27 +82,118 @@ impl<'a> Buffer<'a> {
         })
     }
 
+    /// A view of `rect`, relative to the current bounds.
+    pub fn subdimensions(&mut self, rect: Rect) -> Result<Buffer<'_>, BufferError> {
+        let bounds = self.get_bounds();
+        if rect.0 + rect.2 > bounds.2 || rect.1 + rect.3 > bounds.3 {
+            return Err(BufferError::SubdimensionsTooLarge {
+                subdimensions: rect,
+                bounds,
+            });
+        }
+
+        Ok(Buffer {
+            buf: self.buf,
+            dimensions: self.dimensions,
+            stride: self.stride,
+            format: self.format,
+            damage: self.damage.as_deref_mut(),
+            drawn: None,
+            subdimensions: Some((rect.0 + bounds.0, rect.1 + bounds.1, rect.2, rect.3)),
+        })
+    }
+
     pub fn memset(&mut self, c: &Color) {
-        if let Some(subdim) = self.subdimensions {
-            unsafe {
-                let ptr = self.buf.as_mut_ptr();
-                for y in subdim.1..(subdim.1 + subdim.3) {
-                    for x in subdim.0..(subdim.0 + subdim.2) {
-                        *((ptr as *mut u32).offset((x + y * self.dimensions.0) as isize)) =
-                            c.as_argb8888();
-                    }
-                }
+        let bounds = self.get_bounds();
+        for y in bounds.1..(bounds.1 + bounds.3) {
+            for x in bounds.0..(bounds.0 + bounds.2) {
+                self.write_pixel((x, y), c);
             }
-        } else {
-            unsafe {
-                let ptr = self.buf.as_mut_ptr();
-                for p in 0..(self.dimensions.0 * self.dimensions.1) {
-                    *((ptr as *mut u32).offset(p as isize)) = c.as_argb8888();
-                }
+        }
+    }
+
+    /// Fills the buffer with colors going from `start` at the top to `end` at
+    /// the bottom.
+    pub fn gradient(&mut self, start: &Color, end: &Color) {
+        let bounds = self.get_bounds();
+        let last = bounds.3.saturating_sub(1).max(1) as f32;
+        for y in bounds.1..(bounds.1 + bounds.3) {
+            let c = start.blend(end, (y - bounds.1) as f32 / last);
+            for x in bounds.0..(bounds.0 + bounds.2) {
+                self.write_pixel((x, y), &c);
             }
         }
     }
 
+    /// Copies the `rect` part of tightly packed RGBA pixels of `dim` size to
+    /// the same position in the buffer.
+    pub fn blit_rgba(&mut self, pixels: &[u8], dim: Vect, rect: Rect) {
+        for y in rect.1..(rect.1 + rect.3).min(dim.1) {
+            for x in rect.0..(rect.0 + rect.2).min(dim.0) {
+                let i = 4 * (x + y * dim.0) as usize;
+                let rgba = [pixels[i], pixels[i + 1], pixels[i + 2], pixels[i + 3]];
+                let _ = self.put((x, y), &Color::from_rgba8(rgba));
+            }
+        }
+    }
+
+    /// Draws tightly packed RGBA pixels of `dim` size at the top left,
+    /// blended over `bg`.
+    pub fn blend_rgba(&mut self, pixels: &[u8], dim: Vect, bg: &Color) -> Result<(), BufferError> {
+        for (i, p) in pixels.chunks_exact(4).enumerate() {
+            let c = Color::from_rgba8([p[0], p[1], p[2], 0xFF]);
+            let pos = (i as u32 % dim.0, i as u32 / dim.0);
+            self.put(pos, &bg.blend(&c, p[3] as f32 / 255.0))?;
+        }
+        Ok(())
+    }
+
+    /// The pixels of `rect`, relative to the current bounds, as stored in
+    /// the buffer's format, a row of `rect.2` pixels after another.
+    pub fn copy_rect(&self, rect: Rect) -> Result<Vec<u8>, BufferError> {
+        let (start, row) = self.rect_rows(rect)?;
+        let stride = self.stride as usize;
+        let mut pixels = Vec::with_capacity(row * rect.3 as usize);
+        for y in 0..rect.3 as usize {
+            let offset = start + y * stride;
+            pixels.extend_from_slice(&self.buf[offset..offset + row]);
+        }
+        Ok(pixels)
+    }
+
+    /// Puts back pixels of `rect` as `copy_rect` returns them.
+    pub fn paste_rect(&mut self, rect: Rect, pixels: &[u8]) -> Result<(), BufferError> {
+        let (start, row) = self.rect_rows(rect)?;
+        let bounds = self.get_bounds();
+        let (x, y) = (rect.0 + bounds.0, rect.1 + bounds.1);
+        self.mark((x, y), (x + rect.2, y + rect.3));
+        let stride = self.stride as usize;
+        for (y, src) in pixels
+            .chunks_exact(row.max(1))
+            .take(rect.3 as usize)
+            .enumerate()
+        {
+            let offset = start + y * stride;
+            self.buf[offset..offset + row].copy_from_slice(src);
+        }
+        Ok(())
+    }
+
+    /// Where the first row of `rect` starts in memory, and its length.
+    fn rect_rows(&self, rect: Rect) -> Result<(usize, usize), BufferError> {
+        let bounds = self.get_bounds();
+        if rect.0 + rect.2 > bounds.2 || rect.1 + rect.3 > bounds.3 {
+            return Err(BufferError::SubdimensionsTooLarge {
+                subdimensions: rect,
+                bounds,
+            });
+        }
+        let bpp = self.format.bytes_per_pixel as usize;
+        let start = (rect.1 + bounds.1) as usize * self.stride as usize
+            + (rect.0 + bounds.0) as usize * bpp;
+        Ok((start, rect.2 as usize * bpp))
+    }
+
     pub fn put(&mut self, pos: Vect, c: &Color) -> Result<(), BufferError> {
         let true_pos = if let Some(subdim) = self.subdimensions {
             if pos.0 >= subdim.2 || pos.1 >= subdim.3 {
@@ -96,14 +210,148 @@ impl<'a> Buffer<'a> {
             pos
         };
 
-        unsafe {
-            let ptr = self
-                .buf
-                .as_mut_ptr()
-                .offset(4 * (true_pos.0 + (true_pos.1 * self.dimensions.0)) as isize);
-            *(ptr as *mut u32) = c.as_argb8888();
-        };
+        self.write_pixel(true_pos, c);
 
         Ok(())
     }
+
+    fn write_pixel(&mut self, pos: Vect, c: &Color) {
+        let bpp = self.format.bytes_per_pixel as usize;
+        let offset = pos.1 as usize * self.stride as usize + pos.0 as usize * bpp;
+        let value = self.format.pack(c, pos);
+        self.format
+            .write(&mut self.buf[offset..offset + bpp], value);
+        self.mark(pos, (pos.0 + 1, pos.1 + 1));
+    }
+
+    /// Grows what this view drew to by the rectangle from `start` to `end`.
+    fn mark(&mut self, start: Vect, end: Vect) {
+        let damage = match &mut self.damage {
+            Some(damage) => damage,
+            None => return,
+        };
+        let rect = (start.0, start.1, end.0 - start.0, end.1 - start.1);
+        match self.drawn.and_then(|i| damage.rects.get_mut(i)) {
+            Some((x, y, w, h)) => {
+                let (x0, y0) = ((*x).min(start.0), (*y).min(start.1));
+                let (x1, y1) = ((*x + *w).max(end.0), (*y + *h).max(end.1));
+                (*x, *y, *w, *h) = (x0, y0, x1 - x0, y1 - y0);
+            }
+            None => {
+                self.drawn = Some(damage.rects.len());
+                damage.add(rect);
+            }
+        }
+    }
+}
+
+/// The parts of a frame that changed since it was shown, as rectangles.
+#[derive(Debug, Default, Clone)]
+pub struct Damage {
+    rects: Vec<Rect>,
+}
+
+impl Damage {
+    /// The whole of a frame of `size`.
+    pub fn all(size: Vect) -> Self {
+        Self {
+            rects: vec![(0, 0, size.0, size.1)],
+        }
+    }
+
+    /// Where `new` differs from `old`, frames of `size` with rows `stride`
+    /// bytes apart of `bpp` bytes per pixel. Runs of changed rows make up
+    /// one rectangle, as wide as their changes.
+    pub fn between(old: &[u8], new: &[u8], size: Vect, stride: u32, bpp: u32) -> Self {
+        let mut rects = Vec::new();
+        let mut run: Option<Rect> = None;
+        for y in 0..size.1 {
+            let start = (y * stride) as usize;
+            let row = start..start + (size.0 * bpp) as usize;
+            let (a, b) = match (old.get(row.clone()), new.get(row)) {
+                (Some(a), Some(b)) => (a, b),
+                _ => break,
+            };
+            let first = match a.iter().zip(b).position(|(a, b)| a != b) {
+                Some(first) => first as u32 / bpp,
+                None => {
+                    rects.extend(run.take());
+                    continue;
+                }
+            };
+            let last = a.iter().zip(b).rposition(|(a, b)| a != b).unwrap_or(0) as u32 / bpp;
+            run = Some(match run {
+                Some((x, y, w, h)) => {
+                    let x0 = x.min(first);
+                    let x1 = (x + w).max(last + 1);
+                    (x0, y, x1 - x0, h + 1)
+                }
+                None => (first, y, last + 1 - first, 1),
+            });
+        }
+        rects.extend(run);
+        Self { rects }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rects.is_empty()
+    }
+
+    pub fn add(&mut self, rect: Rect) {
+        self.rects.push(rect);
+    }
+
+    pub fn rects(&self) -> &[Rect] {
+        &self.rects
+    }
+
+    /// Adds what changed in `other` too.
+    pub fn extend(&mut self, other: &Damage) {
+        self.rects.extend_from_slice(&other.rects);
+    }
+
+    /// Copies the changed parts of `src` to `dst`, both laid out as the
+    /// frames compared.
+    pub fn copy(&self, src: &[u8], dst: &mut [u8], stride: u32, bpp: u32) {
+        for &(x, y, w, h) in &self.rects {
+            for row in y..y + h {
+                let start = (row * stride + x * bpp) as usize;
+                let end = start + (w * bpp) as usize;
+                if let (Some(src), Some(dst)) = (src.get(start..end), dst.get_mut(start..end)) {
+                    dst.copy_from_slice(src);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn views_record_what_they_drew() {
+        let mut pixels = vec![0u8; 4 * 10 * 10];
+        let mut damage = Damage::default();
+        let format = PixelFormat::default();
+        {
+            let mut buf = Buffer::new(&mut pixels, (10, 10), 40, format).track(&mut damage);
+            let mut view = buf.offset((2, 3)).unwrap();
+            view.put((1, 1), &Color::WHITE).unwrap();
+            view.put((4, 2), &Color::WHITE).unwrap();
+            buf.put((0, 0), &Color::WHITE).unwrap();
+        }
+        assert_eq!(damage.rects(), [(3, 4, 4, 2), (0, 0, 1, 1)]);
+    }
+
+    #[test]
+    fn pasted_rectangles_are_recorded_whole() {
+        let mut pixels = vec![0u8; 4 * 10 * 10];
+        let mut damage = Damage::default();
+        let format = PixelFormat::default();
+        let mut buf = Buffer::new(&mut pixels, (10, 10), 40, format).track(&mut damage);
+        let mut view = buf.subdimensions((5, 5, 5, 5)).unwrap();
+        view.paste_rect((1, 2, 3, 2), &[0xFF; 4 * 3 * 2]).unwrap();
+        assert_eq!(damage.rects(), [(6, 7, 3, 2)]);
+    }
 }
@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::buffer::{Buffer, BufferError};
+use crate::color::Color;
+
+static FIXED_6X10: &[u8] = include_bytes!("../fonts/misc-fixed/6x10.psf");
+
+const PSF2_MAGIC: u32 = 0x864a_b572;
+const PSF2_HAS_UNICODE_TABLE: u32 = 1;
+const PSF2_HEADER_LEN: usize = 32;
+
+/// With `auto`, screens less tall than this get the cell based UI, as
+/// TrueType text is unreadable at the sizes that would fit.
+pub const AUTO_MAX_HEIGHT: u32 = 200;
+
+/// How the greeter draws its form.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Renderer {
+    #[default]
+    Auto,
+    TrueType,
+    /// A grid of bitmap font cells, with a box drawn around the form.
+    Cells,
+}
+
+impl FromStr for Renderer {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Renderer::Auto),
+            "truetype" => Ok(Renderer::TrueType),
+            "cells" => Ok(Renderer::Cells),
+            _ => Err(format!("unknown renderer {s}")),
+        }
+    }
+}
+
+impl fmt::Display for Renderer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Renderer::Auto => write!(f, "auto"),
+            Renderer::TrueType => write!(f, "truetype"),
+            Renderer::Cells => write!(f, "cells"),
+        }
+    }
+}
+
+impl Renderer {
+    pub fn use_cells(&self, screen_height: u32) -> bool {
+        match self {
+            Renderer::Auto => screen_height < AUTO_MAX_HEIGHT,
+            Renderer::TrueType => false,
+            Renderer::Cells => true,
+        }
+    }
+}
+
+/// A fixed size bitmap font in the PSF2 format used for console fonts.
+pub struct BitmapFont {
+    width: u32,
+    height: u32,
+    glyph_len: usize,
+    glyphs: Vec<u8>,
+    map: HashMap<char, usize>,
+}
+
+impl BitmapFont {
+    /// The built in 6x10 font.
+    pub fn builtin() -> Self {
+        Self::parse(FIXED_6X10).expect("built in font is a valid PSF2 font")
+    }
+
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let word = |i: usize| {
+            let bytes = data.get(i * 4..i * 4 + 4)?;
+            Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        };
+        if word(0)? != PSF2_MAGIC {
+            return None;
+        }
+        let header_len = word(2)? as usize;
+        let flags = word(3)?;
+        let count = word(4)? as usize;
+        let glyph_len = word(5)? as usize;
+        let (height, width) = (word(6)?, word(7)?);
+        if header_len < PSF2_HEADER_LEN || glyph_len < height as usize * width.div_ceil(8) as usize
+        {
+            return None;
+        }
+        let glyphs_end = header_len + count * glyph_len;
+        let glyphs = data.get(header_len..glyphs_end)?.to_vec();
+
+        let mut map = HashMap::new();
+        if flags & PSF2_HAS_UNICODE_TABLE != 0 {
+            let table = &data[glyphs_end..];
+            for (i, entry) in table.split(|b| *b == 0xFF).take(count).enumerate() {
+                // sequences of several code points after 0xFE aren't needed
+                let single = entry.split(|b| *b == 0xFE).next().unwrap_or_default();
+                for ch in String::from_utf8_lossy(single).chars() {
+                    map.entry(ch).or_insert(i);
+                }
+            }
+        } else {
+            for i in 0..count.min(256) {
+                map.insert(i as u8 as char, i);
+            }
+        }
+        Some(Self {
+            width,
+            height,
+            glyph_len,
+            glyphs,
+            map,
+        })
+    }
+
+    /// The size of a character cell in pixels.
+    pub fn cell(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn glyph(&self, ch: char) -> Option<&[u8]> {
+        let i = self
+            .map
+            .get(&ch)
+            .or_else(|| self.map.get(&'\u{FFFD}'))
+            .or_else(|| self.map.get(&'?'))?;
+        self.glyphs
+            .get(i * self.glyph_len..(i + 1) * self.glyph_len)
+    }
+
+    /// Draws `s` starting at the cell at `pos`, clipped to the buffer.
+    pub fn draw_str(
+        &self,
+        buf: &mut Buffer<'_>,
+        pos: (u32, u32),
+        fg: &Color,
+        bg: &Color,
+        s: &str,
+    ) -> Result<(), BufferError> {
+        let row_len = self.width.div_ceil(8) as usize;
+        let bounds = buf.get_bounds();
+        let y0 = pos.1 * self.height;
+        for (col, ch) in (pos.0..).zip(s.chars()) {
+            let x0 = col * self.width;
+            if x0 + self.width > bounds.2 || y0 + self.height > bounds.3 {
+                break;
+            }
+            let glyph = match self.glyph(ch) {
+                Some(glyph) => glyph,
+                None => continue,
+            };
+            for y in 0..self.height {
+                let row = &glyph[y as usize * row_len..(y as usize + 1) * row_len];
+                for x in 0..self.width {
+                    let set = row[x as usize / 8] & (0x80 >> (x % 8)) != 0;
+                    buf.put((x0 + x, y0 + y), if set { fg } else { bg })?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Fits `s` into `width` cells, keeping its end as that's where typing
+/// happens, and pads it with spaces.
+pub fn fit(s: &str, width: usize) -> String {
+    let len = s.chars().count();
+    let mut fitted: String = s.chars().skip(len.saturating_sub(width)).collect();
+    fitted.extend(std::iter::repeat_n(' ', width.saturating_sub(len)));
+    fitted
+}
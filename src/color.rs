@@ -1,6 +1,11 @@
+use std::fmt;
 use std::str::FromStr;
 
-#[derive(Debug, Clone, Copy, Default)]
+/// Contrast below which text is considered unreadable, WCAG's minimum for
+/// large text.
+pub const MIN_CONTRAST: f32 = 3.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
 pub struct Color {
     red: f32,
     green: f32,
@@ -18,24 +23,44 @@ const fn rgb(red: f32, green: f32, blue: f32) -> Color {
 }
 
 impl FromStr for Color {
-    type Err = crate::Error;
+    type Err = String;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let hex = s.strip_prefix("0x").unwrap();
-        match u32::from_str_radix(hex, 16) {
-            Ok(value) => {
-                let red = ((value >> 16) & 0xFF) as f32 / 255.0;
-                let green = ((value >> 8) & 0xFF) as f32 / 255.0;
-                let blue = (value & 0xFF) as f32 / 255.0;
-                Ok(rgb(red, green, blue))
-            }
-            Err(_) => Ok(rgb(255f32, 0f32, 0f32)),
-        }
+        let value = s
+            .strip_prefix("0x")
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| format!("expected a color like 0xRRGGBB, got {s}"))?;
+        let red = ((value >> 16) & 0xFF) as f32 / 255.0;
+        let green = ((value >> 8) & 0xFF) as f32 / 255.0;
+        let blue = (value & 0xFF) as f32 / 255.0;
+        Ok(rgb(red, green, blue))
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [red, green, blue, _] = self
+            .channels()
+            .map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+        write!(f, "0x{red:02x}{green:02x}{blue:02x}")
     }
 }
 
 impl Color {
+    pub const BLACK: Self = rgb(0.0, 0.0, 0.0);
     pub const WHITE: Self = rgb(1.0, 1.0, 1.0);
     pub const YELLOW: Self = rgb(0.75, 0.75, 0.25);
+    pub const RED: Self = rgb(0.75, 0.25, 0.25);
+    pub const GREEN: Self = rgb(0.25, 0.75, 0.25);
+
+    pub fn from_rgba8(rgba: [u8; 4]) -> Self {
+        let [red, green, blue, opacity] = rgba.map(|x| x as f32 / 255.0);
+        Self {
+            red,
+            green,
+            blue,
+            opacity,
+        }
+    }
 
     pub fn blend(&self, other: &Color, ratio: f32) -> Self {
         let ratio = ratio.clamp(0.0, 1.0);
@@ -48,8 +73,41 @@ impl Color {
         }
     }
 
-    pub fn as_argb8888(&self) -> u32 {
-        let argb = [self.opacity, self.red, self.green, self.blue];
-        u32::from_be_bytes(argb.map(|x| (x * 255.0) as u8))
+    /// Relative luminance as defined by WCAG.
+    pub fn luminance(&self) -> f32 {
+        let linear = |c: f32| {
+            if c <= 0.03928 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        };
+        0.2126 * linear(self.red) + 0.7152 * linear(self.green) + 0.0722 * linear(self.blue)
+    }
+
+    /// WCAG contrast ratio, from 1 for equal luminance up to 21.
+    pub fn contrast(&self, other: &Color) -> f32 {
+        let (a, b) = (self.luminance(), other.luminance());
+        (a.max(b) + 0.05) / (a.min(b) + 0.05)
+    }
+
+    /// The color closest to this one on the way to black or white that has a
+    /// contrast of at least `min` with all of `others`.
+    pub fn with_contrast(&self, others: &[Color], min: f32) -> Self {
+        const STEPS: u32 = 20;
+        for step in 0..=STEPS {
+            let ratio = step as f32 / STEPS as f32;
+            for target in [Color::BLACK, Color::WHITE].iter() {
+                let color = self.blend(target, ratio);
+                if others.iter().all(|other| color.contrast(other) >= min) {
+                    return color;
+                }
+            }
+        }
+        *self
+    }
+
+    pub fn channels(&self) -> [f32; 4] {
+        [self.red, self.green, self.blue, self.opacity]
     }
 }
@@ -0,0 +1,59 @@
+use std::time::Duration;
+
+use crate::buffer::Buffer;
+use crate::color::Color;
+
+/// How long the confetti keeps falling.
+pub const DURATION: Duration = Duration::from_secs(3);
+
+const PIECES: u32 = 80;
+const PIECE_SIZE: u32 = 6;
+const PALETTE: [[u8; 4]; 5] = [
+    [0xE5, 0x39, 0x35, 0xFF],
+    [0xFD, 0xD8, 0x35, 0xFF],
+    [0x43, 0xA0, 0x47, 0xFF],
+    [0x1E, 0x88, 0xE5, 0xFF],
+    [0x8E, 0x24, 0xAA, 0xFF],
+];
+
+/// Draws the confetti as it is `elapsed` into falling, over whatever is on
+/// screen.
+pub fn draw(buf: &mut Buffer<'_>, elapsed: Duration) {
+    let bounds = buf.get_bounds();
+    let (w, h) = (bounds.2, bounds.3);
+    if w <= PIECE_SIZE || h <= PIECE_SIZE {
+        return;
+    }
+    let t = elapsed.as_secs_f32();
+    for i in 0..PIECES {
+        let seed = hash(i);
+        // pieces start spread out above the screen, so they don't arrive at once
+        let start = -(((seed >> 8) % (h / 2)) as f32) - PIECE_SIZE as f32;
+        let speed = (h / 3 + (seed >> 16) % (h / 3).max(1)) as f32;
+        let y = start + speed * t;
+        let sway = (t * 3.0 + (seed % 7) as f32).sin() * PIECE_SIZE as f32 * 2.0;
+        let x = (seed % w) as f32 + sway;
+        if y < 0.0 || x < 0.0 {
+            continue;
+        }
+        let (x, y) = (x as u32, y as u32);
+        if x + PIECE_SIZE > w || y + PIECE_SIZE > h {
+            continue;
+        }
+        let color = Color::from_rgba8(PALETTE[(seed >> 24) as usize % PALETTE.len()]);
+        if let Ok(mut piece) = buf.subdimensions((x, y, PIECE_SIZE, PIECE_SIZE)) {
+            piece.memset(&color);
+        }
+    }
+}
+
+/// Spreads the bits of `i`, so pieces look randomly placed but are the same
+/// every frame.
+fn hash(i: u32) -> u32 {
+    let mut x = i.wrapping_mul(0x9E37_79B9) ^ 0x85EB_CA6B;
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7FEB_352D);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846C_A68B);
+    x ^ (x >> 16)
+}
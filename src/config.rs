@@ -0,0 +1,1060 @@
+use std::fmt::Display;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::{de, Deserialize, Deserializer};
+
+use crate::{
+    background, buffer::Vect, cells::Renderer, color::Color, draw::FocusStyle, draw::MaskStyle,
+    dropins, exit, exit::Failure, fbdev::RefreshStrategy, format::ByteOrder, input, keys::Keymap,
+    locale, otp, output::Backend, parse_theme, power::PowerSaver, prerender, scale::Filter,
+    sessions::SessionFilter, state, syslog, variant::DarkHours, variant::Variant, Module, Theme,
+};
+
+/// Options are read from here before the drop-ins, unless `--config` says
+/// otherwise.
+pub const CONFIG_FILE: &str = "/etc/ddlm/config.toml";
+const DEFAULT_FB_DEVICE: &str = "/dev/fb0";
+const DEFAULT_DRM_DEVICE: &str = "/dev/dri/card0";
+const DEFAULT_RECENT_USERS: usize = 5;
+
+#[derive(Default, Clone)]
+/// Everything that can be set from the config file, its drop-ins and the
+/// command line.
+pub struct Config {
+    pub session: Vec<String>,
+    pub theme: Theme,
+    pub dialog: Dialog,
+    pub hide_username: bool,
+    pub recent_users: Option<usize>,
+    pub no_seat_env: bool,
+    pub user_env: bool,
+    pub background_command: Option<String>,
+    pub background_interval: Option<Duration>,
+    pub scale_filter: Filter,
+    pub byte_order: ByteOrder,
+    pub refresh_strategy: RefreshStrategy,
+    pub backend: Backend,
+    pub fb_device: Option<String>,
+    pub drm_device: Option<String>,
+    pub tty: Option<String>,
+    pub input: input::Source,
+    pub key_repeat: Option<(u32, u32)>,
+    pub xkb_layout: Option<String>,
+    pub xkb_variant: Option<String>,
+    pub xkb_options: Option<String>,
+    pub sticky_keys: bool,
+    pub slow_keys: Option<Duration>,
+    pub echo_interval: Option<Duration>,
+    pub integrity_check: Option<Duration>,
+    pub console_loglevel: Option<u8>,
+    pub kbd_backlight: Option<u8>,
+    pub auth_timeout: Option<Duration>,
+    pub clock: bool,
+    pub clock_format: Option<String>,
+    pub status_command: Option<String>,
+    pub schedule: Option<String>,
+    pub hostname: bool,
+    pub os_logo: bool,
+    pub maintenance_lock: bool,
+    pub transcript: bool,
+    pub check_username: bool,
+    pub password_policy: Option<usize>,
+    pub unlock_command: Option<String>,
+    pub unlock_prompt: Option<String>,
+    pub after_auth_command: Option<String>,
+    pub after_auth_password: bool,
+    pub syslog: Option<syslog::Target>,
+    pub metrics_file: Option<String>,
+    pub notify_pipe: Option<String>,
+    pub otp_hints: Option<String>,
+    pub screenshot_dir: Option<String>,
+    pub dbus: bool,
+    pub blank_after: Option<Duration>,
+    pub clear_after: Option<Duration>,
+    pub power_saver: PowerSaver,
+    pub prerender: bool,
+    pub frame_cache: Option<String>,
+    pub focus_style: Option<FocusStyle>,
+    pub mask_style: MaskStyle,
+    pub session_name: Option<String>,
+    pub keymap: Keymap,
+    pub no_hints: bool,
+    pub no_power_menu: bool,
+    pub log_viewer: bool,
+    pub variant: Variant,
+    pub dark_hours: DarkHours,
+    pub fix_contrast: bool,
+    pub session_filter: SessionFilter,
+    pub state_dir: Option<String>,
+    pub user_sessions: Option<String>,
+    pub gallery_theme: Option<String>,
+    pub theme_file: Option<String>,
+    pub print_config: bool,
+    pub theme_edit: Option<String>,
+    pub font_test: bool,
+    pub font_test_dir: Option<String>,
+    pub preview: Option<String>,
+    pub resolution: Option<Vect>,
+    pub renderer: Renderer,
+    pub no_fade: bool,
+}
+
+impl Config {
+    /// Reads the theme from the gallery theme and theme file, as layered by
+    /// `parse_theme`.
+    pub fn load_theme(&mut self) {
+        // the edited theme is drawn instead of the theme file, once it exists
+        let theme_file = match &self.theme_edit {
+            Some(path) if Path::new(path).exists() => Some(path.as_str()),
+            _ => self.theme_file.as_deref(),
+        };
+        self.theme = parse_theme(self.gallery_theme.as_deref(), theme_file);
+        let theme = &mut self.theme;
+        for module in [&mut theme.module, &mut theme.dark, &mut theme.light] {
+            self.dialog.apply(module);
+        }
+        self.theme.check_contrast(self.fix_contrast);
+    }
+
+    pub fn fb_device(&self) -> &str {
+        self.fb_device.as_deref().unwrap_or(DEFAULT_FB_DEVICE)
+    }
+
+    pub fn drm_device(&self) -> &str {
+        self.drm_device.as_deref().unwrap_or(DEFAULT_DRM_DEVICE)
+    }
+
+    pub fn frame_cache(&self) -> String {
+        match (&self.frame_cache, &self.state_dir) {
+            (Some(path), _) => path.clone(),
+            (None, Some(dir)) => format!("{dir}/{}", prerender::FRAME_CACHE_NAME),
+            (None, None) => prerender::FRAME_CACHE.to_string(),
+        }
+    }
+
+    /// How many of the users who logged in last the username field offers,
+    /// none if the username is hidden.
+    pub fn recent_users(&self) -> usize {
+        match self.hide_username {
+            true => 0,
+            false => self.recent_users.unwrap_or(DEFAULT_RECENT_USERS),
+        }
+    }
+
+    pub fn state_dir(&self) -> &str {
+        self.state_dir.as_deref().unwrap_or(state::STATE_DIR)
+    }
+
+    pub fn otp_hints(&self) -> &str {
+        self.otp_hints.as_deref().unwrap_or(otp::OTP_HINTS)
+    }
+
+    /// Where the session each user last logged in to is kept.
+    pub fn user_sessions(&self) -> String {
+        match &self.user_sessions {
+            Some(path) => path.clone(),
+            None => format!("{}/{}", self.state_dir(), state::USER_SESSIONS),
+        }
+    }
+}
+
+/// The login dialog's placement, text size and colors, set over the theme's
+/// own in both its variants.
+#[derive(Default, Clone, Debug)]
+pub struct Dialog {
+    pub horizontal_alignment: Option<f32>,
+    pub vertical_alignment: Option<f32>,
+    /// The size of the form's text, which its rows are spaced by.
+    pub font_size: Option<f32>,
+    pub background_color: Option<Color>,
+    pub focus_color: Option<Color>,
+    pub info_color: Option<Color>,
+    pub error_color: Option<Color>,
+}
+
+impl Dialog {
+    fn apply(&self, module: &mut Module) {
+        if let Some(alignment) = self.horizontal_alignment {
+            module.dialog_horizontal_alignment = alignment;
+        }
+        if let Some(alignment) = self.vertical_alignment {
+            module.dialog_vertical_alignment = alignment;
+        }
+        if let Some(size) = self.font_size {
+            module.font = module.font.resized(size);
+        }
+        // a flat background, as the theme's BackgroundStartColor alone
+        if let Some(color) = self.background_color {
+            module.background_start_color = color;
+            module.background_end_color = color;
+        }
+        module.focus_color = self.focus_color.or(module.focus_color);
+        module.info_color = self.info_color.or(module.info_color);
+        module.error_color = self.error_color.or(module.error_color);
+    }
+}
+
+/// The options a config file or drop-in sets, named like the flags without
+/// the dashes. Those it leaves out keep what the files before it set.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct FileOptions {
+    session: Option<Command>,
+    session_name: Option<String>,
+    session_allow: Option<Vec<String>>,
+    session_deny: Option<Vec<String>>,
+    theme: Option<String>,
+    theme_file: Option<String>,
+    dialog_horizontal_alignment: Option<f32>,
+    dialog_vertical_alignment: Option<f32>,
+    dialog_font_size: Option<f32>,
+    #[serde(deserialize_with = "parsed")]
+    background_color: Option<Color>,
+    #[serde(deserialize_with = "parsed")]
+    focus_color: Option<Color>,
+    #[serde(deserialize_with = "parsed")]
+    info_color: Option<Color>,
+    #[serde(deserialize_with = "parsed")]
+    error_color: Option<Color>,
+    background_command: Option<String>,
+    background_interval: Option<u64>,
+    #[serde(deserialize_with = "parsed")]
+    scale_filter: Option<Filter>,
+    #[serde(deserialize_with = "parsed")]
+    backend: Option<Backend>,
+    fb_device: Option<String>,
+    drm_device: Option<String>,
+    #[serde(deserialize_with = "parsed")]
+    fb_byte_order: Option<ByteOrder>,
+    #[serde(deserialize_with = "parsed")]
+    refresh_strategy: Option<RefreshStrategy>,
+    #[serde(deserialize_with = "parsed")]
+    syslog: Option<syslog::Target>,
+    metrics_file: Option<String>,
+    notify_pipe: Option<String>,
+    otp_hints: Option<String>,
+    screenshot_dir: Option<String>,
+    dbus: Option<bool>,
+    tty: Option<String>,
+    #[serde(deserialize_with = "parsed")]
+    input: Option<input::Source>,
+    key_repeat: Option<String>,
+    xkb_layout: Option<String>,
+    xkb_variant: Option<String>,
+    xkb_options: Option<String>,
+    sticky_keys: Option<bool>,
+    slow_keys: Option<u64>,
+    console_loglevel: Option<u8>,
+    kbd_backlight: Option<u8>,
+    integrity_check: Option<u64>,
+    echo_interval: Option<u64>,
+    auth_timeout: Option<u64>,
+    clock: Option<bool>,
+    clock_format: Option<String>,
+    hostname: Option<bool>,
+    status_command: Option<String>,
+    schedule: Option<String>,
+    os_logo: Option<bool>,
+    transcript: Option<bool>,
+    check_username: Option<bool>,
+    password_policy: Option<usize>,
+    unlock_command: Option<String>,
+    unlock_prompt: Option<String>,
+    after_auth_command: Option<String>,
+    after_auth_password: Option<bool>,
+    maintenance_lock: Option<bool>,
+    clear_after: Option<u64>,
+    blank_after: Option<u64>,
+    #[serde(deserialize_with = "parsed")]
+    power_saver: Option<PowerSaver>,
+    prerender: Option<bool>,
+    frame_cache: Option<String>,
+    state_dir: Option<String>,
+    user_sessions: Option<String>,
+    #[serde(deserialize_with = "parsed")]
+    focus_style: Option<FocusStyle>,
+    #[serde(deserialize_with = "parsed")]
+    mask_style: Option<MaskStyle>,
+    #[serde(deserialize_with = "parsed")]
+    renderer: Option<Renderer>,
+    bind: Option<Vec<String>>,
+    no_hints: Option<bool>,
+    no_power_menu: Option<bool>,
+    log_viewer: Option<bool>,
+    #[serde(deserialize_with = "parsed")]
+    variant: Option<Variant>,
+    #[serde(deserialize_with = "parsed")]
+    dark_hours: Option<DarkHours>,
+    fix_contrast: Option<bool>,
+    no_fade: Option<bool>,
+    hide_username: Option<bool>,
+    recent_users: Option<usize>,
+    no_seat_env: Option<bool>,
+    user_env: Option<bool>,
+    json_errors: Option<bool>,
+    preview: Option<String>,
+    resolution: Option<String>,
+    font_test_dir: Option<String>,
+}
+
+/// A session command, as a line split into words honoring quotes or as the
+/// words themselves, spaces and all.
+#[derive(Deserialize)]
+#[serde(untagged, expecting = "a command line or an array of its words")]
+enum Command {
+    Line(String),
+    Words(Vec<String>),
+}
+
+/// Reads a string option the way its flag is parsed.
+fn parsed<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    let value = String::deserialize(deserializer)?;
+    value.parse().map(Some).map_err(de::Error::custom)
+}
+
+impl FileOptions {
+    /// Sets the options the file gives on `config`, failing with the first
+    /// one whose value can't be used.
+    fn apply(self, config: &mut Config) -> Result<(), String> {
+        match self.session {
+            Some(Command::Line(line)) => {
+                config.session =
+                    shell_words::split(&line).map_err(|err| format!("session: {err}"))?;
+            }
+            Some(Command::Words(words)) => config.session = words,
+            None => {}
+        }
+        set_some(&mut config.session_name, self.session_name);
+        set(&mut config.session_filter.allow, self.session_allow);
+        set(&mut config.session_filter.deny, self.session_deny);
+        if let Some(name) = self.theme {
+            config.gallery_theme = Some(theme_name(name).map_err(|err| format!("theme: {err}"))?);
+        }
+        set_some(&mut config.theme_file, self.theme_file);
+        let dialog = &mut config.dialog;
+        if let Some(value) = self.dialog_horizontal_alignment {
+            dialog.horizontal_alignment = Some(
+                check_alignment(value)
+                    .map_err(|err| format!("dialog-horizontal-alignment: {err}"))?,
+            );
+        }
+        if let Some(value) = self.dialog_vertical_alignment {
+            dialog.vertical_alignment = Some(
+                check_alignment(value)
+                    .map_err(|err| format!("dialog-vertical-alignment: {err}"))?,
+            );
+        }
+        if let Some(size) = self.dialog_font_size {
+            dialog.font_size =
+                Some(check_font_size(size).map_err(|err| format!("dialog-font-size: {err}"))?);
+        }
+        set_some(&mut dialog.background_color, self.background_color);
+        set_some(&mut dialog.focus_color, self.focus_color);
+        set_some(&mut dialog.info_color, self.info_color);
+        set_some(&mut dialog.error_color, self.error_color);
+        set_some(&mut config.background_command, self.background_command);
+        set_some(
+            &mut config.background_interval,
+            self.background_interval.map(Duration::from_secs),
+        );
+        set(&mut config.scale_filter, self.scale_filter);
+        set(&mut config.backend, self.backend);
+        set_some(&mut config.fb_device, self.fb_device);
+        set_some(&mut config.drm_device, self.drm_device);
+        set(&mut config.byte_order, self.fb_byte_order);
+        set(&mut config.refresh_strategy, self.refresh_strategy);
+        set_some(&mut config.syslog, self.syslog);
+        set_some(&mut config.metrics_file, self.metrics_file);
+        set_some(&mut config.notify_pipe, self.notify_pipe);
+        set_some(&mut config.otp_hints, self.otp_hints);
+        set_some(&mut config.screenshot_dir, self.screenshot_dir);
+        set(&mut config.dbus, self.dbus);
+        set_some(&mut config.tty, self.tty);
+        set(&mut config.input, self.input);
+        if let Some(repeat) = self.key_repeat {
+            let repeat = parse_repeat(&repeat)
+                .ok_or("key-repeat: expected <delay>:<period> in milliseconds")?;
+            config.key_repeat = Some(repeat);
+        }
+        set_some(&mut config.xkb_layout, self.xkb_layout);
+        set_some(&mut config.xkb_variant, self.xkb_variant);
+        set_some(&mut config.xkb_options, self.xkb_options);
+        set(&mut config.sticky_keys, self.sticky_keys);
+        set_some(
+            &mut config.slow_keys,
+            self.slow_keys.map(Duration::from_millis),
+        );
+        if let Some(level) = self.console_loglevel {
+            config.console_loglevel =
+                Some(check_loglevel(level).map_err(|err| format!("console-loglevel: {err}"))?);
+        }
+        if let Some(percent) = self.kbd_backlight {
+            config.kbd_backlight =
+                Some(check_percent(percent).map_err(|err| format!("kbd-backlight: {err}"))?);
+        }
+        set_some(
+            &mut config.integrity_check,
+            self.integrity_check.map(Duration::from_secs),
+        );
+        set_some(
+            &mut config.echo_interval,
+            self.echo_interval.map(Duration::from_millis),
+        );
+        set_some(
+            &mut config.auth_timeout,
+            self.auth_timeout.map(Duration::from_secs),
+        );
+        set(&mut config.clock, self.clock);
+        if let Some(format) = self.clock_format {
+            locale::check_format(&format).map_err(|err| format!("clock-format: {err}"))?;
+            config.clock_format = Some(format);
+        }
+        set(&mut config.hostname, self.hostname);
+        set_some(&mut config.status_command, self.status_command);
+        set_some(&mut config.schedule, self.schedule);
+        set(&mut config.os_logo, self.os_logo);
+        set(&mut config.transcript, self.transcript);
+        set(&mut config.check_username, self.check_username);
+        set_some(&mut config.password_policy, self.password_policy);
+        set_some(&mut config.unlock_command, self.unlock_command);
+        set_some(&mut config.unlock_prompt, self.unlock_prompt);
+        set_some(&mut config.after_auth_command, self.after_auth_command);
+        set(&mut config.after_auth_password, self.after_auth_password);
+        set(&mut config.maintenance_lock, self.maintenance_lock);
+        set_some(
+            &mut config.clear_after,
+            self.clear_after.map(Duration::from_secs),
+        );
+        set_some(
+            &mut config.blank_after,
+            self.blank_after.map(Duration::from_secs),
+        );
+        set(&mut config.power_saver, self.power_saver);
+        set(&mut config.prerender, self.prerender);
+        set_some(&mut config.frame_cache, self.frame_cache);
+        set_some(&mut config.state_dir, self.state_dir);
+        set_some(&mut config.user_sessions, self.user_sessions);
+        set_some(&mut config.focus_style, self.focus_style);
+        set(&mut config.mask_style, self.mask_style);
+        set(&mut config.renderer, self.renderer);
+        for binding in self.bind.iter().flatten() {
+            config
+                .keymap
+                .bind_str(binding)
+                .map_err(|err| format!("bind: {err}"))?;
+        }
+        set(&mut config.no_hints, self.no_hints);
+        set(&mut config.no_power_menu, self.no_power_menu);
+        set(&mut config.log_viewer, self.log_viewer);
+        set(&mut config.variant, self.variant);
+        set(&mut config.dark_hours, self.dark_hours);
+        set(&mut config.fix_contrast, self.fix_contrast);
+        set(&mut config.no_fade, self.no_fade);
+        set(&mut config.hide_username, self.hide_username);
+        set_some(&mut config.recent_users, self.recent_users);
+        set(&mut config.no_seat_env, self.no_seat_env);
+        set(&mut config.user_env, self.user_env);
+        set_some(&mut config.preview, self.preview);
+        if let Some(resolution) = self.resolution {
+            let size =
+                background::parse_size(&resolution).ok_or("resolution: expected WIDTHxHEIGHT")?;
+            config.resolution = Some(size);
+        }
+        set_some(&mut config.font_test_dir, self.font_test_dir);
+        Ok(())
+    }
+}
+
+/// Sets `option` to `value`, if the file gives one.
+fn set<T>(option: &mut T, value: Option<T>) {
+    if let Some(value) = value {
+        *option = value;
+    }
+}
+
+/// Sets the optional `option` to `value`, if the file gives one.
+fn set_some<T>(option: &mut Option<T>, value: Option<T>) {
+    if value.is_some() {
+        *option = value;
+    }
+}
+
+fn theme_name(name: String) -> Result<String, String> {
+    match name.is_empty() || name.contains('/') {
+        true => Err(format!("expected a theme name without slashes, got {name}")),
+        false => Ok(name),
+    }
+}
+
+fn check_alignment(value: f32) -> Result<f32, String> {
+    match (0.0..=1.0).contains(&value) {
+        true => Ok(value),
+        false => Err(format!("expected an alignment from 0 to 1, got {value}")),
+    }
+}
+
+fn check_font_size(size: f32) -> Result<f32, String> {
+    match size > 0.0 {
+        true => Ok(size),
+        false => Err(format!("expected a font size in pixels, got {size}")),
+    }
+}
+
+fn check_loglevel(level: u8) -> Result<u8, String> {
+    match level {
+        0..=8 => Ok(level),
+        _ => Err(format!("expected a log level from 0 to 8, got {level}")),
+    }
+}
+
+fn check_percent(percent: u8) -> Result<u8, String> {
+    match percent {
+        0..=100 => Ok(percent),
+        _ => Err(format!("expected a percentage, got {percent}")),
+    }
+}
+
+/// The configuration from the config file, then the drop-ins and then the
+/// command line, each overriding the options the previous one set. The theme
+/// is left to `Config::load_theme`, as it loads fonts.
+pub fn parse_args() -> Config {
+    // skip program name
+    let cli = std::env::args().skip(1).collect::<Vec<_>>();
+    // before anything can fail, the config file and drop-ins setting it
+    // apply once they're read
+    if cli.iter().any(|arg| arg == "--json-errors") {
+        exit::set_json_errors(true);
+    }
+    let path = cli
+        .iter()
+        .position(|arg| arg == "--config")
+        .and_then(|i| cli.get(i + 1));
+    let file = Path::new(path.map_or(CONFIG_FILE, String::as_str));
+    let files = dropins::read_all::<FileOptions>(file, path.is_some(), dropins::CONFIG_DIR)
+        .unwrap_or_else(|err| exit::fail(Failure::Config, err));
+    // before an option can fail, as with the flag
+    if files.iter().rev().find_map(|(_, file)| file.json_errors) == Some(true) {
+        exit::set_json_errors(true);
+    }
+    let mut config = Config::default();
+    for (path, file) in files {
+        if let Err(problem) = file.apply(&mut config) {
+            exit::fail(Failure::Config, format!("{}: {problem}", path.display()));
+        }
+    }
+    let problems = apply(&mut config, cli.into_iter());
+    if !problems.is_empty() {
+        exit::fail(Failure::Config, problems.join("\n"));
+    }
+
+    config
+}
+
+/// Sets the options `args` give on `config`, returning what was wrong with
+/// the others, unknown flags included.
+fn apply(config: &mut Config, mut args: impl Iterator<Item = String>) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut after_session_arg = false;
+    while let Some(arg) = args.next() {
+        // consecutive --session-arg make up one command, replacing any before
+        let starts_session = arg == "--session-arg" && !after_session_arg;
+        after_session_arg = arg == "--session-arg";
+        match arg.as_str() {
+            "--session" => match args.next().map(|v| shell_words::split(&v)) {
+                Some(Ok(words)) => config.session = words,
+                Some(Err(err)) => problems.push(format!("Unable to split --session: {err}")),
+                None => problems.push("Expected a value after --session".into()),
+            },
+            "--session-arg" => match args.next() {
+                Some(value) => {
+                    if starts_session {
+                        config.session.clear();
+                    }
+                    config.session.push(value);
+                }
+                None => problems.push("Expected a value after --session-arg".into()),
+            },
+            "--session-name" => {
+                if let Some(value) = args.next() {
+                    config.session_name = Some(value);
+                } else {
+                    problems.push("Expected a value after --session-name".into());
+                }
+            }
+            "--session-allow" => {
+                if let Some(value) = args.next() {
+                    config.session_filter.allow.push(value);
+                } else {
+                    problems.push("Expected a value after --session-allow".into());
+                }
+            }
+            "--session-deny" => {
+                if let Some(value) = args.next() {
+                    config.session_filter.deny.push(value);
+                } else {
+                    problems.push("Expected a value after --session-deny".into());
+                }
+            }
+            "--theme" => match args.next().map(theme_name) {
+                Some(Ok(name)) => config.gallery_theme = Some(name),
+                Some(Err(_)) => {
+                    problems.push("Expected a theme name without slashes after --theme".into())
+                }
+                None => problems.push("Expected a theme name after --theme".into()),
+            },
+            "--theme-file" => {
+                if let Some(value) = args.next() {
+                    config.theme_file = Some(value);
+                } else {
+                    problems.push("Expected a value after --theme-file".into());
+                }
+            }
+            "--background-command" => {
+                if let Some(value) = args.next() {
+                    config.background_command = Some(value);
+                } else {
+                    problems.push("Expected a value after --background-command".into());
+                }
+            }
+            "--background-interval" => match args.next().map(|v| v.parse()) {
+                Some(Ok(secs)) => config.background_interval = Some(Duration::from_secs(secs)),
+                _ => {
+                    problems.push("Expected a number of seconds after --background-interval".into())
+                }
+            },
+            "--integrity-check" => match args.next().map(|v| v.parse()) {
+                Some(Ok(secs)) => config.integrity_check = Some(Duration::from_secs(secs)),
+                _ => problems.push("Expected a number of seconds after --integrity-check".into()),
+            },
+            "--console-loglevel" => match args.next().map(|v| v.parse().map(check_loglevel)) {
+                Some(Ok(Ok(level))) => config.console_loglevel = Some(level),
+                _ => problems
+                    .push("Expected a log level from 0 to 8 after --console-loglevel".into()),
+            },
+            "--kbd-backlight" => match args.next().map(|v| v.parse().map(check_percent)) {
+                Some(Ok(Ok(percent))) => config.kbd_backlight = Some(percent),
+                _ => problems.push("Expected a percentage after --kbd-backlight".into()),
+            },
+            "--dialog-horizontal-alignment" => {
+                match args.next().map(|v| v.parse().map(check_alignment)) {
+                    Some(Ok(Ok(value))) => config.dialog.horizontal_alignment = Some(value),
+                    _ => problems.push(
+                        "Expected an alignment from 0 to 1 after --dialog-horizontal-alignment"
+                            .into(),
+                    ),
+                }
+            }
+            "--dialog-vertical-alignment" => {
+                match args.next().map(|v| v.parse().map(check_alignment)) {
+                    Some(Ok(Ok(value))) => config.dialog.vertical_alignment = Some(value),
+                    _ => problems.push(
+                        "Expected an alignment from 0 to 1 after --dialog-vertical-alignment"
+                            .into(),
+                    ),
+                }
+            }
+            "--dialog-font-size" => match args.next().map(|v| v.parse().map(check_font_size)) {
+                Some(Ok(Ok(size))) => config.dialog.font_size = Some(size),
+                _ => problems.push("Expected a size in pixels after --dialog-font-size".into()),
+            },
+            "--background-color" | "--focus-color" | "--info-color" | "--error-color" => {
+                match args.next().map(|v| v.parse()) {
+                    Some(Ok(color)) => {
+                        let dialog = &mut config.dialog;
+                        let option = match arg.as_str() {
+                            "--background-color" => &mut dialog.background_color,
+                            "--focus-color" => &mut dialog.focus_color,
+                            "--info-color" => &mut dialog.info_color,
+                            _ => &mut dialog.error_color,
+                        };
+                        *option = Some(color);
+                    }
+                    Some(Err(err)) => problems.push(err),
+                    None => problems.push(format!("Expected a color like 0xRRGGBB after {arg}")),
+                }
+            }
+            "--scale-filter" => match args.next().map(|v| v.parse()) {
+                Some(Ok(filter)) => config.scale_filter = filter,
+                Some(Err(err)) => problems.push(err.to_string()),
+                None => problems
+                    .push("Expected nearest, bilinear or lanczos after --scale-filter".into()),
+            },
+            "--syslog" => match args.next().map(|v| v.parse()) {
+                Some(Ok(target)) => config.syslog = Some(target),
+                Some(Err(err)) => problems.push(err.to_string()),
+                None => problems
+                    .push("Expected udp://HOST:PORT or tcp://HOST:PORT after --syslog".into()),
+            },
+            "--notify-pipe" => {
+                if let Some(value) = args.next() {
+                    config.notify_pipe = Some(value);
+                } else {
+                    problems.push("Expected a path after --notify-pipe".into());
+                }
+            }
+            "--otp-hints" => {
+                if let Some(value) = args.next() {
+                    config.otp_hints = Some(value);
+                } else {
+                    problems.push("Expected a path after --otp-hints".into());
+                }
+            }
+            "--metrics-file" => {
+                if let Some(value) = args.next() {
+                    config.metrics_file = Some(value);
+                } else {
+                    problems.push("Expected a path after --metrics-file".into());
+                }
+            }
+            "--screenshot-dir" => {
+                if let Some(value) = args.next() {
+                    config.screenshot_dir = Some(value);
+                } else {
+                    problems.push("Expected a directory after --screenshot-dir".into());
+                }
+            }
+            "--fb-byte-order" => match args.next().map(|v| v.parse()) {
+                Some(Ok(order)) => config.byte_order = order,
+                Some(Err(err)) => problems.push(err.to_string()),
+                None => {
+                    problems.push("Expected native, little or big after --fb-byte-order".into())
+                }
+            },
+            "--refresh-strategy" => match args.next().map(|v| v.parse()) {
+                Some(Ok(strategy)) => config.refresh_strategy = strategy,
+                Some(Err(err)) => problems.push(err.to_string()),
+                None => problems.push(
+                    "Expected auto, force-activate, pan, msync or none after --refresh-strategy"
+                        .into(),
+                ),
+            },
+            "--config" => {
+                // already read
+                args.next();
+            }
+            "--input" => match args.next().map(|v| v.parse()) {
+                Some(Ok(source)) => config.input = source,
+                Some(Err(err)) => problems.push(err.to_string()),
+                None => problems.push("Expected console or evdev after --input".into()),
+            },
+            "--key-repeat" => match args.next().as_deref().and_then(parse_repeat) {
+                Some(repeat) => config.key_repeat = Some(repeat),
+                None => problems
+                    .push("Expected <delay>:<period> in milliseconds after --key-repeat".into()),
+            },
+            "--xkb-layout" => match args.next() {
+                Some(layout) => config.xkb_layout = Some(layout),
+                None => problems.push("Expected an XKB layout after --xkb-layout".into()),
+            },
+            "--xkb-variant" => match args.next() {
+                Some(variant) => config.xkb_variant = Some(variant),
+                None => problems.push("Expected an XKB layout variant after --xkb-variant".into()),
+            },
+            "--xkb-options" => match args.next() {
+                Some(options) => config.xkb_options = Some(options),
+                None => problems.push("Expected XKB options after --xkb-options".into()),
+            },
+            "--sticky-keys" => config.sticky_keys = true,
+            "--slow-keys" => match args.next().map(|v| v.parse()) {
+                Some(Ok(ms)) => config.slow_keys = Some(Duration::from_millis(ms)),
+                _ => problems.push("Expected a number of milliseconds after --slow-keys".into()),
+            },
+            "--backend" => match args.next().map(|v| v.parse()) {
+                Some(Ok(backend)) => config.backend = backend,
+                Some(Err(err)) => problems.push(err.to_string()),
+                None => problems.push("Expected auto, drm or fbdev after --backend".into()),
+            },
+            "--drm-device" => {
+                if let Some(value) = args.next() {
+                    config.drm_device = Some(value);
+                } else {
+                    problems.push("Expected a path after --drm-device".into());
+                }
+            }
+            "--fb-device" => {
+                if let Some(value) = args.next() {
+                    config.fb_device = Some(value);
+                } else {
+                    problems.push("Expected a path after --fb-device".into());
+                }
+            }
+            "--tty" => {
+                if let Some(value) = args.next() {
+                    config.tty = Some(value);
+                } else {
+                    problems.push("Expected a value after --tty".into());
+                }
+            }
+            "--echo-interval" => match args.next().map(|v| v.parse()) {
+                Some(Ok(ms)) => config.echo_interval = Some(Duration::from_millis(ms)),
+                _ => {
+                    problems.push("Expected a number of milliseconds after --echo-interval".into())
+                }
+            },
+            "--auth-timeout" => match args.next().map(|v| v.parse()) {
+                Some(Ok(secs)) => config.auth_timeout = Some(Duration::from_secs(secs)),
+                _ => problems.push("Expected a number of seconds after --auth-timeout".into()),
+            },
+            "--clock" => config.clock = true,
+            "--clock-format" => match args.next() {
+                Some(format) => match locale::check_format(&format) {
+                    Ok(()) => config.clock_format = Some(format),
+                    Err(err) => problems.push(err.to_string()),
+                },
+                None => problems.push("Expected a strftime format after --clock-format".into()),
+            },
+            "--hostname" => config.hostname = true,
+            "--status-command" => match args.next() {
+                Some(command) => config.status_command = Some(command),
+                None => problems.push("Expected a command after --status-command".into()),
+            },
+            "--schedule" => match args.next() {
+                Some(path) => config.schedule = Some(path),
+                None => problems.push("Expected a path after --schedule".into()),
+            },
+            "--os-logo" => config.os_logo = true,
+            "--transcript" => config.transcript = true,
+            "--check-username" => config.check_username = true,
+            "--password-policy" => match args.next().map(|v| v.parse()) {
+                Some(Ok(len)) => config.password_policy = Some(len),
+                _ => problems.push("Expected a minimum length after --password-policy".into()),
+            },
+            "--unlock-command" => match args.next() {
+                Some(command) => config.unlock_command = Some(command),
+                None => problems.push("Expected a command after --unlock-command".into()),
+            },
+            "--unlock-prompt" => match args.next() {
+                Some(prompt) => config.unlock_prompt = Some(prompt),
+                None => problems.push("Expected a label after --unlock-prompt".into()),
+            },
+            "--after-auth-command" => match args.next() {
+                Some(command) => config.after_auth_command = Some(command),
+                None => problems.push("Expected a command after --after-auth-command".into()),
+            },
+            "--after-auth-password" => config.after_auth_password = true,
+            "--maintenance-lock" => config.maintenance_lock = true,
+            "--blank-after" => match args.next().map(|v| v.parse()) {
+                Some(Ok(secs)) => config.blank_after = Some(Duration::from_secs(secs)),
+                _ => problems.push("Expected a number of seconds after --blank-after".into()),
+            },
+            "--clear-after" => match args.next().map(|v| v.parse()) {
+                Some(Ok(secs)) => config.clear_after = Some(Duration::from_secs(secs)),
+                _ => problems.push("Expected a number of seconds after --clear-after".into()),
+            },
+            "--power-saver" => match args.next().map(|v| v.parse()) {
+                Some(Ok(mode)) => config.power_saver = mode,
+                Some(Err(err)) => problems.push(err.to_string()),
+                None => problems.push("Expected on, off or auto after --power-saver".into()),
+            },
+            "--prerender" => config.prerender = true,
+            "--frame-cache" => {
+                if let Some(value) = args.next() {
+                    config.frame_cache = Some(value);
+                } else {
+                    problems.push("Expected a value after --frame-cache".into());
+                }
+            }
+            "--state-dir" => {
+                if let Some(value) = args.next() {
+                    config.state_dir = Some(value);
+                } else {
+                    problems.push("Expected a value after --state-dir".into());
+                }
+            }
+            "--user-sessions" => {
+                if let Some(value) = args.next() {
+                    config.user_sessions = Some(value);
+                } else {
+                    problems.push("Expected a value after --user-sessions".into());
+                }
+            }
+            "--renderer" => match args.next().map(|v| v.parse()) {
+                Some(Ok(renderer)) => config.renderer = renderer,
+                Some(Err(err)) => problems.push(err.to_string()),
+                None => problems.push("Expected auto, truetype or cells after --renderer".into()),
+            },
+            "--focus-style" => match args.next().map(|v| v.parse()) {
+                Some(Ok(style)) => config.focus_style = Some(style),
+                Some(Err(err)) => problems.push(err.to_string()),
+                None => problems.push(
+                    "Expected none, underline, box, arrow or inverse after --focus-style".into(),
+                ),
+            },
+            "--mask-style" => match args.next().map(|v| v.parse()) {
+                Some(Ok(style)) => config.mask_style = style,
+                Some(Err(err)) => problems.push(err.to_string()),
+                None => problems.push("Expected stars or dots after --mask-style".into()),
+            },
+            "--bind" => {
+                if let Some(value) = args.next() {
+                    if let Err(err) = config.keymap.bind_str(&value) {
+                        problems.push(err.to_string());
+                    }
+                } else {
+                    problems.push("Expected KEY=ACTION after --bind".into());
+                }
+            }
+            "--no-hints" => config.no_hints = true,
+            "--no-power-menu" => config.no_power_menu = true,
+            "--log-viewer" => config.log_viewer = true,
+            "--variant" => match args.next().map(|v| v.parse()) {
+                Some(Ok(variant)) => config.variant = variant,
+                Some(Err(err)) => problems.push(err.to_string()),
+                None => problems.push("Expected dark, light or auto after --variant".into()),
+            },
+            "--dark-hours" => match args.next().map(|v| v.parse()) {
+                Some(Ok(hours)) => config.dark_hours = hours,
+                Some(Err(err)) => problems.push(err.to_string()),
+                None => problems.push("Expected START-END hours after --dark-hours".into()),
+            },
+            "--fix-contrast" => config.fix_contrast = true,
+            "--no-fade" => config.no_fade = true,
+            "--hide-username" => config.hide_username = true,
+            "--recent-users" => match args.next().map(|v| v.parse()) {
+                Some(Ok(count)) => config.recent_users = Some(count),
+                _ => problems.push("Expected a number of users after --recent-users".into()),
+            },
+            "--no-seat-env" => config.no_seat_env = true,
+            "--dbus" => config.dbus = true,
+            "--user-env" => config.user_env = true,
+            "--json-errors" => exit::set_json_errors(true),
+            "print-config" => config.print_config = true,
+            "font-test" => config.font_test = true,
+            "--font-test-dir" => match args.next() {
+                Some(dir) => config.font_test_dir = Some(dir),
+                None => problems.push("Expected a directory after --font-test-dir".into()),
+            },
+            "--preview" => match args.next() {
+                Some(path) => config.preview = Some(path),
+                None => problems.push("Expected a PNG file after --preview".into()),
+            },
+            "--resolution" => match args.next().map(|v| background::parse_size(&v)) {
+                Some(Some(size)) => config.resolution = Some(size),
+                _ => problems.push("Expected WIDTHxHEIGHT after --resolution".into()),
+            },
+            "theme-edit" => {
+                if let Some(value) = args.next() {
+                    config.theme_edit = Some(value);
+                } else {
+                    problems.push("Expected a theme file after theme-edit".into());
+                }
+            }
+            _ if arg.starts_with("--") => {
+                problems.push(format!("Unknown flag: {arg}"));
+            }
+            _ => {
+                problems.push(format!("unknown arg {arg}"));
+            }
+        }
+    }
+    problems
+}
+
+/// Parses a key repeat delay and period in milliseconds, written as
+/// `DELAY:PERIOD`.
+fn parse_repeat(s: &str) -> Option<(u32, u32)> {
+    let (delay, period) = s.split_once(':')?;
+    Some((delay.parse().ok()?, period.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dump;
+
+    fn file(content: &str) -> Result<FileOptions, toml::de::Error> {
+        toml::from_str(content)
+    }
+
+    #[test]
+    fn unknown_options_are_rejected() {
+        assert!(file("colour = \"0x102030\"").is_err());
+        assert!(file("session-arg = \"sway\"").is_err());
+        assert!(file("scale-filter = \"cubic\"").is_err());
+        assert!(file("clock = \"yes\"").is_err());
+    }
+
+    #[test]
+    fn later_files_override_earlier_ones() {
+        let mut config = Config::default();
+        let first = "session = \"sway --unsupported-gpu\"\nclock = true\necho-interval = 50";
+        file(first).unwrap().apply(&mut config).unwrap();
+        let second = "session = [\"my wm\"]\nclock = false\nbackground-color = \"0x102030\"";
+        file(second).unwrap().apply(&mut config).unwrap();
+        assert_eq!(config.session, ["my wm"]);
+        assert!(!config.clock);
+        assert_eq!(config.echo_interval, Some(Duration::from_millis(50)));
+        assert_eq!(
+            config.dialog.background_color.map(|c| c.to_string()),
+            Some("0x102030".to_string())
+        );
+    }
+
+    #[test]
+    fn bad_values_name_the_option() {
+        let mut config = Config::default();
+        let err = file("console-loglevel = 9")
+            .unwrap()
+            .apply(&mut config)
+            .unwrap_err();
+        assert!(err.starts_with("console-loglevel: "), "{}", err);
+        let err = file("dialog-vertical-alignment = 1.5")
+            .unwrap()
+            .apply(&mut config)
+            .unwrap_err();
+        assert!(err.starts_with("dialog-vertical-alignment: "), "{}", err);
+    }
+
+    /// The keys a config file takes, as serde lists them for an unknown one.
+    fn file_keys() -> Vec<String> {
+        let err = file("no-such-key = 1").err().unwrap().to_string();
+        let (_, expected) = err.split_once("expected one of ").unwrap();
+        let expected = expected.lines().next().unwrap();
+        expected
+            .split(", ")
+            .map(|key| key.trim_matches('`').to_string())
+            .collect()
+    }
+
+    #[test]
+    fn printed_config_reads_back() {
+        let mut config = Config::default();
+        let options = "session = \"sway -d\"\nclock = true\nbackground-color = \"0x102030\"\n\
+                       syslog = \"udp://logs.example.com:514\"\nresolution = \"800x600\"";
+        file(options).unwrap().apply(&mut config).unwrap();
+        let printed = dump::config_toml(&config);
+        let mut read_back = Config::default();
+        file(&printed).unwrap().apply(&mut read_back).unwrap();
+        assert_eq!(dump::config_toml(&read_back), printed);
+    }
+
+    #[test]
+    fn printed_config_has_every_key() {
+        let printed = dump::config_toml(&Config::default());
+        let mut printed = printed
+            .lines()
+            .map(|line| line.trim_start_matches("# "))
+            .filter_map(|line| line.split_once(" =").map(|(key, _)| key.to_string()))
+            .collect::<Vec<_>>();
+        let mut keys = file_keys();
+        printed.sort();
+        keys.sort();
+        assert_eq!(printed, keys);
+    }
+
+    #[test]
+    fn unknown_flags_are_problems() {
+        let mut config = Config::default();
+        let args = ["--clock", "--bogus", "--dialog-font-size", "0"];
+        let problems = apply(&mut config, args.iter().map(|arg| arg.to_string()));
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert!(config.clock);
+    }
+}
@@ -0,0 +1,295 @@
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::os::raw::{c_char, c_short};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::panic;
+use std::sync::Mutex;
+
+use framebuffer::KdMode;
+use lazy_static::lazy_static;
+use nix::sys::signal::Signal;
+use nix::sys::termios::{
+    cfmakeraw, tcflush, tcgetattr, tcsetattr, FlushArg, LocalFlags, SetArg, Termios,
+};
+use nix::unistd::{isatty, ttyname, write};
+use termion::cursor;
+
+use crate::logtail;
+
+// from linux/kd.h
+const KDSETMODE: u32 = 0x4B3A;
+// from asm-generic/ioctls.h
+const TIOCEXCL: u32 = 0x540C;
+const TIOCNXCL: u32 = 0x540D;
+// from linux/vt.h
+const VT_SETMODE: u32 = 0x5602;
+const VT_RELDISP: u32 = 0x5605;
+const VT_AUTO: c_char = 0;
+const VT_PROCESS: c_char = 1;
+const VT_ACKACQ: i32 = 2;
+
+const ACTIVE_VT: &str = "/sys/class/tty/tty0/active";
+const PRINTK: &str = "/proc/sys/kernel/printk";
+
+nix::ioctl_write_int_bad!(kdsetmode, KDSETMODE);
+nix::ioctl_none_bad!(tiocexcl, TIOCEXCL);
+nix::ioctl_none_bad!(tiocnxcl, TIOCNXCL);
+nix::ioctl_write_ptr_bad!(vt_setmode, VT_SETMODE, VtMode);
+nix::ioctl_write_int_bad!(vt_reldisp, VT_RELDISP);
+
+#[repr(C)]
+struct VtMode {
+    mode: c_char,
+    waitv: c_char,
+    relsig: c_short,
+    acqsig: c_short,
+    frsig: c_short,
+}
+
+impl VtMode {
+    fn new(mode: c_char, relsig: Option<Signal>, acqsig: Option<Signal>) -> Self {
+        Self {
+            mode,
+            waitv: 0,
+            relsig: relsig.map_or(0, |signal| signal as c_short),
+            acqsig: acqsig.map_or(0, |signal| signal as c_short),
+            frsig: 0,
+        }
+    }
+}
+
+/// What has to be undone to give the terminal back. It's kept outside of the
+/// console so that it's also undone on panics and early exits.
+struct Changes {
+    fd: RawFd,
+    termios: Option<Termios>,
+    graphics: bool,
+    cursor_hidden: bool,
+    grabbed: bool,
+    // whether the kernel asks before switching VTs
+    vt_process: bool,
+    // the console log level from before it was changed
+    loglevel: Option<String>,
+}
+
+impl Changes {
+    fn undo(&mut self) {
+        if self.graphics {
+            let _ = unsafe { kdsetmode(self.fd, KdMode::Text as i32) };
+            self.graphics = false;
+        }
+        if self.cursor_hidden {
+            let _ = write(self.fd, cursor::Show.to_string().as_bytes());
+            self.cursor_hidden = false;
+        }
+        if let Some(termios) = self.termios.take() {
+            let _ = tcsetattr(self.fd, SetArg::TCSANOW, &termios);
+        }
+        if self.grabbed {
+            let _ = unsafe { tiocnxcl(self.fd) };
+            self.grabbed = false;
+        }
+        if self.vt_process {
+            let _ = unsafe { vt_setmode(self.fd, &VtMode::new(VT_AUTO, None, None)) };
+            self.vt_process = false;
+        }
+        if let Some(loglevel) = self.loglevel.take() {
+            let _ = fs::write(PRINTK, loglevel);
+        }
+    }
+}
+
+lazy_static! {
+    static ref CHANGES: Mutex<Option<Changes>> = Mutex::new(None);
+    // stdin's settings from before echo was turned off early
+    static ref EARLY_TERMIOS: Mutex<Option<Termios>> = Mutex::new(None);
+}
+
+fn changes<F: FnOnce(&mut Changes)>(f: F) {
+    // try_lock, as a panic while it's held must not deadlock the panic hook
+    if let Ok(mut changes) = CHANGES.try_lock() {
+        if let Some(changes) = changes.as_mut() {
+            f(changes);
+        }
+    }
+}
+
+/// Gives the terminal back in text mode with its original settings, if a
+/// console is open. Safe to call any number of times.
+pub fn restore_terminal() {
+    // stderr too, so that nothing written on the way out is lost
+    logtail::release();
+    changes(Changes::undo);
+    if let Ok(mut early) = EARLY_TERMIOS.try_lock() {
+        if let Some(termios) = early.take() {
+            // what was typed at the greeter is not for the shell
+            let _ = tcsetattr(0, SetArg::TCSAFLUSH, &termios);
+        }
+    }
+}
+
+/// Lets the kernel switch away from the VT once nothing is drawn to it
+/// anymore, if `release`, or else acknowledges switching back to it.
+pub fn acknowledge_vt_switch(release: bool) {
+    let arg = if release { 1 } else { VT_ACKACQ };
+    changes(|changes| {
+        if let Err(err) = unsafe { vt_reldisp(changes.fd, arg) } {
+            eprintln!("unable to acknowledge the VT switch: {err}");
+        }
+    });
+}
+
+/// Turns echo off on stdin, if it's a terminal, and drops what was typed so
+/// far. Meant to run first thing, so keys typed while the greeter starts
+/// neither show up on the console nor reach a shell afterwards.
+pub fn suppress_echo() {
+    if !isatty(0).unwrap_or(false) {
+        return;
+    }
+    let termios = match tcgetattr(0) {
+        Ok(termios) => termios,
+        Err(_) => return,
+    };
+    let mut quiet = termios.clone();
+    quiet
+        .local_flags
+        .remove(LocalFlags::ECHO | LocalFlags::ECHONL);
+    let _ = tcflush(0, FlushArg::TCIFLUSH);
+    if tcsetattr(0, SetArg::TCSANOW, &quiet).is_ok() {
+        *EARLY_TERMIOS.lock().unwrap_or_else(|err| err.into_inner()) = Some(termios);
+    }
+}
+
+/// Restores the terminal before a panic message is printed, so that it's
+/// readable and the console is usable afterwards.
+pub fn install_panic_hook() {
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        restore_terminal();
+        hook(info);
+    }));
+}
+
+/// The virtual terminal the greeter draws on and reads input from. Whatever
+/// it changed about the terminal is undone when it's dropped.
+pub struct Console {
+    path: String,
+    tty: File,
+}
+
+impl Console {
+    /// Opens `path`, or the terminal on stdin, or the active VT when started
+    /// without a controlling terminal.
+    pub fn open(path: Option<&str>) -> io::Result<Self> {
+        let path = match path {
+            Some(path) => path.to_string(),
+            None => default_tty()?,
+        };
+        let tty = OpenOptions::new().read(true).write(true).open(&path)?;
+        *CHANGES.lock().unwrap_or_else(|err| err.into_inner()) = Some(Changes {
+            fd: tty.as_raw_fd(),
+            termios: None,
+            graphics: false,
+            cursor_hidden: false,
+            grabbed: false,
+            vt_process: false,
+            loglevel: None,
+        });
+        Ok(Self { path, tty })
+    }
+
+    /// The terminal's device, such as `/dev/tty1`.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The VT number, if this is a virtual terminal.
+    pub fn vt(&self) -> Option<u32> {
+        self.path.strip_prefix("/dev/tty")?.parse().ok()
+    }
+
+    pub fn set_kd_mode(&self, mode: KdMode) -> nix::Result<()> {
+        let graphics = matches!(mode, KdMode::Graphics);
+        unsafe { kdsetmode(self.tty.as_raw_fd(), mode as i32) }?;
+        changes(|changes| changes.graphics = graphics);
+        Ok(())
+    }
+
+    pub fn enter_raw_mode(&mut self) -> nix::Result<()> {
+        let termios = tcgetattr(self.tty.as_raw_fd())?;
+        let mut raw = termios.clone();
+        cfmakeraw(&mut raw);
+        // dropping keys typed before the greeter was ready
+        tcsetattr(self.tty.as_raw_fd(), SetArg::TCSAFLUSH, &raw)?;
+        changes(|changes| {
+            changes.termios.get_or_insert(termios);
+        });
+        Ok(())
+    }
+
+    /// Hides the blinking text cursor, which some drivers keep drawing over
+    /// the graphics even in KD_GRAPHICS mode.
+    pub fn hide_cursor(&mut self) -> io::Result<()> {
+        write(self.tty.as_raw_fd(), cursor::Hide.to_string().as_bytes())?;
+        changes(|changes| changes.cursor_hidden = true);
+        Ok(())
+    }
+
+    /// Puts the terminal in exclusive mode, so that nothing else can open it
+    /// and read what is typed, such as passwords, while the greeter runs.
+    /// Only root can still open it.
+    pub fn grab(&mut self) -> nix::Result<()> {
+        unsafe { tiocexcl(self.tty.as_raw_fd()) }?;
+        changes(|changes| changes.grabbed = true);
+        Ok(())
+    }
+
+    /// Has the kernel ask, with SIGUSR1, before switching away from the VT,
+    /// and tell, with SIGUSR2, when it's back, so that the greeter stops
+    /// drawing over another VT and redraws once it's back.
+    pub fn control_vt_switches(&mut self) -> nix::Result<()> {
+        let mode = VtMode::new(VT_PROCESS, Some(Signal::SIGUSR1), Some(Signal::SIGUSR2));
+        unsafe { vt_setmode(self.tty.as_raw_fd(), &mode) }?;
+        changes(|changes| changes.vt_process = true);
+        Ok(())
+    }
+
+    /// Sets the console log level, so that kernel messages below `level`
+    /// aren't printed over the greeter.
+    pub fn set_loglevel(&mut self, level: u8) -> io::Result<()> {
+        let printk = fs::read_to_string(PRINTK)?;
+        let original = printk.split_whitespace().next().unwrap_or("").to_string();
+        fs::write(PRINTK, level.to_string())?;
+        changes(|changes| {
+            changes.loglevel.get_or_insert(original);
+        });
+        Ok(())
+    }
+
+    /// Restores text mode, the cursor and the original terminal settings, and
+    /// releases the terminal for the session.
+    pub fn restore(&mut self) {
+        restore_terminal();
+    }
+
+    /// A separate handle to read keyboard input from.
+    pub fn input(&self) -> io::Result<File> {
+        self.tty.try_clone()
+    }
+}
+
+impl Drop for Console {
+    fn drop(&mut self) {
+        restore_terminal();
+        // the descriptor is closed with the console
+        *CHANGES.lock().unwrap_or_else(|err| err.into_inner()) = None;
+    }
+}
+
+fn default_tty() -> io::Result<String> {
+    if let Ok(tty) = ttyname(0) {
+        return Ok(tty.to_string_lossy().into_owned());
+    }
+    let active = fs::read_to_string(ACTIVE_VT)?;
+    Ok(format!("/dev/{}", active.trim()))
+}
@@ -0,0 +1,554 @@
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::env;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
+
+use nix::poll::{poll, PollFd, PollFlags};
+
+const SYSTEM_BUS: &str = "/run/dbus/system_bus_socket";
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+// message types
+const METHOD_CALL: u8 = 1;
+const METHOD_RETURN: u8 = 2;
+const ERROR: u8 = 3;
+
+// message flags
+const NO_REPLY_EXPECTED: u8 = 1;
+
+// RequestName's flag and the reply for the name being given
+const DO_NOT_QUEUE: u32 = 4;
+const PRIMARY_OWNER: u32 = 1;
+
+// header fields
+const PATH: u8 = 1;
+const INTERFACE: u8 = 2;
+const MEMBER: u8 = 3;
+const ERROR_NAME: u8 = 4;
+const REPLY_SERIAL: u8 = 5;
+const DESTINATION: u8 = 6;
+const SENDER: u8 = 7;
+const SIGNATURE: u8 = 8;
+
+/// An argument of a method call, of the few types the greeter passes.
+#[derive(Debug, Clone, Copy)]
+pub enum Arg<'a> {
+    Bool(bool),
+    U32(u32),
+    Str(&'a str),
+}
+
+/// A header field's value.
+#[derive(Debug, Clone, Copy)]
+enum Field<'a> {
+    Str(&'a str),
+    Path(&'a str),
+    Signature(&'a str),
+    U32(u32),
+}
+
+/// A method another client called on the greeter.
+#[derive(Debug, Clone)]
+pub struct Call {
+    pub path: String,
+    pub interface: String,
+    pub member: String,
+    serial: u32,
+    pub sender: Option<String>,
+    no_reply: bool,
+}
+
+/// A connection to the system bus, speaking just enough of the D-Bus wire
+/// protocol to call methods, read string replies and answer calls without
+/// arguments.
+pub struct Bus {
+    stream: BufReader<UnixStream>,
+    serial: u32,
+    // calls made on the greeter while it waited for a reply
+    calls: Vec<Call>,
+}
+
+impl Bus {
+    /// Connects to the system bus, authenticating as the greeter's user.
+    pub fn system() -> io::Result<Self> {
+        let path = env::var("DBUS_SYSTEM_BUS_ADDRESS")
+            .ok()
+            .and_then(|address| Some(address.strip_prefix("unix:path=")?.to_string()))
+            .unwrap_or_else(|| SYSTEM_BUS.to_string());
+        let mut bus = Self::new(UnixStream::connect(path)?)?;
+        let uid = nix::unistd::getuid().to_string();
+        let hex = uid.bytes().map(|b| format!("{b:02x}")).collect::<String>();
+        bus.stream
+            .get_mut()
+            .write_all(format!("\0AUTH EXTERNAL {hex}\r\n").as_bytes())?;
+        let mut line = String::new();
+        bus.stream.read_line(&mut line)?;
+        if !line.starts_with("OK ") {
+            return Err(io::Error::other(format!(
+                "bus refused authentication: {}",
+                line.trim_end()
+            )));
+        }
+        bus.stream.get_mut().write_all(b"BEGIN\r\n")?;
+        bus.call(
+            "org.freedesktop.DBus",
+            "/org/freedesktop/DBus",
+            "org.freedesktop.DBus",
+            "Hello",
+            &[],
+        )?;
+        Ok(bus)
+    }
+
+    /// Speaks on `stream`, already connected and authenticated.
+    pub fn new(stream: UnixStream) -> io::Result<Self> {
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        Ok(Self {
+            stream: BufReader::new(stream),
+            serial: 0,
+            calls: Vec::new(),
+        })
+    }
+
+    /// Calls `member` and waits for its reply, returning its first argument
+    /// if that's a string. Errors carry the error's name and message.
+    pub fn call(
+        &mut self,
+        destination: &str,
+        path: &str,
+        interface: &str,
+        member: &str,
+        args: &[Arg<'_>],
+    ) -> io::Result<Option<String>> {
+        self.call_message(destination, path, interface, member, args)
+            .map(|reply| reply.first_string)
+    }
+
+    fn call_message(
+        &mut self,
+        destination: &str,
+        path: &str,
+        interface: &str,
+        member: &str,
+        args: &[Arg<'_>],
+    ) -> io::Result<Message> {
+        let fields = [
+            (PATH, Field::Path(path)),
+            (INTERFACE, Field::Str(interface)),
+            (MEMBER, Field::Str(member)),
+            (DESTINATION, Field::Str(destination)),
+        ];
+        let serial = self.send(METHOD_CALL, &fields, args)?;
+        loop {
+            let reply = self.read_message()?;
+            match reply.kind {
+                METHOD_CALL => self.calls.push(reply.into_call()),
+                _ if reply.reply_serial != Some(serial) => continue,
+                METHOD_RETURN => return Ok(reply),
+                ERROR => {
+                    let name = reply.fields.get(&ERROR_NAME).cloned().unwrap_or_default();
+                    let message = reply.first_string.unwrap_or_default();
+                    return Err(io::Error::other(format!("{name}: {}", message.trim_end())));
+                }
+                // signals, like the bus telling the connection its name
+                _ => continue,
+            }
+        }
+    }
+
+    /// Asks the bus for `name`, returning whether the greeter got it. It
+    /// isn't queued for if another client has it.
+    pub fn request_name(&mut self, name: &str) -> io::Result<bool> {
+        let reply = self.call_message(
+            "org.freedesktop.DBus",
+            "/org/freedesktop/DBus",
+            "org.freedesktop.DBus",
+            "RequestName",
+            &[Arg::Str(name), Arg::U32(DO_NOT_QUEUE)],
+        )?;
+        Ok(reply.first_u32 == Some(PRIMARY_OWNER))
+    }
+
+    /// The user the client behind the unique name `sender` runs as.
+    pub fn unix_user(&mut self, sender: &str) -> io::Result<u32> {
+        let reply = self.call_message(
+            "org.freedesktop.DBus",
+            "/org/freedesktop/DBus",
+            "org.freedesktop.DBus",
+            "GetConnectionUnixUser",
+            &[Arg::Str(sender)],
+        )?;
+        reply
+            .first_u32
+            .ok_or_else(|| io::Error::other("bus didn't tell the caller's user"))
+    }
+
+    /// The connection, readable when there are calls to take.
+    pub fn fd(&self) -> RawFd {
+        self.stream.get_ref().as_raw_fd()
+    }
+
+    /// The methods called on the greeter since last asked, without waiting
+    /// for more.
+    pub fn take_calls(&mut self) -> io::Result<Vec<Call>> {
+        let mut calls = std::mem::take(&mut self.calls);
+        loop {
+            if self.stream.buffer().is_empty() {
+                let mut fds = [PollFd::new(self.fd(), PollFlags::POLLIN)];
+                poll(&mut fds, 0)?;
+                if fds[0].revents().is_none_or(|events| events.is_empty()) {
+                    return Ok(calls);
+                }
+            }
+            let msg = self.read_message()?;
+            if msg.kind == METHOD_CALL {
+                calls.push(msg.into_call());
+            }
+        }
+    }
+
+    /// Answers `call` with a string, or else with an error's name and
+    /// message.
+    pub fn reply(&mut self, call: &Call, result: Result<&str, (&str, &str)>) -> io::Result<()> {
+        if call.no_reply {
+            return Ok(());
+        }
+        let mut fields = vec![(REPLY_SERIAL, Field::U32(call.serial))];
+        if let Some(sender) = &call.sender {
+            fields.push((DESTINATION, Field::Str(sender)));
+        }
+        match result {
+            Ok(value) => self.send(METHOD_RETURN, &fields, &[Arg::Str(value)]),
+            Err((name, message)) => {
+                fields.push((ERROR_NAME, Field::Str(name)));
+                self.send(ERROR, &fields, &[Arg::Str(message)])
+            }
+        }
+        .map(drop)
+    }
+
+    /// Sends a message of `kind` with the header `fields`, and `args` as its
+    /// body, returning its serial.
+    fn send(&mut self, kind: u8, fields: &[(u8, Field<'_>)], args: &[Arg<'_>]) -> io::Result<u32> {
+        self.serial += 1;
+        let msg = encode(kind, self.serial, fields, args);
+        self.stream.get_mut().write_all(&msg)?;
+        Ok(self.serial)
+    }
+
+    fn read_message(&mut self) -> io::Result<Message> {
+        decode(&mut self.stream)
+    }
+}
+
+/// A message of `kind` with the header `fields`, and `args` as its body.
+fn encode(kind: u8, serial: u32, fields: &[(u8, Field<'_>)], args: &[Arg<'_>]) -> Vec<u8> {
+    let mut body = Vec::new();
+    let mut signature = String::new();
+    for arg in args {
+        match arg {
+            Arg::Bool(b) => {
+                signature.push('b');
+                put_u32(&mut body, *b as u32);
+            }
+            Arg::U32(u) => {
+                signature.push('u');
+                put_u32(&mut body, *u);
+            }
+            Arg::Str(s) => {
+                signature.push('s');
+                put_str(&mut body, s);
+            }
+        }
+    }
+    let mut msg = vec![b'l', kind, 0, 1];
+    msg.extend((body.len() as u32).to_le_bytes());
+    msg.extend(serial.to_le_bytes());
+    let mut header = Vec::new();
+    for (code, value) in fields {
+        put_field(&mut header, *code, *value);
+    }
+    if !signature.is_empty() {
+        put_field(&mut header, SIGNATURE, Field::Signature(&signature));
+    }
+    // not counting padding after the last field, as there is none yet
+    msg.extend((header.len() as u32).to_le_bytes());
+    msg.extend(&header);
+    pad(&mut msg, 8);
+    msg.extend(&body);
+    msg
+}
+
+/// Reads the next message from `reader`.
+fn decode(reader: &mut impl Read) -> io::Result<Message> {
+    let mut fixed = [0u8; 16];
+    reader.read_exact(&mut fixed)?;
+    let little = match fixed[0] {
+        b'l' => true,
+        b'B' => false,
+        _ => return Err(io::Error::other("bad D-Bus message")),
+    };
+    let body_len = read_u32(&fixed, 4, little).unwrap_or_default() as usize;
+    let fields_len = read_u32(&fixed, 12, little).unwrap_or_default() as usize;
+    let rest = fields_len + padding(16 + fields_len, 8) + body_len;
+    let mut data = vec![0u8; rest];
+    reader.read_exact(&mut data)?;
+    let (fields, numbers) = parse_fields(&data[..fields_len], little)
+        .ok_or_else(|| io::Error::other("bad D-Bus message header"))?;
+    let body = &data[rest - body_len..];
+    let signature = fields.get(&SIGNATURE).map_or("", String::as_str);
+    let first_string = if signature.starts_with('s') {
+        read_str(body, 0, little).map(|(s, _)| s)
+    } else {
+        None
+    };
+    let first_u32 = if signature.starts_with('u') {
+        read_u32(body, 0, little)
+    } else {
+        None
+    };
+    Ok(Message {
+        kind: fixed[1],
+        flags: fixed[2],
+        serial: read_u32(&fixed, 8, little).unwrap_or_default(),
+        reply_serial: numbers.get(&REPLY_SERIAL).copied(),
+        fields,
+        first_string,
+        first_u32,
+    })
+}
+
+struct Message {
+    kind: u8,
+    flags: u8,
+    serial: u32,
+    reply_serial: Option<u32>,
+    // the header fields that are strings, by code
+    fields: HashMap<u8, String>,
+    first_string: Option<String>,
+    first_u32: Option<u32>,
+}
+
+impl Message {
+    fn into_call(mut self) -> Call {
+        Call {
+            path: self.fields.remove(&PATH).unwrap_or_default(),
+            interface: self.fields.remove(&INTERFACE).unwrap_or_default(),
+            member: self.fields.remove(&MEMBER).unwrap_or_default(),
+            serial: self.serial,
+            sender: self.fields.remove(&SENDER),
+            no_reply: self.flags & NO_REPLY_EXPECTED != 0,
+        }
+    }
+}
+
+// a message's string and number header fields, by code
+type Fields = (HashMap<u8, String>, HashMap<u8, u32>);
+
+/// The header fields of a message, which start 8 aligned, right after the
+/// fixed part of the header.
+fn parse_fields(fields: &[u8], little: bool) -> Option<Fields> {
+    let mut strings = HashMap::new();
+    let mut numbers = HashMap::new();
+    let mut i = 0;
+    while i < fields.len() {
+        i += padding(i, 8);
+        let code = *fields.get(i)?;
+        let sig_len = *fields.get(i + 1)? as usize;
+        let sig = *fields.get(i + 2)?;
+        i += 3 + sig_len;
+        let value = match sig {
+            b's' | b'o' => {
+                let (value, end) = read_str(fields, i + padding(i, 4), little)?;
+                i = end;
+                value
+            }
+            b'g' => {
+                let len = *fields.get(i)? as usize;
+                let value = fields.get(i + 1..i + 1 + len)?;
+                i += 1 + len + 1;
+                String::from_utf8_lossy(value).into_owned()
+            }
+            b'u' => {
+                i += padding(i, 4);
+                numbers.insert(code, read_u32(fields, i, little)?);
+                i += 4;
+                continue;
+            }
+            _ => return None,
+        };
+        strings.insert(code, value);
+    }
+    Some((strings, numbers))
+}
+
+fn read_u32(buf: &[u8], i: usize, little: bool) -> Option<u32> {
+    let bytes = buf.get(i..i + 4)?.try_into().ok()?;
+    Some(if little {
+        u32::from_le_bytes(bytes)
+    } else {
+        u32::from_be_bytes(bytes)
+    })
+}
+
+/// The string at `i`, already aligned, and where what follows it starts.
+fn read_str(buf: &[u8], i: usize, little: bool) -> Option<(String, usize)> {
+    let len = read_u32(buf, i, little)? as usize;
+    let bytes = buf.get(i + 4..i + 4 + len)?;
+    Some((String::from_utf8_lossy(bytes).into_owned(), i + 4 + len + 1))
+}
+
+fn padding(len: usize, align: usize) -> usize {
+    (align - len % align) % align
+}
+
+fn pad(buf: &mut Vec<u8>, align: usize) {
+    buf.resize(buf.len() + padding(buf.len(), align), 0);
+}
+
+fn put_u32(buf: &mut Vec<u8>, value: u32) {
+    pad(buf, 4);
+    buf.extend(value.to_le_bytes());
+}
+
+fn put_str(buf: &mut Vec<u8>, s: &str) {
+    put_u32(buf, s.len() as u32);
+    buf.extend(s.as_bytes());
+    buf.push(0);
+}
+
+/// Appends a header field, whose offsets are relative to the start of the
+/// fields, 16 bytes into the message, which keeps the alignment.
+fn put_field(buf: &mut Vec<u8>, code: u8, value: Field<'_>) {
+    pad(buf, 8);
+    match value {
+        Field::Str(s) => {
+            buf.extend([code, 1, b's', 0]);
+            put_str(buf, s);
+        }
+        Field::Path(s) => {
+            buf.extend([code, 1, b'o', 0]);
+            put_str(buf, s);
+        }
+        Field::Signature(s) => {
+            buf.extend([code, 1, b'g', 0]);
+            buf.push(s.len() as u8);
+            buf.extend(s.as_bytes());
+            buf.push(0);
+        }
+        Field::U32(u) => {
+            buf.extend([code, 1, b'u', 0]);
+            put_u32(buf, u);
+        }
+    }
+}
+
+/// Messages as the bus would pass them, to test what's built on `Bus`.
+#[cfg(test)]
+pub mod wire {
+    use super::*;
+
+    /// A call of `member` on `path` made by `sender`.
+    pub fn call(serial: u32, path: &str, member: &str, sender: &str) -> Vec<u8> {
+        let fields = [
+            (PATH, Field::Path(path)),
+            (MEMBER, Field::Str(member)),
+            (SENDER, Field::Str(sender)),
+        ];
+        encode(METHOD_CALL, serial, &fields, &[])
+    }
+
+    /// The reply to the call with `serial`.
+    pub fn reply(serial: u32, value: Arg<'_>) -> Vec<u8> {
+        let fields = [(REPLY_SERIAL, Field::U32(serial))];
+        encode(METHOD_RETURN, 1, &fields, &[value])
+    }
+
+    /// The member called, or the error's name, of the next message.
+    pub fn name_of_next(reader: &mut impl Read) -> String {
+        let mut msg = decode(reader).unwrap();
+        let code = if msg.kind == ERROR {
+            ERROR_NAME
+        } else {
+            MEMBER
+        };
+        msg.fields.remove(&code).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn calls_are_encoded_with_aligned_fields() {
+        let fields = [(PATH, Field::Path("/a")), (MEMBER, Field::Str("Hi"))];
+        let msg = encode(METHOD_CALL, 1, &fields, &[Arg::Str("x")]);
+        #[rustfmt::skip]
+        let expected = [
+            b'l', METHOD_CALL, 0, 1, 6, 0, 0, 0, 1, 0, 0, 0, 39, 0, 0, 0,
+            PATH, 1, b'o', 0, 2, 0, 0, 0, b'/', b'a', 0,
+            0, 0, 0, 0, 0,
+            MEMBER, 1, b's', 0, 2, 0, 0, 0, b'H', b'i', 0,
+            0, 0, 0, 0, 0,
+            SIGNATURE, 1, b'g', 0, 1, b's', 0,
+            0,
+            1, 0, 0, 0, b'x', 0,
+        ];
+        assert_eq!(msg, expected);
+        assert_eq!((msg.len() - 6) % 8, 0);
+    }
+
+    #[test]
+    fn replies_are_decoded_in_either_byte_order() {
+        #[rustfmt::skip]
+        let little = [
+            b'l', METHOD_RETURN, 0, 1, 7, 0, 0, 0, 9, 0, 0, 0, 15, 0, 0, 0,
+            REPLY_SERIAL, 1, b'u', 0, 7, 0, 0, 0,
+            SIGNATURE, 1, b'g', 0, 1, b's', 0,
+            0,
+            2, 0, 0, 0, b'o', b'k', 0,
+        ];
+        let msg = decode(&mut &little[..]).unwrap();
+        assert_eq!(msg.kind, METHOD_RETURN);
+        assert_eq!(msg.serial, 9);
+        assert_eq!(msg.reply_serial, Some(7));
+        assert_eq!(msg.first_string.as_deref(), Some("ok"));
+
+        #[rustfmt::skip]
+        let big = [
+            b'B', METHOD_RETURN, 0, 1, 0, 0, 0, 4, 0, 0, 0, 3, 0, 0, 0, 15,
+            REPLY_SERIAL, 1, b'u', 0, 0, 0, 0, 2,
+            SIGNATURE, 1, b'g', 0, 1, b'u', 0,
+            0,
+            0, 0, 0, PRIMARY_OWNER as u8,
+        ];
+        let msg = decode(&mut &big[..]).unwrap();
+        assert_eq!(msg.serial, 3);
+        assert_eq!(msg.reply_serial, Some(2));
+        assert_eq!(msg.first_u32, Some(PRIMARY_OWNER));
+    }
+
+    #[test]
+    fn replies_are_matched_to_their_call() {
+        let (ours, mut theirs) = UnixStream::pair().unwrap();
+        let mut bus = Bus::new(ours).unwrap();
+        let call = wire::call(2, "/org/ddlm/Greeter", "Screenshot", ":1.5");
+        theirs
+            .write_all(&wire::reply(5, Arg::Str("stale")))
+            .unwrap();
+        theirs.write_all(&call).unwrap();
+        theirs.write_all(&wire::reply(1, Arg::Str("yes"))).unwrap();
+
+        let answer = bus.call("a.b", "/a", "a.b", "Ask", &[]).unwrap();
+        assert_eq!(answer.as_deref(), Some("yes"));
+        let calls = bus.take_calls().unwrap();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].member, "Screenshot");
+        assert_eq!(calls[0].sender.as_deref(), Some(":1.5"));
+    }
+}
@@ -1,23 +1,174 @@
-use crate::buffer::{Buffer};
-use crate::color::Color;
+use crate::buffer::{Buffer, BufferError, Rect, Vect};
+use crate::color::{Color, MIN_CONTRAST};
+use crate::format::PixelFormat;
 
 use std::collections::HashMap;
+use std::fmt;
+use std::process::Command;
 use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard};
 
 use lazy_static::lazy_static;
 use rusttype::{point, Font as RustFont, Scale};
 use thiserror::Error;
 
+#[cfg(feature = "embedded-fonts")]
 pub static DEJAVUSANS_MONO_FONT_DATA: &[u8] = include_bytes!("../fonts/dejavu/DejaVuSansMono.ttf");
-pub static ROBOTO_REGULAR_FONT_DATA: &[u8] = include_bytes!("../fonts/Roboto-Regular.ttf");
 
+#[cfg(not(feature = "embedded-fonts"))]
+const DEJAVUSANS_MONO_PATHS: &[&str] = &[
+    "/usr/share/fonts/TTF/DejaVuSansMono.ttf",
+    "/usr/share/fonts/truetype/dejavu/DejaVuSansMono.ttf",
+    "/usr/share/fonts/dejavu/DejaVuSansMono.ttf",
+    "/usr/share/fonts/ttf-dejavu/DejaVuSansMono.ttf",
+];
+
+#[cfg(feature = "embedded-fonts")]
+lazy_static! {
+    static ref DEJAVUSANS_MONO: Option<RustFont<'static>> =
+        RustFont::try_from_bytes(DEJAVUSANS_MONO_FONT_DATA as &[u8]);
+}
+
+#[cfg(not(feature = "embedded-fonts"))]
 lazy_static! {
-    pub static ref DEJAVUSANS_MONO: RustFont<'static> =
-        RustFont::try_from_bytes(DEJAVUSANS_MONO_FONT_DATA as &[u8])
-            .expect("error constructing DejaVuSansMono");
-    pub static ref ROBOTO_REGULAR: RustFont<'static> =
-        RustFont::try_from_bytes(ROBOTO_REGULAR_FONT_DATA as &[u8])
-            .expect("error constructing Roboto-Regular");
+    static ref DEJAVUSANS_MONO: Option<RustFont<'static>> = system_font(DEJAVUSANS_MONO_PATHS);
+}
+
+/// DejaVu Sans Mono, bundled or else installed.
+pub fn dejavu_sans_mono() -> Result<&'static RustFont<'static>, DrawError> {
+    DEJAVUSANS_MONO.as_ref().ok_or_else(|| {
+        #[cfg(feature = "embedded-fonts")]
+        let name = "DejaVuSansMono".to_string();
+        #[cfg(not(feature = "embedded-fonts"))]
+        let name = format!("DejaVuSansMono in {}", DEJAVUSANS_MONO_PATHS.join(", "));
+        DrawError::FontNotFound(name)
+    })
+}
+
+/// Loads the built in font now, rather than once text is drawn, failing if
+/// it can't be.
+pub fn preload() -> Result<(), DrawError> {
+    dejavu_sans_mono()?;
+    Ok(())
+}
+
+lazy_static! {
+    // fonts loaded from files by path, kept for good as glyphs borrow them
+    static ref LOADED: Mutex<HashMap<String, &'static RustFont<'static>>> =
+        Mutex::new(HashMap::new());
+}
+
+/// The font a theme names: the built in DejaVuSansMono, a path to
+/// a TrueType or OpenType file, or else a family fontconfig knows.
+fn find_font(name: &str) -> Result<&'static RustFont<'static>, String> {
+    match name {
+        "DejaVuSansMono" | "DejaVu Sans Mono" => {
+            return dejavu_sans_mono().map_err(|err| err.to_string())
+        }
+        _ => {}
+    }
+    let path = if name.starts_with('/') {
+        name.to_string()
+    } else {
+        let output = Command::new("fc-match")
+            .arg("--format=%{file}")
+            .arg(name)
+            .output()
+            .map_err(|err| format!("unable to look up font {name}: {err}"))?;
+        let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if !output.status.success() || path.is_empty() {
+            return Err(format!("fontconfig doesn't know font {name}"));
+        }
+        path
+    };
+    let mut loaded = LOADED.lock().unwrap_or_else(|err| err.into_inner());
+    if let Some(font) = loaded.get(&path) {
+        return Ok(font);
+    }
+    let data = std::fs::read(&path).map_err(|err| format!("unable to read font {path}: {err}"))?;
+    let font = RustFont::try_from_vec(data).ok_or_else(|| format!("{path} is not a font"))?;
+    let font = &*Box::leak(Box::new(font));
+    loaded.insert(path, font);
+    Ok(font)
+}
+
+/// Loads the first of `paths` that exists and parses as a font.
+#[cfg(not(feature = "embedded-fonts"))]
+fn system_font(paths: &[&str]) -> Option<RustFont<'static>> {
+    paths
+        .iter()
+        .filter_map(|path| std::fs::read(path).ok())
+        .find_map(RustFont::try_from_vec)
+}
+
+/// How the focused input field is highlighted beyond its label color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FocusStyle {
+    None,
+    #[default]
+    Underline,
+    Box,
+    /// A marker left of the field, for when hue alone isn't distinguishable.
+    Arrow,
+    /// The focused field drawn in inverse video.
+    Inverse,
+}
+
+impl FromStr for FocusStyle {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(FocusStyle::None),
+            "underline" => Ok(FocusStyle::Underline),
+            "box" => Ok(FocusStyle::Box),
+            "arrow" => Ok(FocusStyle::Arrow),
+            "inverse" => Ok(FocusStyle::Inverse),
+            _ => Err(format!("unknown focus style {s}")),
+        }
+    }
+}
+
+impl fmt::Display for FocusStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FocusStyle::None => write!(f, "none"),
+            FocusStyle::Underline => write!(f, "underline"),
+            FocusStyle::Box => write!(f, "box"),
+            FocusStyle::Arrow => write!(f, "arrow"),
+            FocusStyle::Inverse => write!(f, "inverse"),
+        }
+    }
+}
+
+/// How the typed password is shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MaskStyle {
+    /// A star per character.
+    #[default]
+    Stars,
+    /// A fixed row of dots that animates while typing, not revealing the
+    /// length.
+    Dots,
+}
+
+impl FromStr for MaskStyle {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stars" => Ok(MaskStyle::Stars),
+            "dots" => Ok(MaskStyle::Dots),
+            _ => Err(format!("unknown mask style {s}")),
+        }
+    }
+}
+
+impl fmt::Display for MaskStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MaskStyle::Stars => write!(f, "stars"),
+            MaskStyle::Dots => write!(f, "dots"),
+        }
+    }
 }
 
 #[derive(Error, Debug)]
@@ -25,9 +176,12 @@ lazy_static! {
 pub enum DrawError {
     #[error("glyph for {0} not in cache")]
     GlyphNotInCache(char),
+    #[error("{0} not found")]
+    FontNotFound(String),
+    #[error("no font to draw with")]
+    NoFont,
 }
 
-#[derive(Clone)]
 struct CachedGlyph {
     dimensions: (u32, u32),
     origin: (i32, i32),
@@ -35,13 +189,10 @@ struct CachedGlyph {
 }
 
 impl CachedGlyph {
-    fn new(font: &RustFont<'_>, size: f32, ch: char) -> CachedGlyph {
+    /// Renders `ch` from `font`, on the baseline `ascent` below the top.
+    fn new(font: &RustFont<'_>, size: f32, ascent: f32, ch: char) -> CachedGlyph {
         let scale = Scale::uniform(size);
-        let v_metrics = font.v_metrics(scale);
-        let glyph = font
-            .glyph(ch)
-            .scaled(scale)
-            .positioned(point(0.0, v_metrics.ascent));
+        let glyph = font.glyph(ch).scaled(scale).positioned(point(0.0, ascent));
 
         if let Some(bounding_box) = glyph.pixel_bounding_box() {
             let origin = (bounding_box.min.x, bounding_box.min.y);
@@ -91,45 +242,155 @@ impl CachedGlyph {
     }
 }
 
+/// The glyphs of a font at one size rendered so far, shared by its copies
+/// so that every character is rasterized once.
+type GlyphCache = Arc<Mutex<HashMap<char, CachedGlyph>>>;
+
 #[derive(Clone)]
 pub struct Font {
-    glyphs: HashMap<char, CachedGlyph>,
-    font: &'static RustFont<'static>,
+    glyphs: GlyphCache,
+    // as the theme names it, without the size
+    name: String,
+    // the font and then its fallbacks, the first having a glyph drawing it
+    faces: Vec<&'static RustFont<'static>>,
     size: f32,
 }
 
+/// The built in fonts at 72 pixels, or no font, failing to draw with
+/// `DrawError::NoFont`, if they can't be loaded.
 impl Default for Font {
     fn default() -> Self {
-        Font::new(&DEJAVUSANS_MONO, 72.0)
+        Font::with_faces("DejaVuSansMono".to_string(), Vec::new(), 72.0)
     }
 }
 
+/// Parses `<font>[, <fallback>...] <size>`, each font as `find_font` takes
+/// it. Fallbacks that can't be loaded are skipped, the font itself can't be.
 impl FromStr for Font {
-    type Err = crate::Error;
+    type Err = String;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let size = s.split(" ").last().unwrap().parse().unwrap();
-        Ok(Font::new(&DEJAVUSANS_MONO, size))
+        let (names, size) = match s.trim().rsplit_once(' ') {
+            Some((names, size)) => (names, size.parse::<f32>()),
+            None => return Err(format!("expected a font name and size, got {s}")),
+        };
+        let size = match size {
+            Ok(size) if size > 0.0 => size,
+            _ => return Err(format!("expected a font name and size, got {s}")),
+        };
+        let names = names
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .collect::<Vec<_>>();
+        let (first, fallbacks) = match names.split_first() {
+            Some(names) => names,
+            None => return Err(format!("expected a font name and size, got {s}")),
+        };
+        let mut faces = vec![find_font(first)?];
+        for name in fallbacks {
+            match find_font(name) {
+                Ok(face) => faces.push(face),
+                Err(err) => eprintln!("skipping fallback font: {err}"),
+            }
+        }
+        Ok(Font::with_faces(names.join(", "), faces, size))
+    }
+}
+
+impl fmt::Display for Font {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.name, self.size)
     }
 }
 
 impl Font {
-    pub fn new(font: &'static RustFont<'_>, size: f32) -> Font {
+    /// The font named `name` in `to_string`, with the built in fonts as
+    /// fallbacks.
+    pub fn new(name: &str, font: &'static RustFont<'static>, size: f32) -> Font {
+        Font::with_faces(name.to_string(), vec![font], size)
+    }
+
+    /// Loads a font from a file, as a theme's `Font` key takes it: the path
+    /// of a TrueType or OpenType file or a family fontconfig knows, each
+    /// loaded once and kept.
+    pub fn load(name: &str, size: f32) -> Result<Font, DrawError> {
+        let font = find_font(name).map_err(DrawError::FontNotFound)?;
+        Ok(Font::new(name, font, size))
+    }
+
+    /// `faces` followed by the built in font, if it can be loaded, as the
+    /// last fallback.
+    fn with_faces(name: String, mut faces: Vec<&'static RustFont<'static>>, size: f32) -> Font {
+        if let Ok(builtin) = dejavu_sans_mono() {
+            if !faces.iter().any(|face| std::ptr::eq(*face, builtin)) {
+                faces.push(builtin);
+            }
+        }
+        Font {
+            glyphs: GlyphCache::default(),
+            name,
+            faces,
+            size,
+        }
+    }
+
+    /// The same font at another size, with an empty glyph cache.
+    pub fn resized(&self, size: f32) -> Font {
         Font {
-            glyphs: HashMap::new(),
-            font,
+            glyphs: GlyphCache::default(),
+            name: self.name.clone(),
+            faces: self.faces.clone(),
             size,
         }
     }
 
-    pub fn add_str_to_cache(&mut self, s: &str) {
+    fn cache(&self) -> MutexGuard<'_, HashMap<char, CachedGlyph>> {
+        self.glyphs.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    pub fn add_str_to_cache(&self, s: &str) {
+        let mut glyphs = self.cache();
+        if s.chars().all(|ch| glyphs.contains_key(&ch)) {
+            return;
+        }
+        let primary = match self.faces.first() {
+            Some(primary) => *primary,
+            None => return,
+        };
+        // every face on the baseline of the font itself
+        let ascent = primary.v_metrics(Scale::uniform(self.size)).ascent;
         for ch in s.chars() {
-            if self.glyphs.get(&ch).is_none() {
-                let glyph = CachedGlyph::new(self.font, self.size, ch);
-                self.glyphs.insert(ch, glyph);
-            }
+            glyphs.entry(ch).or_insert_with(|| {
+                let face = self
+                    .faces
+                    .iter()
+                    .find(|face| face.glyph(ch).id().0 != 0)
+                    .unwrap_or(&primary);
+                CachedGlyph::new(face, self.size, ascent, ch)
+            });
         }
     }
 
+    /// Whether the font or one of its fallbacks has a glyph for `ch`.
+    pub fn has_glyph(&self, ch: char) -> bool {
+        self.faces.iter().any(|face| face.glyph(ch).id().0 != 0)
+    }
+
+    /// Width of `s` in pixels when drawn with `draw_text`.
+    pub fn text_width(&self, s: &str) -> u32 {
+        self.add_str_to_cache(s);
+        let glyphs = self.cache();
+        s.chars()
+            .filter_map(|ch| glyphs.get(&ch))
+            .map(|glyph| glyph.dimensions.0 as i32 + glyph.origin.0)
+            .sum::<i32>()
+            .max(0) as u32
+    }
+
+    pub fn size(&self) -> f32 {
+        self.size
+    }
+
     pub fn draw_text(
         &self,
         buf: &mut Buffer<'_>,
@@ -137,11 +398,15 @@ impl Font {
         c: &Color,
         s: &str,
     ) -> Result<(u32, u32), DrawError> {
+        if self.faces.is_empty() {
+            return Err(DrawError::NoFont);
+        }
         let mut x_off = 0;
         let mut off = 0;
+        let cache = self.cache();
         let mut glyphs = Vec::with_capacity(s.len());
         for ch in s.chars() {
-            let glyph = match self.glyphs.get(&ch) {
+            let glyph = match cache.get(&ch) {
                 Some(glyph) => glyph,
                 None => return Err(DrawError::GlyphNotInCache(ch)),
             };
@@ -159,7 +424,7 @@ impl Font {
     }
 
     pub fn auto_draw_text(
-        &mut self,
+        &self,
         buf: &mut Buffer<'_>,
         bg: &Color,
         c: &Color,
@@ -170,19 +435,110 @@ impl Font {
     }
 }
 
-// pub fn draw_box(buf: &mut Buffer<'_>, c: &Color, dim: (u32, u32)) -> Result<(), BufferError> {
-//     for x in 0..dim.0 {
-//         let _ = buf.put((x, 0), c);
-//         let _ = buf.put((x, dim.1 - 1), c);
-//     }
-//     for y in 0..dim.1 {
-//         buf.put((0, y), c)?;
-//         buf.put((dim.0 - 1, y), c)?;
-//     }
-//
-//     Ok(())
-// }
-//
+/// What text is drawn over, to pick readable colors for it on background
+/// images.
+pub struct Backdrop<'a> {
+    pixels: &'a [u8],
+    size: Vect,
+    stride: u32,
+    format: PixelFormat,
+}
+
+impl<'a> Backdrop<'a> {
+    /// `pixels` is a whole screen of `size` in `format`, its rows `stride`
+    /// bytes apart.
+    pub fn new(pixels: &'a [u8], size: Vect, stride: u32, format: PixelFormat) -> Self {
+        Self {
+            pixels,
+            size,
+            stride,
+            format,
+        }
+    }
+
+    /// The average color of a grid of samples in `rect`.
+    fn average(&self, rect: Rect) -> Option<Color> {
+        const SAMPLES: u32 = 8;
+        let bpp = self.format.bytes_per_pixel as usize;
+        let x1 = (rect.0 + rect.2).min(self.size.0);
+        let y1 = (rect.1 + rect.3).min(self.size.1);
+        let step_x = (rect.2 / SAMPLES).max(1);
+        let step_y = (rect.3 / SAMPLES).max(1);
+        let mut sum = [0u32; 3];
+        let mut n = 0;
+        for y in (rect.1..y1).step_by(step_y as usize) {
+            for x in (rect.0..x1).step_by(step_x as usize) {
+                let i = (y * self.stride) as usize + x as usize * bpp;
+                if let Some(src) = self.pixels.get(i..i + bpp) {
+                    let rgba = self.format.unpack_rgba8(src);
+                    for c in 0..3 {
+                        sum[c] += rgba[c] as u32;
+                    }
+                    n += 1;
+                }
+            }
+        }
+        (n > 0).then(|| {
+            Color::from_rgba8([
+                (sum[0] / n) as u8,
+                (sum[1] / n) as u8,
+                (sum[2] / n) as u8,
+                0xFF,
+            ])
+        })
+    }
+
+    /// The background and text colors for text in `rect`: the average of what
+    /// is under it, and `fg` if that's readable on it, or else whichever of
+    /// `dark` and `light` contrasts more.
+    pub fn text_colors(
+        &self,
+        rect: Rect,
+        fg: Color,
+        dark: Color,
+        light: Color,
+    ) -> Option<(Color, Color)> {
+        let under = self.average(rect)?;
+        let fg = if fg.contrast(&under) >= MIN_CONTRAST {
+            fg
+        } else if dark.contrast(&under) > light.contrast(&under) {
+            dark
+        } else {
+            light
+        };
+        Some((under, fg))
+    }
+}
+
+pub fn draw_box(buf: &mut Buffer<'_>, c: &Color, dim: (u32, u32)) -> Result<(), BufferError> {
+    if dim.0 == 0 || dim.1 == 0 {
+        return Ok(());
+    }
+    for x in 0..dim.0 {
+        let _ = buf.put((x, 0), c);
+        let _ = buf.put((x, dim.1 - 1), c);
+    }
+    for y in 0..dim.1 {
+        buf.put((0, y), c)?;
+        buf.put((dim.0 - 1, y), c)?;
+    }
+
+    Ok(())
+}
+
+/// Draws a filled triangle pointing right, `height` pixels high and half as
+/// wide.
+pub fn draw_arrow(buf: &mut Buffer<'_>, c: &Color, height: u32) -> Result<(), BufferError> {
+    let half = height / 2;
+    for y in 0..height {
+        let w = half - y.abs_diff(half);
+        for x in 0..w {
+            buf.put((x, y), c)?;
+        }
+    }
+    Ok(())
+}
+
 // pub fn draw_background(
 //     buf: &mut Buffer<'_>,
 //     c: &Color,
@@ -0,0 +1,49 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+
+pub const CONFIG_DIR: &str = "/etc/ddlm/config.d";
+
+/// Reads the config file at `path` and then the `*.toml` drop-ins in `dir`
+/// in lexicographic order, so later files override earlier ones, each as
+/// `T`. Only a `required` config file has to exist.
+pub fn read_all<T: DeserializeOwned>(
+    path: &Path,
+    required: bool,
+    dir: &str,
+) -> Result<Vec<(PathBuf, T)>, String> {
+    let mut paths = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+            .collect::<Vec<_>>(),
+        Err(_) => Vec::new(),
+    };
+    paths.sort();
+    let mut files = Vec::new();
+    if let Some(file) = read(path, required)? {
+        files.push((path.to_path_buf(), file));
+    }
+    for path in paths {
+        if let Some(file) = read(&path, true)? {
+            files.push((path, file));
+        }
+    }
+    Ok(files)
+}
+
+/// The config file at `path`, or `None` if it doesn't exist and isn't
+/// `required`, failing on invalid TOML and on options `T` doesn't have or
+/// values they don't take.
+fn read<T: DeserializeOwned>(path: &Path, required: bool) -> Result<Option<T>, String> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) if !required && err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(format!("unable to read {}: {err}", path.display())),
+    };
+    toml::from_str(&content)
+        .map(Some)
+        .map_err(|err| format!("{}: {err}", path.display()))
+}
@@ -0,0 +1,199 @@
+use std::fmt::Display;
+use std::time::Duration;
+
+use crate::keys::Key;
+use crate::{exit, unlock, Config};
+
+/// The effective configuration as TOML, keyed by option name, with defaults
+/// filled in, which reads back as a config file. Options without a value are
+/// left commented out.
+pub fn config_toml(config: &Config) -> String {
+    let mut out = String::new();
+    let mut set = |key: &str, value: Option<String>| match value {
+        Some(value) => out.push_str(&format!("{key} = {value}\n")),
+        None => out.push_str(&format!("# {key} =\n")),
+    };
+    let secs = |d: Option<Duration>| d.map(|d| d.as_secs().to_string());
+
+    set("session", Some(array(&config.session)));
+    set("session-name", config.session_name.as_deref().map(quote));
+    set("session-allow", Some(array(&config.session_filter.allow)));
+    set("session-deny", Some(array(&config.session_filter.deny)));
+    set("theme", config.gallery_theme.as_deref().map(quote));
+    set("theme-file", config.theme_file.as_deref().map(quote));
+    let dialog = &config.dialog;
+    let float = |v: Option<f32>| v.map(|v| format!("{v:?}"));
+    set(
+        "dialog-horizontal-alignment",
+        float(dialog.horizontal_alignment),
+    );
+    set(
+        "dialog-vertical-alignment",
+        float(dialog.vertical_alignment),
+    );
+    set("dialog-font-size", float(dialog.font_size));
+    set("background-color", dialog.background_color.map(display));
+    set("focus-color", dialog.focus_color.map(display));
+    set("info-color", dialog.info_color.map(display));
+    set("error-color", dialog.error_color.map(display));
+    set(
+        "background-command",
+        config.background_command.as_deref().map(quote),
+    );
+    set("background-interval", secs(config.background_interval));
+    set("scale-filter", Some(display(config.scale_filter)));
+    set("backend", Some(display(config.backend)));
+    set("fb-device", Some(quote(config.fb_device())));
+    set("drm-device", Some(quote(config.drm_device())));
+    set("fb-byte-order", Some(display(config.byte_order)));
+    set("refresh-strategy", Some(display(config.refresh_strategy)));
+    set("syslog", config.syslog.as_ref().map(display));
+    set("metrics-file", config.metrics_file.as_deref().map(quote));
+    set("notify-pipe", config.notify_pipe.as_deref().map(quote));
+    set("otp-hints", Some(quote(config.otp_hints())));
+    set(
+        "screenshot-dir",
+        config.screenshot_dir.as_deref().map(quote),
+    );
+    set("dbus", Some(config.dbus.to_string()));
+    set("tty", config.tty.as_deref().map(quote));
+    set("input", Some(display(config.input)));
+    set(
+        "key-repeat",
+        config
+            .key_repeat
+            .map(|(delay, period)| quote(&format!("{delay}:{period}"))),
+    );
+    set("xkb-layout", config.xkb_layout.as_deref().map(quote));
+    set("xkb-variant", config.xkb_variant.as_deref().map(quote));
+    set("xkb-options", config.xkb_options.as_deref().map(quote));
+    set("sticky-keys", Some(config.sticky_keys.to_string()));
+    set(
+        "slow-keys",
+        config.slow_keys.map(|d| d.as_millis().to_string()),
+    );
+    set(
+        "console-loglevel",
+        config.console_loglevel.map(|l| l.to_string()),
+    );
+    set("kbd-backlight", config.kbd_backlight.map(|p| p.to_string()));
+    set("integrity-check", secs(config.integrity_check));
+    set(
+        "echo-interval",
+        config.echo_interval.map(|d| d.as_millis().to_string()),
+    );
+    set("auth-timeout", secs(config.auth_timeout));
+    set("clock", Some(config.clock.to_string()));
+    set("clock-format", config.clock_format.as_deref().map(quote));
+    set("hostname", Some(config.hostname.to_string()));
+    set(
+        "status-command",
+        config.status_command.as_deref().map(quote),
+    );
+    set("schedule", config.schedule.as_deref().map(quote));
+    set("os-logo", Some(config.os_logo.to_string()));
+    set("transcript", Some(config.transcript.to_string()));
+    set("check-username", Some(config.check_username.to_string()));
+    set(
+        "password-policy",
+        config.password_policy.map(|len| len.to_string()),
+    );
+    set(
+        "unlock-command",
+        config.unlock_command.as_deref().map(quote),
+    );
+    set(
+        "unlock-prompt",
+        Some(quote(
+            config
+                .unlock_prompt
+                .as_deref()
+                .unwrap_or(unlock::DEFAULT_PROMPT),
+        )),
+    );
+    set(
+        "after-auth-command",
+        config.after_auth_command.as_deref().map(quote),
+    );
+    set(
+        "after-auth-password",
+        Some(config.after_auth_password.to_string()),
+    );
+    set(
+        "maintenance-lock",
+        Some(config.maintenance_lock.to_string()),
+    );
+    set("blank-after", secs(config.blank_after));
+    set("clear-after", secs(config.clear_after));
+    set("power-saver", Some(display(config.power_saver)));
+    set("frame-cache", Some(quote(&config.frame_cache())));
+    set("state-dir", Some(quote(config.state_dir())));
+    set("user-sessions", Some(quote(&config.user_sessions())));
+    let focus_style = config
+        .focus_style
+        .or(config.theme.module.focus_style)
+        .unwrap_or_default();
+    set("focus-style", Some(display(focus_style)));
+    set("mask-style", Some(display(config.mask_style)));
+    set("renderer", Some(display(config.renderer)));
+    let bindings = config
+        .keymap
+        .bindings()
+        .iter()
+        .map(|(key, action)| format!("{key}={action}"))
+        .chain(config.keymap.sequences().iter().map(|(keys, action)| {
+            let keys = keys.iter().map(Key::to_string).collect::<Vec<_>>();
+            format!("{}={action}", keys.join(" "))
+        }))
+        .collect::<Vec<_>>();
+    set("bind", Some(array(&bindings)));
+    set("no-hints", Some(config.no_hints.to_string()));
+    set("no-power-menu", Some(config.no_power_menu.to_string()));
+    set("log-viewer", Some(config.log_viewer.to_string()));
+    set("variant", Some(display(config.variant)));
+    set("dark-hours", Some(display(config.dark_hours)));
+    set("fix-contrast", Some(config.fix_contrast.to_string()));
+    set("no-fade", Some(config.no_fade.to_string()));
+    set("hide-username", Some(config.hide_username.to_string()));
+    set("recent-users", Some(config.recent_users().to_string()));
+    set("no-seat-env", Some(config.no_seat_env.to_string()));
+    set("user-env", Some(config.user_env.to_string()));
+    set("json-errors", Some(exit::json_errors().to_string()));
+    set("prerender", Some(config.prerender.to_string()));
+    set("preview", config.preview.as_deref().map(quote));
+    set(
+        "resolution",
+        config
+            .resolution
+            .map(|(width, height)| quote(&format!("{width}x{height}"))),
+    );
+    set("font-test-dir", config.font_test_dir.as_deref().map(quote));
+    out
+}
+
+fn display<T: Display>(value: T) -> String {
+    quote(&value.to_string())
+}
+
+/// Quotes `s` as a basic string, which is also valid JSON.
+pub fn quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04X}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn array<S: AsRef<str>>(items: &[S]) -> String {
+    let items = items.iter().map(|s| quote(s.as_ref())).collect::<Vec<_>>();
+    format!("[{}]", items.join(", "))
+}
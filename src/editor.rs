@@ -0,0 +1,157 @@
+use std::fs;
+use std::io;
+
+use crate::color::Color;
+use crate::keys::Key;
+use crate::Module;
+
+pub const HINTS: [&str; 6] = [
+    "Arrows: move dialog",
+    "b/B: background",
+    "f/F: focus color",
+    "+/-: font size",
+    "Enter: save",
+    "Esc: discard",
+];
+
+const ALIGNMENT_STEP: f32 = 0.01;
+// alignments are written as fractions after a dot, so 1.0 can't be
+const MAX_ALIGNMENT: f32 = 0.99;
+const FONT_STEP: f32 = 1.0;
+const MIN_FONT_SIZE: f32 = 6.0;
+
+const PALETTE: [[u8; 4]; 12] = [
+    [0x00, 0x00, 0x00, 0xFF],
+    [0x1D, 0x1F, 0x21, 0xFF],
+    [0x28, 0x2A, 0x36, 0xFF],
+    [0x2E, 0x34, 0x40, 0xFF],
+    [0x00, 0x2B, 0x36, 0xFF],
+    [0x3B, 0x42, 0x52, 0xFF],
+    [0x5E, 0x81, 0xAC, 0xFF],
+    [0x88, 0xC0, 0xD0, 0xFF],
+    [0xA3, 0xBE, 0x8C, 0xFF],
+    [0xEB, 0xCB, 0x8B, 0xFF],
+    [0xBF, 0x61, 0x6A, 0xFF],
+    [0xFF, 0xFF, 0xFF, 0xFF],
+];
+
+const NEW_THEME: &str = "[Plymouth Theme]\nName=ddlm\nModuleName=ddlm\n\n[ddlm]\n";
+
+/// What the editor should do after a key press.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Continue,
+    Save,
+    Discard,
+}
+
+/// Applies the edit bound to `key` to `module`.
+pub fn apply(module: &mut Module, key: Key) -> Outcome {
+    match key {
+        Key::Left => nudge(&mut module.dialog_horizontal_alignment, -ALIGNMENT_STEP),
+        Key::Right => nudge(&mut module.dialog_horizontal_alignment, ALIGNMENT_STEP),
+        Key::Up => nudge(&mut module.dialog_vertical_alignment, -ALIGNMENT_STEP),
+        Key::Down => nudge(&mut module.dialog_vertical_alignment, ALIGNMENT_STEP),
+        Key::Char('b') => cycle(&mut module.background_start_color, 1),
+        Key::Char('B') => cycle(&mut module.background_start_color, PALETTE.len() - 1),
+        Key::Char('f') => cycle(module.focus_color.get_or_insert(Color::YELLOW), 1),
+        Key::Char('F') => cycle(
+            module.focus_color.get_or_insert(Color::YELLOW),
+            PALETTE.len() - 1,
+        ),
+        Key::Char('+') => module.font = module.font.resized(module.font.size() + FONT_STEP),
+        Key::Char('-') => {
+            let size = (module.font.size() - FONT_STEP).max(MIN_FONT_SIZE);
+            module.font = module.font.resized(size);
+        }
+        Key::Enter => return Outcome::Save,
+        Key::Escape | Key::Ctrl('c') => return Outcome::Discard,
+        _ => {}
+    }
+    Outcome::Continue
+}
+
+fn nudge(alignment: &mut f32, step: f32) {
+    *alignment = (*alignment + step).clamp(0.0, MAX_ALIGNMENT);
+}
+
+/// Replaces `color` with the palette color `step` places after it, or the
+/// first one if it isn't in the palette.
+fn cycle(color: &mut Color, step: usize) {
+    let current = color.to_string();
+    let next = match PALETTE
+        .iter()
+        .position(|c| Color::from_rgba8(*c).to_string() == current)
+    {
+        Some(i) => (i + step) % PALETTE.len(),
+        None => 0,
+    };
+    *color = Color::from_rgba8(PALETTE[next]);
+}
+
+fn alignment(v: f32) -> String {
+    // the theme parser only reads fractions written as ".5"
+    let v = format!("{:.2}", v.clamp(0.0, MAX_ALIGNMENT));
+    v.trim_start_matches('0').to_string()
+}
+
+/// Writes the edited keys of `module` to the theme file at `path`, keeping
+/// everything else in it, or creates a new theme there.
+pub fn write_theme(path: &str, module: &Module) -> io::Result<()> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => NEW_THEME.to_string(),
+        Err(err) => return Err(err),
+    };
+    let mut entries = vec![
+        (
+            "DialogHorizontalAlignment",
+            alignment(module.dialog_horizontal_alignment),
+        ),
+        (
+            "DialogVerticalAlignment",
+            alignment(module.dialog_vertical_alignment),
+        ),
+        (
+            "BackgroundStartColor",
+            module.background_start_color.to_string(),
+        ),
+        (
+            "BackgroundEndColor",
+            module.background_end_color.to_string(),
+        ),
+        ("Font", module.font.to_string()),
+    ];
+    if let Some(color) = module.focus_color {
+        entries.push(("FocusColor", color.to_string()));
+    }
+
+    let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+    // keys in the variant sections are left alone, new keys go before them
+    let mut base_end = lines
+        .iter()
+        .position(|l| matches!(l.trim(), "[dark]" | "[light]"))
+        .unwrap_or(lines.len());
+    let mut insert_at = base_end;
+    while insert_at > 0 && lines[insert_at - 1].trim().is_empty() {
+        insert_at -= 1;
+    }
+    for (key, value) in entries {
+        let line = format!("{key}={value}");
+        let prefix = format!("{key}=");
+        match lines[..base_end]
+            .iter()
+            .position(|l| l.starts_with(&prefix))
+        {
+            Some(i) => lines[i] = line,
+            None => {
+                lines.insert(insert_at, line);
+                insert_at += 1;
+                base_end += 1;
+            }
+        }
+    }
+    let mut content = lines.join("\n");
+    content.push('\n');
+    fs::write(path, content)
+}
@@ -0,0 +1,187 @@
+use std::path::Path;
+use std::{env, fs};
+
+use users::os::unix::UserExt;
+
+const DEFAULT_SEAT: &str = "seat0";
+
+pub const ENV_DIR: &str = "/etc/ddlm/env.d";
+const USER_ENV_FILE: &str = ".config/ddlm/env";
+
+/// Environment variables passed to the session started through greetd.
+#[derive(Default, Clone)]
+pub struct SessionEnv {
+    vars: Vec<(String, String)>,
+}
+
+impl SessionEnv {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the terminal and VT the greeter runs on and detects its seat.
+    pub fn detect_seat(&mut self, tty: Option<&str>, vt: Option<u32>) {
+        if let Some(tty) = tty {
+            self.set("TTY", tty);
+        }
+        if let Some(vt) = vt {
+            self.set("XDG_VTNR", &vt.to_string());
+        }
+        let seat = env::var("XDG_SEAT").unwrap_or_else(|_| DEFAULT_SEAT.to_string());
+        self.set("XDG_SEAT", &seat);
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) {
+        match self.vars.iter_mut().find(|(k, _)| k == key) {
+            Some((_, v)) => *v = value.to_string(),
+            None => self.vars.push((key.to_string(), value.to_string())),
+        }
+    }
+
+    /// Merges every `*.conf` file of `dir` in lexicographic order.
+    pub fn load_dir<P: AsRef<Path>>(&mut self, dir: P) {
+        let mut files: Vec<_> = match fs::read_dir(dir) {
+            Ok(entries) => entries
+                .filter_map(Result::ok)
+                .map(|e| e.path())
+                .filter(|p| p.extension().is_some_and(|ext| ext == "conf"))
+                .collect(),
+            Err(_) => return,
+        };
+        files.sort();
+        for file in files {
+            self.load_file(file);
+        }
+    }
+
+    /// Merges `KEY=value` lines from `path`, ignoring blank lines and comments
+    /// and skipping lines that aren't such an assignment or whose value isn't
+    /// a single word, as `parse_value` reads it.
+    pub fn load_file<P: AsRef<Path>>(&mut self, path: P) {
+        let path = path.as_ref();
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(_) => return,
+        };
+        for (n, l) in content.lines().enumerate() {
+            let l = l.trim();
+            if l.is_empty() || l.starts_with('#') {
+                continue;
+            }
+            let l = l.strip_prefix("export ").unwrap_or(l);
+            match l.split_once('=') {
+                Some((key, value)) if is_name(key.trim()) => match parse_value(value) {
+                    Ok(value) => self.set(key.trim(), &value),
+                    Err(err) => eprintln!("{}:{}: {err}, skipping", path.display(), n + 1),
+                },
+                _ => eprintln!("{}:{}: expected KEY=value, skipping", path.display(), n + 1),
+            }
+        }
+    }
+
+    /// Merges `~username/.config/ddlm/env`, if it exists and is readable.
+    pub fn load_user(&mut self, username: &str) {
+        if let Some(user) = users::get_user_by_name(username) {
+            self.load_file(user.home_dir().join(USER_ENV_FILE));
+        }
+    }
+
+    /// greetd_ipc has no way to pass an environment along with `start_session`,
+    /// so the command is run through env(1) instead. greetd joins the words
+    /// with spaces and has `sh -c` run them, so each is quoted to reach env(1)
+    /// and the session as it is.
+    pub fn wrap_command(&self, cmd: Vec<String>) -> Vec<String> {
+        let mut wrapped = Vec::new();
+        if !self.vars.is_empty() {
+            wrapped.push("env".to_string());
+        }
+        for (key, value) in &self.vars {
+            wrapped.push(format!("{key}={value}"));
+        }
+        wrapped.extend(cmd);
+        wrapped
+            .iter()
+            .map(|word| shell_words::quote(word).into_owned())
+            .collect()
+    }
+}
+
+/// A value as the shell would assign it, with its quotes and backslashes
+/// removed but nothing expanded. Words that aren't quoted together are an
+/// error, as the shell would run all but the first as a command.
+fn parse_value(value: &str) -> Result<String, String> {
+    let mut words = shell_words::split(value).map_err(|err| err.to_string())?;
+    match words.len() {
+        0 => Ok(String::new()),
+        1 => Ok(words.remove(0)),
+        _ => Err("expected a single word, quote values with spaces".into()),
+    }
+}
+
+/// Whether `key` is a valid variable name, which env(1) won't take as an
+/// option or split at another `=`.
+fn is_name(key: &str) -> bool {
+    let mut chars = key.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(words: &[&str]) -> Vec<String> {
+        words.iter().map(|word| word.to_string()).collect()
+    }
+
+    #[test]
+    fn values_are_unquoted_but_not_expanded() {
+        assert_eq!(parse_value("sway").unwrap(), "sway");
+        assert_eq!(parse_value("\"a b\"").unwrap(), "a b");
+        assert_eq!(parse_value("'$HOME; rm'").unwrap(), "$HOME; rm");
+        assert_eq!(parse_value("\"it's\"").unwrap(), "it's");
+        assert_eq!(parse_value("a\\ b").unwrap(), "a b");
+        assert_eq!(parse_value("").unwrap(), "");
+    }
+
+    #[test]
+    fn malformed_values_are_rejected() {
+        assert!(parse_value("a b").is_err());
+        assert!(parse_value("\"unterminated").is_err());
+        assert!(parse_value("x; reboot").is_err());
+    }
+
+    #[test]
+    fn commands_without_variables_are_not_wrapped() {
+        let env = SessionEnv::new();
+        assert_eq!(env.wrap_command(words(&["sway", "-d"])), ["sway", "-d"]);
+    }
+
+    #[test]
+    fn wrapped_commands_reach_env_as_they_were() {
+        let mut env = SessionEnv::new();
+        env.set("TTY", "/dev/tty1");
+        env.set("GREETING", "it's a \"nice\" $DAY");
+        let cmd = words(&["my wm", "--config=~/a b", ""]);
+        let wrapped = env.wrap_command(cmd.clone());
+        // as greetd has the shell split them
+        let split = shell_words::split(&wrapped.join(" ")).unwrap();
+        let mut expected = words(&["env", "TTY=/dev/tty1", "GREETING=it's a \"nice\" $DAY"]);
+        expected.extend(cmd);
+        assert_eq!(split, expected);
+    }
+
+    #[test]
+    fn later_values_replace_earlier_ones() {
+        let mut env = SessionEnv::new();
+        env.set("LANG", "C");
+        env.set("LANG", "de_DE.UTF-8");
+        let wrapped = env.wrap_command(Vec::new());
+        assert_eq!(
+            shell_words::split(&wrapped.join(" ")).unwrap(),
+            ["env", "LANG=de_DE.UTF-8"]
+        );
+    }
+}
@@ -0,0 +1,501 @@
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read};
+use std::mem;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::time::Instant;
+
+use crate::accessx::{AccessX, Filtered, Modifier};
+use crate::keys::Key;
+#[cfg(feature = "xkb")]
+use crate::xkb::Xkb;
+
+const EV_KEY: u16 = 1;
+// released, pressed and repeated by the kernel
+const RELEASE: i32 = 0;
+const PRESS: i32 = 1;
+const REPEAT: i32 = 2;
+
+const KEY_ESC: u16 = 1;
+const KEY_BACKSPACE: u16 = 14;
+const KEY_TAB: u16 = 15;
+const KEY_ENTER: u16 = 28;
+const KEY_LEFTCTRL: u16 = 29;
+const KEY_A: u16 = 30;
+const KEY_LEFTSHIFT: u16 = 42;
+const KEY_LEFTALT: u16 = 56;
+const KEY_RIGHTSHIFT: u16 = 54;
+const KEY_SPACE: u16 = 57;
+const KEY_CAPSLOCK: u16 = 58;
+const KEY_F1: u16 = 59;
+const KEY_F10: u16 = 68;
+const KEY_F11: u16 = 87;
+const KEY_F12: u16 = 88;
+const KEY_KPENTER: u16 = 96;
+const KEY_RIGHTCTRL: u16 = 97;
+const KEY_RIGHTALT: u16 = 100;
+const KEY_UP: u16 = 103;
+const KEY_LEFT: u16 = 105;
+const KEY_RIGHT: u16 = 106;
+const KEY_DOWN: u16 = 108;
+const KEY_MAX: usize = 0x2FF;
+
+// the console keymap's tables, by modifier
+const KG_SHIFT: u8 = 1;
+const KG_ALTGR: u8 = 2;
+// kernel keysym types that are characters, `KT_LETTER` affected by caps lock
+const KT_LATIN: u16 = 0;
+const KT_LETTER: u16 = 11;
+
+/// The US layout, by key code from 2, for when the console has no keymap.
+const US_NORMAL: &str = "1234567890-=\x08\tqwertyuiop[]\r\0asdfghjkl;'`\0\\zxcvbnm,./";
+const US_SHIFT: &str = "!@#$%^&*()_+\x08\tQWERTYUIOP{}\r\0ASDFGHJKL:\"~\0|ZXCVBNM<>?";
+
+#[repr(C)]
+struct KbEntry {
+    table: u8,
+    index: u8,
+    value: u16,
+}
+
+const KDGKBENT: u16 = 0x4B46;
+// from linux/vt.h
+const VT_ACTIVATE: u16 = 0x5606;
+nix::ioctl_readwrite_bad!(kdgkbent, KDGKBENT, KbEntry);
+nix::ioctl_write_int_bad!(vt_activate, VT_ACTIVATE);
+nix::ioctl_read_buf!(eviocgbit_key, b'E', 0x20 + EV_KEY as u8, u8);
+nix::ioctl_write_ptr!(eviocsrep, b'E', 0x03, [u32; 2]);
+nix::ioctl_write_int!(eviocgrab, b'E', 0x90);
+
+/// Every keyboard among the input devices, read together, with keys turned
+/// into characters by the console's keymap, or an XKB layout if given.
+pub struct Keyboards {
+    devices: Vec<File>,
+    grabbed: bool,
+    #[cfg(feature = "xkb")]
+    xkb: Option<Xkb>,
+    shift: bool,
+    altgr: bool,
+    ctrl: bool,
+    alt: bool,
+    caps_lock: bool,
+    accessx: AccessX,
+}
+
+impl Keyboards {
+    /// Opens the keyboards among the `event*` devices in `dir` and grabs
+    /// them, setting their key repeat delay and period in milliseconds if
+    /// given, with sticky and slow keys as `accessx` has them.
+    pub fn open(dir: &str, repeat: Option<(u32, u32)>, accessx: AccessX) -> io::Result<Self> {
+        let mut paths = fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.file_name()
+                    .is_some_and(|name| name.to_string_lossy().starts_with("event"))
+            })
+            .collect::<Vec<_>>();
+        paths.sort();
+        let mut devices = Vec::new();
+        for path in paths {
+            let device = match OpenOptions::new()
+                .read(true)
+                .write(true)
+                .custom_flags(nix::libc::O_NONBLOCK)
+                .open(&path)
+            {
+                Ok(device) => device,
+                Err(_) => continue,
+            };
+            if !is_keyboard(&device) {
+                continue;
+            }
+            if let Some((delay, period)) = repeat {
+                // SAFETY: the ioctl reads two ints
+                if let Err(err) = unsafe { eviocsrep(device.as_raw_fd(), &[delay, period]) } {
+                    eprintln!("unable to set key repeat of {}: {err}", path.display());
+                }
+            }
+            devices.push(device);
+        }
+        let mut keyboards = Self {
+            devices,
+            grabbed: false,
+            #[cfg(feature = "xkb")]
+            xkb: None,
+            shift: false,
+            altgr: false,
+            ctrl: false,
+            alt: false,
+            caps_lock: false,
+            accessx,
+        };
+        keyboards.grab(true);
+        Ok(keyboards)
+    }
+
+    /// Takes the keyboards for the greeter alone, so that what's typed
+    /// doesn't also reach the console or anything else reading them, or
+    /// gives them back.
+    pub fn grab(&mut self, grab: bool) {
+        if grab == self.grabbed {
+            return;
+        }
+        for device in &self.devices {
+            // SAFETY: the ioctl takes the int as is
+            if let Err(err) = unsafe { eviocgrab(device.as_raw_fd(), grab as _) } {
+                let verb = if grab { "grab" } else { "release" };
+                eprintln!("unable to {verb} a keyboard: {err}");
+            }
+        }
+        self.grabbed = grab;
+    }
+
+    /// Turns keys into characters with `xkb` rather than the console's
+    /// keymap.
+    #[cfg(feature = "xkb")]
+    pub fn use_layout(&mut self, xkb: Xkb) {
+        self.xkb = Some(xkb);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.devices.is_empty()
+    }
+
+    /// Whether Caps Lock was turned on since the keyboards were opened.
+    pub fn caps_lock(&self) -> bool {
+        self.caps_lock
+    }
+
+    /// The name of the XKB layout keys are typed with, if several are set
+    /// up.
+    pub fn layout(&self) -> Option<&str> {
+        #[cfg(feature = "xkb")]
+        if let Some(xkb) = &self.xkb {
+            return xkb.layout();
+        }
+        None
+    }
+
+    /// Which of sticky and slow keys was turned on or off by its gesture,
+    /// since last asked.
+    pub fn take_notice(&mut self) -> Option<&'static str> {
+        self.accessx.take_notice()
+    }
+
+    /// The devices to wait on.
+    pub fn fds(&self) -> impl Iterator<Item = RawFd> + '_ {
+        self.devices.iter().map(File::as_raw_fd)
+    }
+
+    /// Reads the pending events, queueing the keys typed, as the keymap of
+    /// the `console` maps them. Keyboards that went away are closed.
+    pub fn read(&mut self, console: RawFd, keys: &mut VecDeque<Key>) {
+        let size = mem::size_of::<nix::libc::input_event>();
+        let mut chunk = vec![0u8; size * 64];
+        // close enough to the events' own time, as they are read as they come
+        let now = Instant::now();
+        let mut i = 0;
+        while i < self.devices.len() {
+            let err: io::Error = loop {
+                let n = match self.devices[i].read(&mut chunk) {
+                    Ok(0) => break io::ErrorKind::UnexpectedEof.into(),
+                    Ok(n) => n,
+                    Err(err) => break err,
+                };
+                for event in chunk[..n].chunks_exact(size) {
+                    // type, code and value follow the timestamp
+                    let tail = &event[size - 8..];
+                    let kind = u16::from_ne_bytes([tail[0], tail[1]]);
+                    let code = u16::from_ne_bytes([tail[2], tail[3]]);
+                    let value = i32::from_ne_bytes([tail[4], tail[5], tail[6], tail[7]]);
+                    if kind == EV_KEY {
+                        keys.extend(self.key(console, code, value, now));
+                    }
+                }
+            };
+            if err.kind() == io::ErrorKind::WouldBlock {
+                i += 1;
+            } else {
+                self.devices.remove(i);
+            }
+        }
+    }
+
+    /// Tracks the modifiers and returns the key pressed, if any, once slow
+    /// keys accepted it and with the modifiers sticky keys hold.
+    fn key(&mut self, console: RawFd, code: u16, value: i32, now: Instant) -> Option<Key> {
+        let pressed = value != RELEASE;
+        let modifier = match code {
+            KEY_LEFTSHIFT | KEY_RIGHTSHIFT => Some(Modifier::Shift),
+            KEY_LEFTCTRL | KEY_RIGHTCTRL => Some(Modifier::Ctrl),
+            KEY_RIGHTALT => Some(Modifier::AltGr),
+            _ => None,
+        };
+        if let Some(modifier) = modifier {
+            self.accessx.modifier(modifier, value, now);
+            *self.held(modifier) = pressed;
+        } else if code == KEY_LEFTALT {
+            self.alt = pressed;
+        } else if code == KEY_CAPSLOCK {
+            if value != REPEAT {
+                self.caps_lock ^= pressed;
+            }
+        } else {
+            let value = match self.accessx.filter(code, value, now) {
+                Filtered::Press => PRESS,
+                Filtered::Repeat => REPEAT,
+                Filtered::Release => RELEASE,
+                Filtered::Ignore => return None,
+            };
+            return self.typed(console, code, value);
+        }
+        self.update_layout(code, value);
+        None
+    }
+
+    /// The key `code` types, pressed or repeated, with the modifiers sticky
+    /// keys hold added to those held down.
+    fn typed(&mut self, console: RawFd, code: u16, value: i32) -> Option<Key> {
+        self.update_layout(code, value);
+        if value == RELEASE {
+            return None;
+        }
+        let stuck = self
+            .accessx
+            .stuck()
+            .filter(|&modifier| !self.is_held(modifier))
+            .collect::<Vec<_>>();
+        if value == PRESS {
+            self.accessx.unlatch();
+        }
+        for &modifier in &stuck {
+            *self.held(modifier) = true;
+            self.update_layout(modifier_code(modifier), PRESS);
+        }
+        // the grab keeps the console from switching VTs itself
+        let key = match vt_of(code) {
+            Some(vt) if self.ctrl && self.alt => {
+                if value == PRESS {
+                    // SAFETY: the ioctl takes the int as is
+                    if let Err(err) = unsafe { vt_activate(console, vt as _) } {
+                        eprintln!("unable to switch to VT {vt}: {err}");
+                    }
+                }
+                None
+            }
+            _ => self.translate(console, code),
+        };
+        for &modifier in &stuck {
+            *self.held(modifier) = false;
+            self.update_layout(modifier_code(modifier), RELEASE);
+        }
+        key
+    }
+
+    fn is_held(&self, modifier: Modifier) -> bool {
+        match modifier {
+            Modifier::Shift => self.shift,
+            Modifier::Ctrl => self.ctrl,
+            Modifier::AltGr => self.altgr,
+        }
+    }
+
+    fn held(&mut self, modifier: Modifier) -> &mut bool {
+        match modifier {
+            Modifier::Shift => &mut self.shift,
+            Modifier::Ctrl => &mut self.ctrl,
+            Modifier::AltGr => &mut self.altgr,
+        }
+    }
+
+    /// Follows the key in the XKB layout's state, if there is one.
+    #[cfg_attr(not(feature = "xkb"), allow(unused_variables))]
+    fn update_layout(&mut self, code: u16, value: i32) {
+        #[cfg(feature = "xkb")]
+        if let Some(xkb) = &mut self.xkb {
+            if value != REPEAT {
+                xkb.update(code, value != RELEASE);
+            }
+        }
+    }
+
+    /// The key `code` is, or the character it types.
+    fn translate(&mut self, console: RawFd, code: u16) -> Option<Key> {
+        let key = match code {
+            KEY_ESC => Key::Escape,
+            KEY_BACKSPACE => Key::Backspace,
+            KEY_TAB => Key::Tab,
+            KEY_ENTER | KEY_KPENTER => Key::Enter,
+            KEY_UP => Key::Up,
+            KEY_DOWN => Key::Down,
+            KEY_LEFT => Key::Left,
+            KEY_RIGHT => Key::Right,
+            KEY_F1..=KEY_F10 => Key::F((code - KEY_F1 + 1) as u8),
+            KEY_F11 => Key::F(11),
+            KEY_F12 => Key::F(12),
+            _ if self.ctrl => Key::Ctrl(self.letter(console, code)?),
+            _ => Key::Char(self.char(console, code)?),
+        };
+        Some(key)
+    }
+
+    /// The letter on `code`, lowercase, for bindings held with Ctrl.
+    fn letter(&self, console: RawFd, code: u16) -> Option<char> {
+        #[cfg(feature = "xkb")]
+        if let Some(xkb) = &self.xkb {
+            return xkb.letter(code);
+        }
+        match self.console_char(console, code, false)? {
+            c if c.is_ascii_alphabetic() => Some(c.to_ascii_lowercase()),
+            _ => None,
+        }
+    }
+
+    /// The character `code` types, from the XKB layout if there is one.
+    fn char(&mut self, console: RawFd, code: u16) -> Option<char> {
+        #[cfg(feature = "xkb")]
+        if let Some(xkb) = &mut self.xkb {
+            return xkb.char(code);
+        }
+        self.console_char(console, code, self.shift)
+    }
+
+    /// The character `code` types, from the console's keymap, or the US
+    /// layout if it can't be read.
+    fn console_char(&self, console: RawFd, code: u16, shift: bool) -> Option<char> {
+        let index = u8::try_from(code).ok()?;
+        let mut table = 0;
+        if shift {
+            table |= KG_SHIFT;
+        }
+        if self.altgr {
+            table |= KG_ALTGR;
+        }
+        let value = match keymap_entry(console, table, index) {
+            Some(value) => value,
+            None => return us_char(code, shift, self.caps_lock),
+        };
+        // caps lock types letters as with the opposite shift
+        let value = if self.caps_lock && value >> 8 == KT_LETTER {
+            keymap_entry(console, table ^ KG_SHIFT, index)?
+        } else {
+            value
+        };
+        keysym_char(value)
+    }
+}
+
+/// The key a sticky `modifier` is taken to be held down with.
+fn modifier_code(modifier: Modifier) -> u16 {
+    match modifier {
+        Modifier::Shift => KEY_LEFTSHIFT,
+        Modifier::Ctrl => KEY_LEFTCTRL,
+        Modifier::AltGr => KEY_RIGHTALT,
+    }
+}
+
+/// The VT Ctrl+Alt with the key `code` switches to, as the console's own
+/// keymap has F1 to F12 switch to VTs 1 to 12.
+fn vt_of(code: u16) -> Option<u32> {
+    match code {
+        KEY_F1..=KEY_F10 => Some((code - KEY_F1 + 1) as u32),
+        KEY_F11 => Some(11),
+        KEY_F12 => Some(12),
+        _ => None,
+    }
+}
+
+/// The keysym in the console keymap's `table` for the key code `index`.
+fn keymap_entry(console: RawFd, table: u8, index: u8) -> Option<u16> {
+    let mut entry = KbEntry {
+        table,
+        index,
+        value: 0,
+    };
+    // SAFETY: the ioctl fills in the entry's value
+    unsafe { kdgkbent(console, &mut entry) }.ok()?;
+    Some(entry.value)
+}
+
+/// The character a keysym types, if it's one.
+fn keysym_char(value: u16) -> Option<char> {
+    // unicode keymaps have the character itself, flagged
+    let c = if value >= 0xF000 {
+        char::from_u32((value ^ 0xF000) as u32)?
+    } else {
+        match value >> 8 {
+            KT_LATIN | KT_LETTER => (value & 0xFF) as u8 as char,
+            _ => return None,
+        }
+    };
+    (!c.is_control()).then_some(c)
+}
+
+/// Whether the device has letter keys and Enter, rather than only a few
+/// buttons, like a power button or a mouse.
+fn is_keyboard(device: &File) -> bool {
+    let mut bits = [0u8; KEY_MAX / 8 + 1];
+    // SAFETY: the ioctl writes at most the buffer's length
+    if unsafe { eviocgbit_key(device.as_raw_fd(), &mut bits) }.is_err() {
+        return false;
+    }
+    let has = |code: u16| bits[code as usize / 8] & (1 << (code % 8)) != 0;
+    has(KEY_A) && has(KEY_ENTER)
+}
+
+fn us_char(code: u16, shift: bool, caps_lock: bool) -> Option<char> {
+    if code == KEY_SPACE {
+        return Some(' ');
+    }
+    let table = if shift { US_SHIFT } else { US_NORMAL };
+    let c = table.chars().nth(code.checked_sub(2)? as usize)?;
+    match c {
+        c if c.is_control() => None,
+        c if caps_lock && c.is_ascii_uppercase() => Some(c.to_ascii_lowercase()),
+        c if caps_lock => Some(c.to_ascii_uppercase()),
+        c => Some(c),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn function_keys_switch_to_their_vt() {
+        assert_eq!(vt_of(KEY_F1), Some(1));
+        assert_eq!(vt_of(KEY_F10), Some(10));
+        assert_eq!(vt_of(KEY_F11), Some(11));
+        assert_eq!(vt_of(KEY_F12), Some(12));
+        assert_eq!(vt_of(KEY_A), None);
+        assert_eq!(vt_of(KEY_ESC), None);
+    }
+
+    #[test]
+    fn ctrl_alt_function_keys_are_not_greeter_keys() {
+        let mut keyboards = Keyboards {
+            devices: Vec::new(),
+            grabbed: false,
+            #[cfg(feature = "xkb")]
+            xkb: None,
+            shift: false,
+            altgr: false,
+            ctrl: false,
+            alt: false,
+            caps_lock: false,
+            accessx: AccessX::new(false, None),
+        };
+        let now = Instant::now();
+        let f3 = KEY_F1 + 2;
+        // no console to switch VTs on, the key is still taken
+        assert_eq!(keyboards.key(-1, f3, PRESS, now), Some(Key::F(3)));
+        keyboards.key(-1, KEY_LEFTCTRL, PRESS, now);
+        keyboards.key(-1, KEY_LEFTALT, PRESS, now);
+        assert_eq!(keyboards.key(-1, f3, PRESS, now), None);
+        assert_eq!(keyboards.key(-1, f3, RELEASE, now), None);
+        keyboards.key(-1, KEY_LEFTALT, RELEASE, now);
+        assert_eq!(keyboards.key(-1, f3, PRESS, now), Some(Key::F(3)));
+    }
+}
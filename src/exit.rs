@@ -0,0 +1,62 @@
+use std::fmt::Display;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::console;
+use crate::dump::quote;
+
+static JSON_ERRORS: AtomicBool = AtomicBool::new(false);
+
+/// Why ddlm gave up, each with its own exit code so that wrappers and service
+/// units can tell them apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    Other = 1,
+    /// An unusable option or theme file.
+    Config = 2,
+    Framebuffer = 3,
+    /// The terminal couldn't be set up or its input went away.
+    Console = 4,
+    Greetd = 5,
+    /// The user quit without logging in.
+    Cancelled = 6,
+}
+
+impl Failure {
+    fn name(&self) -> &'static str {
+        match self {
+            Failure::Other => "other",
+            Failure::Config => "config",
+            Failure::Framebuffer => "framebuffer",
+            Failure::Console => "console",
+            Failure::Greetd => "greetd",
+            Failure::Cancelled => "cancelled",
+        }
+    }
+}
+
+/// Report failures as one JSON object per line on stderr.
+pub fn set_json_errors(json: bool) {
+    JSON_ERRORS.store(json, Ordering::Relaxed);
+}
+
+pub fn json_errors() -> bool {
+    JSON_ERRORS.load(Ordering::Relaxed)
+}
+
+/// Gives the terminal back, reports `message` and exits with the failure's
+/// code.
+pub fn fail<M: Display>(failure: Failure, message: M) -> ! {
+    console::restore_terminal();
+    if json_errors() {
+        eprintln!(
+            "{{\"error\": {}, \"code\": {}, \"message\": {}}}",
+            quote(failure.name()),
+            failure as i32,
+            quote(&message.to_string())
+        );
+    } else {
+        eprintln!("{message}");
+    }
+    process::exit(failure as i32)
+}
@@ -0,0 +1,274 @@
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::os::raw::c_void;
+use std::os::unix::io::AsRawFd;
+use std::str::FromStr;
+
+use framebuffer::{Framebuffer, VarScreeninfo};
+use nix::sys::mman::{msync, MsFlags};
+
+use crate::buffer::{Damage, Vect};
+use crate::format::{ByteOrder, PixelFormat};
+use crate::output::{Output, Surface};
+
+// from linux/fb.h
+const FBIOBLANK: u32 = 0x4611;
+const FB_BLANK_UNBLANK: i32 = 0;
+const FB_BLANK_POWERDOWN: i32 = 4;
+const FB_ACTIVATE_NOW: u32 = 0;
+const FB_ACTIVATE_FORCE: u32 = 128;
+
+// drivers that only see writes to their memory when it's synced, as they
+// send it over USB or SPI, and the fbtft panels' prefix
+const DEFERRED_IO_DRIVERS: [&str; 3] = ["udlfb", "smscufx", "fb_"];
+// the suffix of DRM's framebuffer emulation, which refreshes on a pan
+const DRM_EMULATION: &str = "drmfb";
+
+nix::ioctl_write_int_bad!(fbioblank, FBIOBLANK);
+
+/// How the framebuffer is told about a new frame in its memory. All but
+/// `None` also force the mode to be activated on the first frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RefreshStrategy {
+    /// Picked by driver, and otherwise nothing after the first frame.
+    #[default]
+    Auto,
+    /// Activates the mode again with `FB_ACTIVATE_FORCE` after every frame.
+    ForceActivate,
+    /// Pans the display to the page on screen after every frame.
+    Pan,
+    /// Syncs the mapped memory after every frame.
+    Msync,
+    None,
+}
+
+impl RefreshStrategy {
+    /// The strategy for the driver named `id`, `Auto` still if there's
+    /// nothing known about it.
+    fn resolve(self, id: &str) -> Self {
+        if self != RefreshStrategy::Auto {
+            self
+        } else if DEFERRED_IO_DRIVERS.iter().any(|name| id.starts_with(name)) {
+            RefreshStrategy::Msync
+        } else if id.ends_with(DRM_EMULATION) {
+            RefreshStrategy::Pan
+        } else {
+            RefreshStrategy::Auto
+        }
+    }
+}
+
+impl FromStr for RefreshStrategy {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(RefreshStrategy::Auto),
+            "force-activate" => Ok(RefreshStrategy::ForceActivate),
+            "pan" => Ok(RefreshStrategy::Pan),
+            "msync" => Ok(RefreshStrategy::Msync),
+            "none" => Ok(RefreshStrategy::None),
+            _ => Err(format!("unknown refresh strategy {s}")),
+        }
+    }
+}
+
+impl fmt::Display for RefreshStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RefreshStrategy::Auto => write!(f, "auto"),
+            RefreshStrategy::ForceActivate => write!(f, "force-activate"),
+            RefreshStrategy::Pan => write!(f, "pan"),
+            RefreshStrategy::Msync => write!(f, "msync"),
+            RefreshStrategy::None => write!(f, "none"),
+        }
+    }
+}
+
+/// The framebuffer device, drawn to off screen so that only what changed
+/// is copied to it.
+pub struct Fbdev {
+    pub fb: Framebuffer,
+    frame: Vec<u8>,
+    refresh: RefreshStrategy,
+}
+
+/// Shows frames drawn off screen by copying what changed to the framebuffer
+/// memory.
+struct Scanout<'a> {
+    device: &'a File,
+    var_screen_info: &'a VarScreeninfo,
+    // the framebuffer memory, both pages of it when flipping
+    memory: &'a mut [u8],
+    page_len: usize,
+    // the page on screen
+    page: u32,
+    // when flipping, what the other page missed while it was shown
+    flip: Option<Damage>,
+    // what the page on screen holds, to find what changed
+    shown: Vec<u8>,
+    size: Vect,
+    stride: u32,
+    bpp: u32,
+    // whether the next frame is copied whole
+    full: bool,
+    activated: bool,
+    refresh: RefreshStrategy,
+}
+
+impl Fbdev {
+    pub fn new(fb: Framebuffer, refresh: RefreshStrategy) -> Self {
+        Self {
+            fb,
+            frame: Vec::new(),
+            refresh,
+        }
+    }
+
+    /// Draws off screen, flipping between two pages if `flip` and the
+    /// virtual screen is tall enough for them.
+    pub fn surface(&mut self, byte_order: ByteOrder, flip: bool) -> Surface<'_> {
+        let fb = &mut self.fb;
+        let size = (fb.var_screen_info.xres, fb.var_screen_info.yres);
+        let mut format = PixelFormat::from_screeninfo(&fb.var_screen_info, &fb.fix_screen_info);
+        format.byte_order = byte_order;
+        match format.fourcc() {
+            Some(fourcc) => eprintln!("framebuffer format {}", fourcc.code()),
+            None => eprintln!("unknown framebuffer format {format:?}"),
+        }
+        if let Err(err) = format.install_palette(&fb.device) {
+            eprintln!("unable to install framebuffer palette: {err}");
+        }
+        // rows may be padded past the visible width
+        let stride = fb.fix_screen_info.line_length;
+        let page_len = (stride * size.1) as usize;
+        let can_flip =
+            flip && fb.var_screen_info.yres_virtual >= 2 * size.1 && fb.frame.len() >= 2 * page_len;
+        if can_flip {
+            eprintln!("flipping between two framebuffer pages");
+        }
+        let id = String::from_utf8_lossy(&fb.fix_screen_info.id);
+        let refresh = self.refresh.resolve(id.trim_end_matches('\0'));
+        eprintln!("framebuffer refresh strategy {refresh}");
+        // drawing starts from what's on screen, such as a prerendered frame
+        self.frame.clear();
+        self.frame.extend_from_slice(&fb.frame[..page_len]);
+        let scanout = Scanout {
+            device: &fb.device,
+            var_screen_info: &fb.var_screen_info,
+            memory: &mut fb.frame[..],
+            page_len,
+            page: 0,
+            flip: can_flip.then(|| Damage::all(size)),
+            shown: self.frame.clone(),
+            size,
+            stride,
+            bpp: format.bytes_per_pixel,
+            full: false,
+            activated: false,
+            refresh,
+        };
+        Surface {
+            buf: &mut self.frame,
+            size,
+            stride,
+            format,
+            output: Box::new(scanout),
+        }
+    }
+}
+
+impl Scanout<'_> {
+    fn page_mut(&mut self, page: u32) -> &mut [u8] {
+        let start = page as usize * self.page_len;
+        &mut self.memory[start..start + self.page_len]
+    }
+
+    /// Tells the driver about the frame just copied to the page on screen,
+    /// as `refresh` has it.
+    fn refresh(&mut self) -> io::Result<()> {
+        let first = !self.activated;
+        self.activated = true;
+        let force = match self.refresh {
+            RefreshStrategy::None => false,
+            RefreshStrategy::ForceActivate => true,
+            _ => first,
+        };
+        if force {
+            let mut screeninfo = self.var_screen_info.clone();
+            screeninfo.activate |= FB_ACTIVATE_NOW | FB_ACTIVATE_FORCE;
+            Framebuffer::put_var_screeninfo(self.device, &screeninfo)
+                .map_err(|err| io::Error::other(err.to_string()))?;
+        }
+        match self.refresh {
+            RefreshStrategy::Pan => {
+                let mut screeninfo = self.var_screen_info.clone();
+                screeninfo.yoffset = self.page * screeninfo.yres;
+                Framebuffer::pan_display(self.device, &screeninfo)
+                    .map(|_| ())
+                    .map_err(|err| io::Error::other(err.to_string()))
+            }
+            RefreshStrategy::Msync => {
+                let memory = self.memory.as_mut_ptr() as *mut c_void;
+                // SAFETY: the memory is the whole mapping of the device
+                unsafe { msync(memory, self.memory.len(), MsFlags::MS_SYNC) }
+                    .map_err(io::Error::from)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<'a> Output<'a> for Scanout<'a> {
+    fn present(&mut self, buf: &mut &'a mut [u8]) -> io::Result<()> {
+        let (size, stride, bpp) = (self.size, self.stride, self.bpp);
+        let damage = if self.full {
+            Damage::all(size)
+        } else {
+            Damage::between(&self.shown, buf, size, stride, bpp)
+        };
+        self.full = false;
+        if damage.is_empty() && self.activated {
+            return Ok(());
+        }
+        damage.copy(buf, &mut self.shown, stride, bpp);
+        if let Some(missed) = self.flip.take() {
+            let back = 1 - self.page;
+            let mut stale = missed;
+            stale.extend(&damage);
+            stale.copy(buf, self.page_mut(back), stride, bpp);
+            let mut screeninfo = self.var_screen_info.clone();
+            screeninfo.yoffset = back * screeninfo.yres;
+            if Framebuffer::pan_display(self.device, &screeninfo).is_ok() {
+                self.page = back;
+                self.flip = Some(damage);
+                self.activated = true;
+                return Ok(());
+            }
+            eprintln!("unable to flip framebuffer pages, drawing to the shown one");
+        }
+        let page = self.page;
+        damage.copy(buf, self.page_mut(page), stride, bpp);
+        self.refresh()
+    }
+
+    fn shown(&self) -> Option<&[u8]> {
+        let start = self.page as usize * self.page_len;
+        self.memory.get(start..start + self.page_len)
+    }
+
+    fn invalidate(&mut self) {
+        self.full = true;
+    }
+
+    fn blank(&mut self, blank: bool) -> io::Result<()> {
+        let mode = if blank {
+            FB_BLANK_POWERDOWN
+        } else {
+            FB_BLANK_UNBLANK
+        };
+        unsafe { fbioblank(self.device.as_raw_fd(), mode) }
+            .map(|_| ())
+            .map_err(io::Error::from)
+    }
+}
@@ -0,0 +1,80 @@
+use std::time::{Duration, Instant};
+
+use crate::buffer::Buffer;
+use crate::color::Color;
+use crate::draw::Font;
+use crate::Error;
+
+/// How the password field hides what's typed, as the theme has it: a glyph
+/// per character, some pixels apart, and the last character typed shown as
+/// is for a moment first if `echo` is set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PasswordField {
+    pub glyph: char,
+    pub spacing: u32,
+    pub echo: Option<Duration>,
+}
+
+impl Default for PasswordField {
+    fn default() -> Self {
+        Self {
+            glyph: '*',
+            spacing: 0,
+            echo: None,
+        }
+    }
+}
+
+impl PasswordField {
+    /// The mask for `password`, its last character as is if it was typed
+    /// at `typed` and is still echoed.
+    pub fn mask(&self, password: &str, typed: Option<Instant>) -> String {
+        let mut chars = password.chars();
+        let last = chars.next_back().filter(|_| self.echo_end(typed).is_some());
+        let mut mask = chars.map(|_| self.glyph).collect::<String>();
+        match last {
+            Some(c) => mask.push(c),
+            None if !password.is_empty() => mask.push(self.glyph),
+            None => {}
+        }
+        mask
+    }
+
+    /// When the character typed at `typed` stops being echoed, unless it
+    /// already has.
+    pub fn echo_end(&self, typed: Option<Instant>) -> Option<Instant> {
+        let end = typed? + self.echo?;
+        (Instant::now() < end).then_some(end)
+    }
+
+    /// Width of `mask` drawn with `draw`.
+    pub fn width(&self, font: &Font, mask: &str) -> u32 {
+        let gaps = mask.chars().count().saturating_sub(1) as u32 * self.spacing;
+        mask.chars()
+            .map(|c| font.text_width(&c.to_string()))
+            .sum::<u32>()
+            + gaps
+    }
+
+    /// Draws `mask`, as made by `mask`, a glyph at a time with the spacing
+    /// between them.
+    pub fn draw(
+        &self,
+        buf: &mut Buffer<'_>,
+        font: &Font,
+        bg: &Color,
+        fg: &Color,
+        mask: &str,
+    ) -> Result<(u32, u32), Error> {
+        if self.spacing == 0 {
+            return Ok(font.auto_draw_text(buf, bg, fg, mask)?);
+        }
+        let mut x = 0;
+        for c in mask.chars() {
+            let glyph = c.to_string();
+            let (w, _) = font.auto_draw_text(&mut buf.offset((x, 0))?, bg, fg, &glyph)?;
+            x += w + self.spacing;
+        }
+        Ok((x.saturating_sub(self.spacing), font.size() as u32))
+    }
+}
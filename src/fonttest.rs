@@ -0,0 +1,115 @@
+use std::ops::RangeInclusive;
+
+use crate::buffer::Buffer;
+use crate::color::Color;
+use crate::draw::Font;
+use crate::Error;
+
+const MARGIN: u32 = 20;
+const CELL_PADDING: u32 = 6;
+
+/// The scripts each font is checked for, a block or a sample of it.
+const RANGES: &[(&str, RangeInclusive<char>)] = &[
+    ("ASCII", ' '..='~'),
+    ("Latin-1", '\u{a0}'..='\u{ff}'),
+    ("Latin Extended-A", '\u{100}'..='\u{17f}'),
+    ("Greek", '\u{370}'..='\u{3ff}'),
+    ("Cyrillic", '\u{400}'..='\u{4ff}'),
+    ("Armenian", '\u{531}'..='\u{58a}'),
+    ("Hebrew", '\u{5d0}'..='\u{5ea}'),
+    ("Arabic", '\u{600}'..='\u{6ff}'),
+    ("Devanagari", '\u{900}'..='\u{97f}'),
+    ("Thai", '\u{e00}'..='\u{e7f}'),
+    ("Georgian", '\u{10a0}'..='\u{10ff}'),
+    ("Hiragana", '\u{3040}'..='\u{309f}'),
+    ("Katakana", '\u{30a0}'..='\u{30ff}'),
+    ("CJK (sample)", '\u{4e00}'..='\u{4eff}'),
+    ("Hangul (sample)", '\u{ac00}'..='\u{acff}'),
+];
+
+/// One script in one of the theme's fonts.
+pub struct Page<'a> {
+    key: &'static str,
+    font: &'a Font,
+    range: &'static (&'static str, RangeInclusive<char>),
+}
+
+/// Every script in each of `fonts`, named by their theme keys.
+pub fn pages<'a>(fonts: &[(&'static str, &'a Font)]) -> Vec<Page<'a>> {
+    fonts
+        .iter()
+        .flat_map(|&(key, font)| RANGES.iter().map(move |range| Page { key, font, range }))
+        .collect()
+}
+
+impl Page<'_> {
+    /// Names the page for a file, such as `font-cyrillic`.
+    pub fn file_name(&self) -> String {
+        let script = self
+            .range
+            .0
+            .split(' ')
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        format!("{}-{script}", self.key.to_lowercase())
+    }
+
+    /// Draws the page's characters in a grid under a header saying how many
+    /// of them the font, or one of its fallbacks, has a glyph for.
+    pub fn draw(
+        &self,
+        buf: &mut Buffer<'_>,
+        bg: &Color,
+        index: usize,
+        count: usize,
+    ) -> Result<(), Error> {
+        let (name, range) = self.range;
+        let chars = range
+            .clone()
+            .filter(|c| !c.is_control())
+            .collect::<Vec<_>>();
+        let covered = chars.iter().filter(|c| self.font.has_glyph(**c)).count();
+        let header = format!(
+            "{}: {} {name}, U+{:04X}-U+{:04X}, {covered} of {} ({}/{count})",
+            self.key,
+            self.font,
+            *range.start() as u32,
+            *range.end() as u32,
+            chars.len(),
+            index + 1,
+        );
+        let header_font = self.font.resized(16.0);
+        header_font.auto_draw_text(
+            &mut buf.offset((MARGIN, MARGIN))?,
+            bg,
+            &Color::WHITE,
+            &header,
+        )?;
+        let hint = "Left/Right to page, Esc to quit";
+        let bottom = buf.get_bounds().3.saturating_sub(MARGIN + 16);
+        header_font.auto_draw_text(&mut buf.offset((MARGIN, bottom))?, bg, &Color::WHITE, hint)?;
+
+        let cell = self.font.size() as u32 + CELL_PADDING;
+        let width = buf.get_bounds().2.saturating_sub(2 * MARGIN);
+        let columns = (width / cell).max(1);
+        let top = MARGIN + 16 + CELL_PADDING * 2;
+        for (i, c) in chars.iter().enumerate() {
+            let (col, row) = (i as u32 % columns, i as u32 / columns);
+            let pos = (MARGIN + col * cell, top + row * cell);
+            if pos.1 + cell > bottom {
+                // the rest doesn't fit on the screen
+                break;
+            }
+            // the font's missing glyph box, in red, where no font has one
+            let color = match self.font.has_glyph(*c) {
+                true => Color::WHITE,
+                false => Color::RED,
+            };
+            let text = c.to_string();
+            self.font
+                .auto_draw_text(&mut buf.offset(pos)?, bg, &color, &text)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,529 @@
+use std::fmt;
+use std::fs::File;
+use std::os::unix::io::AsRawFd;
+use std::str::FromStr;
+
+use framebuffer::{Bitfield, FixScreeninfo, VarScreeninfo};
+
+use crate::color::Color;
+
+// from linux/fb.h
+const FBIOPUTCMAP: u32 = 0x4605;
+const FB_VISUAL_PSEUDOCOLOR: u32 = 3;
+
+#[repr(C)]
+pub struct FbCmap {
+    start: u32,
+    len: u32,
+    red: *const u16,
+    green: *const u16,
+    blue: *const u16,
+    transp: *const u16,
+}
+
+nix::ioctl_write_ptr_bad!(fbioputcmap, FBIOPUTCMAP, FbCmap);
+
+// 4x4 Bayer matrix for ordered dithering
+const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Channel {
+    pub offset: u32,
+    pub length: u32,
+}
+
+impl From<&Bitfield> for Channel {
+    fn from(bitfield: &Bitfield) -> Self {
+        Self {
+            offset: bitfield.offset,
+            length: bitfield.length,
+        }
+    }
+}
+
+impl Channel {
+    /// Quantizes `value` to the channel depth and shifts it into place,
+    /// nudging it by `threshold` (in units of one level) to dither.
+    fn pack(&self, value: f32, threshold: f32) -> u32 {
+        if self.length == 0 {
+            return 0;
+        }
+        let levels = ((1u64 << self.length) - 1) as f32;
+        let v = (value * levels + threshold).round().clamp(0.0, levels) as u32;
+        v << self.offset
+    }
+
+    /// Extracts the channel from a packed pixel value, scaled to 8 bits.
+    fn unpack(&self, value: u32) -> u8 {
+        if self.length == 0 {
+            return 0;
+        }
+        let levels = (1u64 << self.length) - 1;
+        let v = (value as u64 >> self.offset) & levels;
+        (v * 255 / levels) as u8
+    }
+}
+
+/// Layout of a framebuffer pixel as described by the var screeninfo.
+#[derive(Debug, Clone, Copy)]
+pub struct PixelFormat {
+    pub bytes_per_pixel: u32,
+    /// Pixels are indices into the palette installed by `install_palette`.
+    pub indexed: bool,
+    pub byte_order: ByteOrder,
+    pub red: Channel,
+    pub green: Channel,
+    pub blue: Channel,
+    pub transp: Channel,
+    /// Colors are written inverted, and read back as they were.
+    pub invert: bool,
+}
+
+impl Default for PixelFormat {
+    fn default() -> Self {
+        Fourcc::Argb8888.format()
+    }
+}
+
+const fn channel(offset: u32, length: u32) -> Channel {
+    Channel { offset, length }
+}
+
+/// Order in which the bytes of a packed pixel are stored in memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ByteOrder {
+    #[default]
+    Native,
+    Little,
+    Big,
+}
+
+impl FromStr for ByteOrder {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "native" => Ok(ByteOrder::Native),
+            "little" => Ok(ByteOrder::Little),
+            "big" => Ok(ByteOrder::Big),
+            _ => Err(format!("unknown byte order {s}")),
+        }
+    }
+}
+
+impl fmt::Display for ByteOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ByteOrder::Native => write!(f, "native"),
+            ByteOrder::Little => write!(f, "little"),
+            ByteOrder::Big => write!(f, "big"),
+        }
+    }
+}
+
+/// Packed pixel layouts known by name, following the DRM fourcc convention
+/// of describing the pixel as a little-endian word from the most significant bit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fourcc {
+    Argb8888,
+    Xrgb8888,
+    Abgr8888,
+    Xbgr8888,
+    Rgba8888,
+    Rgbx8888,
+    Bgra8888,
+    Bgrx8888,
+    Rgb888,
+    Bgr888,
+    Rgb565,
+    Bgr565,
+    Rgb332,
+}
+
+impl Fourcc {
+    const ALL: [Fourcc; 13] = [
+        Fourcc::Argb8888,
+        Fourcc::Xrgb8888,
+        Fourcc::Abgr8888,
+        Fourcc::Xbgr8888,
+        Fourcc::Rgba8888,
+        Fourcc::Rgbx8888,
+        Fourcc::Bgra8888,
+        Fourcc::Bgrx8888,
+        Fourcc::Rgb888,
+        Fourcc::Bgr888,
+        Fourcc::Rgb565,
+        Fourcc::Bgr565,
+        Fourcc::Rgb332,
+    ];
+
+    /// The four character code as used by DRM.
+    pub fn code(self) -> &'static str {
+        match self {
+            Fourcc::Argb8888 => "AR24",
+            Fourcc::Xrgb8888 => "XR24",
+            Fourcc::Abgr8888 => "AB24",
+            Fourcc::Xbgr8888 => "XB24",
+            Fourcc::Rgba8888 => "RA24",
+            Fourcc::Rgbx8888 => "RX24",
+            Fourcc::Bgra8888 => "BA24",
+            Fourcc::Bgrx8888 => "BX24",
+            Fourcc::Rgb888 => "RG24",
+            Fourcc::Bgr888 => "BG24",
+            Fourcc::Rgb565 => "RG16",
+            Fourcc::Bgr565 => "BG16",
+            Fourcc::Rgb332 => "RGB8",
+        }
+    }
+
+    /// The channel layout of this format in native byte order.
+    pub fn format(self) -> PixelFormat {
+        let (bytes_per_pixel, red, green, blue, transp) = match self {
+            Fourcc::Argb8888 => (4, (16, 8), (8, 8), (0, 8), (24, 8)),
+            Fourcc::Xrgb8888 => (4, (16, 8), (8, 8), (0, 8), (0, 0)),
+            Fourcc::Abgr8888 => (4, (0, 8), (8, 8), (16, 8), (24, 8)),
+            Fourcc::Xbgr8888 => (4, (0, 8), (8, 8), (16, 8), (0, 0)),
+            Fourcc::Rgba8888 => (4, (24, 8), (16, 8), (8, 8), (0, 8)),
+            Fourcc::Rgbx8888 => (4, (24, 8), (16, 8), (8, 8), (0, 0)),
+            Fourcc::Bgra8888 => (4, (8, 8), (16, 8), (24, 8), (0, 8)),
+            Fourcc::Bgrx8888 => (4, (8, 8), (16, 8), (24, 8), (0, 0)),
+            Fourcc::Rgb888 => (3, (16, 8), (8, 8), (0, 8), (0, 0)),
+            Fourcc::Bgr888 => (3, (0, 8), (8, 8), (16, 8), (0, 0)),
+            Fourcc::Rgb565 => (2, (11, 5), (5, 6), (0, 5), (0, 0)),
+            Fourcc::Bgr565 => (2, (0, 5), (5, 6), (11, 5), (0, 0)),
+            Fourcc::Rgb332 => (1, (5, 3), (2, 3), (0, 2), (0, 0)),
+        };
+        PixelFormat {
+            bytes_per_pixel,
+            indexed: self == Fourcc::Rgb332,
+            byte_order: ByteOrder::Native,
+            red: channel(red.0, red.1),
+            green: channel(green.0, green.1),
+            blue: channel(blue.0, blue.1),
+            transp: channel(transp.0, transp.1),
+            invert: false,
+        }
+    }
+}
+
+impl PixelFormat {
+    pub fn from_screeninfo(info: &VarScreeninfo, fix_info: &FixScreeninfo) -> Self {
+        if fix_info.visual == FB_VISUAL_PSEUDOCOLOR && info.bits_per_pixel == 8 {
+            return Fourcc::Rgb332.format();
+        }
+        Self {
+            bytes_per_pixel: info.bits_per_pixel.div_ceil(8),
+            indexed: false,
+            byte_order: ByteOrder::Native,
+            red: (&info.red).into(),
+            green: (&info.green).into(),
+            blue: (&info.blue).into(),
+            transp: (&info.transp).into(),
+            invert: false,
+        }
+    }
+
+    /// Detects which named layout this is, ignoring the byte order.
+    pub fn fourcc(&self) -> Option<Fourcc> {
+        Fourcc::ALL.iter().copied().find(|fourcc| {
+            let other = fourcc.format();
+            self.bytes_per_pixel == other.bytes_per_pixel
+                && [self.red, self.green, self.blue, self.transp]
+                    .iter()
+                    .zip([other.red, other.green, other.blue, other.transp].iter())
+                    .all(|(a, b)| a.length == b.length && (a.length == 0 || a.offset == b.offset))
+        })
+    }
+
+    /// Whether every channel is a whole byte, so that scaling the bytes of a
+    /// pixel scales its color.
+    pub fn has_byte_channels(&self) -> bool {
+        !self.indexed
+            && [self.red, self.green, self.blue]
+                .iter()
+                .all(|c| c.length == 8 && c.offset % 8 == 0)
+    }
+
+    /// Whether any color channel has less than 8 bits and thus gets dithered.
+    pub fn is_low_color(&self) -> bool {
+        [self.red, self.green, self.blue]
+            .iter()
+            .any(|c| c.length < 8)
+    }
+
+    /// Converts `c` into the native pixel value, applying ordered dithering
+    /// based on `pos` for channels with less than 8 bits.
+    pub fn pack(&self, c: &Color, pos: (u32, u32)) -> u32 {
+        let [mut red, mut green, mut blue, opacity] = c.channels();
+        if self.invert {
+            (red, green, blue) = (1.0 - red, 1.0 - green, 1.0 - blue);
+        }
+        let threshold = if self.is_low_color() {
+            (BAYER[(pos.1 % 4) as usize][(pos.0 % 4) as usize] as f32 + 0.5) / 16.0 - 0.5
+        } else {
+            0.0
+        };
+        self.red.pack(red, threshold)
+            | self.green.pack(green, threshold)
+            | self.blue.pack(blue, threshold)
+            | self.transp.pack(opacity, 0.0)
+    }
+
+    /// Installs a 3-3-2 color map so that indexed pixels can be packed like
+    /// any other channel layout.
+    pub fn install_palette(&self, device: &File) -> nix::Result<()> {
+        if !self.indexed {
+            return Ok(());
+        }
+        let level = |v: u32, max: u32| (v * 0xFFFF / max) as u16;
+        let red: Vec<u16> = (0..256).map(|i| level((i >> 5) & 7, 7)).collect();
+        let green: Vec<u16> = (0..256).map(|i| level((i >> 2) & 7, 7)).collect();
+        let blue: Vec<u16> = (0..256).map(|i| level(i & 3, 3)).collect();
+        let cmap = FbCmap {
+            start: 0,
+            len: 256,
+            red: red.as_ptr(),
+            green: green.as_ptr(),
+            blue: blue.as_ptr(),
+            transp: std::ptr::null(),
+        };
+        unsafe { fbioputcmap(device.as_raw_fd(), &cmap) }.map(|_| ())
+    }
+
+    fn is_big_endian(&self) -> bool {
+        match self.byte_order {
+            ByteOrder::Native => cfg!(target_endian = "big"),
+            ByteOrder::Little => false,
+            ByteOrder::Big => true,
+        }
+    }
+
+    /// Writes a packed pixel value into `dst`, which is `bytes_per_pixel` long.
+    pub fn write(&self, dst: &mut [u8], value: u32) {
+        let n = self.bytes_per_pixel as usize;
+        if self.is_big_endian() {
+            dst.copy_from_slice(&value.to_be_bytes()[4 - n..]);
+        } else {
+            dst.copy_from_slice(&value.to_le_bytes()[..n]);
+        }
+    }
+
+    /// Converts the pixel in `src`, which is `bytes_per_pixel` long, back to
+    /// opaque 8 bit RGBA.
+    pub fn unpack_rgba8(&self, src: &[u8]) -> [u8; 4] {
+        let mut bytes = [0u8; 4];
+        let value = if self.is_big_endian() {
+            bytes[4 - src.len()..].copy_from_slice(src);
+            u32::from_be_bytes(bytes)
+        } else {
+            bytes[..src.len()].copy_from_slice(src);
+            u32::from_le_bytes(bytes)
+        };
+        let [red, green, blue] = [self.red, self.green, self.blue].map(|c| c.unpack(value));
+        if self.invert {
+            [!red, !green, !blue, 0xFF]
+        } else {
+            [red, green, blue, 0xFF]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLOR: [u8; 4] = [0x11, 0x22, 0x33, 0xFF];
+
+    fn with_order(fourcc: Fourcc, byte_order: ByteOrder) -> PixelFormat {
+        PixelFormat {
+            byte_order,
+            ..fourcc.format()
+        }
+    }
+
+    fn bytes(fourcc: Fourcc, byte_order: ByteOrder, rgba: [u8; 4]) -> Vec<u8> {
+        let format = with_order(fourcc, byte_order);
+        let mut dst = vec![0; format.bytes_per_pixel as usize];
+        format.write(&mut dst, format.pack(&Color::from_rgba8(rgba), (0, 0)));
+        dst
+    }
+
+    #[test]
+    fn every_fourcc_is_detected_from_its_format() {
+        for fourcc in Fourcc::ALL {
+            assert_eq!(fourcc.format().fourcc(), Some(fourcc), "{}", fourcc.code());
+        }
+    }
+
+    #[test]
+    fn formats_have_their_sizes() {
+        for fourcc in Fourcc::ALL {
+            let format = fourcc.format();
+            let bits = [format.red, format.green, format.blue, format.transp]
+                .iter()
+                .map(|c| c.length)
+                .sum::<u32>();
+            let expected = match fourcc {
+                Fourcc::Rgb888 | Fourcc::Bgr888 => 3,
+                Fourcc::Rgb565 | Fourcc::Bgr565 => 2,
+                Fourcc::Rgb332 => 1,
+                _ => 4,
+            };
+            assert_eq!(format.bytes_per_pixel, expected, "{}", fourcc.code());
+            assert!(bits <= 8 * expected, "{}", fourcc.code());
+            assert_eq!(
+                format.indexed,
+                fourcc == Fourcc::Rgb332,
+                "{}",
+                fourcc.code()
+            );
+        }
+    }
+
+    #[test]
+    fn packs_little_endian() {
+        let order = ByteOrder::Little;
+        assert_eq!(
+            bytes(Fourcc::Argb8888, order, COLOR),
+            [0x33, 0x22, 0x11, 0xFF]
+        );
+        assert_eq!(
+            bytes(Fourcc::Xrgb8888, order, COLOR),
+            [0x33, 0x22, 0x11, 0x00]
+        );
+        assert_eq!(
+            bytes(Fourcc::Abgr8888, order, COLOR),
+            [0x11, 0x22, 0x33, 0xFF]
+        );
+        assert_eq!(
+            bytes(Fourcc::Xbgr8888, order, COLOR),
+            [0x11, 0x22, 0x33, 0x00]
+        );
+        assert_eq!(
+            bytes(Fourcc::Rgba8888, order, COLOR),
+            [0xFF, 0x33, 0x22, 0x11]
+        );
+        assert_eq!(
+            bytes(Fourcc::Rgbx8888, order, COLOR),
+            [0x00, 0x33, 0x22, 0x11]
+        );
+        assert_eq!(
+            bytes(Fourcc::Bgra8888, order, COLOR),
+            [0xFF, 0x11, 0x22, 0x33]
+        );
+        assert_eq!(
+            bytes(Fourcc::Bgrx8888, order, COLOR),
+            [0x00, 0x11, 0x22, 0x33]
+        );
+        assert_eq!(bytes(Fourcc::Rgb888, order, COLOR), [0x33, 0x22, 0x11]);
+        assert_eq!(bytes(Fourcc::Bgr888, order, COLOR), [0x11, 0x22, 0x33]);
+    }
+
+    #[test]
+    fn packs_big_endian() {
+        let order = ByteOrder::Big;
+        assert_eq!(
+            bytes(Fourcc::Argb8888, order, COLOR),
+            [0xFF, 0x11, 0x22, 0x33]
+        );
+        assert_eq!(
+            bytes(Fourcc::Xrgb8888, order, COLOR),
+            [0x00, 0x11, 0x22, 0x33]
+        );
+        assert_eq!(
+            bytes(Fourcc::Abgr8888, order, COLOR),
+            [0xFF, 0x33, 0x22, 0x11]
+        );
+        assert_eq!(
+            bytes(Fourcc::Xbgr8888, order, COLOR),
+            [0x00, 0x33, 0x22, 0x11]
+        );
+        assert_eq!(
+            bytes(Fourcc::Rgba8888, order, COLOR),
+            [0x11, 0x22, 0x33, 0xFF]
+        );
+        assert_eq!(
+            bytes(Fourcc::Rgbx8888, order, COLOR),
+            [0x11, 0x22, 0x33, 0x00]
+        );
+        assert_eq!(
+            bytes(Fourcc::Bgra8888, order, COLOR),
+            [0x33, 0x22, 0x11, 0xFF]
+        );
+        assert_eq!(
+            bytes(Fourcc::Bgrx8888, order, COLOR),
+            [0x33, 0x22, 0x11, 0x00]
+        );
+        assert_eq!(bytes(Fourcc::Rgb888, order, COLOR), [0x11, 0x22, 0x33]);
+        assert_eq!(bytes(Fourcc::Bgr888, order, COLOR), [0x33, 0x22, 0x11]);
+    }
+
+    #[test]
+    fn packs_16_bit() {
+        let white = [0xFF; 4];
+        let red = [0xFF, 0x00, 0x00, 0xFF];
+        assert_eq!(
+            bytes(Fourcc::Rgb565, ByteOrder::Little, white),
+            [0xFF, 0xFF]
+        );
+        assert_eq!(bytes(Fourcc::Rgb565, ByteOrder::Little, red), [0x00, 0xF8]);
+        assert_eq!(bytes(Fourcc::Rgb565, ByteOrder::Big, red), [0xF8, 0x00]);
+        assert_eq!(bytes(Fourcc::Bgr565, ByteOrder::Little, red), [0x1F, 0x00]);
+        assert_eq!(bytes(Fourcc::Bgr565, ByteOrder::Big, red), [0x00, 0x1F]);
+    }
+
+    #[test]
+    fn unpacks_what_was_written() {
+        for fourcc in Fourcc::ALL {
+            let format = fourcc.format();
+            if format.red.length < 8 || format.green.length < 8 || format.blue.length < 8 {
+                continue;
+            }
+            for order in [ByteOrder::Little, ByteOrder::Big] {
+                let format = with_order(fourcc, order);
+                let dst = bytes(fourcc, order, COLOR);
+                assert_eq!(
+                    format.unpack_rgba8(&dst),
+                    COLOR,
+                    "{} {order}",
+                    fourcc.code()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn unpacks_16_bit_to_the_nearest_level() {
+        for order in [ByteOrder::Little, ByteOrder::Big] {
+            let format = with_order(Fourcc::Rgb565, order);
+            let dst = bytes(Fourcc::Rgb565, order, [0xFF, 0x00, 0xFF, 0xFF]);
+            assert_eq!(
+                format.unpack_rgba8(&dst),
+                [0xFF, 0x00, 0xFF, 0xFF],
+                "{order}"
+            );
+        }
+    }
+
+    #[test]
+    fn inverts_both_ways() {
+        let format = PixelFormat {
+            invert: true,
+            ..with_order(Fourcc::Xrgb8888, ByteOrder::Little)
+        };
+        let mut dst = [0; 4];
+        format.write(&mut dst, format.pack(&Color::from_rgba8(COLOR), (0, 0)));
+        assert_eq!(dst, [0xCC, 0xDD, 0xEE, 0x00]);
+        assert_eq!(format.unpack_rgba8(&dst), COLOR);
+    }
+
+    #[test]
+    fn native_order_follows_the_target() {
+        let native = bytes(Fourcc::Argb8888, ByteOrder::Native, COLOR);
+        let expected = if cfg!(target_endian = "big") {
+            ByteOrder::Big
+        } else {
+            ByteOrder::Little
+        };
+        assert_eq!(native, bytes(Fourcc::Argb8888, expected, COLOR));
+    }
+}
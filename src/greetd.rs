@@ -1,83 +1,201 @@
 use std::env;
 use std::error::Error;
 use std::fmt;
+use std::os::unix::io::AsRawFd;
 use std::os::unix::net::UnixStream;
-use std::process;
+use std::time::{Duration, Instant};
+
+use nix::poll::{poll, PollFd, PollFlags};
 
 use greetd_ipc::{codec::SyncCodec, AuthMessageType, Request, Response};
 
+use zeroize::Zeroize;
+
+use crate::exit::{self, Failure};
+use crate::secret::Secret;
+
 #[derive(Debug)]
 struct LoginError(String);
 
 impl fmt::Display for LoginError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "There is an error: {}", self.0)
+        write!(f, "Login failed: {}", self.0)
     }
 }
 
 impl Error for LoginError {}
 
+/// greetd did not answer within the configured auth timeout.
+#[derive(Debug)]
+pub struct AuthTimeout;
+
+impl fmt::Display for AuthTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Authentication timed out")
+    }
+}
+
+impl Error for AuthTimeout {}
+
+/// Failed logins take at least this long, so that how quickly one fails
+/// doesn't tell whether the user exists.
+pub const FAILURE_FLOOR: Duration = Duration::from_secs(2);
+
+/// What greetd asks of the user during a conversation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Prompt {
+    /// Asks for an answer that can be shown as typed.
+    Visible(String),
+    /// Asks for an answer that has to be masked.
+    Secret(String),
+    /// Tells something, without expecting an answer.
+    Info(String),
+    /// Tells of an error, without expecting an answer.
+    Error(String),
+}
+
+impl Prompt {
+    pub fn message(&self) -> &str {
+        match self {
+            Prompt::Visible(msg) | Prompt::Secret(msg) | Prompt::Info(msg) | Prompt::Error(msg) => {
+                msg
+            }
+        }
+    }
+
+    /// Whether the prompt waits for an answer from the user.
+    pub fn is_question(&self) -> bool {
+        matches!(self, Prompt::Visible(_) | Prompt::Secret(_))
+    }
+}
+
+/// The next step of a conversation.
+#[derive(Debug)]
+pub enum Step {
+    Prompt(Prompt),
+    /// Every question was answered, the session can be started.
+    Authenticated,
+}
+
 pub struct GreetD {
-    pub stream: UnixStream,
+    // hung up after a timeout, and connected again for the next conversation
+    stream: Option<UnixStream>,
+    timeout: Option<Duration>,
+    // when the conversation started, for the failure floor
+    started: Option<Instant>,
 }
 
 impl GreetD {
-    pub fn new() -> Self {
-        let socket = env::var("GREETD_SOCK");
-        if socket.is_err() {
-            eprintln!("GREETD_SOCK must be defined");
-            process::exit(1);
+    pub fn new(timeout: Option<Duration>) -> Self {
+        GreetD {
+            stream: Some(connect()),
+            timeout,
+            started: None,
         }
-        match UnixStream::connect(socket.unwrap()) {
-            Ok(stream) => GreetD { stream },
+    }
 
-            Err(err) => {
-                eprintln!("{}", err);
-                process::exit(1);
-            }
+    fn stream(&mut self) -> &mut UnixStream {
+        self.stream.get_or_insert_with(connect)
+    }
+
+    /// Waits for the next response, giving up after the auth timeout. A late
+    /// answer would desync the protocol, so the connection is dropped, which
+    /// has greetd cancel the pending session.
+    fn read_response(&mut self) -> Result<Response, Box<dyn Error>> {
+        if !self.wait_readable()? {
+            self.stream = None;
+            return Err(Box::new(AuthTimeout));
+        }
+        Ok(Response::read_from(self.stream())?)
+    }
+
+    fn wait_readable(&mut self) -> nix::Result<bool> {
+        let timeout = match self.timeout {
+            Some(timeout) => timeout.as_millis().min(i32::MAX as u128) as i32,
+            None => return Ok(true),
+        };
+        let mut fds = [PollFd::new(self.stream().as_raw_fd(), PollFlags::POLLIN)];
+        Ok(poll(&mut fds, timeout)? > 0)
+    }
+
+    /// Starts a conversation for `username`, returning greetd's first step.
+    /// Every prompt is passed on, to be answered with `respond`, so PAM stacks
+    /// asking more than a password work.
+    pub fn converse(&mut self, username: String) -> Result<Step, Box<dyn Error>> {
+        self.started = Some(Instant::now());
+        let _ = Request::CreateSession { username }.write_to(self.stream());
+        self.step()
+    }
+
+    /// Answers the last prompt, `None` for ones that aren't questions. The
+    /// copy of the answer the request needs is wiped once it's sent.
+    pub fn respond(&mut self, response: Option<&Secret>) -> Result<Step, Box<dyn Error>> {
+        let mut request = Request::PostAuthMessageResponse {
+            response: response.map(|response| response.to_string()),
+        };
+        let _ = request.write_to(self.stream());
+        if let Request::PostAuthMessageResponse {
+            response: Some(response),
+        } = &mut request
+        {
+            response.zeroize();
         }
+        self.step()
     }
 
-    pub fn login(
-        &mut self,
-        username: String,
-        password: String,
-        cmd: Vec<String>,
-    ) -> Result<(), Box<dyn Error>> {
-        let _ = Request::CreateSession { username }.write_to(&mut self.stream);
-        let _ = Request::PostAuthMessageResponse {
-            response: Some(password),
+    /// Starts `cmd` once the conversation is authenticated.
+    pub fn start_session(&mut self, cmd: Vec<String>) -> Result<(), Box<dyn Error>> {
+        let _ = Request::StartSession { cmd }.write_to(self.stream());
+        match self.read_response() {
+            Ok(Response::Success) => Ok(()),
+            Ok(Response::Error { description, .. }) => Err(LoginError(description).into()),
+            Ok(Response::AuthMessage { .. }) => Err(LoginError("Unknown error".into()).into()),
+            Err(err) => Err(err),
         }
-        .write_to(&mut self.stream);
-        let response = Response::read_from(&mut self.stream)?;
-        match response {
-            Response::AuthMessage {
-                auth_message: _,
+    }
+
+    fn step(&mut self) -> Result<Step, Box<dyn Error>> {
+        match self.read_response() {
+            Ok(Response::Success) => Ok(Step::Authenticated),
+            Ok(Response::Error { description, .. }) => Err(LoginError(description).into()),
+            Ok(Response::AuthMessage {
+                auth_message,
                 auth_message_type,
-            } => match auth_message_type {
-                AuthMessageType::Secret => {
-                    let _ = Request::StartSession { cmd }.write_to(&mut self.stream);
-                    let resp = Response::read_from(&mut self.stream)?;
-                    match resp {
-                        Response::Success => Ok(()),
-                        Response::Error { .. } | Response::AuthMessage { .. } => {
-                            Err(Box::new(LoginError("Wrong username or password".into())))
-                        }
-                    }
-                }
-                _ => Err(Box::new(LoginError("Wrong username".into()))),
-            },
-            Response::Success => {
-                let _ = Request::StartSession { cmd }.write_to(&mut self.stream);
-                let _ = Response::read_from(&mut self.stream)?;
-                Ok(())
-            }
-            _ => Err(Box::new(LoginError("Unknown error".into()))),
+            }) => Ok(Step::Prompt(match auth_message_type {
+                AuthMessageType::Visible => Prompt::Visible(auth_message),
+                AuthMessageType::Secret => Prompt::Secret(auth_message),
+                AuthMessageType::Info => Prompt::Info(auth_message),
+                AuthMessageType::Error => Prompt::Error(auth_message),
+            })),
+            Err(err) => Err(err),
         }
     }
 
+    /// When the failure of the conversation that just ended may be told: the
+    /// failure floor after it started, or from now if it never did.
+    pub fn failure_due(&mut self) -> Instant {
+        self.started.take().unwrap_or_else(Instant::now) + FAILURE_FLOOR
+    }
+
     pub fn cancel(&mut self) {
-        let _ = Request::CancelSession.write_to(&mut self.stream);
-        let _ = Response::read_from(&mut self.stream);
+        self.started = None;
+        if self.stream.is_none() {
+            // greetd cancelled it when the connection was dropped
+            return;
+        }
+        let _ = Request::CancelSession.write_to(self.stream());
+        let _ = self.read_response();
+    }
+}
+
+fn connect() -> UnixStream {
+    let socket = env::var("GREETD_SOCK");
+    if socket.is_err() {
+        exit::fail(Failure::Greetd, "GREETD_SOCK must be defined");
+    }
+    match UnixStream::connect(socket.unwrap()) {
+        Ok(stream) => stream,
+
+        Err(err) => exit::fail(Failure::Greetd, err),
     }
 }
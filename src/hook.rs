@@ -0,0 +1,61 @@
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long the login waits for the hook, which is left running after that.
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Runs `command` through `sh -c` once `user` authenticated, as the greeter's
+/// user, with `DDLM_USER` set and `secret` on its standard input if given.
+/// Its output goes to the greeter's log, and failures are only logged, as the
+/// login goes on regardless.
+pub fn after_auth(command: &str, user: &str, secret: Option<&str>) {
+    let stdin = if secret.is_some() {
+        Stdio::piped()
+    } else {
+        Stdio::null()
+    };
+    let child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("DDLM_USER", user)
+        .stdin(stdin)
+        .stdout(io::stderr())
+        .stderr(io::stderr())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(err) => {
+            eprintln!("unable to run the after auth command: {err}");
+            return;
+        }
+    };
+    if let (Some(mut stdin), Some(secret)) = (child.stdin.take(), secret) {
+        let _ = stdin.write_all(secret.as_bytes());
+    }
+    let started = Instant::now();
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => return,
+            Ok(Some(status)) => {
+                eprintln!("after auth command exited with {status}");
+                return;
+            }
+            Ok(None) if started.elapsed() >= TIMEOUT => {
+                eprintln!(
+                    "after auth command still running after {}s, not waiting for it",
+                    TIMEOUT.as_secs()
+                );
+                return;
+            }
+            Ok(None) => thread::sleep(POLL_INTERVAL),
+            Err(err) => {
+                eprintln!("unable to wait for the after auth command: {err}");
+                return;
+            }
+        }
+    }
+}
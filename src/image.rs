@@ -0,0 +1,84 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use thiserror::Error;
+
+use crate::buffer::Vect;
+
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum ImageError {
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    #[cfg(feature = "png")]
+    #[error("{0}")]
+    Png(#[from] png::DecodingError),
+    #[cfg(feature = "theme-images")]
+    #[error("{0}")]
+    Jpeg(#[from] jpeg_decoder::Error),
+    #[error("{0}")]
+    Unsupported(&'static str),
+}
+
+/// Decodes the PNG or JPEG image at `path` into tightly packed RGBA.
+pub fn decode(path: &Path) -> Result<(Vec<u8>, Vect), ImageError> {
+    let data = fs::read(path)?;
+    if data.starts_with(b"\x89PNG") {
+        decode_png(&data)
+    } else if data.starts_with(b"\xFF\xD8") {
+        decode_jpeg(&data)
+    } else {
+        Err(ImageError::Unsupported("not a PNG or JPEG image"))
+    }
+}
+
+#[cfg(feature = "png")]
+fn decode_png(data: &[u8]) -> Result<(Vec<u8>, Vect), ImageError> {
+    let mut decoder = png::Decoder::new(data);
+    // palettes, low bit depths and 16 bit channels become 8 bit RGB(A)
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf)?;
+    buf.truncate(info.buffer_size());
+    let pixels = match info.color_type {
+        png::ColorType::Rgba => buf,
+        png::ColorType::Rgb => rgba(&buf, 3, |p| [p[0], p[1], p[2], 0xFF]),
+        png::ColorType::GrayscaleAlpha => rgba(&buf, 2, |p| [p[0], p[0], p[0], p[1]]),
+        _ => rgba(&buf, 1, |p| [p[0], p[0], p[0], 0xFF]),
+    };
+    Ok((pixels, (info.width, info.height)))
+}
+
+#[cfg(not(feature = "png"))]
+fn decode_png(_data: &[u8]) -> Result<(Vec<u8>, Vect), ImageError> {
+    Err(ImageError::Unsupported("built without PNG support"))
+}
+
+#[cfg(feature = "theme-images")]
+fn decode_jpeg(data: &[u8]) -> Result<(Vec<u8>, Vect), ImageError> {
+    use jpeg_decoder::PixelFormat;
+
+    let mut decoder = jpeg_decoder::Decoder::new(data);
+    let buf = decoder.decode()?;
+    let info = decoder
+        .info()
+        .ok_or(ImageError::Unsupported("JPEG without a frame"))?;
+    let pixels = match info.pixel_format {
+        PixelFormat::RGB24 => rgba(&buf, 3, |p| [p[0], p[1], p[2], 0xFF]),
+        PixelFormat::L8 => rgba(&buf, 1, |p| [p[0], p[0], p[0], 0xFF]),
+        _ => return Err(ImageError::Unsupported("unsupported JPEG pixel format")),
+    };
+    Ok((pixels, (info.width as u32, info.height as u32)))
+}
+
+#[cfg(not(feature = "theme-images"))]
+fn decode_jpeg(_data: &[u8]) -> Result<(Vec<u8>, Vect), ImageError> {
+    Err(ImageError::Unsupported("built without JPEG support"))
+}
+
+#[cfg(any(feature = "png", feature = "theme-images"))]
+fn rgba(buf: &[u8], channels: usize, pixel: impl Fn(&[u8]) -> [u8; 4]) -> Vec<u8> {
+    buf.chunks_exact(channels).flat_map(pixel).collect()
+}
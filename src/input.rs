@@ -0,0 +1,175 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::str::FromStr;
+use std::time::Duration;
+
+use nix::poll::{poll, PollFd, PollFlags};
+
+use crate::evdev::Keyboards;
+use crate::keys::Key;
+
+const READ_CHUNK: usize = 256;
+
+// the keyboard's lock flags, rather than its lights
+const KDGKBLED: u16 = 0x4B64;
+const K_CAPSLOCK: u8 = 0x04;
+nix::ioctl_read_bad!(kdgkbled, KDGKBLED, u8);
+
+/// Where keys are read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Source {
+    /// The console, which has to be the active VT.
+    #[default]
+    Console,
+    /// Every keyboard's event device, through the console's keymap.
+    Evdev,
+}
+
+impl FromStr for Source {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "console" => Ok(Source::Console),
+            "evdev" => Ok(Source::Evdev),
+            _ => Err(format!("unknown input source {s}")),
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Console => write!(f, "console"),
+            Source::Evdev => write!(f, "evdev"),
+        }
+    }
+}
+
+/// Keyboard input from the console or the keyboards, queued so that
+/// everything typed since the last frame can be handled before drawing the
+/// next one.
+pub struct Input {
+    file: File,
+    queue: VecDeque<u8>,
+    // read instead of the console if set, whose input is then discarded
+    keyboards: Option<Keyboards>,
+    keys: VecDeque<Key>,
+    // other descriptors that end the wait when readable
+    watched: Vec<RawFd>,
+}
+
+impl Input {
+    pub fn new(file: File) -> Self {
+        Self {
+            file,
+            queue: VecDeque::with_capacity(READ_CHUNK),
+            keyboards: None,
+            keys: VecDeque::new(),
+            watched: Vec::new(),
+        }
+    }
+
+    /// Reads keys from `keyboards` rather than the console.
+    pub fn read_keyboards(&mut self, keyboards: Keyboards) {
+        self.keyboards = Some(keyboards);
+    }
+
+    /// Grabs the keyboards keys are read from, if any, or releases them.
+    pub fn grab_keyboards(&mut self, grab: bool) {
+        if let Some(keyboards) = &mut self.keyboards {
+            keyboards.grab(grab);
+        }
+    }
+
+    /// Also stops waiting when `fd` becomes readable.
+    pub fn watch(&mut self, fd: RawFd) {
+        self.watched.push(fd);
+    }
+
+    /// Stops waiting on `fd`, before it's closed.
+    pub fn unwatch(&mut self, fd: RawFd) {
+        self.watched.retain(|&watched| watched != fd);
+    }
+
+    /// Waits up to `timeout` (forever if `None`) for input and then queues
+    /// everything that is pending. Returns false on end of input.
+    pub fn wait(&mut self, timeout: Option<Duration>) -> io::Result<bool> {
+        let timeout = timeout.map_or(-1, |t| t.as_millis().min(i32::MAX as u128) as i32);
+        if self.poll(timeout)? {
+            loop {
+                let mut chunk = [0u8; READ_CHUNK];
+                let n = self.file.read(&mut chunk)?;
+                if n == 0 {
+                    return Ok(false);
+                }
+                if self.keyboards.is_none() {
+                    self.queue.extend(&chunk[..n]);
+                }
+                if !self.poll(0)? {
+                    break;
+                }
+            }
+        }
+        if let Some(keyboards) = &mut self.keyboards {
+            keyboards.read(self.file.as_raw_fd(), &mut self.keys);
+        }
+        Ok(true)
+    }
+
+    /// Whether the console has input, after waiting up to `timeout` for it or
+    /// the watched descriptors.
+    fn poll(&self, timeout: i32) -> io::Result<bool> {
+        let mut fds = vec![PollFd::new(self.file.as_raw_fd(), PollFlags::POLLIN)];
+        fds.extend(
+            self.watched
+                .iter()
+                .copied()
+                .chain(self.keyboards.iter().flat_map(Keyboards::fds))
+                .map(|fd| PollFd::new(fd, PollFlags::POLLIN)),
+        );
+        poll(&mut fds, timeout)?;
+        Ok(fds[0].revents().is_some_and(|events| !events.is_empty()))
+    }
+
+    /// What the keyboards' accessibility gestures turned on or off, since
+    /// last asked.
+    pub fn take_notice(&mut self) -> Option<&'static str> {
+        self.keyboards.as_mut()?.take_notice()
+    }
+
+    /// Whether Caps Lock is on. The console keeps track of it however keys
+    /// are read, the keyboards only know of it being pressed.
+    pub fn caps_lock(&self) -> bool {
+        let mut flags = 0;
+        // SAFETY: the ioctl writes one byte
+        match unsafe { kdgkbled(self.file.as_raw_fd(), &mut flags) } {
+            Ok(_) => flags & K_CAPSLOCK != 0,
+            Err(_) => self.keyboards.as_ref().is_some_and(Keyboards::caps_lock),
+        }
+    }
+
+    /// The name of the XKB layout keys are typed with, if several are set
+    /// up.
+    pub fn layout(&self) -> Option<&str> {
+        self.keyboards.as_ref()?.layout()
+    }
+
+    pub fn has_pending(&self) -> bool {
+        !self.queue.is_empty() || !self.keys.is_empty()
+    }
+
+    /// Pops the next key, skipping escape sequences that don't map to one.
+    pub fn pop_key(&mut self) -> Option<Key> {
+        if let Some(key) = self.keys.pop_front() {
+            return Some(key);
+        }
+        loop {
+            if let Some(key) = Key::decode(&mut self.queue)? {
+                return Some(key);
+            }
+        }
+    }
+}
@@ -0,0 +1,499 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::logind::PowerAction;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Key {
+    Char(char),
+    Ctrl(char),
+    Tab,
+    Enter,
+    Backspace,
+    Escape,
+    Up,
+    Down,
+    Left,
+    Right,
+    F(u8),
+}
+
+impl Key {
+    /// Decodes the next key from raw console input, consuming the bytes of an
+    /// unknown escape sequence without producing a key for it.
+    pub fn decode(bytes: &mut VecDeque<u8>) -> Option<Option<Key>> {
+        let byte = bytes.pop_front()?;
+        let key = match byte {
+            b'\t' => Key::Tab,
+            b'\r' | b'\n' => Key::Enter,
+            0x7F | 0x08 => Key::Backspace,
+            0x1B => return Some(decode_escape(bytes)),
+            0x01..=0x1A => Key::Ctrl((b'a' + byte - 1) as char),
+            0x80.. => return decode_utf8(byte, bytes),
+            _ => Key::Char(byte as char),
+        };
+        Some(Some(key))
+    }
+}
+
+/// Decodes a multi-byte UTF-8 character starting with `lead`. The bytes are
+/// put back to wait for the rest of the character if it isn't all there yet,
+/// and invalid ones are dropped.
+fn decode_utf8(lead: u8, bytes: &mut VecDeque<u8>) -> Option<Option<Key>> {
+    let len = match lead {
+        0xC0..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        0xF0..=0xF7 => 4,
+        _ => return Some(None),
+    };
+    let rest = bytes
+        .iter()
+        .take(len - 1)
+        .take_while(|&&b| b & 0xC0 == 0x80)
+        .count();
+    if rest < len - 1 {
+        if rest == bytes.len() {
+            bytes.push_front(lead);
+            return None;
+        }
+        return Some(None);
+    }
+    let mut buf = [lead, 0, 0, 0];
+    for b in &mut buf[1..len] {
+        *b = bytes.pop_front()?;
+    }
+    let key = std::str::from_utf8(&buf[..len])
+        .ok()
+        .and_then(|s| s.chars().next())
+        .map(Key::Char);
+    Some(key)
+}
+
+/// Decodes what follows an escape byte, as sent by the linux console and
+/// common terminals.
+fn decode_escape(bytes: &mut VecDeque<u8>) -> Option<Key> {
+    match bytes.front() {
+        Some(b'[') | Some(b'O') => {}
+        _ => return Some(Key::Escape),
+    }
+    let intro = bytes.pop_front()?;
+    let next = bytes.pop_front()?;
+    match (intro, next) {
+        (b'[', b'A') => Some(Key::Up),
+        (b'[', b'B') => Some(Key::Down),
+        (b'[', b'C') => Some(Key::Right),
+        (b'[', b'D') => Some(Key::Left),
+        (b'O', b'P'..=b'S') => Some(Key::F(next - b'P' + 1)),
+        (b'[', b'[') => match bytes.pop_front()? {
+            f @ b'A'..=b'E' => Some(Key::F(f - b'A' + 1)),
+            _ => None,
+        },
+        (b'[', b'0'..=b'9') => {
+            let mut n = (next - b'0') as u32;
+            loop {
+                match bytes.pop_front()? {
+                    d @ b'0'..=b'9' => n = n * 10 + (d - b'0') as u32,
+                    b'~' => break,
+                    _ => return None,
+                }
+            }
+            match n {
+                11..=15 => Some(Key::F((n - 10) as u8)),
+                17..=21 => Some(Key::F((n - 11) as u8)),
+                23 | 24 => Some(Key::F((n - 12) as u8)),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Key::Char(c) => write!(f, "{c}"),
+            Key::Ctrl(c) => write!(f, "Ctrl-{}", c.to_ascii_uppercase()),
+            Key::Tab => write!(f, "Tab"),
+            Key::Enter => write!(f, "Enter"),
+            Key::Backspace => write!(f, "Backspace"),
+            Key::Escape => write!(f, "Esc"),
+            Key::Up => write!(f, "Up"),
+            Key::Down => write!(f, "Down"),
+            Key::Left => write!(f, "Left"),
+            Key::Right => write!(f, "Right"),
+            Key::F(n) => write!(f, "F{n}"),
+        }
+    }
+}
+
+impl FromStr for Key {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let key = match s {
+            "Tab" => Key::Tab,
+            "Enter" => Key::Enter,
+            "Backspace" => Key::Backspace,
+            "Esc" => Key::Escape,
+            "Up" => Key::Up,
+            "Down" => Key::Down,
+            "Left" => Key::Left,
+            "Right" => Key::Right,
+            _ => {
+                if let Some(c) = s.strip_prefix("Ctrl-").and_then(single_char) {
+                    Key::Ctrl(c.to_ascii_lowercase())
+                } else if let Some(Ok(n @ 1..=12)) = s.strip_prefix('F').map(str::parse) {
+                    Key::F(n)
+                } else if let Some(c) = single_char(s) {
+                    Key::Char(c)
+                } else {
+                    return Err(format!("unknown key {s}"));
+                }
+            }
+        };
+        Ok(key)
+    }
+}
+
+fn single_char(s: &str) -> Option<char> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
+/// Something a key can be bound to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    NextField,
+    Submit,
+    DeleteBack,
+    ClearField,
+    NextSession,
+    PrevSession,
+    /// Opens the session list from any field, or closes it.
+    ChooseSession,
+    Quit,
+    /// Allows logging in while the maintenance lock is on.
+    MaintenanceBypass,
+    /// Saves the screen, without the password, to the screenshot directory.
+    Screenshot,
+    /// Lets confetti rain over the screen for a moment.
+    Confetti,
+    /// Opens the menu of what logind allows doing to the machine, or closes
+    /// it.
+    PowerMenu,
+    /// Has logind power off, reboot or suspend the machine right away.
+    Power(PowerAction),
+    /// Inverts every color on screen, or restores them, for panels that
+    /// are hard to read.
+    InvertColors,
+    /// Opens the admin palette, or closes it.
+    AdminPalette,
+    /// Shows the greeter's own log in a panel, with `--log-viewer`, or
+    /// hides it.
+    LogViewer,
+}
+
+impl Action {
+    /// Short description for the hint bar.
+    pub fn description(&self) -> &'static str {
+        match self {
+            Action::NextField => "switch field",
+            Action::Submit => "submit",
+            Action::DeleteBack => "delete",
+            Action::ClearField => "clear field",
+            Action::NextSession => "next session",
+            Action::PrevSession => "previous session",
+            Action::ChooseSession => "sessions",
+            Action::Quit => "quit",
+            Action::MaintenanceBypass => "bypass maintenance",
+            Action::Screenshot => "screenshot",
+            Action::Confetti => "confetti",
+            Action::PowerMenu => "power menu",
+            Action::Power(PowerAction::PowerOff) => "power off",
+            Action::Power(PowerAction::Reboot) => "reboot",
+            Action::Power(PowerAction::Suspend) => "suspend",
+            Action::InvertColors => "invert colors",
+            Action::AdminPalette => "admin palette",
+            Action::LogViewer => "log",
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Action::NextField => "next-field",
+            Action::Submit => "submit",
+            Action::DeleteBack => "delete",
+            Action::ClearField => "clear-field",
+            Action::NextSession => "next-session",
+            Action::PrevSession => "prev-session",
+            Action::ChooseSession => "choose-session",
+            Action::Quit => "quit",
+            Action::MaintenanceBypass => "maintenance-bypass",
+            Action::Screenshot => "screenshot",
+            Action::Confetti => "confetti",
+            Action::PowerMenu => "power-menu",
+            Action::InvertColors => "invert-colors",
+            Action::AdminPalette => "admin-palette",
+            Action::LogViewer => "log-viewer",
+            Action::Power(action) => return write!(f, "{action}"),
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "next-field" => Ok(Action::NextField),
+            "submit" => Ok(Action::Submit),
+            "delete" => Ok(Action::DeleteBack),
+            "clear-field" => Ok(Action::ClearField),
+            "next-session" => Ok(Action::NextSession),
+            "prev-session" => Ok(Action::PrevSession),
+            "choose-session" => Ok(Action::ChooseSession),
+            "quit" => Ok(Action::Quit),
+            "maintenance-bypass" => Ok(Action::MaintenanceBypass),
+            "screenshot" => Ok(Action::Screenshot),
+            "confetti" => Ok(Action::Confetti),
+            "power-menu" => Ok(Action::PowerMenu),
+            "invert-colors" => Ok(Action::InvertColors),
+            "admin-palette" => Ok(Action::AdminPalette),
+            "log-viewer" => Ok(Action::LogViewer),
+            _ => match s.parse() {
+                Ok(action) => Ok(Action::Power(action)),
+                Err(_) => Err(format!("unknown action {s}")),
+            },
+        }
+    }
+}
+
+// how to get around the form comes first in the hints
+const FIRST_HINTS: [Action; 3] = [Action::NextField, Action::ChooseSession, Action::PowerMenu];
+const HINT_SEPARATOR: &str = "  ";
+
+/// Which keys trigger which actions.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: Vec<(Key, Action)>,
+    sequences: Vec<(Vec<Key>, Action)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self {
+            bindings: vec![
+                (Key::Tab, Action::NextField),
+                (Key::Enter, Action::Submit),
+                (Key::Backspace, Action::DeleteBack),
+                (Key::Ctrl('u'), Action::ClearField),
+                (Key::Ctrl('k'), Action::ClearField),
+                (Key::Down, Action::NextSession),
+                (Key::Up, Action::PrevSession),
+                (Key::F(1), Action::ChooseSession),
+                (Key::Ctrl('c'), Action::Quit),
+                (Key::Ctrl('d'), Action::Quit),
+                (Key::Escape, Action::PowerMenu),
+                (Key::F(3), Action::InvertColors),
+            ],
+            sequences: Vec::new(),
+        }
+    }
+}
+
+impl Keymap {
+    /// Binds `key` to `action`, replacing what it was bound to before.
+    pub fn bind(&mut self, key: Key, action: Action) {
+        self.bindings.retain(|(k, _)| *k != key);
+        self.bindings.push((key, action));
+    }
+
+    /// Binds the sequence of `keys` to `action`, replacing what it was bound
+    /// to before.
+    pub fn bind_sequence(&mut self, keys: Vec<Key>, action: Action) {
+        self.sequences.retain(|(k, _)| *k != keys);
+        self.sequences.push((keys, action));
+    }
+
+    /// Parses and applies a `KEY=ACTION` binding, or a `KEY KEY...=ACTION`
+    /// sequence binding.
+    pub fn bind_str(&mut self, binding: &str) -> Result<(), String> {
+        let (keys, action) = binding
+            .split_once('=')
+            .ok_or_else(|| format!("expected KEY=ACTION, got {binding}"))?;
+        let keys = keys
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<Result<Vec<Key>, _>>()?;
+        match keys[..] {
+            [] => return Err(format!("expected KEY=ACTION, got {binding}")),
+            [key] => self.bind(key, action.parse()?),
+            _ => self.bind_sequence(keys, action.parse()?),
+        }
+        Ok(())
+    }
+
+    /// Unbinds the keys and sequences bound to the actions `drop` picks.
+    pub fn unbind(&mut self, drop: impl Fn(Action) -> bool) {
+        self.bindings.retain(|(_, action)| !drop(*action));
+        self.sequences.retain(|(_, action)| !drop(*action));
+    }
+
+    pub fn bindings(&self) -> &[(Key, Action)] {
+        &self.bindings
+    }
+
+    pub fn sequences(&self) -> &[(Vec<Key>, Action)] {
+        &self.sequences
+    }
+
+    pub fn action(&self, key: Key) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, action)| *action)
+    }
+
+    /// Every action with the keys bound to it, as `Tab: switch field`,
+    /// moving between the fields, the sessions and the power menu first.
+    /// The maintenance bypass and the admin palette are meant for admins
+    /// only and left out, and so are sequences.
+    pub fn hints(&self) -> Vec<String> {
+        let mut help = self.grouped();
+        help.sort_by_key(|(action, _)| {
+            FIRST_HINTS
+                .iter()
+                .position(|first| first == action)
+                .unwrap_or(FIRST_HINTS.len())
+        });
+        help.iter()
+            .map(|(action, keys)| format!("{}: {}", keys.join("/"), action.description()))
+            .collect()
+    }
+
+    /// The keys bound to every action shown in the hints, as `Tab/Ctrl-N`,
+    /// with the action's description.
+    pub fn help(&self) -> Vec<(String, &'static str)> {
+        self.grouped()
+            .iter()
+            .map(|(action, keys)| (keys.join("/"), action.description()))
+            .collect()
+    }
+
+    /// The keys bound to each action shown in the hints, in the order the
+    /// actions were first bound.
+    fn grouped(&self) -> Vec<(Action, Vec<String>)> {
+        let mut help: Vec<(Action, Vec<String>)> = Vec::new();
+        for (key, action) in &self.bindings {
+            if matches!(action, Action::MaintenanceBypass | Action::AdminPalette) {
+                continue;
+            }
+            match help.iter_mut().find(|(a, _)| a == action) {
+                Some((_, keys)) => keys.push(key.to_string()),
+                None => help.push((*action, vec![key.to_string()])),
+            }
+        }
+        help
+    }
+}
+
+/// Lays `hints` out in at most `max_lines` lines no wider than `width`, as
+/// `measure` has a text's width. Hints that don't fit are dropped, so the
+/// first ones are shown whatever the width.
+pub fn wrap_hints<S: AsRef<str>>(
+    hints: &[S],
+    width: u32,
+    max_lines: usize,
+    measure: impl Fn(&str) -> u32,
+) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for hint in hints {
+        let hint = hint.as_ref();
+        if let Some(line) = lines.last_mut() {
+            let joined = format!("{line}{HINT_SEPARATOR}{hint}");
+            if measure(&joined) <= width {
+                *line = joined;
+                continue;
+            }
+        }
+        if lines.len() < max_lines && measure(hint) <= width {
+            lines.push(hint.to_string());
+        }
+    }
+    lines
+}
+
+/// Recognizes bound key sequences as they are typed, whatever else the keys
+/// do.
+#[derive(Debug, Default)]
+pub struct Sequences {
+    recent: VecDeque<Key>,
+}
+
+impl Sequences {
+    /// Records `key`, returning the action of the sequence it completes, if
+    /// any.
+    pub fn feed(&mut self, keymap: &Keymap, key: Key) -> Option<Action> {
+        let longest = keymap.sequences.iter().map(|(keys, _)| keys.len()).max()?;
+        self.recent.push_back(key);
+        while self.recent.len() > longest {
+            self.recent.pop_front();
+        }
+        let action = keymap
+            .sequences
+            .iter()
+            .find(|(keys, _)| {
+                keys.len() <= self.recent.len()
+                    && keys
+                        .iter()
+                        .rev()
+                        .eq(self.recent.iter().rev().take(keys.len()))
+            })
+            .map(|(_, action)| *action)?;
+        self.recent.clear();
+        Some(action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(text: &str) -> u32 {
+        text.chars().count() as u32
+    }
+
+    #[test]
+    fn hints_list_rebound_keys() {
+        let mut keymap = Keymap::default();
+        keymap.bind_str("F10=power-menu").unwrap();
+        keymap.unbind(|action| action == Action::InvertColors);
+        keymap.bind_str("F5=invert-colors").unwrap();
+        let hints = keymap.hints();
+        assert!(hints.contains(&"Esc/F10: power menu".to_string()));
+        assert!(hints.contains(&"F5: invert colors".to_string()));
+        assert!(!hints.iter().any(|hint| hint.starts_with("F3")));
+    }
+
+    #[test]
+    fn hints_start_with_getting_around() {
+        let hints = Keymap::default().hints();
+        assert_eq!(
+            hints[..3],
+            ["Tab: switch field", "F1: sessions", "Esc: power menu"]
+        );
+    }
+
+    #[test]
+    fn hints_wrap_and_drop_what_doesnt_fit() {
+        let hints = ["aaaa", "bbbb", "cccc", "dd", "eeeeeeeeeeee"];
+        assert_eq!(wrap_hints(&hints, 10, 2, chars), ["aaaa  bbbb", "cccc  dd"]);
+        assert_eq!(wrap_hints(&hints, 100, 1, chars).len(), 1);
+        assert_eq!(wrap_hints(&hints, 3, 2, chars), ["dd"]);
+    }
+}
@@ -0,0 +1,258 @@
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::unix::io::{AsFd, BorrowedFd};
+
+use drm::buffer::{Buffer as _, DrmFourcc};
+use drm::control::{
+    connector, crtc, dumbbuffer::DumbBuffer, framebuffer, property, Device as ControlDevice, Event,
+    Mode, ModeTypeFlags, PageFlipFlags,
+};
+use drm::Device;
+use memmap::{MmapMut, MmapOptions};
+
+use crate::buffer::Vect;
+use crate::format::{ByteOrder, Fourcc};
+use crate::output::{Output, Surface};
+
+// from the DPMS connector property
+const DPMS_ON: u64 = 0;
+const DPMS_OFF: u64 = 3;
+
+struct Card(File);
+
+impl AsFd for Card {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.0.as_fd()
+    }
+}
+
+impl Device for Card {}
+impl ControlDevice for Card {}
+
+/// A dumb buffer scanned out from, mapped for drawing.
+struct Page {
+    buffer: DumbBuffer,
+    fb: framebuffer::Handle,
+    map: MmapMut,
+}
+
+/// The CRTC driving the first connected display and the two pages it flips
+/// between.
+struct Scanout {
+    card: Card,
+    crtc: crtc::Handle,
+    connector: connector::Handle,
+    mode: Mode,
+    size: Vect,
+    pages: Vec<Page>,
+    // which page is on screen
+    shown: usize,
+    dpms: Option<property::Handle>,
+    // the CRTC as it was before, set back when the card is handed over
+    saved: crtc::Info,
+}
+
+/// A DRM card set to its first connected display's preferred mode. Frames are
+/// drawn to memory and copied to the page not shown, which is then flipped
+/// to, as dumb buffers may have padded rows. The CRTC is set back as it was
+/// when dropped, or while another VT is shown.
+pub struct Drm {
+    scanout: Scanout,
+    frame: Vec<u8>,
+}
+
+impl Drm {
+    pub fn open(path: &str) -> io::Result<Self> {
+        let card = Card(OpenOptions::new().read(true).write(true).open(path)?);
+        let resources = card.resource_handles()?;
+        let connector = resources
+            .connectors()
+            .iter()
+            .filter_map(|&handle| card.get_connector(handle, true).ok())
+            .find(|info| info.state() == connector::State::Connected && !info.modes().is_empty())
+            .ok_or_else(|| not_found("no connected display"))?;
+        let mode = preferred_mode(connector.modes());
+        let crtc = connector
+            .current_encoder()
+            .into_iter()
+            .chain(connector.encoders().iter().copied())
+            .filter_map(|handle| card.get_encoder(handle).ok())
+            .find_map(|encoder| {
+                encoder.crtc().or_else(|| {
+                    resources
+                        .filter_crtcs(encoder.possible_crtcs())
+                        .first()
+                        .copied()
+                })
+            })
+            .ok_or_else(|| not_found("no CRTC for the display"))?;
+        let saved = card.get_crtc(crtc)?;
+        let (w, h) = mode.size();
+        let size = (w as u32, h as u32);
+        eprintln!(
+            "DRM mode {}x{}@{} on {}",
+            size.0,
+            size.1,
+            mode.vrefresh(),
+            connector
+        );
+        let pages = (0..2)
+            .map(|_| Page::new(&card, size))
+            .collect::<io::Result<Vec<_>>>()?;
+        card.set_crtc(
+            crtc,
+            Some(pages[0].fb),
+            (0, 0),
+            &[connector.handle()],
+            Some(mode),
+        )?;
+        let dpms = card
+            .get_properties(connector.handle())?
+            .iter()
+            .map(|(&handle, _)| handle)
+            .find(|&handle| {
+                card.get_property(handle)
+                    .is_ok_and(|info| info.name().to_bytes() == b"DPMS")
+            });
+        Ok(Self {
+            scanout: Scanout {
+                card,
+                crtc,
+                connector: connector.handle(),
+                mode,
+                size,
+                pages,
+                shown: 0,
+                dpms,
+                saved,
+            },
+            frame: vec![0; (size.0 * size.1 * 4) as usize],
+        })
+    }
+
+    pub fn surface(&mut self) -> Surface<'_> {
+        let mut format = Fourcc::Xrgb8888.format();
+        format.byte_order = ByteOrder::Little;
+        Surface {
+            buf: &mut self.frame,
+            size: self.scanout.size,
+            // packed, and copied to the page row by row at its pitch
+            stride: self.scanout.size.0 * 4,
+            format,
+            output: Box::new(&mut self.scanout),
+        }
+    }
+}
+
+impl Drop for Drm {
+    fn drop(&mut self) {
+        let scanout = &mut self.scanout;
+        let _ = scanout.restore();
+        let card = &scanout.card;
+        for page in scanout.pages.drain(..) {
+            let _ = card.destroy_framebuffer(page.fb);
+            drop(page.map);
+            let _ = card.destroy_dumb_buffer(page.buffer);
+        }
+    }
+}
+
+impl Page {
+    fn new(card: &Card, size: Vect) -> io::Result<Self> {
+        let buffer = card.create_dumb_buffer(size, DrmFourcc::Xrgb8888, 32)?;
+        let fb = card.add_framebuffer(&buffer, 24, 32)?;
+        let offset = drm_ffi::mode::dumbbuffer::map(card.as_fd(), buffer.handle().into(), 0, 0)?;
+        let len = (buffer.pitch() * size.1) as usize;
+        let map = unsafe {
+            MmapOptions::new()
+                .offset(offset.offset)
+                .len(len)
+                .map_mut(&card.0)?
+        };
+        Ok(Self { buffer, fb, map })
+    }
+}
+
+impl Scanout {
+    /// Sets the CRTC to show the page on screen in the display's mode.
+    fn set(&self) -> io::Result<()> {
+        self.card.set_crtc(
+            self.crtc,
+            Some(self.pages[self.shown].fb),
+            (0, 0),
+            &[self.connector],
+            Some(self.mode),
+        )
+    }
+
+    /// Sets the CRTC back as it was before the card was opened.
+    fn restore(&self) -> io::Result<()> {
+        self.card.set_crtc(
+            self.crtc,
+            self.saved.framebuffer(),
+            self.saved.position(),
+            &[self.connector],
+            self.saved.mode(),
+        )
+    }
+}
+
+impl<'a> Output<'a> for &mut Scanout {
+    fn present(&mut self, buf: &mut &'a mut [u8]) -> io::Result<()> {
+        let back = 1 - self.shown;
+        let page = &mut self.pages[back];
+        let row = (self.size.0 * 4) as usize;
+        let pitch = page.buffer.pitch() as usize;
+        for (src, dst) in buf.chunks_exact(row).zip(page.map.chunks_exact_mut(pitch)) {
+            dst[..row].copy_from_slice(src);
+        }
+        let card = &self.card;
+        card.page_flip(self.crtc, page.fb, PageFlipFlags::EVENT, None)?;
+        // the next frame waits for this one to be shown, not to draw over it
+        loop {
+            if card
+                .receive_events()?
+                .any(|event| matches!(event, Event::PageFlip(_)))
+            {
+                break;
+            }
+        }
+        self.shown = back;
+        Ok(())
+    }
+
+    fn blank(&mut self, blank: bool) -> io::Result<()> {
+        let dpms = self
+            .dpms
+            .ok_or_else(|| io::Error::new(io::ErrorKind::Unsupported, "no DPMS property"))?;
+        let value = if blank { DPMS_OFF } else { DPMS_ON };
+        self.card.set_property(self.connector, dpms, value)
+    }
+
+    fn release(&mut self) -> io::Result<()> {
+        let restored = self.restore();
+        // whatever runs on the other VT becomes master, even if the CRTC
+        // couldn't be set back
+        self.card.release_master_lock()?;
+        restored
+    }
+
+    fn acquire(&mut self) -> io::Result<()> {
+        self.card.acquire_master_lock()?;
+        // the other VT may have changed the mode, so page flips would fail
+        self.set()
+    }
+}
+
+/// The display's preferred mode, or else the first one it lists.
+fn preferred_mode(modes: &[Mode]) -> Mode {
+    modes
+        .iter()
+        .find(|mode| mode.mode_type().contains(ModeTypeFlags::PREFERRED))
+        .unwrap_or(&modes[0])
+        .to_owned()
+}
+
+fn not_found(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, msg)
+}
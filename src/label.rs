@@ -0,0 +1,60 @@
+use crate::buffer::{Buffer, Rect, Vect};
+use crate::color::Color;
+use crate::draw::Font;
+use crate::Error;
+
+/// A field's label drawn ahead of time over the background under it, as
+/// shown with the field unfocused and focused, to be copied into each frame
+/// rather than drawn again.
+pub struct Label {
+    pub text: String,
+    pub pos: Vect,
+    // the field's background and text colors, unfocused and then focused
+    colors: [(Color, Color); 2],
+    rect: Rect,
+    pixels: [Vec<u8>; 2],
+}
+
+impl Label {
+    /// Draws `text` at `pos` of `buf`, which holds the background there, in
+    /// both `colors`, leaving the background as it was.
+    pub fn render(
+        buf: &mut Buffer<'_>,
+        font: &Font,
+        text: &str,
+        pos: Vect,
+        colors: [(Color, Color); 2],
+    ) -> Result<Self, Error> {
+        let bounds = buf.get_bounds();
+        let rect = (
+            pos.0,
+            pos.1,
+            font.text_width(text).min(bounds.2.saturating_sub(pos.0)),
+            (font.size() as u32).min(bounds.3.saturating_sub(pos.1)),
+        );
+        let under = buf.copy_rect(rect)?;
+        let mut pixels = [Vec::new(), Vec::new()];
+        for ((bg, fg), pixels) in colors.iter().zip(pixels.iter_mut()) {
+            font.auto_draw_text(&mut buf.offset(pos)?, bg, fg, text)?;
+            *pixels = buf.copy_rect(rect)?;
+            buf.paste_rect(rect, &under)?;
+        }
+        Ok(Self {
+            text: text.to_string(),
+            pos,
+            colors,
+            rect,
+            pixels,
+        })
+    }
+
+    /// The field's background and text colors.
+    pub fn colors(&self, focused: bool) -> (Color, Color) {
+        self.colors[focused as usize]
+    }
+
+    /// Copies the label into `buf`, the whole screen.
+    pub fn blit(&self, buf: &mut Buffer<'_>, focused: bool) -> Result<(), Error> {
+        Ok(buf.paste_rect(self.rect, &self.pixels[focused as usize])?)
+    }
+}
@@ -0,0 +1,58 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const LEDS_DIR: &str = "/sys/class/leds";
+
+/// A keyboard backlight, turned on while the greeter runs and put back as it
+/// was when dropped.
+pub struct KeyboardBacklight {
+    path: PathBuf,
+    previous: u32,
+}
+
+impl KeyboardBacklight {
+    /// Sets the first `*::kbd_backlight` LED to `percent` of its maximum
+    /// brightness, or returns `None` if there's no keyboard backlight.
+    pub fn activate(percent: u8) -> io::Result<Option<Self>> {
+        let path = match find()? {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let max = read(&path, "max_brightness")?;
+        let previous = read(&path, "brightness")?;
+        let level = (max * percent.min(100) as u32).div_ceil(100);
+        fs::write(path.join("brightness"), level.to_string())?;
+        Ok(Some(Self { path, previous }))
+    }
+}
+
+impl Drop for KeyboardBacklight {
+    fn drop(&mut self) {
+        if let Err(err) = fs::write(self.path.join("brightness"), self.previous.to_string()) {
+            eprintln!(
+                "unable to restore keyboard backlight {}: {err}",
+                self.path.display()
+            );
+        }
+    }
+}
+
+fn find() -> io::Result<Option<PathBuf>> {
+    let mut paths = fs::read_dir(LEDS_DIR)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.file_name()
+                .is_some_and(|name| name.to_string_lossy().ends_with("::kbd_backlight"))
+        })
+        .collect::<Vec<_>>();
+    paths.sort();
+    Ok(paths.into_iter().next())
+}
+
+fn read(led: &Path, attr: &str) -> io::Result<u32> {
+    fs::read_to_string(led.join(attr))?
+        .trim()
+        .parse()
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
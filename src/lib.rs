@@ -0,0 +1,21 @@
+//! The drawing primitives the greeter is built on, for other programs to
+//! draw to the console with: a [`Buffer`] over a [`Surface`] of a
+//! [`Screen`], [`Color`]s, text and boxes drawn with [`Font`] and
+//! [`draw_box`], and images resized with [`scale::resize`].
+#![deny(rust_2018_idioms)]
+
+pub mod buffer;
+pub mod color;
+pub mod draw;
+pub mod fbdev;
+pub mod format;
+#[cfg(feature = "drm")]
+pub mod kms;
+pub mod output;
+pub mod scale;
+
+pub use crate::buffer::{Buffer, BufferError};
+pub use crate::color::Color;
+pub use crate::draw::{draw_box, DrawError, Font};
+pub use crate::fbdev::RefreshStrategy;
+pub use crate::output::{Backend, Output, Screen, ScreenError, Surface};
@@ -0,0 +1,107 @@
+#[cfg(feature = "locales")]
+use std::convert::TryFrom;
+use std::env;
+use std::fmt::Display;
+use std::fs;
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, TimeZone};
+
+// where systemd keeps the system locale, for when greetd doesn't pass it on
+const LOCALE_CONF: &str = "/etc/locale.conf";
+
+/// How dates and times are written: month and day names, and whether the
+/// clock has 12 or 24 hours.
+#[derive(Debug, Clone, Copy)]
+pub struct TimeLocale {
+    #[cfg(feature = "locales")]
+    locale: chrono::Locale,
+}
+
+impl TimeLocale {
+    /// The locale named by `LC_ALL`, `LC_TIME` or `LANG`, the first one set,
+    /// as libc picks it, or else by `/etc/locale.conf`.
+    pub fn from_env() -> Self {
+        let name = ["LC_ALL", "LC_TIME", "LANG"]
+            .iter()
+            .find_map(|var| env::var(var).ok().filter(|value| !value.is_empty()))
+            .or_else(|| {
+                let conf = fs::read_to_string(LOCALE_CONF).ok()?;
+                conf_value(&conf, "LC_TIME").or_else(|| conf_value(&conf, "LANG"))
+            })
+            .unwrap_or_default();
+        Self::named(&name)
+    }
+
+    /// The locale named like `de_DE.UTF-8`, POSIX's if it's unknown.
+    #[cfg(feature = "locales")]
+    pub fn named(name: &str) -> Self {
+        // the codeset and modifier don't change the names
+        let base = name.split(['.', '@']).next().unwrap_or_default();
+        let locale = match base {
+            "" | "C" | "POSIX" => chrono::Locale::POSIX,
+            base => chrono::Locale::try_from(base).unwrap_or_else(|_| {
+                eprintln!("unknown locale {name}, writing dates as in POSIX");
+                chrono::Locale::POSIX
+            }),
+        };
+        Self { locale }
+    }
+
+    #[cfg(not(feature = "locales"))]
+    pub fn named(_name: &str) -> Self {
+        Self {}
+    }
+
+    /// Formats `time` with the strftime `format`, which must be valid.
+    #[cfg(feature = "locales")]
+    pub fn format<Tz: TimeZone>(&self, time: &DateTime<Tz>, format: &str) -> String
+    where
+        Tz::Offset: Display,
+    {
+        time.format_localized(format, self.locale).to_string()
+    }
+
+    #[cfg(not(feature = "locales"))]
+    pub fn format<Tz: TimeZone>(&self, time: &DateTime<Tz>, format: &str) -> String
+    where
+        Tz::Offset: Display,
+    {
+        time.format(format).to_string()
+    }
+
+    /// Whether the locale writes times on a 12 hour clock, going by how it
+    /// writes 13:00.
+    pub fn is_12h(&self) -> bool {
+        let time = chrono::Utc.with_ymd_and_hms(2000, 1, 1, 13, 0, 0).unwrap();
+        !self.format(&time, "%X").contains("13")
+    }
+
+    /// The clock's format when none is given: hours and minutes on the
+    /// locale's clock.
+    pub fn clock_format(&self) -> &'static str {
+        if self.is_12h() {
+            "%I:%M %p"
+        } else {
+            "%H:%M"
+        }
+    }
+}
+
+/// Checks that `format` is a strftime format chrono can write.
+pub fn check_format(format: &str) -> Result<(), String> {
+    if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+        Err(format!("invalid time format {format}"))
+    } else {
+        Ok(())
+    }
+}
+
+/// The value of `key` in a shell style `KEY=value` file.
+fn conf_value(conf: &str, key: &str) -> Option<String> {
+    conf.lines()
+        .filter_map(|line| line.trim().split_once('='))
+        .find(|(k, _)| *k == key)
+        .map(|(_, value)| value.trim_matches('"').to_string())
+        .filter(|value| !value.is_empty())
+}
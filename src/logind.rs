@@ -0,0 +1,106 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::dbus::{Arg, Bus};
+
+const LOGIND: &str = "org.freedesktop.login1";
+const LOGIND_PATH: &str = "/org/freedesktop/login1";
+const LOGIND_MANAGER: &str = "org.freedesktop.login1.Manager";
+
+/// What systemd-logind can do to the machine from the greeter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerAction {
+    PowerOff,
+    Reboot,
+    Suspend,
+}
+
+impl PowerAction {
+    pub const ALL: [PowerAction; 3] = [
+        PowerAction::PowerOff,
+        PowerAction::Reboot,
+        PowerAction::Suspend,
+    ];
+
+    /// The name of logind's method, which its `Can` method is named after.
+    fn method(&self) -> &'static str {
+        match self {
+            PowerAction::PowerOff => "PowerOff",
+            PowerAction::Reboot => "Reboot",
+            PowerAction::Suspend => "Suspend",
+        }
+    }
+
+    /// How the power menu shows it.
+    pub fn label(&self) -> &'static str {
+        match self {
+            PowerAction::PowerOff => "Power off",
+            PowerAction::Reboot => "Reboot",
+            PowerAction::Suspend => "Suspend",
+        }
+    }
+}
+
+impl FromStr for PowerAction {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "power-off" => Ok(PowerAction::PowerOff),
+            "reboot" => Ok(PowerAction::Reboot),
+            "suspend" => Ok(PowerAction::Suspend),
+            _ => Err(format!("unknown power action {s}")),
+        }
+    }
+}
+
+impl fmt::Display for PowerAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PowerAction::PowerOff => write!(f, "power-off"),
+            PowerAction::Reboot => write!(f, "reboot"),
+            PowerAction::Suspend => write!(f, "suspend"),
+        }
+    }
+}
+
+/// The actions logind allows the greeter, possibly after asking polkit,
+/// or none if it can't be reached.
+pub fn available() -> Vec<PowerAction> {
+    let mut bus = match Bus::system() {
+        Ok(bus) => bus,
+        Err(err) => {
+            eprintln!("unable to connect to the system bus: {err}");
+            return Vec::new();
+        }
+    };
+    PowerAction::ALL
+        .iter()
+        .copied()
+        .filter(|action| {
+            let method = format!("Can{}", action.method());
+            match bus.call(LOGIND, LOGIND_PATH, LOGIND_MANAGER, &method, &[]) {
+                Ok(Some(answer)) => answer == "yes" || answer == "challenge",
+                Ok(None) => false,
+                Err(err) => {
+                    eprintln!("unable to ask logind about {action}: {err}");
+                    false
+                }
+            }
+        })
+        .collect()
+}
+
+/// Asks logind to carry out `action`, without letting polkit prompt for a
+/// password, as there is no one to ask it of.
+pub fn request(action: PowerAction) -> Result<(), String> {
+    let mut bus = Bus::system().map_err(|err| format!("Unable to reach logind: {err}"))?;
+    bus.call(
+        LOGIND,
+        LOGIND_PATH,
+        LOGIND_MANAGER,
+        action.method(),
+        &[Arg::Bool(false)],
+    )
+    .map(|_| ())
+    .map_err(|err| format!("Unable to {}: {err}", action.label().to_lowercase()))
+}
@@ -0,0 +1,79 @@
+use std::fs;
+#[cfg(feature = "os-logo")]
+use std::path::Path;
+
+use crate::buffer::Vect;
+use crate::scale::Filter;
+#[cfg(feature = "os-logo")]
+use crate::{image, scale};
+
+const OS_RELEASE_PATHS: &[&str] = &["/etc/os-release", "/usr/lib/os-release"];
+
+#[cfg(feature = "os-logo")]
+const ICON_SIZES: &[u32] = &[256, 128, 96, 64, 48];
+
+/// The icon names the distribution's logo may be installed under, from the
+/// `LOGO` key of os-release, or `<id>-logo` for its `ID` and `ID_LIKE`.
+pub fn detect() -> Vec<String> {
+    let content = match OS_RELEASE_PATHS
+        .iter()
+        .find_map(|path| fs::read_to_string(path).ok())
+    {
+        Some(content) => content,
+        None => return Vec::new(),
+    };
+    let mut logo = None;
+    let mut ids = Vec::new();
+    for line in content.lines() {
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim().trim_matches('"')),
+            None => continue,
+        };
+        match key {
+            "LOGO" => logo = Some(value.to_string()),
+            "ID" => ids.insert(0, value.to_string()),
+            "ID_LIKE" => ids.extend(value.split_whitespace().map(str::to_string)),
+            _ => {}
+        }
+    }
+    logo.into_iter()
+        .chain(ids.iter().map(|id| format!("{id}-logo")))
+        .collect()
+}
+
+/// Loads the first of the `names` icons found in the pixmaps directory or the
+/// hicolor icon theme, as RGBA scaled to fit within `size`. Names may also be
+/// absolute paths, as desktop entries' `Icon` can be.
+#[cfg(feature = "os-logo")]
+pub fn load(names: &[String], size: u32, filter: Filter) -> Option<(Vec<u8>, Vect)> {
+    let path = names.iter().find_map(|name| {
+        if name.starts_with('/') {
+            return fs::metadata(name).is_ok().then(|| name.clone());
+        }
+        std::iter::once(format!("/usr/share/pixmaps/{name}.png"))
+            .chain(
+                ICON_SIZES
+                    .iter()
+                    .map(|s| format!("/usr/share/icons/hicolor/{s}x{s}/apps/{name}.png")),
+            )
+            .find(|path| fs::metadata(path).is_ok())
+    })?;
+    let (pixels, dim) = match image::decode(Path::new(&path)) {
+        Ok(image) => image,
+        Err(err) => {
+            eprintln!("unable to load icon {path}: {err}");
+            return None;
+        }
+    };
+    let ratio = (size as f32 / dim.0.max(dim.1) as f32).min(1.0);
+    let fit = (
+        ((dim.0 as f32 * ratio) as u32).max(1),
+        ((dim.1 as f32 * ratio) as u32).max(1),
+    );
+    Some((scale::resize(&pixels, dim, fit, filter), fit))
+}
+
+#[cfg(not(feature = "os-logo"))]
+pub fn load(_names: &[String], _size: u32, _filter: Filter) -> Option<(Vec<u8>, Vect)> {
+    None
+}
@@ -0,0 +1,99 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use nix::fcntl::{fcntl, FcntlArg, OFlag};
+use nix::unistd::{dup2, pipe2};
+
+use lazy_static::lazy_static;
+
+/// How many of the last lines are kept.
+const MAX_LINES: usize = 500;
+// a line that never ends doesn't get to fill the memory
+const MAX_PENDING: usize = 4096;
+/// How long giving stderr back waits for what's left in the pipe, which
+/// children still holding it keep open.
+const DRAIN_TIMEOUT: Duration = Duration::from_millis(200);
+
+struct Capture {
+    original: RawFd,
+    drained: Receiver<()>,
+}
+
+lazy_static! {
+    static ref LINES: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+    static ref CAPTURE: Mutex<Option<Capture>> = Mutex::new(None);
+}
+static CHANGED: AtomicBool = AtomicBool::new(false);
+
+/// Keeps the last lines the greeter and its children write to stderr, for
+/// the log viewer, passing them on to where stderr went before.
+pub fn capture() -> io::Result<()> {
+    let (read, write) = pipe2(OFlag::O_CLOEXEC)?;
+    let original = fcntl(2, FcntlArg::F_DUPFD_CLOEXEC(3))?;
+    dup2(write, 2)?;
+    let _ = nix::unistd::close(write);
+    // SAFETY: both are fresh descriptors owned by nothing else
+    let (mut pipe, mut out) = unsafe { (File::from_raw_fd(read), File::from_raw_fd(original)) };
+    let (done, drained) = mpsc::channel();
+    thread::spawn(move || {
+        let mut pending = Vec::new();
+        let mut chunk = [0u8; 4096];
+        while let Ok(n @ 1..) = pipe.read(&mut chunk) {
+            let _ = out.write_all(&chunk[..n]);
+            pending.extend_from_slice(&chunk[..n]);
+            let mut lines = LINES.lock().unwrap_or_else(|err| err.into_inner());
+            while let Some(end) = pending.iter().position(|b| *b == b'\n') {
+                let line = pending.drain(..=end).collect::<Vec<_>>();
+                if lines.len() == MAX_LINES {
+                    lines.pop_front();
+                }
+                lines.push_back(String::from_utf8_lossy(&line).trim_end().to_string());
+            }
+            if pending.len() > MAX_PENDING {
+                pending.clear();
+            }
+            CHANGED.store(true, Ordering::Relaxed);
+        }
+        let _ = done.send(());
+    });
+    *CAPTURE.lock().unwrap_or_else(|err| err.into_inner()) = Some(Capture { original, drained });
+    Ok(())
+}
+
+/// Whether stderr is being kept for the log viewer.
+pub fn is_capturing() -> bool {
+    CAPTURE.try_lock().map_or(true, |capture| capture.is_some())
+}
+
+/// The lines kept so far, oldest first.
+pub fn lines() -> Vec<String> {
+    let lines = LINES.lock().unwrap_or_else(|err| err.into_inner());
+    lines.iter().cloned().collect()
+}
+
+/// Whether lines came in since last asked.
+pub fn take_changed() -> bool {
+    CHANGED.swap(false, Ordering::Relaxed)
+}
+
+/// Points stderr back to where it went before capturing, once what's still
+/// in the pipe was passed on. Safe to call any number of times.
+pub fn release() {
+    // try_lock, as this runs from the panic hook
+    let capture = match CAPTURE.try_lock() {
+        Ok(mut capture) => capture.take(),
+        Err(_) => return,
+    };
+    if let Some(capture) = capture {
+        // closes the pipe's end on stderr, ending it unless a child has it
+        let _ = dup2(capture.original, 2);
+        let _ = capture.drained.recv_timeout(DRAIN_TIMEOUT);
+    }
+}
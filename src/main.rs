@@ -1,19 +1,70 @@
 #![deny(rust_2018_idioms)]
 
 use std::fs;
+use std::path::Path;
 use std::str::FromStr;
+use std::time::Duration;
 
-use framebuffer::{Framebuffer, KdMode};
-use termion::raw::IntoRawMode;
+use framebuffer::KdMode;
 use thiserror::Error;
 
-use crate::{color::Color, draw::Font, manager::LoginManager};
+use ndlm::{buffer, color, draw, fbdev, format, output, scale};
 
-mod buffer;
-mod color;
-mod draw;
+use crate::{
+    color::Color, config::Config, console::Console, draw::FocusStyle, draw::Font, exit::Failure,
+    field::PasswordField, manager::LoginManager, output::Screen, output::Surface,
+};
+
+mod accessx;
+mod activity;
+mod admin;
+mod assets;
+mod background;
+mod cells;
+mod confetti;
+mod config;
+mod console;
+mod dbus;
+mod dropins;
+mod dump;
+mod editor;
+mod env;
+mod evdev;
+mod exit;
+mod field;
+mod fonttest;
 mod greetd;
+mod hook;
+mod image;
+mod input;
+mod keys;
+mod label;
+mod leds;
+mod locale;
+mod logind;
+mod logo;
+mod logtail;
+mod maintenance;
 mod manager;
+mod metrics;
+mod notify;
+mod otp;
+mod policy;
+mod power;
+mod prerender;
+mod schedule;
+mod screenshot;
+mod secret;
+mod service;
+mod sessions;
+mod signals;
+mod state;
+mod syslog;
+mod unlock;
+mod variant;
+mod widget;
+#[cfg(feature = "xkb")]
+mod xkb;
 
 #[derive(Error, Debug)]
 #[non_exhaustive]
@@ -37,46 +88,201 @@ struct Module {
     title_vertical_alignment: f32,
     watermark_horizontal_alignment: f32,
     watermark_vertical_alignment: f32,
+    clock_horizontal_alignment: Option<f32>,
+    clock_vertical_alignment: Option<f32>,
     horizontal_alignment: f32,
     vertical_alignment: f32,
     background_start_color: Color,
     background_end_color: Color,
+    text_color: Option<Color>,
+    focus_color: Option<Color>,
+    focus_style: Option<FocusStyle>,
+    dark_text_color: Option<Color>,
+    light_text_color: Option<Color>,
+    info_color: Option<Color>,
+    error_color: Option<Color>,
+    info_icon: Option<String>,
+    error_icon: Option<String>,
+    password_field: PasswordField,
 }
 
-impl FromStr for Module {
-    type Err = Error;
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut module = Module::default();
+impl Module {
+    /// Sets a theme key, warning about values it can't take rather than
+    /// leaving the greeter unable to start.
+    fn set(&mut self, key: &str, value: &str) {
+        let res = match key {
+            "Font" => value.parse().map(|font| self.font = font),
+            "TitleFont" => value.parse().map(|font| self.title_font = font),
+            "ImageDir" => {
+                self.image_dir = value.to_string();
+                Ok(())
+            }
+            "DialogHorizontalAlignment" => {
+                alignment(value).map(|v| self.dialog_horizontal_alignment = v)
+            }
+            "DialogVerticalAlignment" => {
+                alignment(value).map(|v| self.dialog_vertical_alignment = v)
+            }
+            "TitleHorizontalAlignment" => {
+                alignment(value).map(|v| self.title_horizontal_alignment = v)
+            }
+            "TitleVerticalAlignment" => alignment(value).map(|v| self.title_vertical_alignment = v),
+            "HorizontalAlignment" => alignment(value).map(|v| self.horizontal_alignment = v),
+            "VerticalAlignment" => alignment(value).map(|v| self.vertical_alignment = v),
+            "WatermarkHorizontalAlignment" => {
+                alignment(value).map(|v| self.watermark_horizontal_alignment = v)
+            }
+            "WatermarkVerticalAlignment" => {
+                alignment(value).map(|v| self.watermark_vertical_alignment = v)
+            }
+            "ClockHorizontalAlignment" => {
+                alignment(value).map(|v| self.clock_horizontal_alignment = Some(v))
+            }
+            "ClockVerticalAlignment" => {
+                alignment(value).map(|v| self.clock_vertical_alignment = Some(v))
+            }
+            // a theme setting only the start color gets a flat background,
+            // rather than a gradient to the end color of the theme it's
+            // layered on
+            "BackgroundStartColor" => value.parse().map(|c| {
+                self.background_start_color = c;
+                self.background_end_color = c;
+            }),
+            "BackgroundEndColor" => value.parse().map(|c| self.background_end_color = c),
+            "TextColor" => value.parse().map(|c| self.text_color = Some(c)),
+            "FocusColor" => value.parse().map(|c| self.focus_color = Some(c)),
+            "DarkTextColor" => value.parse().map(|c| self.dark_text_color = Some(c)),
+            "LightTextColor" => value.parse().map(|c| self.light_text_color = Some(c)),
+            "InfoColor" => value.parse().map(|c| self.info_color = Some(c)),
+            "ErrorColor" => value.parse().map(|c| self.error_color = Some(c)),
+            "MaskChar" => {
+                let mut chars = value.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => {
+                        self.password_field.glyph = c;
+                        Ok(())
+                    }
+                    _ => Err(format!("expected a single character, got {value}")),
+                }
+            }
+            "MaskSpacing" => value
+                .parse()
+                .map(|px| self.password_field.spacing = px)
+                .map_err(|_| format!("expected a number of pixels, got {value}")),
+            "MaskEcho" => value
+                .parse()
+                .map(|ms| {
+                    self.password_field.echo = (ms > 0).then(|| Duration::from_millis(ms));
+                })
+                .map_err(|_| format!("expected a number of milliseconds, got {value}")),
+            "InfoIcon" => {
+                self.info_icon = Some(value.to_string()).filter(|icon| !icon.is_empty());
+                Ok(())
+            }
+            "ErrorIcon" => {
+                self.error_icon = Some(value.to_string()).filter(|icon| !icon.is_empty());
+                Ok(())
+            }
+            "FocusStyle" => value.parse().map(|style| self.focus_style = Some(style)),
+            _ => Ok(()),
+        };
+        if let Err(err) = res {
+            eprintln!("theme key {key}: {err}");
+        }
+    }
+
+    /// Color of the form's labels and fields, white by default.
+    fn text_color(&self) -> Color {
+        self.text_color.unwrap_or(Color::WHITE)
+    }
+
+    /// Color of the focused field and the selected entry of a list, yellow
+    /// by default.
+    fn focus_color(&self) -> Color {
+        self.focus_color.unwrap_or(Color::YELLOW)
+    }
+
+    /// Color of informational messages, white by default.
+    fn info_color(&self) -> Color {
+        self.info_color.unwrap_or(Color::WHITE)
+    }
+
+    /// Color of errors, red by default.
+    fn error_color(&self) -> Color {
+        self.error_color.unwrap_or(Color::RED)
+    }
+
+    /// Applies the keys of `section` on top of this module, or, with `None`,
+    /// the keys outside of the `[dark]` and `[light]` variant sections.
+    fn apply(&mut self, s: &str, section: Option<&str>) {
+        let mut current = None;
         for l in s.lines() {
-            if l.contains("=") {
+            if let Some(name) = l.trim().strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                current = match name {
+                    "dark" | "light" => Some(name),
+                    _ => None,
+                };
+            } else if l.contains("=") && current == section {
                 let (key, value) = match &l.split("=").collect::<Vec<&str>>()[..] {
                     &[first, second, ..] => (first, second),
                     _ => unreachable!(),
                 };
-                let mut v = 0f32;
-                if value.starts_with(".") {
-                    v = format!("0{}", value).parse().unwrap();
-                }
-                match key {
-                    "Font" => module.font = value.to_string().parse().unwrap(),
-                    "TitleFont" => module.title_font = value.to_string().parse().unwrap(),
-                    "ImageDir" => module.image_dir = value.to_string(),
-                    "DialogHorizontalAlignment" => module.dialog_horizontal_alignment = v,
-                    "DialogVerticalAlignment" => module.dialog_vertical_alignment = v,
-                    "TitleHorizontalAlignment" => module.title_horizontal_alignment = v,
-                    "TitleVerticalAlignment" => module.title_vertical_alignment = v,
-                    "HorizontalAlignment" => module.horizontal_alignment = v,
-                    "VerticalAlignment" => module.vertical_alignment = v,
-                    "WatermarkHorizontalAlignment" => module.watermark_horizontal_alignment = v,
-                    "WatermarkVerticalAlignment" => module.watermark_vertical_alignment = v,
-                    "BackgroundStartColor" => {
-                        module.background_start_color = value.parse().unwrap()
-                    }
-                    "BackgroundEndColor" => module.background_end_color = value.parse().unwrap(),
-                    _ => {}
+                self.set(key, value);
+            }
+        }
+    }
+}
+
+/// Parses an alignment from 0 to 1, as plymouth writes them, like `.5`.
+fn alignment(value: &str) -> Result<f32, String> {
+    match format!("0{value}").parse() {
+        Ok(v) if (0.0..=1.0).contains(&v) => Ok(v),
+        _ => Err(format!("expected an alignment from 0 to 1, got {value}")),
+    }
+}
+
+impl Module {
+    /// Warns about text that would be hard to read on the background and, if
+    /// `fix`, adjusts the theme's colors until it isn't.
+    fn check_contrast(&mut self, variant: &str, fix: bool) {
+        let texts = [
+            ("TextColor", self.text_color()),
+            ("FocusColor", self.focus_color()),
+            ("ErrorColor", self.error_color()),
+        ];
+        let backgrounds = [
+            ("BackgroundStartColor", &mut self.background_start_color),
+            ("BackgroundEndColor", &mut self.background_end_color),
+        ];
+        for (key, bg) in backgrounds {
+            for (name, text) in texts.iter() {
+                let contrast = text.contrast(bg);
+                if contrast < color::MIN_CONTRAST {
+                    eprintln!("{variant} theme: {name} has a contrast of {contrast:.1} on {key}");
                 }
             }
+            if fix {
+                let colors = texts.iter().map(|(_, c)| *c).collect::<Vec<_>>();
+                *bg = bg.with_contrast(&colors, color::MIN_CONTRAST);
+            }
+        }
+        if !fix {
+            return;
         }
+        // the text colors themselves, for what the backgrounds couldn't fix
+        let backgrounds = [self.background_start_color, self.background_end_color];
+        let fixed = |c: Color| c.with_contrast(&backgrounds, color::MIN_CONTRAST);
+        self.text_color = Some(fixed(self.text_color()));
+        self.focus_color = Some(fixed(self.focus_color()));
+        self.error_color = Some(fixed(self.error_color()));
+    }
+}
+
+impl FromStr for Module {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut module = Module::default();
+        module.apply(s, None);
         Ok(module)
     }
 }
@@ -86,6 +292,8 @@ struct Theme {
     name: String,
     description: Option<String>,
     module: Module,
+    dark: Module,
+    light: Module,
 }
 
 impl FromStr for Theme {
@@ -106,60 +314,238 @@ impl FromStr for Theme {
                 }
             }
         }
+        theme.dark = theme.module.clone();
+        theme.dark.apply(s, Some("dark"));
+        theme.light = theme.module.clone();
+        theme.light.apply(s, Some("light"));
         Ok(theme)
     }
 }
 
-#[derive(Default, Clone)]
-struct Config {
-    session: Vec<String>,
-    theme: Theme,
+impl Theme {
+    /// Checks the variants, as the module is replaced by one of them.
+    fn check_contrast(&mut self, fix: bool) {
+        self.dark.check_contrast("dark", fix);
+        self.light.check_contrast("light", fix);
+    }
+
+    /// Draws with the dark or the light variant from now on.
+    fn select(&mut self, dark: bool) {
+        self.module = if dark {
+            self.dark.clone()
+        } else {
+            self.light.clone()
+        };
+    }
 }
 
-fn parse_theme(theme_file: String) -> Theme {
-    let content = fs::read_to_string(theme_file).expect("Unable to read theme file");
+/// The built in theme, which every other theme is layered on, so keys a
+/// theme leaves out still have sensible values.
+static DEFAULT_THEME: &str = include_str!("../themes/default.plymouth");
+
+/// What `--preview` draws at without `--resolution`.
+const PREVIEW_RESOLUTION: (u32, u32) = (1920, 1080);
+
+/// Where themes are installed, each as `<name>/theme.conf`.
+const THEME_GALLERY: &str = "/usr/share/ddlm/themes";
+const DEFAULT_GALLERY_THEME: &str = "default";
+/// Site wide tweaks layered on the gallery theme.
+const SITE_THEME: &str = "/etc/ddlm/theme.conf";
+
+/// The built in theme with the gallery theme `name`, the site theme and then
+/// `theme_file` layered on top, later keys winning. A theme file that can't
+/// be read is skipped rather than leaving the greeter unthemed.
+fn parse_theme(name: Option<&str>, theme_file: Option<&str>) -> Theme {
+    let mut content = DEFAULT_THEME.to_string();
+    let mut layer = |path: &str, required: bool| match fs::read_to_string(path) {
+        Ok(layer) => {
+            // the header ends a variant section the previous layer left open
+            content.push_str("\n[layer]\n");
+            let dir = Path::new(path).parent().unwrap_or(Path::new("/"));
+            content.push_str(&resolve_theme_paths(&layer, dir));
+        }
+        Err(err) if !required && err.kind() == std::io::ErrorKind::NotFound => {}
+        Err(err) => eprintln!("Unable to read theme file {path}: {err}"),
+    };
+    let gallery = format!(
+        "{THEME_GALLERY}/{}/theme.conf",
+        name.unwrap_or(DEFAULT_GALLERY_THEME)
+    );
+    layer(&gallery, name.is_some());
+    layer(SITE_THEME, false);
+    if let Some(path) = theme_file {
+        layer(path, true);
+    }
     content.parse().unwrap()
 }
 
-fn parse_args() -> Config {
-    let mut args = std::env::args().skip(1); // skip program name
-    let mut config = Config::default();
-
-    while let Some(arg) = args.next() {
-        match arg.as_str() {
-            "--session" => {
-                if let Some(value) = args.next() {
-                    config.session = value.split(" ").map(|s| s.to_string()).collect();
-                } else {
-                    eprintln!("Expected a value after --session");
-                }
-            }
-            "--theme-file" => {
-                if let Some(value) = args.next() {
-                    config.theme = parse_theme(value);
-                } else {
-                    eprintln!("Expected a value after --theme-file");
-                }
-            }
-            _ if arg.starts_with("--") => {
-                eprintln!("Unknown flag: {}", arg);
-            }
-            _ => {
-                println!("unknown arg {arg}");
+/// Makes relative asset paths in `theme` relative to `dir`, the directory of
+/// the theme file, as its layers are parsed together.
+fn resolve_theme_paths(theme: &str, dir: &Path) -> String {
+    let mut resolved = String::with_capacity(theme.len());
+    for line in theme.lines() {
+        match line.split_once('=') {
+            Some(("ImageDir", value)) if Path::new(value).is_relative() => {
+                resolved.push_str(&format!("ImageDir={}", dir.join(value).display()));
             }
+            _ => resolved.push_str(line),
         }
+        resolved.push('\n');
     }
-
-    config
+    resolved
 }
 
 fn main() {
-    let mut framebuffer = Framebuffer::new("/dev/fb0").expect("unable to open framebuffer device");
-    let raw = std::io::stdout()
-        .into_raw_mode()
-        .expect("unable to enter raw mode");
-    Framebuffer::set_kd_mode(KdMode::Graphics).expect("unable to enter graphics mode");
-    LoginManager::new(&mut framebuffer, parse_args()).start();
-    Framebuffer::set_kd_mode(KdMode::Text).expect("unable to leave graphics mode");
-    drop(raw);
+    console::suppress_echo();
+    console::install_panic_hook();
+    signals::install();
+    let mut config = config::parse_args();
+    if config.log_viewer && !config.print_config {
+        if let Err(err) = logtail::capture() {
+            eprintln!("unable to keep the log for the log viewer: {err}");
+        }
+    }
+    if config.print_config {
+        config.load_theme();
+        console::restore_terminal();
+        print!("{}", dump::config_toml(&config));
+        return;
+    }
+    if let Some(path) = config.preview.clone() {
+        load_theme(&mut config);
+        console::restore_terminal();
+        let mut screen = Screen::memory(config.resolution.unwrap_or(PREVIEW_RESOLUTION));
+        let surface = screen.surface(config.byte_order, false);
+        match login_manager(surface, None, config).preview(&path) {
+            Ok(()) => println!("Saved preview to {path}"),
+            Err(err) => exit::fail(Failure::Other, format!("unable to save preview: {err}")),
+        }
+        return;
+    }
+    let mut screen = match Screen::open(
+        config.backend,
+        config.fb_device(),
+        config.drm_device(),
+        config.refresh_strategy,
+    ) {
+        Ok(screen) => screen,
+        Err(err) => exit::fail(Failure::Framebuffer, err),
+    };
+    let frame_cache = config.frame_cache();
+    let greeting = !config.prerender && config.theme_edit.is_none() && !config.font_test;
+    if let (true, Some(fb)) = (greeting, screen.framebuffer()) {
+        // before anything slower, fonts included
+        prerender::show(fb, &frame_cache);
+    }
+    load_theme(&mut config);
+    let mut console = match Console::open(config.tty.as_deref()) {
+        Ok(console) => console,
+        Err(err) => exit::fail(Failure::Console, format!("unable to open console: {err}")),
+    };
+    let byte_order = config.byte_order;
+    if config.prerender {
+        if screen.framebuffer().is_none() {
+            exit::fail(Failure::Config, "prerendering needs the fbdev backend");
+        }
+        login_manager(screen.surface(byte_order, false), Some(&console), config).prerender();
+        let fb = screen.framebuffer().expect("checked above");
+        if let Err(err) = prerender::save(fb, frame_cache) {
+            exit::fail(Failure::Other, format!("unable to save frame: {err}"));
+        }
+        return;
+    }
+    if let Err(err) = console.enter_raw_mode() {
+        exit::fail(Failure::Console, format!("unable to enter raw mode: {err}"));
+    }
+    if let Err(err) = console.grab() {
+        eprintln!("unable to grab the terminal: {err}");
+    }
+    if let Err(err) = console.hide_cursor() {
+        eprintln!("unable to hide cursor: {err}");
+    }
+    if let Some(level) = config.console_loglevel {
+        if let Err(err) = console.set_loglevel(level) {
+            eprintln!("unable to set the console log level: {err}");
+        }
+    }
+    if let Err(err) = console.set_kd_mode(KdMode::Graphics) {
+        exit::fail(
+            Failure::Console,
+            format!("unable to enter graphics mode: {err}"),
+        );
+    }
+    if let Some(path) = config.theme_edit.clone() {
+        let surface = screen.surface(byte_order, true);
+        let res = login_manager(surface, Some(&console), config).edit_theme(&path);
+        console.restore();
+        exit_if_terminated(screen);
+        match res {
+            Ok(true) => println!("Saved theme to {path}"),
+            Ok(false) => {}
+            Err(err) => exit::fail(Failure::Other, format!("unable to save theme: {err}")),
+        }
+        return;
+    }
+    if config.font_test {
+        let dir = config.font_test_dir.clone();
+        let surface = screen.surface(byte_order, true);
+        let res = login_manager(surface, Some(&console), config).font_test(dir.as_deref());
+        console.restore();
+        exit_if_terminated(screen);
+        match res {
+            Ok(saved) => saved.iter().for_each(|path| println!("Saved {path}")),
+            Err(err) => exit::fail(Failure::Other, format!("unable to save font test: {err}")),
+        }
+        return;
+    }
+    if console.vt().is_some() {
+        if let Err(err) = console.control_vt_switches() {
+            eprintln!("unable to control VT switches: {err}");
+        }
+    }
+    let logged_in = login_manager(screen.surface(byte_order, true), Some(&console), config).start();
+    console.restore();
+    exit_if_terminated(screen);
+    if !logged_in {
+        exit::fail(Failure::Cancelled, "login cancelled");
+    }
+}
+
+/// The greeter for `surface`, failing if the console's input can't be opened.
+fn login_manager<'a>(
+    surface: Surface<'a>,
+    console: Option<&Console>,
+    config: Config,
+) -> LoginManager<'a> {
+    LoginManager::new(surface, console, config).unwrap_or_else(|err| {
+        exit::fail(
+            Failure::Console,
+            format!("unable to open console input: {err}"),
+        )
+    })
+}
+
+/// Loads the theme and everything it refers to, failing if any of it is
+/// missing.
+fn load_theme(config: &mut Config) {
+    config.load_theme();
+    let missing = assets::preload(&config.theme);
+    if !missing.is_empty() {
+        exit::fail(
+            Failure::Config,
+            format!("missing theme assets:\n{}", missing.join("\n")),
+        );
+    }
+}
+
+/// Exits as cancelled if a signal asked to, once `screen` is given back, as
+/// exiting skips dropping it.
+fn exit_if_terminated(screen: Screen) {
+    drop(screen);
+    if let Some(signal) = signals::terminated() {
+        exit::fail(
+            Failure::Cancelled,
+            format!("terminated by {}", signal.as_str()),
+        );
+    }
 }
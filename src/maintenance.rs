@@ -0,0 +1,23 @@
+use std::fs;
+
+/// While this file exists its contents are shown as a banner, and logins
+/// can be disabled with `--maintenance-lock`.
+pub const MAINTENANCE_FILE: &str = "/etc/ddlm/maintenance";
+
+const DEFAULT_NOTICE: &str = "This system is under maintenance";
+
+/// The notice to show, if the flag file at `path` exists, on a single line.
+pub fn notice(path: &str) -> Option<String> {
+    let content = fs::read_to_string(path).ok()?;
+    let notice = content
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    if notice.is_empty() {
+        Some(DEFAULT_NOTICE.to_string())
+    } else {
+        Some(notice)
+    }
+}
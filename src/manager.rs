@@ -1,221 +1,2807 @@
-use std::io::{Read, StdinLock};
-use std::{fs, io::Bytes};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::hash::Hasher;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use crate::color::Color;
-use framebuffer::{Framebuffer, KdMode, VarScreeninfo};
+use crate::confetti;
 
-use crate::{buffer, greetd, Config, Error};
+use crate::accessx::AccessX;
+use crate::activity::{Activity, INPUT_DIR};
+use crate::admin::{self, AdminAction, Palette};
+use crate::buffer::{Damage, Rect, Vect};
+use crate::cells::{fit, BitmapFont};
+use crate::draw::{draw_arrow, draw_box, Backdrop, FocusStyle, Font, MaskStyle};
+use crate::editor::{self, Outcome};
+use crate::env::{SessionEnv, ENV_DIR};
+use crate::evdev::Keyboards;
+use crate::field::PasswordField;
+use crate::fonttest;
+use crate::format::PixelFormat;
+use crate::keys::{self, Action, Key, Sequences};
+use crate::label::Label;
+use crate::leds::KeyboardBacklight;
+use crate::locale::TimeLocale;
+use crate::logind::{self, PowerAction};
+use crate::logo;
+use crate::logtail;
+use crate::maintenance::{self, MAINTENANCE_FILE};
+use crate::metrics::Metrics;
+use crate::notify::{Notifier, Toasts};
+use crate::otp::{self, Hints};
+use crate::output::{Output, Surface};
+use crate::policy;
+use crate::screenshot;
+use crate::secret::Secret;
+use crate::service::{self, Request, Service};
+use crate::sessions::{self, Session, SessionKind};
+use crate::signals::{self, VtRequest};
+use crate::state::{self, StateStore, UserSessions};
+use crate::syslog::Syslog;
+use crate::widget::{Clock, Hostname, Logo, Schedule, StatusBadge, Widget};
+#[cfg(feature = "xkb")]
+use crate::xkb::Xkb;
+use crate::{
+    background::Background,
+    buffer,
+    console::{self, Console},
+    exit,
+    exit::Failure,
+    greetd, hook, input,
+    input::Input,
+    unlock, Config, Error,
+};
 const USERNAME_CAP: usize = 64;
 const PASSWORD_CAP: usize = 64;
 
-const LAST_USER_USERNAME: &str = "/var/cache/ndlm/lastuser";
+// what --preview fills the form with
+const PREVIEW_USER: &str = "alice";
+const PREVIEW_FAILURE: &str = "Login failed: Authentication failure";
 
-// from linux/fb.h
-const FB_ACTIVATE_NOW: u32 = 0;
-const FB_ACTIVATE_FORCE: u32 = 128;
+const UNEXPECTED_EXIT: Duration = Duration::from_secs(30);
+
+// how often the maintenance flag file is checked
+const MAINTENANCE_POLL: Duration = Duration::from_secs(30);
+
+// in power saver mode widgets are redrawn at most this often, and the screen
+// is blanked after a third of the usual time or this if blanking is disabled
+const SAVER_WIDGET_INTERVAL: Duration = Duration::from_secs(300);
+const SAVER_BLANK_AFTER: Duration = Duration::from_secs(60);
+
+// the cell based form is at most this many cells wide
+const CELL_FORM_WIDTH: usize = 32;
+
+// the logo is scaled down to fit this, or a sixth of the screen height
+const LOGO_SIZE: u32 = 96;
+const LOGO_MARGIN: u32 = 16;
+// session icons fit the prompt's rows at the usual font sizes
+const SESSION_ICON_SIZE: u32 = 16;
+
+const HELP_PADDING: u32 = 20;
+
+// between the rows of the prompt, on top of the font size
+const ROW_GAP: u32 = 4;
+
+const TOAST_PADDING: u32 = 6;
+
+// the hint bar wraps onto this many lines before dropping hints
+const HINT_LINES: usize = 2;
+
+// the most recent transcript lines shown
+const TRANSCRIPT_LINES: usize = 6;
+
+// how often the log viewer looks for new lines while it's open
+const LOG_POLL: Duration = Duration::from_secs(1);
+
+// how often Caps Lock is looked at while the console, which doesn't say when
+// it's pressed, is read
+const CAPS_LOCK_POLL: Duration = Duration::from_millis(250);
+
+// how many rows of the screen are compared by the integrity check
+const INTEGRITY_ROWS: u32 = 16;
+
+const FADE_OUT: Duration = Duration::from_millis(250);
+const FADE_STEPS: u32 = 8;
+
+const FOCUS_ANIMATION: Duration = Duration::from_millis(200);
+const ANIMATION_FRAME: Duration = Duration::from_millis(30);
+
+// the dots mask style: how many, how often the lit one moves and for how
+// long after a keystroke
+const MASK_DOTS: usize = 4;
+const MASK_DOT_STEP: Duration = Duration::from_millis(150);
+const MASK_ANIMATION: Duration = Duration::from_secs(1);
 
 #[derive(PartialEq, Copy, Clone)]
 enum Mode {
     EditingUsername,
     EditingPassword,
+    SelectingSession,
 }
 
 pub struct LoginManager<'a> {
+    // the frame drawn to, shown by the output
     buf: &'a mut [u8],
-    device: &'a fs::File,
+    output: Box<dyn Output<'a> + 'a>,
     screen_size: (u32, u32),
+    // bytes from one row of `buf` to the next
+    stride: u32,
+    format: PixelFormat,
     mode: Mode,
-    greetd: greetd::GreetD,
+    drawn_mode: Option<Mode>,
+    focus_changed: Instant,
+    greetd: Option<greetd::GreetD>,
+    auth_timeout: Option<Duration>,
     config: Config,
-    var_screen_info: &'a VarScreeninfo,
+    session_env: SessionEnv,
+    sessions: Vec<Session>,
+    // the sessions' icons, scaled for the prompt
+    session_icons: Vec<Option<(Vec<u8>, Vect)>>,
+    session: usize,
+    // whether the session was chosen by hand, not to be replaced by the one
+    // the user last logged in to
+    session_picked: bool,
+    state: Box<dyn StateStore>,
+    user_sessions: UserSessions,
+    otp_hints: Hints,
+    // the hints' images by path, scaled for the prompt
+    otp_images: HashMap<String, Option<(Vec<u8>, Vect)>>,
+    syslog: Option<Syslog>,
+    metrics: Option<Metrics>,
+    notifier: Option<Notifier>,
+    service: Option<Service>,
+    // from the notification pipe and greetd's info messages
+    toasts: Toasts,
+    // put back as it was when dropped
+    kbd_backlight: Option<KeyboardBacklight>,
+    background: Background,
+    // the background as drawn to the whole screen, copied back under
+    // whatever is redrawn instead of drawing it again
+    background_layer: Option<Vec<u8>>,
+    // what was drawn over that layer since it was last copied back, to copy
+    // back only there
+    drawn: Damage,
+    // the fields' labels and what each widget drew last, over that layer,
+    // copied into frames until they change
+    labels: Vec<Label>,
+    widget_frames: Vec<Option<Vec<u8>>>,
+    widgets: Vec<Box<dyn Widget>>,
+    should_redraw: bool,
     should_refresh: bool,
-    stdin_bytes: Bytes<StdinLock<'static>>,
+    last_draw: Instant,
+    last_widget_tick: Instant,
+    activity: Activity,
+    // sampled rows of the screen as last drawn, for --integrity-check
+    frame_checksum: Option<u64>,
+    integrity_checked: Instant,
+    power_saver: bool,
+    blanked: bool,
+    // false while another VT is shown, not to be drawn over
+    vt_active: bool,
+    dark: bool,
+    input: Input,
+    sequences: Sequences,
+    confetti: Option<Instant>,
     username: String,
-    password: String,
+    password: Secret,
+    password_typed: Instant,
+    // when the last character of the password was typed, to echo it
+    password_echo: Option<Instant>,
+    // asking for the unlock command's secret in the password field, holding
+    // a password typed before it was asked for
+    unlocking: bool,
+    held_answer: Option<Secret>,
+    // who the unlock command last succeeded for, not to be asked again
+    unlocked: Option<String>,
+    // the first secret answered, kept for --after-auth-password
+    auth_secret: Option<Secret>,
+    // whether anything was typed since starting over, for --clear-after
+    entered: bool,
+    // the conversation with greetd: what was said, shown with --transcript,
+    // and the question waiting for an answer
+    transcript: Vec<(Color, String)>,
+    prompt: Option<greetd::Prompt>,
+    drawn_mask: String,
+    message: Option<String>,
+    // a failed login, told once the failure floor is over
+    failure: Option<(Instant, String)>,
+    banner: Option<String>,
+    maintenance: Option<String>,
+    maintenance_checked: Option<Instant>,
+    maintenance_bypassed: bool,
+    // what logind allows, and which one is picked, while the menu is open
+    power_menu: Option<(Vec<PowerAction>, usize)>,
+    admin: Option<Palette>,
+    // how many lines the log viewer is scrolled back, while it's open
+    log_view: Option<usize>,
+    // the users who logged in last, most recent first
+    recent_users: Vec<String>,
+    // which of them is picked, while the username field offers them
+    recent: Option<usize>,
+    // as last drawn, the lock and the XKB layout if several are set up
+    caps_lock: bool,
+    layout: Option<String>,
+    // the key help shown until dismissed on the first run
+    show_help: bool,
     should_quit: bool,
+    logged_in: bool,
+    editing_theme: bool,
+    cells: Option<BitmapFont>,
 }
 
 impl<'a> LoginManager<'a> {
-    pub fn new(fb: &'a mut Framebuffer, config: Config) -> Self {
-        Self {
-            buf: &mut fb.frame,
-            device: &fb.device,
-            screen_size: (fb.var_screen_info.xres, fb.var_screen_info.yres),
+    /// Sets up the greeter to draw to `surface`, reading keys from `console`,
+    /// or from nowhere without one, failing if that input can't be opened.
+    pub fn new(
+        surface: Surface<'a>,
+        console: Option<&Console>,
+        mut config: Config,
+    ) -> Result<Self, Error> {
+        let dark = config.variant.is_dark(config.dark_hours);
+        config.theme.select(dark);
+        if config.log_viewer && config.keymap.action(Key::F(12)).is_none() {
+            config.keymap.bind(Key::F(12), Action::LogViewer);
+        }
+        if config.no_power_menu {
+            config
+                .keymap
+                .unbind(|action| matches!(action, Action::PowerMenu | Action::Power(_)));
+        }
+        let mut session_env = SessionEnv::new();
+        if !config.no_seat_env {
+            session_env.detect_seat(console.map(Console::path), console.and_then(Console::vt));
+        }
+        session_env.load_dir(ENV_DIR);
+        let Surface {
+            buf,
+            size: screen_size,
+            stride,
+            mut format,
+            output,
+        } = surface;
+        let mut background = Background::new(
+            config.background_command.clone(),
+            config.background_interval,
+            config.scale_filter,
+            screen_size,
+        );
+        let module = &config.theme.module;
+        background.load_theme(
+            &module.image_dir,
+            (
+                module.watermark_horizontal_alignment,
+                module.watermark_vertical_alignment,
+            ),
+        );
+        let auth_timeout = config.auth_timeout.filter(|timeout| !timeout.is_zero());
+        let mut sessions = Vec::new();
+        if let Some(cmd) = config.session.first() {
+            let name = config
+                .session_name
+                .clone()
+                .unwrap_or_else(|| cmd.rsplit('/').next().unwrap_or(cmd).to_string());
+            sessions.push(Session {
+                id: name.clone(),
+                name,
+                command: config.session.clone(),
+                kind: SessionKind::Command,
+                icon: None,
+            });
+        }
+        sessions.extend(sessions::scan(&config.session_filter));
+        let session_icons = sessions
+            .iter()
+            .map(|session| {
+                let icon = session.icon.as_ref()?;
+                logo::load(
+                    std::slice::from_ref(icon),
+                    SESSION_ICON_SIZE,
+                    config.scale_filter,
+                )
+            })
+            .collect();
+        let state = state::open(config.state_dir());
+        let user_sessions = UserSessions::load(config.user_sessions());
+        let otp_hints = Hints::load(config.otp_hints());
+        format.invert = state.get(state::INVERT).is_some();
+        let show_help =
+            !config.no_hints && state.is_persistent() && state.get(state::HELP_SEEN).is_none();
+        let syslog = config.syslog.clone().map(Syslog::new);
+        let metrics = config.metrics_file.clone().map(Metrics::new);
+        let cells = config
+            .renderer
+            .use_cells(screen_size.1)
+            .then(BitmapFont::builtin);
+        let file = match console {
+            Some(console) => console.input(),
+            None => File::open("/dev/null"),
+        };
+        let mut input = Input::new(file?);
+        if config.input == input::Source::Evdev {
+            let accessx = AccessX::new(config.sticky_keys, config.slow_keys);
+            match Keyboards::open(INPUT_DIR, config.key_repeat, accessx) {
+                Ok(mut keyboards) if !keyboards.is_empty() => {
+                    use_layout(&mut keyboards, &config);
+                    input.read_keyboards(keyboards)
+                }
+                Ok(_) => eprintln!("no keyboards found in {INPUT_DIR}, reading the console"),
+                Err(err) => eprintln!("unable to read {INPUT_DIR}: {err}, reading the console"),
+            }
+        } else {
+            if config.key_repeat.is_some() {
+                eprintln!("--key-repeat needs --input evdev, ignoring it");
+            }
+            if config.xkb_layout.is_some() || config.xkb_variant.is_some() {
+                eprintln!("--xkb-layout and --xkb-variant need --input evdev, ignoring them");
+            }
+            if config.sticky_keys || config.slow_keys.is_some() {
+                eprintln!("--sticky-keys and --slow-keys need --input evdev, ignoring them");
+            }
+        }
+        let notifier = config
+            .notify_pipe
+            .as_ref()
+            .and_then(|path| match Notifier::open(path) {
+                Ok(notifier) => Some(notifier),
+                Err(err) => {
+                    eprintln!("unable to open notification pipe {path}: {err}");
+                    None
+                }
+            });
+        if let Some(notifier) = &notifier {
+            input.watch(notifier.fd());
+        }
+        let service = if config.dbus {
+            Service::start().map_err(|err| eprintln!("{err}")).ok()
+        } else {
+            None
+        };
+        if let Some(service) = &service {
+            input.watch(service.fd());
+        }
+        if let Some(fd) = signals::fd() {
+            input.watch(fd);
+        }
+        let activity = Activity::open(INPUT_DIR);
+        for fd in activity.fds() {
+            input.watch(fd);
+        }
+        let kbd_backlight = match config.kbd_backlight {
+            Some(percent) if !config.prerender && config.preview.is_none() => {
+                match KeyboardBacklight::activate(percent) {
+                    Ok(backlight) => backlight,
+                    Err(err) => {
+                        eprintln!("unable to turn on the keyboard backlight: {err}");
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
+        let mut widgets: Vec<Box<dyn Widget>> = Vec::new();
+        if config.clock {
+            let module = &config.theme.module;
+            // the top right corner unless the theme says otherwise
+            let alignment = (
+                module.clock_horizontal_alignment.unwrap_or(1.0),
+                module.clock_vertical_alignment.unwrap_or(0.0),
+            );
+            widgets.push(Box::new(Clock::new(
+                module.font.clone(),
+                Color::WHITE,
+                screen_size,
+                alignment,
+                config.clock_format.clone(),
+                TimeLocale::from_env(),
+            )));
+        }
+        if let Some(command) = &config.status_command {
+            let font = config.theme.module.font.clone();
+            widgets.push(Box::new(StatusBadge::new(
+                font,
+                command.clone(),
+                screen_size,
+            )));
+        }
+        if config.hostname {
+            let module = &config.theme.module;
+            let alignment = (
+                module.title_horizontal_alignment,
+                module.title_vertical_alignment,
+            );
+            let font = module.title_font.clone();
+            widgets.push(Box::new(Hostname::new(
+                font,
+                Color::WHITE,
+                alignment,
+                screen_size,
+            )));
+        }
+        if let Some(path) = &config.schedule {
+            let module = &config.theme.module;
+            let font = module.font.resized(module.font.size() * 0.75);
+            // beside the form, halfway between it and the right edge
+            let x = (screen_size.0 as f32 * module.dialog_horizontal_alignment) as u32;
+            let y = (screen_size.1 as f32 * module.dialog_vertical_alignment) as u32;
+            let x = x + screen_size.0.saturating_sub(x) / 2;
+            widgets.push(Box::new(Schedule::new(
+                path.clone(),
+                font,
+                Color::WHITE,
+                (x, y.saturating_sub(10)),
+                screen_size,
+            )));
+        }
+        if config.os_logo && !cfg!(feature = "os-logo") {
+            eprintln!("built without the os-logo feature, not showing a logo");
+        }
+        if config.os_logo {
+            let size = LOGO_SIZE.min(screen_size.1 / 6);
+            if let Some((pixels, dim)) = logo::load(&logo::detect(), size, config.scale_filter) {
+                let module = &config.theme.module;
+                // centered above the title
+                let x = (screen_size.0 as f32 * module.title_horizontal_alignment) as u32;
+                let x = x
+                    .saturating_sub(dim.0 / 2)
+                    .min(screen_size.0.saturating_sub(dim.0));
+                let y = (screen_size.1 as f32 * module.title_vertical_alignment) as u32;
+                let y = y.saturating_sub(dim.1 + LOGO_MARGIN);
+                widgets.push(Box::new(Logo::new(pixels, (x, y, dim.0, dim.1))));
+            }
+        }
+        Ok(Self {
+            buf,
+            output,
+            screen_size,
+            stride,
+            format,
             mode: Mode::EditingUsername,
-            greetd: greetd::GreetD::new(),
-            var_screen_info: &fb.var_screen_info,
+            drawn_mode: None,
+            focus_changed: Instant::now(),
+            greetd: None,
+            auth_timeout,
+            should_redraw: true,
             should_refresh: false,
-            stdin_bytes: std::io::stdin().lock().bytes(),
+            last_draw: Instant::now(),
+            last_widget_tick: Instant::now(),
+            activity,
+            frame_checksum: None,
+            integrity_checked: Instant::now(),
+            power_saver: config.power_saver.is_active(),
+            blanked: false,
+            vt_active: true,
+            dark,
+            input,
+            sequences: Sequences::default(),
+            confetti: None,
             username: String::with_capacity(USERNAME_CAP),
-            password: String::with_capacity(PASSWORD_CAP),
+            password: Secret::with_capacity(PASSWORD_CAP),
+            password_typed: Instant::now(),
+            password_echo: None,
+            unlocking: false,
+            held_answer: None,
+            unlocked: None,
+            auth_secret: None,
+            entered: false,
+            transcript: Vec::new(),
+            prompt: None,
+            drawn_mask: String::new(),
+            message: None,
+            failure: None,
+            banner: None,
+            maintenance: None,
+            maintenance_checked: None,
+            maintenance_bypassed: false,
+            power_menu: None,
+            admin: None,
+            log_view: None,
+            recent_users: Vec::new(),
+            recent: None,
+            caps_lock: false,
+            layout: None,
             config,
+            session_env,
+            sessions,
+            session_icons,
+            session: 0,
+            session_picked: false,
+            state,
+            user_sessions,
+            otp_hints,
+            otp_images: HashMap::new(),
+            syslog,
+            metrics,
+            notifier,
+            service,
+            toasts: Toasts::default(),
+            kbd_backlight,
+            background,
+            background_layer: None,
+            drawn: Damage::default(),
+            labels: Vec::new(),
+            widget_frames: Vec::new(),
+            widgets,
+            show_help,
             should_quit: false,
+            logged_in: false,
+            editing_theme: false,
+            cells,
+        })
+    }
+
+    /// Connects to greetd on first use, so that startup doesn't wait for it.
+    fn greetd(&mut self) -> &mut greetd::GreetD {
+        let timeout = self.auth_timeout;
+        self.greetd
+            .get_or_insert_with(|| greetd::GreetD::new(timeout))
+    }
+
+    /// Cancels the session greetd is setting up, without connecting just to
+    /// do so.
+    fn cancel_session(&mut self) {
+        if let Some(greetd) = &mut self.greetd {
+            greetd.cancel();
         }
     }
 
     fn refresh(&mut self) {
         if self.should_refresh {
             self.should_refresh = false;
-            let mut screeninfo = self.var_screen_info.clone();
-            screeninfo.activate |= FB_ACTIVATE_NOW | FB_ACTIVATE_FORCE;
-            Framebuffer::put_var_screeninfo(self.device, &screeninfo)
-                .expect("Failed to refresh framebuffer");
+            if let Err(err) = self.output.present(&mut self.buf) {
+                eprintln!("unable to refresh the screen: {err}");
+            }
         }
     }
 
     fn clear(&mut self) {
-        let mut buf = buffer::Buffer::new(self.buf, self.screen_size);
-        let bg = self.config.theme.module.background_start_color;
-        buf.memset(&bg);
+        self.draw_background((0, 0, self.screen_size.0, self.screen_size.1));
+        self.drawn = Damage::default();
+        self.should_refresh = true;
+    }
+
+    /// Restores the background only where it was drawn over, such as the
+    /// fields and the message, rather than under the whole screen.
+    fn clear_drawn(&mut self) {
+        if self.background_layer.is_none() {
+            return self.clear();
+        }
+        for &rect in std::mem::take(&mut self.drawn).rects() {
+            self.draw_background(rect);
+        }
         self.should_refresh = true;
     }
 
+    /// Restores the background under `rect`, drawing and keeping the whole
+    /// background layer first if there is none.
+    fn draw_background(&mut self, rect: Rect) {
+        let bpp = self.format.bytes_per_pixel as usize;
+        let stride = self.stride as usize;
+        let len = stride * self.screen_size.1 as usize;
+        let layer = match &self.background_layer {
+            Some(layer) => layer,
+            None => {
+                let mut buf =
+                    buffer::Buffer::new(self.buf, self.screen_size, self.stride, self.format);
+                let module = &self.config.theme.module;
+                self.background.draw(
+                    &mut buf,
+                    &module.background_start_color,
+                    &module.background_end_color,
+                );
+                self.background_layer = Some(self.buf[..len].to_vec());
+                self.labels.clear();
+                self.widget_frames.clear();
+                return;
+            }
+        };
+        let x0 = rect.0.min(self.screen_size.0) as usize * bpp;
+        let x1 = (rect.0 + rect.2).min(self.screen_size.0) as usize * bpp;
+        for y in rect.1..(rect.1 + rect.3).min(self.screen_size.1) {
+            let row = y as usize * stride;
+            self.buf[row + x0..row + x1].copy_from_slice(&layer[row + x0..row + x1]);
+        }
+    }
+
+    /// Redraws the widgets whose timer expired, or all of them if `force`,
+    /// touching only their own rectangles.
+    fn draw_widgets(&mut self, force: bool) {
+        let now = Instant::now();
+        if !force && self.next_widget_tick().is_none_or(|tick| tick > now) {
+            return;
+        }
+        self.last_widget_tick = now;
+        self.widget_frames.resize(self.widgets.len(), None);
+        let mut i = 0;
+        while i < self.widgets.len() {
+            let due = self.widgets[i].next_tick().is_some_and(|tick| tick <= now);
+            if !force && !self.power_saver && !due {
+                i += 1;
+                continue;
+            }
+            self.should_refresh = true;
+            match self.draw_widget(i, due) {
+                Ok(()) => i += 1,
+                // such as one placed off screen, which won't fit any better
+                // next time
+                Err(err) => {
+                    eprintln!("unable to draw a widget, leaving it out: {err}");
+                    self.widgets.remove(i);
+                    self.widget_frames.remove(i);
+                }
+            }
+        }
+    }
+
+    /// Draws widget `i`, or copies what it drew last if it isn't `due`.
+    fn draw_widget(&mut self, i: usize, due: bool) -> Result<(), Error> {
+        let bg = self.config.theme.module.background_start_color;
+        let rect = self.widgets[i].bounds();
+        if let (false, Some(pixels)) = (due, &self.widget_frames[i]) {
+            // what it drew last is current until its tick
+            let mut buf = buffer::Buffer::new(self.buf, self.screen_size, self.stride, self.format)
+                .track(&mut self.drawn);
+            buf.paste_rect(rect, pixels)?;
+            return Ok(());
+        }
+        self.draw_background(rect);
+        let mut buf = buffer::Buffer::new(self.buf, self.screen_size, self.stride, self.format)
+            .track(&mut self.drawn);
+        self.widgets[i].draw(&mut buf.subdimensions(rect)?, &bg)?;
+        // drawing the background layer forgot them all
+        self.widget_frames.resize(self.widgets.len(), None);
+        self.widget_frames[i] = Some(buf.copy_rect(rect)?);
+        Ok(())
+    }
+
+    /// When the first widget needs to be redrawn. In power saver mode all
+    /// widgets are redrawn together, and not more often than every few minutes.
+    fn next_widget_tick(&self) -> Option<Instant> {
+        let tick = self.widgets.iter().filter_map(|w| w.next_tick()).min()?;
+        if self.power_saver {
+            Some(tick.max(self.last_widget_tick + SAVER_WIDGET_INTERVAL))
+        } else {
+            Some(tick)
+        }
+    }
+
+    fn blank_after(&self) -> Option<Duration> {
+        match (self.config.blank_after, self.power_saver) {
+            (Some(after), true) => Some(after / 3),
+            (Some(after), false) => Some(after),
+            (None, true) => Some(SAVER_BLANK_AFTER),
+            (None, false) => None,
+        }
+    }
+
+    fn set_blanked(&mut self, blanked: bool) {
+        if let Err(err) = self.output.blank(blanked) {
+            eprintln!("unable to blank the screen: {err}");
+        }
+        self.blanked = blanked;
+        if !blanked {
+            self.power_saver = self.config.power_saver.is_active();
+            self.should_redraw = true;
+        }
+    }
+
+    /// Switches between the dark and light theme variants when their hours
+    /// start.
+    fn update_variant(&mut self) {
+        let dark = self.config.variant.is_dark(self.config.dark_hours);
+        if dark != self.dark {
+            self.dark = dark;
+            self.apply_theme();
+        }
+    }
+
+    /// Draws with the current variant of the theme, reloading its images.
+    fn apply_theme(&mut self) {
+        self.config.theme.select(self.dark);
+        let module = &self.config.theme.module;
+        self.background.load_theme(
+            &module.image_dir,
+            (
+                module.watermark_horizontal_alignment,
+                module.watermark_vertical_alignment,
+            ),
+        );
+        self.background_layer = None;
+        self.should_redraw = true;
+    }
+
+    /// Picks up Caps Lock and the XKB layout changing, to show them.
+    fn update_keyboard_state(&mut self) {
+        let caps_lock = self.input.caps_lock();
+        let layout = self.input.layout();
+        if caps_lock != self.caps_lock || layout != self.layout.as_deref() {
+            self.layout = layout.map(str::to_string);
+            self.caps_lock = caps_lock;
+            self.should_redraw = true;
+        }
+    }
+
+    /// Shows, updates or hides the maintenance banner as the flag file comes
+    /// and goes.
+    fn update_maintenance(&mut self) {
+        if self
+            .maintenance_checked
+            .is_some_and(|checked| checked.elapsed() < MAINTENANCE_POLL)
+        {
+            return;
+        }
+        self.maintenance_checked = Some(Instant::now());
+        let notice = maintenance::notice(MAINTENANCE_FILE);
+        if notice != self.maintenance {
+            self.maintenance = notice;
+            self.should_redraw = true;
+        }
+    }
+
+    /// Redraws everything if the screen was drawn over since the last frame,
+    /// such as by kernel messages, when it's time to check.
+    fn check_integrity(&mut self) {
+        let interval = match self.config.integrity_check {
+            Some(interval) => interval,
+            None => return,
+        };
+        if self.integrity_checked.elapsed() < interval {
+            return;
+        }
+        self.integrity_checked = Instant::now();
+        if self
+            .frame_checksum
+            .is_some_and(|checksum| checksum != self.checksum())
+        {
+            eprintln!("the screen was drawn over, redrawing");
+            self.output.invalidate();
+            self.should_redraw = true;
+        }
+    }
+
+    /// A hash of evenly spaced rows of the screen, as shown if it can be
+    /// read back.
+    fn checksum(&self) -> u64 {
+        let frame = self.output.shown().unwrap_or(self.buf);
+        let stride = self.stride as usize;
+        let mut hasher = DefaultHasher::new();
+        for i in 0..INTEGRITY_ROWS {
+            let y = (self.screen_size.1 * i / INTEGRITY_ROWS) as usize;
+            if let Some(row) = frame.get(y * stride..(y + 1) * stride) {
+                hasher.write(row);
+            }
+        }
+        hasher.finish()
+    }
+
+    fn is_maintenance_locked(&self) -> bool {
+        self.config.maintenance_lock && self.maintenance.is_some() && !self.maintenance_bypassed
+    }
+
+    /// How long until something needs to happen without user input.
+    fn next_timeout(&self, redraw_delay: Option<Duration>) -> Option<Duration> {
+        if self.blanked {
+            return None;
+        }
+        let now = Instant::now();
+        let blank = self.blank_after().map(|after| self.activity.last() + after);
+        let clear = self
+            .config
+            .clear_after
+            .filter(|_| self.entered)
+            .map(|after| self.activity.last() + after);
+        let animation = self.is_animating().then(|| now + ANIMATION_FRAME);
+        redraw_delay
+            .map(|delay| now + delay)
+            .into_iter()
+            .chain(animation)
+            .chain(self.next_mask_frame())
+            .chain(self.confetti.map(|_| now + ANIMATION_FRAME))
+            .chain(self.next_widget_tick())
+            .chain(self.background.next_update())
+            .chain(self.config.variant.next_switch())
+            .chain(
+                self.maintenance_checked
+                    .map(|checked| checked + MAINTENANCE_POLL),
+            )
+            .chain(self.metrics.as_ref().map(Metrics::next_write))
+            .chain(self.toasts.next_expiry())
+            .chain(self.failure.as_ref().map(|(due, _)| *due))
+            .chain(self.log_view.map(|_| now + LOG_POLL))
+            .chain((self.config.input == input::Source::Console).then(|| now + CAPS_LOCK_POLL))
+            .chain(
+                self.config
+                    .integrity_check
+                    .map(|interval| self.integrity_checked + interval),
+            )
+            .chain(blank)
+            .chain(clear)
+            .min()
+            .map(|tick| tick.saturating_duration_since(now))
+    }
+
     fn draw_prompt(&mut self, offset: (u32, u32)) -> Result<(), Error> {
-        let mut buf = buffer::Buffer::new(self.buf, self.screen_size);
-        let mut prompt_font = self.config.theme.module.font.clone();
+        let progress = self.focus_progress();
+        let session = self.session_name();
+        let mode = self.mode;
+        let inverse = (self.focus_style() == FocusStyle::Inverse).then(|| {
+            let bg = self.config.theme.module.background_start_color;
+            let color = self.config.theme.module.focus_color();
+            bg.blend(&color, progress)
+        });
+        let stars = self.password_mask();
+        // the theme's spacing is for its mask glyph, not the dots or an answer
+        // shown as typed
+        let spaced = self.config.mask_style == MaskStyle::Stars
+            && !matches!(self.prompt, Some(greetd::Prompt::Visible(_)));
+        let policy = match (&self.prompt, self.config.password_policy) {
+            (Some(prompt), Some(min_length)) if policy::asks_new_password(prompt) => {
+                Some(policy::indicators(&self.password, min_length))
+            }
+            _ => None,
+        };
+        let password_label = match &self.prompt {
+            Some(prompt) => prompt.message().trim_end().to_string(),
+            None => "Password:".to_string(),
+        };
+        // which second factor to use, as the admin told
+        let otp_hint = match &self.prompt {
+            Some(prompt) if otp::asks_otp(prompt) => self.otp_hints.get(&self.username).cloned(),
+            _ => None,
+        };
+        if let Some(path) = otp_hint.as_ref().and_then(|hint| hint.image.clone()) {
+            let filter = self.config.scale_filter;
+            self.otp_images.entry(path.clone()).or_insert_with(|| {
+                logo::load(std::slice::from_ref(&path), SESSION_ICON_SIZE, filter)
+            });
+        }
+        self.clear_drawn();
+        let mut buf = buffer::Buffer::new(self.buf, self.screen_size, self.stride, self.format)
+            .track(&mut self.drawn);
+        let bounds = buf.get_bounds();
+        let prompt_font = &self.config.theme.module.font;
+        let plain = PasswordField::default();
+        let password_field = match spaced {
+            true => &self.config.theme.module.password_field,
+            false => &plain,
+        };
         let bg = self.config.theme.module.background_start_color;
-        buf.memset(&bg);
-        let mut stars = "".to_string();
-        for _ in 0..self.password.len() {
-            stars += "*";
+        // text over a background image or gradient gets colors readable on
+        // what it covers
+        let (screen_size, stride, format) = (self.screen_size, self.stride, self.format);
+        let gradient = bg != self.config.theme.module.background_end_color;
+        let backdrop = match &self.background_layer {
+            Some(layer) if gradient || self.background.has_image() => {
+                Some(Backdrop::new(layer, screen_size, stride, format))
+            }
+            _ => None,
+        };
+        let dark = self
+            .config
+            .theme
+            .module
+            .dark_text_color
+            .unwrap_or(Color::BLACK);
+        let error_color = self.config.theme.module.error_color();
+        let text_color = self.config.theme.module.text_color();
+        let focus_color = self.config.theme.module.focus_color();
+        let light = self
+            .config
+            .theme
+            .module
+            .light_text_color
+            .unwrap_or(Color::WHITE);
+        let colors = |font: &Font, pos: (u32, u32), text: &str, fg: Color| {
+            let rect = (pos.0, pos.1, font.text_width(text), font.size() as u32);
+            backdrop
+                .as_ref()
+                .and_then(|backdrop| backdrop.text_colors(rect, fg, dark, light))
+                .unwrap_or((bg, fg))
+        };
+        let (username_color, password_color, session_color) = match self.mode {
+            Mode::EditingUsername => (focus_color, text_color, text_color),
+            Mode::EditingPassword => (text_color, focus_color, text_color),
+            Mode::SelectingSession => (text_color, text_color, focus_color),
+        };
+
+        let username = if self.config.hide_username {
+            mask(&self.username)
+        } else {
+            self.username.clone()
+        };
+
+        let (x, y) = (offset.0.saturating_sub(40), offset.1.saturating_sub(10));
+        // the form's rows, and then a list under it
+        let row = prompt_font.size().ceil() as u32 + ROW_GAP;
+        let (password_y, message_y, session_y, list_y) =
+            (y + row, y + 2 * row, y + 3 * row, y + 4 * row);
+        let password_label = format!("{password_label} ");
+        // drawn again only when they or the background change
+        let labels = [
+            ("Username: ", (x, y)),
+            (password_label.as_str(), (x, password_y)),
+            ("Session: ", (x, session_y)),
+        ];
+        for (i, (text, pos)) in labels.iter().enumerate() {
+            let current = self.labels.get(i);
+            if current.is_some_and(|label| label.text == *text && label.pos == *pos) {
+                continue;
+            }
+            let colors = [
+                colors(prompt_font, *pos, text, text_color),
+                colors(prompt_font, *pos, text, focus_color),
+            ];
+            let label = Label::render(&mut buf, prompt_font, text, *pos, colors)?;
+            match self.labels.get_mut(i) {
+                Some(current) => *current = label,
+                None => self.labels.push(label),
+            }
+        }
+
+        if let Some(banner) = self.banner.as_ref().or(self.maintenance.as_ref()) {
+            let pos = (x, y.saturating_sub(row + 10));
+            let (bg, fg) = colors(prompt_font, pos, banner, focus_color);
+            prompt_font.auto_draw_text(&mut buf.offset(pos)?, &bg, &fg, banner)?;
         }
-        let (username_color, password_color) = match self.mode {
-            Mode::EditingUsername => (Color::YELLOW, Color::WHITE),
-            Mode::EditingPassword => (Color::WHITE, Color::YELLOW),
+        let focused = mode == Mode::EditingUsername;
+        let username_dim = match inverse.filter(|_| focused) {
+            Some(inverse) => {
+                let text = format!("Username: {username}");
+                let (field_bg, fg) = colors(prompt_font, (x, y), &text, username_color);
+                draw_field(
+                    &mut buf.offset((x, y))?,
+                    prompt_font,
+                    &field_bg,
+                    &fg,
+                    &text,
+                    Some(inverse),
+                )?
+            }
+            None => draw_after_label(
+                &mut buf,
+                prompt_font,
+                &self.labels[0],
+                focused,
+                |buf, bg, fg| Ok(prompt_font.auto_draw_text(buf, bg, fg, &username)?),
+            )?,
         };
 
-        let username = self.username.clone();
+        let focused = mode == Mode::EditingPassword;
+        let password_dim = match inverse.filter(|_| focused) {
+            Some(inverse) => {
+                let text = format!("{password_label}{stars}");
+                let (field_bg, fg) = colors(prompt_font, (x, password_y), &text, password_color);
+                draw_password_field(
+                    &mut buf.offset((x, password_y))?,
+                    prompt_font,
+                    password_field,
+                    &field_bg,
+                    &fg,
+                    (&password_label, &stars),
+                    Some(inverse),
+                )?
+            }
+            None => draw_after_label(
+                &mut buf,
+                prompt_font,
+                &self.labels[1],
+                focused,
+                |buf, bg, fg| password_field.draw(buf, prompt_font, bg, fg, &stars),
+            )?,
+        };
+
+        // after the field, lit up as the new password meets each rule
+        let mut pos = (x + password_dim.0 + 10, password_y);
+        for (label, met) in policy.iter().flatten() {
+            let color = if *met {
+                Color::GREEN
+            } else {
+                bg.blend(&text_color, 0.4)
+            };
+            let (bg, fg) = colors(prompt_font, pos, label, color);
+            let dim = prompt_font.auto_draw_text(&mut buf.offset(pos)?, &bg, &fg, label)?;
+            pos.0 += dim.0 + 8;
+        }
+        let caps_lock = self.caps_lock.then_some("Caps Lock is on");
+        let indicators = [
+            (self.layout.as_deref(), text_color),
+            (caps_lock, focus_color),
+        ];
+        for (text, color) in indicators {
+            if let Some(text) = text {
+                let (bg, fg) = colors(prompt_font, pos, text, color);
+                let dim = prompt_font.auto_draw_text(&mut buf.offset(pos)?, &bg, &fg, text)?;
+                pos.0 += dim.0 + 8;
+            }
+        }
+        if let Some(hint) = &otp_hint {
+            let images = &self.otp_images;
+            let image = hint.image.as_ref().and_then(|path| images.get(path));
+            if let Some(Some((pixels, dim))) = image {
+                buf.offset(pos)?.blend_rgba(pixels, *dim, &bg)?;
+                pos.0 += dim.0 + 6;
+            }
+            if let Some(text) = &hint.text {
+                let (bg, fg) = colors(prompt_font, pos, text, text_color);
+                prompt_font.auto_draw_text(&mut buf.offset(pos)?, &bg, &fg, text)?;
+            }
+        }
+
+        if let Some(message) = &self.message {
+            let (bg, fg) = colors(prompt_font, (x, message_y), message, error_color);
+            prompt_font.auto_draw_text(&mut buf.offset((x, message_y))?, &bg, &fg, message)?;
+        }
+
+        let focused = mode == Mode::SelectingSession;
+        let session_dim = match inverse.filter(|_| focused) {
+            Some(inverse) => {
+                let text = format!("Session: {session}");
+                let (field_bg, fg) = colors(prompt_font, (x, session_y), &text, session_color);
+                draw_field(
+                    &mut buf.offset((x, session_y))?,
+                    prompt_font,
+                    &field_bg,
+                    &fg,
+                    &text,
+                    Some(inverse),
+                )?
+            }
+            None => draw_after_label(
+                &mut buf,
+                prompt_font,
+                &self.labels[2],
+                focused,
+                |buf, bg, fg| Ok(prompt_font.auto_draw_text(buf, bg, fg, &session)?),
+            )?,
+        };
+
+        if let Some(Some((pixels, dim))) = self.session_icons.get(self.session) {
+            let pos = (x + session_dim.0 + 6, session_y);
+            buf.offset(pos)?.blend_rgba(pixels, *dim, &bg)?;
+        }
+
+        let focus = match self.mode {
+            Mode::EditingUsername => (x, y, username_dim.0, username_dim.1),
+            Mode::EditingPassword => (x, password_y, password_dim.0, password_dim.1),
+            Mode::SelectingSession => (x, session_y, session_dim.0, session_dim.1),
+        };
+
+        if let Some(palette) = &self.admin {
+            for (i, (color, line)) in admin_lines(palette, text_color, focus_color)
+                .iter()
+                .enumerate()
+            {
+                // the header, and then what's under it
+                let indent = if i == 0 { 0 } else { 20 };
+                let pos = (x + indent, list_y + i as u32 * row);
+                let (bg, fg) = colors(prompt_font, pos, line, *color);
+                prompt_font.auto_draw_text(&mut buf.offset(pos)?, &bg, &fg, line)?;
+            }
+        } else if let Some((actions, selected)) = &self.power_menu {
+            let header = "Power";
+            let (bg, fg) = colors(prompt_font, (x, list_y), header, text_color);
+            prompt_font.auto_draw_text(&mut buf.offset((x, list_y))?, &bg, &fg, header)?;
+            for (i, action) in actions.iter().enumerate() {
+                let color = if i == *selected {
+                    focus_color
+                } else {
+                    text_color
+                };
+                let pos = (x + 20, list_y + (i as u32 + 1) * row);
+                let (bg, fg) = colors(prompt_font, pos, action.label(), color);
+                prompt_font.auto_draw_text(&mut buf.offset(pos)?, &bg, &fg, action.label())?;
+            }
+        } else if let Some(selected) = self.recent {
+            let header = "Recent users";
+            let (bg, fg) = colors(prompt_font, (x, list_y), header, text_color);
+            prompt_font.auto_draw_text(&mut buf.offset((x, list_y))?, &bg, &fg, header)?;
+            for (i, user) in self.recent_users.iter().enumerate() {
+                let color = if i == selected {
+                    focus_color
+                } else {
+                    text_color
+                };
+                let pos = (x + 20, list_y + (i as u32 + 1) * row);
+                let (bg, fg) = colors(prompt_font, pos, user, color);
+                prompt_font.auto_draw_text(&mut buf.offset(pos)?, &bg, &fg, user)?;
+            }
+        } else if self.config.transcript && self.mode != Mode::SelectingSession {
+            let skip = self.transcript.len().saturating_sub(TRANSCRIPT_LINES);
+            for (i, (color, line)) in self.transcript.iter().skip(skip).enumerate() {
+                let pos = (x, list_y + i as u32 * row);
+                let (bg, fg) = colors(prompt_font, pos, line, *color);
+                prompt_font.auto_draw_text(&mut buf.offset(pos)?, &bg, &fg, line)?;
+            }
+        }
+
+        if self.mode == Mode::SelectingSession {
+            // the session list, grouped by kind under headers
+            let mut line_y = list_y;
+            let mut kind = None;
+            for (i, session) in self.sessions.iter().enumerate() {
+                if kind != Some(session.kind) {
+                    kind = Some(session.kind);
+                    let header = session.kind.header();
+                    let (bg, fg) = colors(prompt_font, (x, line_y), header, text_color);
+                    prompt_font.auto_draw_text(&mut buf.offset((x, line_y))?, &bg, &fg, header)?;
+                    line_y += row;
+                }
+                let color = if i == self.session {
+                    focus_color
+                } else {
+                    text_color
+                };
+                if let Some(Some((pixels, dim))) = self.session_icons.get(i) {
+                    buf.offset((x + 2, line_y))?.blend_rgba(pixels, *dim, &bg)?;
+                }
+                let pos = (x + 20, line_y);
+                let (bg, fg) = colors(prompt_font, pos, &session.name, color);
+                prompt_font.auto_draw_text(&mut buf.offset(pos)?, &bg, &fg, &session.name)?;
+                line_y += row;
+            }
+        }
+
+        let mut hint_lines = Vec::new();
+        if !self.config.no_hints || self.editing_theme {
+            let hints = if self.editing_theme {
+                editor::HINTS.map(str::to_string).to_vec()
+            } else {
+                self.config.keymap.hints()
+            };
+            hint_lines =
+                keys::wrap_hints(&hints, bounds.2.saturating_sub(20), HINT_LINES, |text| {
+                    prompt_font.text_width(text)
+                });
+            // the last line at the bottom, the others above it
+            let bottom_y = bounds.3.saturating_sub(prompt_font.size() as u32 + 10);
+            for (i, line) in hint_lines.iter().rev().enumerate() {
+                let hint_y = bottom_y.saturating_sub(i as u32 * row);
+                let (bg, fg) = colors(prompt_font, (10, hint_y), line, text_color);
+                prompt_font.auto_draw_text(&mut buf.offset((10, hint_y))?, &bg, &fg, line)?;
+            }
+        }
+
+        // stacked up from above the hints, newest at the bottom
+        let line_height = prompt_font.size() as u32 + 2 * TOAST_PADDING;
+        let panel = bg.blend(&Color::BLACK, 0.6);
+        let above_hints = hint_lines.len().saturating_sub(1) as u32 * row;
+        let mut toast_y = bounds
+            .3
+            .saturating_sub(prompt_font.size() as u32 + 20 + above_hints);
+        for (toast, color) in self.toasts.iter().rev() {
+            toast_y = toast_y.saturating_sub(line_height + 4);
+            let w = (prompt_font.text_width(toast) + 2 * TOAST_PADDING).min(bounds.2);
+            let x = (bounds.2 - w) / 2;
+            let mut buf = buf.subdimensions((x, toast_y, w, line_height))?;
+            buf.memset(&panel);
+            let mut text = buf.offset((TOAST_PADDING, TOAST_PADDING))?;
+            prompt_font.auto_draw_text(&mut text, &panel, &color, toast)?;
+        }
+
+        self.draw_focus(focus, progress, &bg)
+    }
+
+    /// Name of the session started on login, from the config or else the
+    /// executable of the session command.
+    fn session_name(&self) -> String {
+        match self.sessions.get(self.session) {
+            Some(session) => session.name.clone(),
+            None => "none".to_string(),
+        }
+    }
+
+    fn select_session(&mut self, next: bool) {
+        let len = self.sessions.len();
+        if len > 1 {
+            self.session_picked = true;
+            self.session = if next {
+                (self.session + 1) % len
+            } else {
+                (self.session + len - 1) % len
+            };
+        }
+    }
+
+    /// How far the focus highlight has animated in, from 0 to 1.
+    fn focus_progress(&self) -> f32 {
+        if self.is_animating() {
+            self.focus_changed.elapsed().as_secs_f32() / FOCUS_ANIMATION.as_secs_f32()
+        } else {
+            1.0
+        }
+    }
+
+    /// Draws the form as a box of bitmap font cells, for screens too small
+    /// for TrueType text.
+    fn draw_cells(&mut self) -> Result<(), Error> {
+        let username = if self.config.hide_username {
+            mask(&self.username)
+        } else {
+            self.username.clone()
+        };
+        let fields = [
+            ("User ", username, Mode::EditingUsername),
+            ("Pass ", self.password_mask(), Mode::EditingPassword),
+            ("Sess ", self.session_name(), Mode::SelectingSession),
+        ];
+        let hints = (!self.config.no_hints).then(|| self.config.keymap.hints());
+        let error_color = self.config.theme.module.error_color();
+        self.clear_drawn();
+        let font = match &self.cells {
+            Some(font) => font,
+            None => return Ok(()),
+        };
+        let mut buf = buffer::Buffer::new(self.buf, self.screen_size, self.stride, self.format)
+            .track(&mut self.drawn);
+        let bg = self.config.theme.module.background_start_color;
+        let bounds = buf.get_bounds();
 
-        let (x, y) = (offset.0 - 40, offset.1 - 10);
-        prompt_font.auto_draw_text(
-            &mut buf.offset((x, y))?,
+        let (cols, rows) = (bounds.2 / font.cell().0, bounds.3 / font.cell().1);
+        let width = (cols as usize).min(CELL_FORM_WIDTH);
+        let inner = width.saturating_sub(2);
+        let left = (cols as usize - width) as u32 / 2;
+        let top = rows.saturating_sub(fields.len() as u32 + 3) / 2;
+        let border = "─".repeat(inner);
+        font.draw_str(
+            &mut buf,
+            (left, top),
+            &Color::WHITE,
             &bg,
-            &username_color,
-            &format!("Username: {username}"),
+            &format!("┌{border}┐"),
         )?;
-
-        prompt_font.auto_draw_text(
-            &mut buf.offset((x, y + 20))?,
+        for ((label, value, mode), row) in fields.iter().zip(top + 1..) {
+            let focused = self.mode == *mode;
+            let (marker, color) = if focused {
+                ('>', Color::YELLOW)
+            } else {
+                (' ', Color::WHITE)
+            };
+            let value = fit(value, inner.saturating_sub(label.len() + 1));
+            let line = format!("│{marker}{label}{value}│");
+            font.draw_str(&mut buf, (left, row), &color, &bg, &line)?;
+        }
+        let bottom = top + fields.len() as u32 + 1;
+        font.draw_str(
+            &mut buf,
+            (left, bottom),
+            &Color::WHITE,
             &bg,
-            &password_color,
-            &format!("Password: {stars}"),
+            &format!("└{border}┘"),
         )?;
 
+        if let Some(palette) = &self.admin {
+            let line = match palette {
+                Palette::Menu(selected) => {
+                    let items = AdminAction::ALL
+                        .iter()
+                        .enumerate()
+                        .map(|(i, action)| match i == *selected {
+                            true => format!("[{}]", action.label()),
+                            false => action.label().to_string(),
+                        })
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    format!("Admin: {items}")
+                }
+                Palette::Logs(lines) => lines.last().cloned().unwrap_or_default(),
+            };
+            font.draw_str(&mut buf, (0, bottom + 1), &Color::YELLOW, &bg, &line)?;
+        } else if let Some(back) = self.log_view {
+            // one line of the log at a time
+            let log = logtail::lines();
+            let line = log.iter().rev().nth(back).map_or("", String::as_str);
+            font.draw_str(&mut buf, (0, bottom + 1), &Color::WHITE, &bg, line)?;
+        } else if let Some((actions, selected)) = &self.power_menu {
+            let items = actions
+                .iter()
+                .enumerate()
+                .map(|(i, action)| match i == *selected {
+                    true => format!("[{}]", action.label()),
+                    false => action.label().to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(" ");
+            let line = format!("Power: {items}");
+            font.draw_str(&mut buf, (0, bottom + 1), &Color::YELLOW, &bg, &line)?;
+        } else if let Some(message) = &self.message {
+            font.draw_str(&mut buf, (0, bottom + 1), &error_color, &bg, message)?;
+        } else if self.caps_lock {
+            let line = "Caps Lock is on";
+            font.draw_str(&mut buf, (0, bottom + 1), &Color::YELLOW, &bg, line)?;
+        } else if let Some((toast, color)) = self.toasts.iter().next_back() {
+            font.draw_str(&mut buf, (0, bottom + 1), &color, &bg, toast)?;
+        } else if let Some(banner) = self.banner.as_ref().or(self.maintenance.as_ref()) {
+            font.draw_str(&mut buf, (0, bottom + 1), &Color::YELLOW, &bg, banner)?;
+        } else if let Some(prompt) = &self.prompt {
+            // the field's label is too short for the question
+            let question = prompt.message().trim_end();
+            font.draw_str(&mut buf, (0, bottom + 1), &Color::YELLOW, &bg, question)?;
+        }
+        if let Some(hints) = hints.filter(|_| rows > bottom + 2) {
+            // one row of the grid, as many hints as fit in it
+            let line = keys::wrap_hints(&hints, cols, 1, |text| text.chars().count() as u32);
+            if let Some(line) = line.first() {
+                font.draw_str(&mut buf, (0, rows - 1), &Color::WHITE, &bg, line)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Highlights the focused field at `rect`, animating the highlight in
+    /// right after the focus moved.
+    fn draw_focus(&mut self, rect: Rect, progress: f32, bg: &Color) -> Result<(), Error> {
+        let style = self.focus_style();
+        let mut buf = buffer::Buffer::new(self.buf, self.screen_size, self.stride, self.format)
+            .track(&mut self.drawn);
+        let color = self.config.theme.module.focus_color();
+        let (x, y, w, h) = rect;
+        match style {
+            FocusStyle::None | FocusStyle::Inverse => {}
+            FocusStyle::Underline => {
+                let w = (w as f32 * (1.0 - (1.0 - progress).powi(2))) as u32;
+                buf.subdimensions((x, y + h + 1, w, 2))?.memset(&color);
+            }
+            FocusStyle::Box => {
+                let color = bg.blend(&color, progress);
+                draw_box(
+                    &mut buf.offset((x.saturating_sub(3), y.saturating_sub(3)))?,
+                    &color,
+                    (w + 6, h + 6),
+                )?;
+            }
+            FocusStyle::Arrow => {
+                let slide = ((1.0 - progress) * h as f32) as u32;
+                let x = x.saturating_sub(h / 2 + 6 + slide);
+                draw_arrow(&mut buf.offset((x, y))?, &color, h)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The key bindings and how to pick a session, in a panel over the middle
+    /// of the screen.
+    fn draw_help(&mut self) -> Result<(), Error> {
+        let keymap = &self.config.keymap;
+        let session_keys = [Action::NextSession, Action::PrevSession]
+            .iter()
+            .flat_map(|action| {
+                keymap
+                    .bindings()
+                    .iter()
+                    .filter(move |(_, a)| a == action)
+                    .map(|(key, _)| key.to_string())
+            })
+            .collect::<Vec<_>>();
+        let field_keys = keymap
+            .bindings()
+            .iter()
+            .filter(|(_, a)| *a == Action::NextField)
+            .map(|(key, _)| key.to_string())
+            .collect::<Vec<_>>();
+        let mut lines = vec!["Welcome".to_string(), String::new()];
+        lines.extend(
+            keymap
+                .help()
+                .iter()
+                .map(|(keys, description)| format!("{keys:<16}{description}")),
+        );
+        if !field_keys.is_empty() && !session_keys.is_empty() {
+            lines.push(String::new());
+            lines.push(format!(
+                "To pick a session, press {} until the session field is selected,",
+                field_keys.join("/")
+            ));
+            lines.push(format!("then {} to choose one.", session_keys.join("/")));
+        }
+        lines.push(String::new());
+        lines.push("Press any key to continue".to_string());
+
+        let font = &self.config.theme.module.font;
+        let line_height = font.size() as u32 + 4;
+        let width = lines
+            .iter()
+            .map(|line| font.text_width(line))
+            .max()
+            .unwrap_or(0);
+        let (w, h) = (
+            width + 2 * HELP_PADDING,
+            lines.len() as u32 * line_height + 2 * HELP_PADDING,
+        );
+        let x = self.screen_size.0.saturating_sub(w) / 2;
+        let y = self.screen_size.1.saturating_sub(h) / 2;
+        let bg = self
+            .config
+            .theme
+            .module
+            .background_start_color
+            .blend(&Color::BLACK, 0.6);
+        let border = self.config.theme.module.focus_color();
+        let mut buf = buffer::Buffer::new(self.buf, self.screen_size, self.stride, self.format)
+            .track(&mut self.drawn);
+        let bounds = buf.get_bounds();
+        let (w, h) = (w.min(bounds.2 - x), h.min(bounds.3 - y));
+        let mut panel = buf.subdimensions((x, y, w, h))?;
+        panel.memset(&bg);
+        draw_box(&mut panel, &border, (w, h))?;
+        for (i, line) in lines.iter().enumerate() {
+            let pos = (HELP_PADDING, HELP_PADDING + i as u32 * line_height);
+            if let Ok(mut buf) = panel.offset(pos) {
+                font.auto_draw_text(&mut buf, &bg, &Color::WHITE, line)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The end of the greeter's log, scrolled back as far as asked, in a
+    /// panel over most of the screen.
+    fn draw_log_view(&mut self) -> Result<(), Error> {
+        let font = &self.config.theme.module.font;
+        let line_height = font.size() as u32 + 4;
+        let (w, h) = (self.screen_size.0 * 9 / 10, self.screen_size.1 * 4 / 5);
+        let rows = (h.saturating_sub(2 * HELP_PADDING) / line_height).max(1) as usize;
+        let log = logtail::lines();
+        // not scrolled back past the first page
+        let back = self
+            .log_view
+            .unwrap_or(0)
+            .min(log.len().saturating_sub(rows));
+        self.log_view = Some(back);
+        let end = log.len() - back;
+        let shown = &log[end.saturating_sub(rows)..end];
+        let x = (self.screen_size.0 - w) / 2;
+        let y = (self.screen_size.1 - h) / 2;
+        let bg = self
+            .config
+            .theme
+            .module
+            .background_start_color
+            .blend(&Color::BLACK, 0.8);
+        let border = self.config.theme.module.focus_color();
+        let mut buf = buffer::Buffer::new(self.buf, self.screen_size, self.stride, self.format)
+            .track(&mut self.drawn);
+        let mut panel = buf.subdimensions((x, y, w, h))?;
+        panel.memset(&bg);
+        draw_box(&mut panel, &border, (w, h))?;
+        for (i, line) in shown.iter().enumerate() {
+            let pos = (HELP_PADDING, HELP_PADDING + i as u32 * line_height);
+            if let Ok(mut buf) = panel.offset(pos) {
+                font.auto_draw_text(&mut buf, &bg, &Color::WHITE, line)?;
+            }
+        }
         Ok(())
     }
 
+    /// The focus style from the command line, or else from the theme.
+    fn focus_style(&self) -> FocusStyle {
+        self.config
+            .focus_style
+            .or(self.config.theme.module.focus_style)
+            .unwrap_or_default()
+    }
+
+    fn is_animating(&self) -> bool {
+        self.focus_style() != FocusStyle::None
+            && self.cells.is_none()
+            && !self.power_saver
+            && self.focus_changed.elapsed() < FOCUS_ANIMATION
+    }
+
+    /// The password as shown, in the configured mask style.
+    fn password_mask(&self) -> String {
+        if matches!(self.prompt, Some(greetd::Prompt::Visible(_))) {
+            return self.password.to_string();
+        }
+        if self.config.mask_style == MaskStyle::Stars {
+            let field = &self.config.theme.module.password_field;
+            return field.mask(&self.password, self.password_echo);
+        }
+        if self.password.is_empty() {
+            return String::new();
+        }
+        let typed = self.password_typed.elapsed();
+        let lit = (typed < MASK_ANIMATION && !self.power_saver)
+            .then(|| (typed.as_millis() / MASK_DOT_STEP.as_millis()) as usize % MASK_DOTS);
+        dots(lit)
+    }
+
+    /// An answer to `prompt` as it is shown in the transcript.
+    fn shown_answer(&self, prompt: &greetd::Prompt, answer: &str) -> String {
+        match (prompt, self.config.mask_style) {
+            (greetd::Prompt::Visible(_), _) => answer.to_string(),
+            (_, MaskStyle::Stars) => mask(answer),
+            (_, MaskStyle::Dots) => dots(None),
+        }
+    }
+
+    /// When the dots mask style moves on to its next frame, while it animates.
+    fn next_mask_frame(&self) -> Option<Instant> {
+        let animating = self.config.mask_style == MaskStyle::Dots
+            && !self.password.is_empty()
+            && !self.power_saver
+            && self.password_typed.elapsed() < MASK_ANIMATION;
+        let echo = self
+            .config
+            .theme
+            .module
+            .password_field
+            .echo_end(self.password_echo)
+            .filter(|_| self.config.mask_style == MaskStyle::Stars);
+        animating.then(|| Instant::now() + MASK_DOT_STEP).or(echo)
+    }
+
     fn goto_next_mode(&mut self) {
+        if self.mode == Mode::EditingUsername {
+            self.select_user_session();
+        }
         self.mode = match self.mode {
             Mode::EditingUsername => Mode::EditingPassword,
-            Mode::EditingPassword => Mode::EditingUsername,
+            Mode::EditingPassword if self.sessions.len() > 1 => Mode::SelectingSession,
+            Mode::EditingPassword | Mode::SelectingSession => Mode::EditingUsername,
         }
     }
 
-    fn draw(&mut self) {
+    /// Draws the frame, every part of it even if one can't be, returning the
+    /// first error.
+    fn draw(&mut self) -> Result<(), Error> {
+        let started = Instant::now();
+        if self.drawn_mode != Some(self.mode) {
+            // no animation for the initial focus
+            if self.drawn_mode.is_some() {
+                self.focus_changed = Instant::now();
+            }
+            self.drawn_mode = Some(self.mode);
+        }
         let xoff = self.config.theme.module.dialog_horizontal_alignment;
         let yoff = self.config.theme.module.dialog_vertical_alignment;
         let x = (self.screen_size.0 as f32 * xoff) as u32;
         let y = (self.screen_size.1 as f32 * yoff) as u32;
-        self.draw_prompt((x, y)).expect("unable to draw prompt");
+        let prompt = if self.cells.is_some() {
+            self.draw_cells()
+        } else {
+            self.draw_prompt((x, y))
+        };
+        self.draw_widgets(true);
+        if self
+            .confetti
+            .is_some_and(|started| started.elapsed() >= confetti::DURATION)
+        {
+            self.confetti = None;
+        }
+        let help = if self.show_help && self.cells.is_none() && !self.editing_theme {
+            self.draw_help()
+        } else {
+            Ok(())
+        };
+        let log_view = if self.log_view.is_some() && self.cells.is_none() {
+            self.draw_log_view()
+        } else {
+            Ok(())
+        };
+        if let Some(started) = self.confetti {
+            let mut buf = buffer::Buffer::new(self.buf, self.screen_size, self.stride, self.format)
+                .track(&mut self.drawn);
+            confetti::draw(&mut buf, started.elapsed());
+        }
+        self.drawn_mask = self.password_mask();
+        self.should_redraw = false;
         self.should_refresh = true;
+        self.last_draw = Instant::now();
+        if let Some(metrics) = &mut self.metrics {
+            metrics.observe_frame(started.elapsed());
+        }
+        prompt.and(help).and(log_view)
     }
 
-    fn read_byte(&mut self) -> u8 {
-        self.stdin_bytes
-            .next()
-            .and_then(Result::ok)
-            .unwrap_or_else(quit)
+    /// How long to hold back redrawing the password echo, if throttled.
+    fn redraw_delay(&self) -> Option<Duration> {
+        let interval = self.config.echo_interval?;
+        if self.mode != Mode::EditingPassword {
+            return None;
+        }
+        interval.checked_sub(self.last_draw.elapsed())
     }
 
-    fn handle_keyboard(&mut self) {
-        match self.read_byte() as char {
-            '\x15' | '\x0B' => match self.mode {
-                // ctrl-k/ctrl-u
-                Mode::EditingUsername => self.username.clear(),
-                Mode::EditingPassword => self.password.clear(),
-            },
-            '\x03' | '\x04' => {
-                // ctrl-c/ctrl-D
-                self.username.clear();
-                self.password.clear();
-                self.greetd.cancel();
-                self.should_quit = true;
+    /// Waits for input and handles everything typed since the last frame, so
+    /// that a burst of keys results in a single redraw.
+    fn handle_input(&mut self, timeout: Option<Duration>) {
+        match self.input.wait(timeout) {
+            Ok(true) => {}
+            Ok(false) | Err(_) => self.quit(),
+        }
+        for request in signals::take() {
+            self.switch_vt(request);
+        }
+        if signals::terminated().is_some() {
+            self.should_quit = true;
+            return;
+        }
+        if !self.vt_active {
+            // typed on the VT shown instead, read from the keyboards too
+            while self.input.pop_key().is_some() {}
+            return;
+        }
+        if self.input.has_pending() {
+            self.activity.touch();
+            if self.blanked {
+                self.set_blanked(false);
+            }
+        }
+        while let Some(key) = self.input.pop_key() {
+            if self.failure.is_some() {
+                // the form starts over once it's told
+                continue;
+            }
+            self.should_redraw = true;
+            self.handle_keyboard(key);
+            if self.should_quit {
                 return;
             }
-            '\x7F' => match self.mode {
-                // backspace
-                Mode::EditingUsername => {
-                    self.username.pop();
+        }
+        if let Some(notice) = self.input.take_notice() {
+            self.banner = Some(notice.to_string());
+            self.should_redraw = true;
+        }
+    }
+
+    /// Stops drawing before another VT is shown, or draws everything again
+    /// once it's back.
+    fn switch_vt(&mut self, request: VtRequest) {
+        match request {
+            VtRequest::Release => {
+                self.vt_active = false;
+                // for the VT shown instead
+                self.input.grab_keyboards(false);
+                if let Err(err) = self.output.release() {
+                    eprintln!("unable to release the display: {err}");
                 }
-                Mode::EditingPassword => {
-                    self.password.pop();
+                console::acknowledge_vt_switch(true);
+            }
+            VtRequest::Acquire => {
+                if let Err(err) = self.output.acquire() {
+                    eprintln!("unable to take back the display: {err}");
                 }
-            },
-            '\t' => self.goto_next_mode(),
-            '\r' => match self.mode {
-                Mode::EditingUsername => {
-                    if !self.username.is_empty() {
-                        self.mode = Mode::EditingPassword;
-                    }
+                console::acknowledge_vt_switch(false);
+                self.vt_active = true;
+                self.input.grab_keyboards(true);
+                if self.blanked {
+                    self.set_blanked(false);
                 }
-                Mode::EditingPassword => {
-                    if self.password.is_empty() {
-                        self.username.clear();
-                        self.mode = Mode::EditingUsername;
-                    } else {
-                        let res = self.greetd.login(
-                            self.username.clone(),
-                            self.password.clone(),
-                            self.config.session.clone(),
-                        );
-                        match res {
-                            Ok(_) => {
-                                let _ = fs::write(LAST_USER_USERNAME, self.username.clone());
-                                self.should_quit = true;
-                                return;
-                            }
-                            Err(_) => {
-                                self.username = String::with_capacity(USERNAME_CAP);
-                                self.password = String::with_capacity(PASSWORD_CAP);
-                                self.mode = Mode::EditingUsername;
-                                self.greetd.cancel();
-                            }
-                        }
+                self.output.invalidate();
+                self.should_redraw = true;
+            }
+        }
+    }
+
+    fn quit(&self) -> ! {
+        exit::fail(Failure::Console, "console input closed");
+    }
+
+    fn handle_keyboard(&mut self, key: Key) {
+        if self.show_help && self.cells.is_none() {
+            self.show_help = false;
+            if let Err(err) = self.state.set(state::HELP_SEEN, "") {
+                eprintln!("unable to remember that the help was seen: {err}");
+            }
+            return;
+        }
+        self.message = None;
+        self.banner = None;
+        self.entered = true;
+        if self.power_menu.is_some() {
+            self.handle_power_menu(key);
+            return;
+        }
+        if self.admin.is_some() {
+            self.handle_admin(key);
+            return;
+        }
+        if self.log_view.is_some() {
+            self.handle_log_view(key);
+            return;
+        }
+        // any other key closes the recent users
+        let recent = self.recent.take();
+        let sequence = self.sequences.feed(&self.config.keymap, key);
+        let action = match (sequence.or_else(|| self.config.keymap.action(key)), key) {
+            (Some(action), _) => action,
+            (None, Key::Char(v)) => {
+                match self.mode {
+                    Mode::EditingUsername => {
+                        self.username.push(v);
+                        self.cancel_conversation();
                     }
+                    Mode::EditingPassword => {
+                        self.password.push(v);
+                        self.password_typed = Instant::now();
+                        self.password_echo = Some(self.password_typed);
+                    }
+                    Mode::SelectingSession => {}
+                }
+                return;
+            }
+            (None, _) => return,
+        };
+        match action {
+            Action::ClearField => match self.mode {
+                Mode::EditingUsername => {
+                    self.username.clear();
+                    self.cancel_conversation();
                 }
+                Mode::EditingPassword => self.password.clear(),
+                Mode::SelectingSession => {}
+            },
+            Action::NextSession | Action::PrevSession
+                if self.mode == Mode::EditingUsername && !self.recent_users.is_empty() =>
+            {
+                self.pick_recent(recent, action == Action::NextSession)
+            }
+            Action::NextSession => self.select_session(true),
+            Action::PrevSession => self.select_session(false),
+            Action::ChooseSession => match self.mode {
+                Mode::SelectingSession => self.leave_session_list(),
+                _ if self.sessions.len() > 1 => self.mode = Mode::SelectingSession,
+                _ => {}
             },
-            v => match self.mode {
-                Mode::EditingUsername => self.username.push(v as char),
-                Mode::EditingPassword => self.password.push(v as char),
+            Action::Quit => {
+                self.username.clear();
+                self.password.clear();
+                self.cancel_session();
+                self.should_quit = true;
+            }
+            Action::DeleteBack => match self.mode {
+                Mode::EditingUsername => {
+                    self.username.pop();
+                    self.cancel_conversation();
+                }
+                Mode::EditingPassword => {
+                    self.password.pop();
+                    self.password_typed = Instant::now();
+                    self.password_echo = None;
+                }
+                Mode::SelectingSession => {}
+            },
+            Action::NextField => self.goto_next_mode(),
+            Action::Screenshot => self.screenshot(),
+            Action::PowerMenu => self.open_power_menu(),
+            Action::AdminPalette => self.open_admin(),
+            Action::LogViewer => self.open_log_view(),
+            Action::InvertColors => self.invert_colors(),
+            Action::Power(action) => self.power(action),
+            Action::Confetti => {
+                if !self.power_saver {
+                    self.confetti = Some(Instant::now());
+                }
+            }
+            Action::MaintenanceBypass => {
+                if self.is_maintenance_locked() {
+                    self.maintenance_bypassed = true;
+                    self.banner = Some("Maintenance lock bypassed".to_string());
+                }
+            }
+            Action::Submit => match self.mode {
+                Mode::EditingUsername if self.username.is_empty() => {}
+                Mode::EditingUsername => {
+                    if self.config.check_username
+                        && users::get_user_by_name(&self.username).is_none()
+                    {
+                        // as slow to fail as a login for the user would be
+                        let due = Instant::now() + greetd::FAILURE_FLOOR;
+                        self.fail_at(due, "Unknown user".to_string());
+                    } else {
+                        // greetd says what to ask for next
+                        self.begin_login(None);
+                    }
+                }
+                Mode::EditingPassword => {
+                    let session_check = self.sessions.get(self.session).map(Session::check);
+                    if self.prompt.is_none() && self.password.is_empty() {
+                        self.username.clear();
+                        self.mode = Mode::EditingUsername;
+                    } else if self.is_maintenance_locked() {
+                        self.message = Some("Logins are disabled for maintenance".to_string());
+                        self.password.clear();
+                    } else if let Some(Err(err)) = session_check {
+                        self.message = Some(err);
+                        self.password.clear();
+                    } else if self.prompt.is_some() {
+                        self.answer_prompt();
+                    } else {
+                        let answer = std::mem::take(&mut self.password);
+                        self.begin_login(Some(answer));
+                    }
+                }
+                Mode::SelectingSession => self.leave_session_list(),
             },
         }
     }
 
-    fn setup(&mut self) {
+    /// Moves to the next or previous of the recent users from `picked`,
+    /// opening the list if nothing was picked, and puts them in the field.
+    fn pick_recent(&mut self, picked: Option<usize>, next: bool) {
+        let len = self.recent_users.len();
+        let i = match (picked, next) {
+            (Some(i), true) => (i + 1) % len,
+            (Some(i), false) => (i + len - 1) % len,
+            (None, true) => 0,
+            (None, false) => len - 1,
+        };
+        self.recent = Some(i);
+        self.username = self.recent_users[i].clone();
+        self.cancel_conversation();
+        self.select_user_session();
+    }
+
+    /// Picks the session the user in the username field last logged in to,
+    /// unless one was chosen by hand.
+    fn select_user_session(&mut self) {
+        if self.session_picked {
+            return;
+        }
+        let id = match self.user_sessions.get(&self.username) {
+            Some(id) => id,
+            None => return,
+        };
+        if let Some(i) = self.sessions.iter().position(|s| s.id == id) {
+            self.session = i;
+            self.should_redraw = true;
+        }
+    }
+
+    /// Inverts the colors of everything drawn from now on, redrawing it all,
+    /// and remembers it for the next start.
+    fn invert_colors(&mut self) {
+        self.format.invert = !self.format.invert;
+        let res = if self.format.invert {
+            self.state.set(state::INVERT, "")
+        } else {
+            self.state.remove(state::INVERT)
+        };
+        if let Err(err) = res {
+            eprintln!("unable to remember the color inversion: {err}");
+        }
+        self.background_layer = None;
+        self.should_redraw = true;
+    }
+
+    /// Opens the power menu with what logind allows, if anything.
+    fn open_power_menu(&mut self) {
+        let actions = logind::available();
+        if actions.is_empty() {
+            self.message = Some("Nothing to do with the power".to_string());
+            return;
+        }
+        if self.mode == Mode::SelectingSession {
+            self.leave_session_list();
+        }
+        self.power_menu = Some((actions, 0));
+    }
+
+    /// Moves through the power menu, carries out the picked action, or
+    /// closes the menu.
+    fn handle_power_menu(&mut self, key: Key) {
+        let (actions, selected) = match &mut self.power_menu {
+            Some(menu) => menu,
+            None => return,
+        };
+        match (self.config.keymap.action(key), key) {
+            (_, Key::Up) => *selected = selected.checked_sub(1).unwrap_or(actions.len() - 1),
+            (_, Key::Down) => *selected = (*selected + 1) % actions.len(),
+            (_, Key::Enter) => {
+                let action = actions[*selected];
+                self.power_menu = None;
+                self.power(action);
+            }
+            (Some(Action::PowerMenu), _) | (_, Key::Escape) => self.power_menu = None,
+            _ => {}
+        }
+    }
+
+    /// Opens the admin palette.
+    fn open_admin(&mut self) {
+        if self.mode == Mode::SelectingSession {
+            self.leave_session_list();
+        }
+        self.admin = Some(Palette::Menu(0));
+    }
+
+    /// Moves through the palette and carries out the picked action, or
+    /// closes the palette.
+    fn handle_admin(&mut self, key: Key) {
+        let palette = match &mut self.admin {
+            Some(palette) => palette,
+            None => return,
+        };
+        if key == Key::Escape || self.config.keymap.action(key) == Some(Action::AdminPalette) {
+            self.admin = None;
+            return;
+        }
+        let len = AdminAction::ALL.len();
+        match (palette, key) {
+            (Palette::Menu(selected), Key::Up) => {
+                *selected = selected.checked_sub(1).unwrap_or(len - 1)
+            }
+            (Palette::Menu(selected), Key::Down) => *selected = (*selected + 1) % len,
+            (Palette::Menu(selected), Key::Enter) => {
+                let action = AdminAction::ALL[*selected];
+                self.admin_action(action);
+            }
+            // any key goes back from the log
+            (Palette::Logs(_), _) => self.admin = Some(Palette::Menu(0)),
+            _ => {}
+        }
+    }
+
+    fn admin_action(&mut self, action: AdminAction) {
+        match action {
+            AdminAction::RestartGreetd => match admin::restart_greetd() {
+                Ok(()) => {
+                    self.admin = None;
+                    self.banner = Some("Restarting greetd...".to_string());
+                }
+                Err(err) => self.message = Some(err),
+            },
+            AdminAction::ReloadTheme => {
+                self.config.load_theme();
+                self.apply_theme();
+                self.banner = Some("Theme reloaded".to_string());
+            }
+            AdminAction::ShowLogs => match admin::logs() {
+                Ok(lines) => self.admin = Some(Palette::Logs(lines)),
+                Err(err) => self.message = Some(err),
+            },
+            AdminAction::LogViewer => {
+                self.admin = None;
+                self.open_log_view();
+            }
+        }
+    }
+
+    /// Opens the log viewer at the newest lines, if the log is kept.
+    fn open_log_view(&mut self) {
+        if !logtail::is_capturing() {
+            self.message = Some("The log is only kept with --log-viewer".to_string());
+            return;
+        }
+        logtail::take_changed();
+        self.log_view = Some(0);
+    }
+
+    /// Scrolls the log viewer, or closes it.
+    fn handle_log_view(&mut self, key: Key) {
+        let back = match &mut self.log_view {
+            Some(back) => back,
+            None => return,
+        };
+        match (self.config.keymap.action(key), key) {
+            (_, Key::Up) => *back = (*back + 1).min(logtail::lines().len().saturating_sub(1)),
+            (_, Key::Down) => *back = back.saturating_sub(1),
+            (Some(Action::LogViewer), _) | (_, Key::Escape) => self.log_view = None,
+            _ => {}
+        }
+    }
+
+    /// Has logind carry out `action`.
+    fn power(&mut self, action: PowerAction) {
+        match logind::request(action) {
+            Ok(()) if action == PowerAction::Suspend => {}
+            Ok(()) => self.banner = Some(format!("{}...", action.label())),
+            Err(err) => self.message = Some(err),
+        }
+    }
+
+    /// Goes back from the session list to the field still to be filled in.
+    fn leave_session_list(&mut self) {
+        self.mode = if self.username.is_empty() {
+            Mode::EditingUsername
+        } else {
+            Mode::EditingPassword
+        };
+    }
+
+    /// Asks for the unlock command's secret if there is one the user hasn't
+    /// passed yet, or else starts the conversation with greetd, answering its
+    /// first question with `answer` if given.
+    fn begin_login(&mut self, answer: Option<Secret>) {
+        self.select_user_session();
+        if self.config.unlock_command.is_none()
+            || self.unlocked.as_deref() == Some(self.username.as_str())
+        {
+            self.start_conversation(answer);
+            return;
+        }
+        self.cancel_conversation();
+        let label = self
+            .config
+            .unlock_prompt
+            .clone()
+            .unwrap_or_else(|| unlock::DEFAULT_PROMPT.to_string());
+        self.prompt = Some(greetd::Prompt::Secret(label));
+        self.unlocking = true;
+        self.held_answer = answer;
+        self.password.clear();
+        self.mode = Mode::EditingPassword;
+    }
+
+    /// Pipes the password field to the unlock command, going on to greetd if
+    /// it succeeds, or else asking again.
+    fn unlock(&mut self) {
+        let command = match &self.config.unlock_command {
+            Some(command) => command.clone(),
+            None => return,
+        };
+        let secret = std::mem::take(&mut self.password);
+        // the command may take a while for LUKS
+        self.show_status("Unlocking...");
+        match unlock::run(&command, &self.username, &secret) {
+            Ok(()) => {
+                let answer = self.held_answer.take();
+                self.unlocking = false;
+                self.prompt = None;
+                self.unlocked = Some(self.username.clone());
+                self.start_conversation(answer);
+            }
+            Err(err) => self.message = Some(err),
+        }
+    }
+
+    /// Starts a conversation with greetd for the username, answering its
+    /// first question with `answer` if given, such as a password typed before
+    /// the conversation started.
+    fn start_conversation(&mut self, answer: Option<Secret>) {
+        self.cancel_conversation();
+        self.transcript.clear();
+        self.auth_secret = None;
+        let username = self.username.clone();
+        self.show_status("Authenticating...");
+        let step = self.greetd().converse(username);
+        self.converse(step, answer);
+    }
+
+    /// Shows `status` in the banner while the greeter blocks on something,
+    /// which is gone again once it's done.
+    fn show_status(&mut self, status: &str) {
+        self.banner = Some(status.to_string());
+        if let Err(err) = self.draw() {
+            eprintln!("unable to draw: {err}");
+        }
+        self.refresh();
+        self.banner = None;
+    }
+
+    /// Answers the pending question with the password field.
+    fn answer_prompt(&mut self) {
+        if self.unlocking {
+            self.unlock();
+            return;
+        }
+        let prompt = match self.prompt.take() {
+            Some(prompt) => prompt,
+            None => return,
+        };
+        let answer = std::mem::take(&mut self.password);
+        self.record_answer(&prompt, &answer);
+        self.show_status("Authenticating...");
+        let step = self.greetd().respond(Some(&answer));
+        self.keep_secret(&prompt, answer);
+        self.converse(step, None);
+    }
+
+    /// Drops the pending question, if any, and the conversation with it.
+    fn cancel_conversation(&mut self) {
+        let unlocking = std::mem::take(&mut self.unlocking);
+        self.held_answer = None;
+        if self.prompt.take().is_some() && !unlocking {
+            self.cancel_session();
+        }
+    }
+
+    /// The theme's color for an error or info message from greetd, and the
+    /// message behind the theme's icon for it.
+    fn styled_message(&self, is_error: bool, message: &str) -> (Color, String) {
+        let module = &self.config.theme.module;
+        let (color, icon) = if is_error {
+            (module.error_color(), &module.error_icon)
+        } else {
+            (module.info_color(), &module.info_icon)
+        };
+        let message = message.trim_end();
+        match icon {
+            Some(icon) => (color, format!("{icon} {message}")),
+            None => (color, message.to_string()),
+        }
+    }
+
+    /// Takes the conversation with greetd as far as it goes without the user:
+    /// messages are shown and acknowledged, and questions wait in the password
+    /// field, labelled with what they ask, unless `answer` is left for the
+    /// first one.
+    fn converse(
+        &mut self,
+        mut step: Result<greetd::Step, Box<dyn std::error::Error>>,
+        mut answer: Option<Secret>,
+    ) {
+        loop {
+            match step {
+                Ok(greetd::Step::Prompt(prompt)) if !prompt.is_question() => {
+                    let is_error = matches!(prompt, greetd::Prompt::Error(_));
+                    let (color, message) = self.styled_message(is_error, prompt.message());
+                    self.transcript.push((color, message.clone()));
+                    if !self.config.transcript {
+                        // errors stay until the next key, infos go away
+                        if is_error {
+                            self.message = Some(message);
+                        } else {
+                            self.toasts.push(&message, color);
+                        }
+                    }
+                    // shown while greetd waits, e.g. on a push notification
+                    if let Err(err) = self.draw() {
+                        eprintln!("unable to draw: {err}");
+                    }
+                    self.refresh();
+                    step = self.greetd().respond(None);
+                }
+                Ok(greetd::Step::Prompt(prompt)) => match answer.take() {
+                    Some(answer) => {
+                        self.record_answer(&prompt, &answer);
+                        step = self.greetd().respond(Some(&answer));
+                        self.keep_secret(&prompt, answer);
+                    }
+                    None => {
+                        self.prompt = Some(prompt);
+                        self.password.clear();
+                        self.mode = Mode::EditingPassword;
+                        return;
+                    }
+                },
+                Ok(greetd::Step::Authenticated) => {
+                    match self.session_command() {
+                        Ok(cmd) => {
+                            if let Some(command) = self.config.after_auth_command.clone() {
+                                let secret = self.auth_secret.take();
+                                hook::after_auth(&command, &self.username, secret.as_deref());
+                            }
+                            // the session reads the keyboards once the greeter exits
+                            self.input.grab_keyboards(false);
+                            let res = self.greetd().start_session(cmd);
+                            if res.is_err() {
+                                self.input.grab_keyboards(true);
+                            }
+                            self.finish_login(res);
+                        }
+                        Err(err) => {
+                            self.message = Some(err);
+                            self.cancel_session();
+                            self.mode = Mode::SelectingSession;
+                        }
+                    }
+                    return;
+                }
+                Err(err) => {
+                    let color = self.config.theme.module.error_color();
+                    self.transcript.push((color, err.to_string()));
+                    self.finish_login(Err(err));
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Keeps the first secret answered for `--after-auth-password`, wiping
+    /// any other answer once it was sent.
+    fn keep_secret(&mut self, prompt: &greetd::Prompt, answer: Secret) {
+        if self.config.after_auth_password
+            && self.auth_secret.is_none()
+            && matches!(prompt, greetd::Prompt::Secret(_))
+        {
+            self.auth_secret = Some(answer);
+        }
+    }
+
+    fn record_answer(&mut self, prompt: &greetd::Prompt, answer: &str) {
+        let message = prompt.message().trim_end();
+        let shown = self.shown_answer(prompt, answer);
+        let color = self.config.theme.module.text_color();
+        self.transcript.push((color, format!("{message} {shown}")));
+    }
+
+    /// The selected session's command, in the session environment, if the
+    /// session can be started.
+    fn session_command(&self) -> Result<Vec<String>, String> {
+        let mut session_env = self.session_env.clone();
+        if self.config.user_env {
+            session_env.load_user(&self.username);
+        }
+        let cmd = match self.sessions.get(self.session) {
+            Some(session) => {
+                session.check()?;
+                session.command.clone()
+            }
+            None => Vec::new(),
+        };
+        Ok(session_env.wrap_command(cmd))
+    }
+
+    /// Reports a login attempt and starts the session, or else resets the form
+    /// for the next attempt.
+    fn finish_login(&mut self, res: Result<(), Box<dyn std::error::Error>>) {
+        if let Some(syslog) = &self.syslog {
+            let failure = res.as_ref().err().map(|err| err.to_string());
+            syslog.auth_attempt(&self.username, failure.as_deref());
+        }
+        if let Some(metrics) = &mut self.metrics {
+            metrics.observe_auth(res.is_ok());
+            metrics.write();
+        }
+        match res {
+            Ok(_) => {
+                self.save_state();
+                self.logged_in = true;
+                self.should_quit = true;
+                if let Err(err) = self.draw_splash() {
+                    eprintln!("unable to draw the session splash: {err}");
+                }
+                self.fade_out();
+                // restored before the session takes over the keyboard
+                self.kbd_backlight = None;
+            }
+            Err(err) => {
+                self.auth_secret = None;
+                let due = self.greetd().failure_due();
+                self.cancel_session();
+                // greetd's reason, or the timeout, in place of the form
+                // silently starting over
+                self.fail_at(due, err.to_string());
+            }
+        }
+    }
+
+    /// Keeps looking busy until `due` and then tells of a failed login with
+    /// `message`, so that how quickly it failed doesn't tell anything.
+    fn fail_at(&mut self, due: Instant, message: String) {
+        self.failure = Some((due, message));
+        self.banner = Some("Authenticating...".to_string());
+        self.should_redraw = true;
+    }
+
+    /// Tells of the failed login once it's due, starting the form over.
+    fn tell_failure(&mut self) {
+        match &self.failure {
+            Some((due, _)) if Instant::now() >= *due => {}
+            _ => return,
+        }
+        let (_, message) = self.failure.take().expect("checked above");
+        self.banner = None;
+        self.message = Some(message);
+        self.username = String::with_capacity(USERNAME_CAP);
+        self.password = Secret::with_capacity(PASSWORD_CAP);
+        self.mode = Mode::EditingUsername;
+        self.should_redraw = true;
+    }
+
+    /// Saves the screen as it is drawn without the password, so that not even
+    /// its length ends up in the picture, returning where.
+    fn save_screenshot(&mut self) -> Result<String, String> {
+        let dir = match &self.config.screenshot_dir {
+            Some(dir) => dir.clone(),
+            None => return Err("Screenshots need --screenshot-dir".to_string()),
+        };
+        let password = std::mem::take(&mut self.password);
+        if let Err(err) = self.draw() {
+            eprintln!("unable to draw: {err}");
+        }
+        let res = screenshot::save(&dir, self.buf, self.screen_size, self.stride, &self.format);
+        self.password = password;
+        self.should_redraw = true;
+        res.map_err(|err| format!("Unable to save screenshot: {err}"))
+    }
+
+    /// Saves a screenshot for the key bound to it, showing where.
+    fn screenshot(&mut self) {
+        match self.save_screenshot() {
+            Ok(path) => self.banner = Some(format!("Saved {path}")),
+            Err(err) => self.message = Some(err),
+        }
+    }
+
+    /// Carries out what was asked over D-Bus since last time.
+    fn serve(&mut self) {
+        let requests = match self.service.as_mut().map(Service::take) {
+            Some(Ok(requests)) => requests,
+            Some(Err(err)) => {
+                eprintln!("lost the system bus: {err}");
+                if let Some(service) = self.service.take() {
+                    self.input.unwatch(service.fd());
+                }
+                return;
+            }
+            None => return,
+        };
+        for request in requests {
+            match request {
+                Request::Screenshot(call) => {
+                    let res = self.save_screenshot();
+                    if let Some(service) = &mut self.service {
+                        let answer = res
+                            .as_deref()
+                            .map_err(|err| (service::FAILED, err.as_str()));
+                        service.answer(&call, answer);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Shows the icon and name of the session being started, if it has an
+    /// icon, to be faded out.
+    fn draw_splash(&mut self) -> Result<(), Error> {
+        let (session, icon) = match self.sessions.get(self.session) {
+            Some(session) => (session.name.clone(), session.icon.clone()),
+            None => return Ok(()),
+        };
+        let size = LOGO_SIZE.min(self.screen_size.1 / 6);
+        let (pixels, dim) =
+            match icon.and_then(|icon| logo::load(&[icon], size, self.config.scale_filter)) {
+                Some(icon) => icon,
+                None => return Ok(()),
+            };
+        let bg = self.config.theme.module.background_start_color;
+        let font = self.config.theme.module.font.clone();
+        let x = self.screen_size.0.saturating_sub(dim.0) / 2;
+        let y = self.screen_size.1.saturating_sub(dim.1) / 2;
+        let text_x = self.screen_size.0.saturating_sub(font.text_width(&session)) / 2;
         self.clear();
-        self.draw();
-        match fs::read_to_string(LAST_USER_USERNAME) {
-            Ok(user) => {
-                self.username = user;
-                self.mode = Mode::EditingPassword;
+        let mut buf = buffer::Buffer::new(self.buf, self.screen_size, self.stride, self.format)
+            .track(&mut self.drawn);
+        buf.offset((x, y))?.blend_rgba(&pixels, dim, &bg)?;
+        let mut text = buf.offset((text_x, y + dim.1 + LOGO_MARGIN))?;
+        font.auto_draw_text(&mut text, &bg, &Color::WHITE, &session)?;
+        self.should_refresh = true;
+        self.refresh();
+        Ok(())
+    }
+
+    /// Fades the screen to black and leaves it cleared, so that the session
+    /// doesn't start on a frozen greeter frame.
+    fn fade_out(&mut self) {
+        let len = (self.stride * self.screen_size.1) as usize;
+        let animate = !self.config.no_fade && !self.power_saver && self.format.has_byte_channels();
+        if animate && len <= self.buf.len() {
+            let frame = self.buf[..len].to_vec();
+            for step in 1..FADE_STEPS {
+                let keep = FADE_STEPS - step;
+                for (dst, src) in self.buf[..len].iter_mut().zip(&frame) {
+                    *dst = (*src as u32 * keep / FADE_STEPS) as u8;
+                }
+                self.should_refresh = true;
+                self.refresh();
+                std::thread::sleep(FADE_OUT / FADE_STEPS);
             }
-            Err(_) => {}
+        }
+        // black on the panel, whether colors are inverted or not
+        let format = PixelFormat {
+            invert: false,
+            ..self.format
         };
+        buffer::Buffer::new(self.buf, self.screen_size, self.stride, format).memset(&Color::BLACK);
+        self.drawn = Damage::all(self.screen_size);
+        self.should_refresh = true;
+        self.refresh();
     }
 
-    pub fn start(&mut self) {
-        self.setup();
+    /// Remembers what to preselect next time and when the session started.
+    fn save_state(&mut self) {
+        if let Ok(now) = SystemTime::now().duration_since(UNIX_EPOCH) {
+            let _ = self
+                .state
+                .set(state::SESSION_START, &now.as_secs().to_string());
+        }
+        if let Some(session) = self.sessions.get(self.session) {
+            let _ = self.state.set(state::LAST_SESSION, &session.id);
+        }
+        if !self.config.hide_username {
+            let _ = self.state.set(state::LAST_USER, &self.username);
+            if let Some(session) = self.sessions.get(self.session) {
+                let _ = self.user_sessions.set(&self.username, &session.id);
+            }
+        }
+        let keep = self.config.recent_users();
+        if keep == 0 {
+            let _ = self.state.remove(state::RECENT_USERS);
+            return;
+        }
+        let mut recent = vec![self.username.as_str()];
+        recent.extend(
+            self.recent_users
+                .iter()
+                .map(String::as_str)
+                .filter(|user| *user != self.username),
+        );
+        recent.truncate(keep);
+        let _ = self.state.set(state::RECENT_USERS, &recent.join("\n"));
+    }
+
+    /// Whether greetd respawned us right after starting a session, meaning
+    /// that the session ended unexpectedly.
+    fn session_died(&mut self) -> bool {
+        let started = match self.state.get(state::SESSION_START) {
+            Some(started) => started,
+            None => return false,
+        };
+        let _ = self.state.remove(state::SESSION_START);
+        let started = match started.trim().parse() {
+            Ok(secs) => UNIX_EPOCH + Duration::from_secs(secs),
+            Err(_) => return false,
+        };
+        SystemTime::now()
+            .duration_since(started)
+            .is_ok_and(|lived| lived < UNEXPECTED_EXIT)
+    }
+
+    fn setup(&mut self) {
+        if self.session_died() {
+            self.banner = Some("Your previous session ended unexpectedly".to_string());
+        }
+        self.background.update();
+        self.clear();
+        if let Err(err) = self.draw() {
+            eprintln!("unable to draw: {err}");
+        }
+        if let Some(id) = self.state.get(state::LAST_SESSION) {
+            if let Some(i) = self.sessions.iter().position(|s| s.id == id) {
+                self.session = i;
+                self.should_redraw = true;
+            }
+        }
+        if let Some(users) = self.state.get(state::RECENT_USERS) {
+            self.recent_users = users
+                .lines()
+                .filter(|user| !user.is_empty())
+                .take(self.config.recent_users())
+                .map(str::to_string)
+                .collect();
+        }
+        self.preselect_user();
+    }
+
+    /// Fills in the last user, unless the username is hidden.
+    fn preselect_user(&mut self) {
+        if self.config.hide_username {
+            return;
+        }
+        if let Some(user) = self.state.get(state::LAST_USER) {
+            self.username = user;
+            self.mode = Mode::EditingPassword;
+            self.should_redraw = true;
+            self.select_user_session();
+        }
+    }
+
+    /// Forgets what was typed and any login under way, going back to how
+    /// the greeter started.
+    fn start_over(&mut self) {
+        self.entered = false;
+        self.cancel_conversation();
+        self.auth_secret = None;
+        self.password.clear();
+        self.username.clear();
+        self.mode = Mode::EditingUsername;
+        self.power_menu = None;
+        self.admin = None;
+        self.recent = None;
+        self.message = None;
+        self.failure = None;
+        self.banner = None;
+        self.transcript.clear();
+        self.should_redraw = true;
+        self.preselect_user();
+    }
+
+    /// Lets the user adjust the theme live and writes it to `path` if they
+    /// choose to save, returning whether they did.
+    pub fn edit_theme(&mut self, path: &str) -> io::Result<bool> {
+        self.editing_theme = true;
+        self.banner = Some(format!("Editing {path}"));
+        self.background.update_now();
+        self.clear();
         loop {
-            self.draw();
-            self.handle_keyboard();
+            if let Err(err) = self.draw() {
+                eprintln!("unable to draw: {err}");
+            }
             self.refresh();
+            match self.input.wait(None) {
+                Ok(true) => {}
+                Ok(false) | Err(_) => self.quit(),
+            }
+            if signals::terminated().is_some() {
+                return Ok(false);
+            }
+            while let Some(key) = self.input.pop_key() {
+                match editor::apply(&mut self.config.theme.module, key) {
+                    Outcome::Continue => self.background_layer = None,
+                    Outcome::Save => {
+                        editor::write_theme(path, &self.config.theme.module)?;
+                        return Ok(true);
+                    }
+                    Outcome::Discard => return Ok(false),
+                }
+            }
+        }
+    }
+
+    /// Pages through the scripts the theme's fonts cover, or saves each page
+    /// as a PNG in `dir`, returning the paths saved to.
+    pub fn font_test(&mut self, dir: Option<&str>) -> io::Result<Vec<String>> {
+        let module = self.config.theme.module.clone();
+        let pages = fonttest::pages(&[("Font", &module.font), ("TitleFont", &module.title_font)]);
+        let bg = module.background_start_color;
+        if let Some(dir) = dir {
+            fs::create_dir_all(dir)?;
+        }
+        let mut page = 0;
+        let mut saved = Vec::new();
+        loop {
+            self.clear();
+            let mut buf = buffer::Buffer::new(self.buf, self.screen_size, self.stride, self.format)
+                .track(&mut self.drawn);
+            pages[page]
+                .draw(&mut buf, &bg, page, pages.len())
+                .map_err(io::Error::other)?;
+            self.refresh();
+            if let Some(dir) = dir {
+                let path = format!("{dir}/{}.png", pages[page].file_name());
+                screenshot::save_as(&path, self.buf, self.screen_size, self.stride, &self.format)?;
+                saved.push(path);
+                page += 1;
+                if page == pages.len() {
+                    return Ok(saved);
+                }
+                continue;
+            }
+            match self.input.wait(None) {
+                Ok(true) => {}
+                Ok(false) | Err(_) => self.quit(),
+            }
+            if signals::terminated().is_some() {
+                return Ok(saved);
+            }
+            while let Some(key) = self.input.pop_key() {
+                match key {
+                    Key::Right | Key::Down | Key::Enter | Key::Char(' ') => {
+                        page = (page + 1) % pages.len()
+                    }
+                    Key::Left | Key::Up | Key::Backspace => {
+                        page = (page + pages.len() - 1) % pages.len()
+                    }
+                    Key::Escape | Key::Char('q') => return Ok(saved),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Draws the greeter as it looks asking for the password after a failed
+    /// login, with a sample user, and saves it as a PNG at `path`.
+    pub fn preview(&mut self, path: &str) -> io::Result<()> {
+        self.show_help = false;
+        self.background.update_now();
+        if self.username.is_empty() {
+            self.username = PREVIEW_USER.to_string();
+        }
+        for c in PREVIEW_USER.chars() {
+            self.password.push(c);
+        }
+        self.mode = Mode::EditingPassword;
+        self.message = Some(self.styled_message(true, PREVIEW_FAILURE).1);
+        self.draw().map_err(io::Error::other)?;
+        screenshot::save_as(path, self.buf, self.screen_size, self.stride, &self.format)
+    }
+
+    /// Draws the initial screen without waiting for input, to be saved as the
+    /// frame shown right away on the next start.
+    pub fn prerender(&mut self) {
+        self.show_help = false;
+        self.background.update_now();
+        if let Err(err) = self.draw() {
+            eprintln!("unable to draw: {err}");
+        }
+        self.refresh();
+    }
+
+    /// Runs the greeter until the user logs in or quits, returning whether a
+    /// session was started.
+    pub fn start(&mut self) -> bool {
+        self.setup();
+        loop {
+            let mut delay = None;
+            // drained even while blanked, or the pipe would keep waking us
+            if let Some(notifier) = &mut self.notifier {
+                if notifier.update(&mut self.toasts) {
+                    self.should_redraw = true;
+                }
+            }
+            self.serve();
+            self.tell_failure();
+            if self.toasts.expire() {
+                self.should_redraw = true;
+            }
+            if self.log_view.is_some() && logtail::take_changed() {
+                self.should_redraw = true;
+            }
+            self.update_keyboard_state();
+            if self.activity.update() && self.blanked && self.vt_active {
+                self.set_blanked(false);
+            }
+            for device in self.activity.take_lost() {
+                self.input.unwatch(device.as_raw_fd());
+            }
+            if !self.blanked && self.vt_active {
+                self.update_variant();
+                self.update_maintenance();
+                if self.background.update() {
+                    self.background_layer = None;
+                    self.should_redraw = true;
+                }
+                if self.is_animating()
+                    || self.confetti.is_some()
+                    || self.password_mask() != self.drawn_mask
+                {
+                    self.should_redraw = true;
+                }
+                self.check_integrity();
+                delay = self.redraw_delay().filter(|_| self.should_redraw);
+                if self.should_redraw && delay.is_none() {
+                    if let Err(err) = self.draw() {
+                        eprintln!("unable to draw: {err}");
+                    }
+                }
+                self.draw_widgets(false);
+                let refreshed = self.should_refresh;
+                self.refresh();
+                if refreshed && self.config.integrity_check.is_some() {
+                    self.frame_checksum = Some(self.checksum());
+                }
+            }
+            if let Some(metrics) = &mut self.metrics {
+                metrics.write_if_due();
+            }
+            self.handle_input(self.next_timeout(delay));
+            let idle = self.activity.idle();
+            if self.entered && self.config.clear_after.is_some_and(|after| idle >= after) {
+                self.start_over();
+            }
+            let idle_blank = self.blank_after().is_some_and(|after| idle >= after);
+            if !self.blanked && self.vt_active && idle_blank {
+                self.set_blanked(true);
+            }
             if self.should_quit {
-                return;
+                return self.logged_in;
             }
         }
     }
 }
-fn quit() -> u8 {
-    Framebuffer::set_kd_mode(KdMode::Text).expect("unable to leave graphics mode");
-    std::process::exit(1);
+/// Draws an input field, in inverse video on `inverse` if given.
+fn draw_field(
+    buf: &mut buffer::Buffer<'_>,
+    font: &Font,
+    bg: &Color,
+    fg: &Color,
+    text: &str,
+    inverse: Option<Color>,
+) -> Result<(u32, u32), Error> {
+    let inverse = match inverse {
+        Some(inverse) => inverse,
+        None => return Ok(font.auto_draw_text(buf, bg, fg, text)?),
+    };
+    let dim = (font.text_width(text), font.size() as u32);
+    buf.subdimensions((0, 0, dim.0, dim.1))?.memset(&inverse);
+    Ok(font.auto_draw_text(buf, &inverse, bg, text)?)
+}
+
+/// Copies in `label`, as drawn ahead of time for the field focused or not,
+/// and has `value` draw the rest of the field after it in the label's colors.
+fn draw_after_label(
+    buf: &mut buffer::Buffer<'_>,
+    font: &Font,
+    label: &Label,
+    focused: bool,
+    value: impl FnOnce(&mut buffer::Buffer<'_>, &Color, &Color) -> Result<(u32, u32), Error>,
+) -> Result<(u32, u32), Error> {
+    let (bg, fg) = label.colors(focused);
+    label.blit(buf, focused)?;
+    let w = font.text_width(&label.text);
+    let x = (label.pos.0 + w).min(buf.get_bounds().2);
+    let (value_w, _) = value(&mut buf.offset((x, label.pos.1))?, &bg, &fg)?;
+    Ok((w + value_w, font.size() as u32))
+}
+
+/// Draws the password field's `label` and then its `mask` as `field` spaces
+/// it, in inverse video on `inverse` if given.
+fn draw_password_field(
+    buf: &mut buffer::Buffer<'_>,
+    font: &Font,
+    field: &PasswordField,
+    bg: &Color,
+    fg: &Color,
+    (label, mask): (&str, &str),
+    inverse: Option<Color>,
+) -> Result<(u32, u32), Error> {
+    let dim = (
+        font.text_width(label) + field.width(font, mask),
+        font.size() as u32,
+    );
+    let (bg, fg) = match inverse {
+        Some(inverse) => {
+            buf.subdimensions((0, 0, dim.0, dim.1))?.memset(&inverse);
+            (inverse, *bg)
+        }
+        None => (*bg, *fg),
+    };
+    let (w, _) = font.auto_draw_text(buf, &bg, &fg, label)?;
+    field.draw(&mut buf.offset((w, 0))?, font, &bg, &fg, mask)?;
+    Ok(dim)
+}
+
+/// Has `keyboards` type with the XKB layout the config names, if any.
+#[cfg(feature = "xkb")]
+fn use_layout(keyboards: &mut Keyboards, config: &Config) {
+    if config.xkb_layout.is_none() && config.xkb_variant.is_none() {
+        return;
+    }
+    let xkb = Xkb::new(
+        config.xkb_layout.as_deref(),
+        config.xkb_variant.as_deref(),
+        config.xkb_options.as_deref(),
+    );
+    match xkb {
+        Ok(xkb) => keyboards.use_layout(xkb),
+        Err(err) => eprintln!("{err}, using the console keymap"),
+    }
+}
+
+#[cfg(not(feature = "xkb"))]
+fn use_layout(_keyboards: &mut Keyboards, config: &Config) {
+    if config.xkb_layout.is_some() || config.xkb_variant.is_some() {
+        eprintln!("built without the xkb feature, using the console keymap");
+    }
+}
+
+/// The admin palette as drawn under the form, a header and then its lines,
+/// in `text` with the selected action in `focus`.
+fn admin_lines(palette: &Palette, text: Color, focus: Color) -> Vec<(Color, String)> {
+    match palette {
+        Palette::Menu(selected) => {
+            let mut lines = vec![(text, "Admin".to_string())];
+            lines.extend(AdminAction::ALL.iter().enumerate().map(|(i, action)| {
+                let color = if i == *selected { focus } else { text };
+                (color, action.label().to_string())
+            }));
+            lines
+        }
+        Palette::Logs(log) => {
+            let mut lines = vec![(text, "greetd's log".to_string())];
+            lines.extend(log.iter().map(|line| (text, line.clone())));
+            lines
+        }
+    }
+}
+
+fn mask(s: &str) -> String {
+    "*".repeat(s.chars().count())
+}
+
+/// The dots mask, with only the `lit` dot lit, or all of them if `None`.
+fn dots(lit: Option<usize>) -> String {
+    (0..MASK_DOTS)
+        .map(|i| {
+            if lit.is_none_or(|lit| lit == i) {
+                "•"
+            } else {
+                "·"
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
 }
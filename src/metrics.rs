@@ -0,0 +1,168 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::time::{Duration, Instant};
+
+/// How often the metrics file is rewritten.
+pub const INTERVAL: Duration = Duration::from_secs(60);
+
+// upper bounds of the frame time histogram buckets, in milliseconds
+const FRAME_BUCKETS_MS: [f64; 8] = [1.0, 2.0, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0];
+
+/// Counters written in the Prometheus text format for node_exporter's
+/// textfile collector.
+pub struct Metrics {
+    path: String,
+    started: Instant,
+    written: Option<Instant>,
+    auth_success: u64,
+    auth_failure: u64,
+    frame_buckets: [u64; FRAME_BUCKETS_MS.len()],
+    frame_count: u64,
+    frame_sum_ms: f64,
+}
+
+impl Metrics {
+    pub fn new(path: String) -> Self {
+        Self {
+            path,
+            started: Instant::now(),
+            written: None,
+            auth_success: 0,
+            auth_failure: 0,
+            frame_buckets: [0; FRAME_BUCKETS_MS.len()],
+            frame_count: 0,
+            frame_sum_ms: 0.0,
+        }
+    }
+
+    pub fn observe_auth(&mut self, success: bool) {
+        if success {
+            self.auth_success += 1;
+        } else {
+            self.auth_failure += 1;
+        }
+    }
+
+    pub fn observe_frame(&mut self, time: Duration) {
+        let ms = time.as_secs_f64() * 1000.0;
+        for (count, le) in self.frame_buckets.iter_mut().zip(FRAME_BUCKETS_MS) {
+            if ms <= le {
+                *count += 1;
+            }
+        }
+        self.frame_count += 1;
+        self.frame_sum_ms += ms;
+    }
+
+    /// When the file should be rewritten next.
+    pub fn next_write(&self) -> Instant {
+        self.written
+            .map_or_else(Instant::now, |written| written + INTERVAL)
+    }
+
+    /// Rewrites the file if it's due, warning if that fails.
+    pub fn write_if_due(&mut self) {
+        if self.next_write() <= Instant::now() {
+            self.write();
+        }
+    }
+
+    /// Rewrites the file, through a temporary file so the collector never
+    /// reads a partial one.
+    pub fn write(&mut self) {
+        self.written = Some(Instant::now());
+        let tmp = format!("{}.tmp", self.path);
+        let res = fs::write(&tmp, self.render()).and_then(|_| fs::rename(&tmp, &self.path));
+        if let Err(err) = res {
+            eprintln!("unable to write metrics to {}: {err}", self.path);
+        }
+    }
+
+    fn render(&self) -> String {
+        let mut out = String::new();
+        let mut counter = |name: &str, help: &str, value: u64| {
+            let _ = writeln!(out, "# HELP ddlm_{name} {help}");
+            let _ = writeln!(out, "# TYPE ddlm_{name} counter");
+            let _ = writeln!(out, "ddlm_{name} {value}");
+        };
+        counter(
+            "auth_success_total",
+            "Logins that started a session.",
+            self.auth_success,
+        );
+        counter(
+            "auth_failure_total",
+            "Login attempts that failed.",
+            self.auth_failure,
+        );
+
+        let _ = writeln!(
+            out,
+            "# HELP ddlm_uptime_seconds Time since the greeter started."
+        );
+        let _ = writeln!(out, "# TYPE ddlm_uptime_seconds gauge");
+        let _ = writeln!(
+            out,
+            "ddlm_uptime_seconds {}",
+            self.started.elapsed().as_secs()
+        );
+
+        let _ = writeln!(out, "# HELP ddlm_frame_time_ms Time taken to draw a frame.");
+        let _ = writeln!(out, "# TYPE ddlm_frame_time_ms histogram");
+        for (count, le) in self.frame_buckets.iter().zip(FRAME_BUCKETS_MS) {
+            let _ = writeln!(out, "ddlm_frame_time_ms_bucket{{le=\"{le}\"}} {count}");
+        }
+        let _ = writeln!(
+            out,
+            "ddlm_frame_time_ms_bucket{{le=\"+Inf\"}} {}",
+            self.frame_count
+        );
+        let _ = writeln!(out, "ddlm_frame_time_ms_sum {}", self.frame_sum_ms);
+        let _ = writeln!(out, "ddlm_frame_time_ms_count {}", self.frame_count);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_counts_logins() {
+        let mut metrics = Metrics::new(String::new());
+        metrics.observe_auth(false);
+        metrics.observe_auth(false);
+        metrics.observe_auth(true);
+        let out = metrics.render();
+        assert!(out.contains("ddlm_auth_success_total 1\n"), "{}", out);
+        assert!(out.contains("ddlm_auth_failure_total 2\n"), "{}", out);
+    }
+
+    #[test]
+    fn render_counts_frames_into_cumulative_buckets() {
+        let mut metrics = Metrics::new(String::new());
+        for ms in [0.5, 3.0, 5.0, 30.0, 300.0] {
+            metrics.observe_frame(Duration::from_secs_f64(ms / 1000.0));
+        }
+        let out = metrics.render();
+        let buckets = out
+            .lines()
+            .filter_map(|line| line.strip_prefix("ddlm_frame_time_ms_bucket"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            buckets,
+            [
+                "{le=\"1\"} 1",
+                "{le=\"2\"} 1",
+                "{le=\"5\"} 3",
+                "{le=\"10\"} 3",
+                "{le=\"25\"} 3",
+                "{le=\"50\"} 4",
+                "{le=\"100\"} 4",
+                "{le=\"250\"} 4",
+                "{le=\"+Inf\"} 5",
+            ]
+        );
+        assert!(out.contains("ddlm_frame_time_ms_count 5\n"), "{}", out);
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read};
+use std::os::unix::fs::{FileTypeExt, OpenOptionsExt};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use nix::sys::stat::Mode;
+use nix::unistd::mkfifo;
+
+use crate::color::Color;
+
+/// How long a message stays on screen.
+pub const TOAST_DURATION: Duration = Duration::from_secs(10);
+
+const MAX_TOASTS: usize = 3;
+const MAX_CHARS: usize = 120;
+// a writer that never ends its line doesn't get to fill the memory
+const MAX_PENDING: usize = 4096;
+
+/// Messages shown for a while above the hints, each in its color.
+#[derive(Default)]
+pub struct Toasts {
+    toasts: VecDeque<(String, Color, Instant)>,
+}
+
+impl Toasts {
+    /// Shows `message`, dropping the oldest toast if there are too many.
+    pub fn push(&mut self, message: &str, color: Color) {
+        if let Some(message) = sanitize(message) {
+            if self.toasts.len() == MAX_TOASTS {
+                self.toasts.pop_front();
+            }
+            self.toasts.push_back((message, color, Instant::now()));
+        }
+    }
+
+    /// Drops expired toasts, returning whether there were any.
+    pub fn expire(&mut self) -> bool {
+        let before = self.toasts.len();
+        self.toasts
+            .retain(|(_, _, shown)| shown.elapsed() < TOAST_DURATION);
+        self.toasts.len() != before
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&str, Color)> {
+        self.toasts
+            .iter()
+            .map(|(message, color, _)| (message.as_str(), *color))
+    }
+
+    /// When the oldest toast goes away.
+    pub fn next_expiry(&self) -> Option<Instant> {
+        self.toasts
+            .front()
+            .map(|(_, _, shown)| *shown + TOAST_DURATION)
+    }
+}
+
+/// A named pipe scripts write lines to, shown as toasts on the greeter.
+pub struct Notifier {
+    file: File,
+    pending: Vec<u8>,
+}
+
+impl Notifier {
+    /// Opens the pipe at `path`, creating it if it doesn't exist.
+    pub fn open(path: &str) -> io::Result<Self> {
+        match fs::metadata(path) {
+            Ok(meta) if meta.file_type().is_fifo() => {}
+            Ok(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    "exists and isn't a named pipe",
+                ))
+            }
+            Err(_) => {
+                if let Some(dir) = Path::new(path).parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                mkfifo(path, Mode::from_bits_truncate(0o620))?;
+            }
+        }
+        // opened for writing too, so there is no end of file when the last
+        // writer goes away
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(nix::libc::O_NONBLOCK)
+            .open(path)?;
+        Ok(Self {
+            file,
+            pending: Vec::new(),
+        })
+    }
+
+    pub fn fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+
+    /// Reads what was written to the pipe into `toasts`, returning whether
+    /// anything was.
+    pub fn update(&mut self, toasts: &mut Toasts) -> bool {
+        let mut changed = false;
+        let mut chunk = [0u8; 512];
+        loop {
+            match self.file.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(n) => self.pending.extend_from_slice(&chunk[..n]),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) => {
+                    eprintln!("unable to read notifications: {err}");
+                    break;
+                }
+            }
+        }
+        while let Some(end) = self.pending.iter().position(|b| *b == b'\n') {
+            let line = self.pending.drain(..=end).collect::<Vec<_>>();
+            toasts.push(&String::from_utf8_lossy(&line), Color::WHITE);
+            changed = true;
+        }
+        if self.pending.len() > MAX_PENDING {
+            self.pending.clear();
+        }
+        changed
+    }
+}
+
+/// The message without control characters and surrounding whitespace,
+/// shortened to fit, if anything is left.
+fn sanitize(line: &str) -> Option<String> {
+    let message = line
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect::<String>();
+    let message = message.trim();
+    if message.is_empty() {
+        return None;
+    }
+    Some(message.chars().take(MAX_CHARS).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitize_trims_and_blanks_control_characters() {
+        assert_eq!(
+            sanitize("  backup\tdone\x1b[31m \n").as_deref(),
+            Some("backup done [31m")
+        );
+    }
+
+    #[test]
+    fn sanitize_drops_empty_messages() {
+        assert_eq!(sanitize(""), None);
+        assert_eq!(sanitize(" \t\r\n"), None);
+    }
+
+    #[test]
+    fn sanitize_shortens_by_characters() {
+        let long = "é".repeat(MAX_CHARS + 10);
+        let message = sanitize(&long).unwrap();
+        assert_eq!(message.chars().count(), MAX_CHARS);
+    }
+}
@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use toml::Value;
+
+use crate::greetd::Prompt;
+
+/// Where the admin says which second factor each user has.
+pub const OTP_HINTS: &str = "/etc/ddlm/otp-hints.toml";
+
+// what PAM modules such as pam_oath, pam_google_authenticator, pam_yubico
+// and pam_u2f ask with
+const OTP_WORDS: [&str; 8] = [
+    "otp",
+    "one-time",
+    "one time",
+    "verification code",
+    "token",
+    "passcode",
+    "yubikey",
+    "authenticator",
+];
+
+/// Whether `prompt` asks for a one-time password or a hardware token.
+pub fn asks_otp(prompt: &Prompt) -> bool {
+    match prompt {
+        Prompt::Visible(msg) | Prompt::Secret(msg) => {
+            let msg = msg.to_lowercase();
+            OTP_WORDS.iter().any(|word| msg.contains(word))
+        }
+        _ => false,
+    }
+}
+
+/// What to show a user asked for their second factor, such as which of
+/// their keys to use.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Hint {
+    pub text: Option<String>,
+    pub image: Option<String>,
+}
+
+/// The hints of each user, read from a TOML file with a table per user:
+///
+/// ```toml
+/// [alice]
+/// text = "Use the blue YubiKey"
+/// image = "keys/blue.png"
+/// ```
+///
+/// Relative image paths are relative to the file.
+#[derive(Default)]
+pub struct Hints(HashMap<String, Hint>);
+
+impl Hints {
+    /// Reads the hints in `path`, none if it doesn't exist.
+    pub fn load(path: &str) -> Self {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Self::default(),
+            Err(err) => {
+                eprintln!("unable to read {path}: {err}");
+                return Self::default();
+            }
+        };
+        let table = match toml::from_str::<toml::Table>(&content) {
+            Ok(table) => table,
+            Err(err) => {
+                eprintln!("{path}: {err}");
+                return Self::default();
+            }
+        };
+        let dir = Path::new(path).parent().unwrap_or(Path::new("/"));
+        let mut hints = HashMap::new();
+        for (user, value) in table {
+            let string = |key: &str| match value.get(key) {
+                Some(Value::String(s)) => Some(s.clone()),
+                Some(_) => {
+                    eprintln!("{path}: {user}.{key} has to be a string");
+                    None
+                }
+                None => None,
+            };
+            let hint = Hint {
+                text: string("text"),
+                image: string("image").map(|image| dir.join(image).display().to_string()),
+            };
+            if hint == Hint::default() {
+                eprintln!("{path}: {user} has neither a text nor an image");
+                continue;
+            }
+            hints.insert(user, hint);
+        }
+        Self(hints)
+    }
+
+    /// The hint for `user`, if the admin gave one.
+    pub fn get(&self, user: &str) -> Option<&Hint> {
+        self.0.get(user)
+    }
+}
@@ -0,0 +1,179 @@
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+use framebuffer::{Framebuffer, FramebufferError};
+use thiserror::Error;
+
+use crate::buffer::Vect;
+use crate::fbdev::{Fbdev, RefreshStrategy};
+use crate::format::{ByteOrder, Fourcc, PixelFormat};
+#[cfg(feature = "drm")]
+use crate::kms::Drm;
+
+/// Which kind of device frames are shown on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    /// The framebuffer device if there is one, else DRM.
+    #[default]
+    Auto,
+    Drm,
+    Fbdev,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Backend::Auto),
+            "drm" => Ok(Backend::Drm),
+            "fbdev" => Ok(Backend::Fbdev),
+            _ => Err(format!("unknown display backend {s}")),
+        }
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Backend::Auto => write!(f, "auto"),
+            Backend::Drm => write!(f, "drm"),
+            Backend::Fbdev => write!(f, "fbdev"),
+        }
+    }
+}
+
+/// Why a display device couldn't be opened.
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum ScreenError {
+    #[error("unable to open framebuffer device {0}: {1}")]
+    Framebuffer(String, FramebufferError),
+    #[error("unable to set up DRM device {0}: {1}")]
+    Drm(String, io::Error),
+    #[error("no framebuffer device {0} and built without the drm feature for {1}")]
+    NoDrm(String, String),
+}
+
+/// What shows the frames drawn to a surface's buffer.
+pub trait Output<'a> {
+    /// Shows the frame drawn to `buf`, which may be swapped for another page
+    /// holding the same frame to draw the next one to.
+    fn present(&mut self, buf: &mut &'a mut [u8]) -> io::Result<()>;
+    /// Powers the display down or back up.
+    fn blank(&mut self, blank: bool) -> io::Result<()>;
+    /// The frame on screen, laid out as the drawn one, if it can be read
+    /// back to notice something else drawing over it.
+    fn shown(&self) -> Option<&[u8]> {
+        None
+    }
+    /// Has the next frame shown whole, rather than only what changed.
+    fn invalidate(&mut self) {}
+    /// Hands the display over before another VT is shown.
+    fn release(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+    /// Takes the display back once its VT is shown again.
+    fn acquire(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The memory frames are drawn to, rows of `size.0` pixels of `format`
+/// starting `stride` bytes apart, and what shows them.
+pub struct Surface<'a> {
+    pub buf: &'a mut [u8],
+    pub size: Vect,
+    pub stride: u32,
+    pub format: PixelFormat,
+    pub output: Box<dyn Output<'a> + 'a>,
+}
+
+/// Shows nothing, for frames only kept in memory.
+struct Offscreen;
+
+impl<'a> Output<'a> for Offscreen {
+    fn present(&mut self, _buf: &mut &'a mut [u8]) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn blank(&mut self, _blank: bool) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// An opened display device, or memory standing in for one.
+pub enum Screen {
+    Fbdev(Box<Fbdev>),
+    #[cfg(feature = "drm")]
+    Drm(Box<Drm>),
+    /// XRGB8888 pixels of the size, drawn to without being shown.
+    Memory(Vec<u8>, Vect),
+}
+
+impl Screen {
+    /// Opens the device of `backend`, for auto the framebuffer device if it
+    /// exists and else the DRM card, a framebuffer refreshed as `refresh`
+    /// has it.
+    pub fn open(
+        backend: Backend,
+        fb_device: &str,
+        drm_device: &str,
+        refresh: RefreshStrategy,
+    ) -> Result<Self, ScreenError> {
+        let backend = match backend {
+            Backend::Auto if Path::new(fb_device).exists() => Backend::Fbdev,
+            Backend::Auto => Backend::Drm,
+            backend => backend,
+        };
+        match backend {
+            Backend::Fbdev | Backend::Auto => Framebuffer::new(fb_device)
+                .map(|fb| Screen::Fbdev(Box::new(Fbdev::new(fb, refresh))))
+                .map_err(|err| ScreenError::Framebuffer(fb_device.to_string(), err)),
+            #[cfg(feature = "drm")]
+            Backend::Drm => Drm::open(drm_device)
+                .map(|drm| Screen::Drm(Box::new(drm)))
+                .map_err(|err| ScreenError::Drm(drm_device.to_string(), err)),
+            #[cfg(not(feature = "drm"))]
+            Backend::Drm => Err(ScreenError::NoDrm(
+                fb_device.to_string(),
+                drm_device.to_string(),
+            )),
+        }
+    }
+
+    /// A screen of `size` in memory, such as to save what's drawn as an
+    /// image.
+    pub fn memory(size: Vect) -> Self {
+        Screen::Memory(vec![0; size.0 as usize * size.1 as usize * 4], size)
+    }
+
+    /// The surface to draw to. Pages are only flipped if `flip`, as
+    /// prerendered frames are saved from the first page.
+    pub fn surface(&mut self, byte_order: ByteOrder, flip: bool) -> Surface<'_> {
+        match self {
+            Screen::Fbdev(fbdev) => fbdev.surface(byte_order, flip),
+            #[cfg(feature = "drm")]
+            Screen::Drm(drm) => drm.surface(),
+            Screen::Memory(pixels, size) => Surface {
+                buf: pixels,
+                size: *size,
+                stride: size.0 * 4,
+                format: Fourcc::Xrgb8888.format(),
+                output: Box::new(Offscreen),
+            },
+        }
+    }
+
+    /// The framebuffer device, which prerendered frames are saved from and
+    /// shown on.
+    pub fn framebuffer(&mut self) -> Option<&mut Framebuffer> {
+        match self {
+            Screen::Fbdev(fbdev) => Some(&mut fbdev.fb),
+            #[cfg(feature = "drm")]
+            Screen::Drm(_) => None,
+            Screen::Memory(..) => None,
+        }
+    }
+}
@@ -0,0 +1,27 @@
+use crate::greetd::Prompt;
+
+/// Whether `prompt` asks for a new password, as PAM does when a password has
+/// expired or must be changed on first login.
+pub fn asks_new_password(prompt: &Prompt) -> bool {
+    match prompt {
+        Prompt::Secret(msg) => msg.to_lowercase().contains("new"),
+        _ => false,
+    }
+}
+
+/// What a new password has of what password policies commonly ask for: the
+/// label of each character class and of the minimum length, and whether it's
+/// met.
+pub fn indicators(password: &str, min_length: usize) -> [(String, bool); 5] {
+    let has = |f: fn(char) -> bool| password.chars().any(f);
+    [
+        ("A-Z".to_string(), has(char::is_uppercase)),
+        ("a-z".to_string(), has(char::is_lowercase)),
+        ("0-9".to_string(), has(|c| c.is_ascii_digit())),
+        ("#?!".to_string(), has(|c| !c.is_alphanumeric())),
+        (
+            format!("{min_length}+"),
+            password.chars().count() >= min_length,
+        ),
+    ]
+}
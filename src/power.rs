@@ -0,0 +1,74 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+const POWER_SUPPLY_DIR: &str = "/sys/class/power_supply";
+
+/// When to trade responsiveness for power draw.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PowerSaver {
+    Off,
+    On,
+    /// Only while running on battery.
+    #[default]
+    Auto,
+}
+
+impl FromStr for PowerSaver {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(PowerSaver::Off),
+            "on" => Ok(PowerSaver::On),
+            "auto" => Ok(PowerSaver::Auto),
+            _ => Err(format!("unknown power saver mode {s}")),
+        }
+    }
+}
+
+impl fmt::Display for PowerSaver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PowerSaver::Off => write!(f, "off"),
+            PowerSaver::On => write!(f, "on"),
+            PowerSaver::Auto => write!(f, "auto"),
+        }
+    }
+}
+
+impl PowerSaver {
+    pub fn is_active(&self) -> bool {
+        match self {
+            PowerSaver::Off => false,
+            PowerSaver::On => true,
+            PowerSaver::Auto => on_battery(),
+        }
+    }
+}
+
+/// Whether there is a battery and no mains supply is online.
+pub fn on_battery() -> bool {
+    let entries = match fs::read_dir(POWER_SUPPLY_DIR) {
+        Ok(entries) => entries,
+        Err(_) => return false,
+    };
+    let mut has_battery = false;
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        match read_attr(&path, "type").as_deref() {
+            Some("Mains") | Some("USB") if read_attr(&path, "online").as_deref() == Some("1") => {
+                return false
+            }
+            Some("Battery") => has_battery = true,
+            _ => {}
+        }
+    }
+    has_battery
+}
+
+fn read_attr(supply: &Path, attr: &str) -> Option<String> {
+    fs::read_to_string(supply.join(attr))
+        .ok()
+        .map(|s| s.trim().to_string())
+}
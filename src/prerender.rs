@@ -0,0 +1,57 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use framebuffer::Framebuffer;
+
+pub const FRAME_CACHE: &str = "/var/cache/ddlm/frame.raw";
+/// Name of the frame cache in a configured state directory.
+pub const FRAME_CACHE_NAME: &str = "frame.raw";
+
+const MAGIC: &[u8; 8] = b"DDLMFRM1";
+
+/// Identifies the framebuffer mode a cached frame was rendered for.
+fn header(fb: &Framebuffer) -> Vec<u8> {
+    let info = &fb.var_screen_info;
+    let mut header = MAGIC.to_vec();
+    for v in [
+        info.xres,
+        info.yres,
+        info.bits_per_pixel,
+        fb.fix_screen_info.line_length,
+    ] {
+        header.extend_from_slice(&v.to_le_bytes());
+    }
+    header
+}
+
+fn visible_len(fb: &Framebuffer) -> usize {
+    (fb.fix_screen_info.line_length * fb.var_screen_info.yres) as usize
+}
+
+/// Saves the visible part of the framebuffer so that it can be shown on the
+/// next start before anything else is initialized.
+pub fn save<P: AsRef<Path>>(fb: &Framebuffer, path: P) -> io::Result<()> {
+    if let Some(dir) = path.as_ref().parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut frame = header(fb);
+    frame.extend_from_slice(&fb.frame[..visible_len(fb)]);
+    fs::write(path, frame)
+}
+
+/// Copies a frame saved by `save` to the framebuffer if it was rendered for
+/// the current mode, returning whether it did.
+pub fn show<P: AsRef<Path>>(fb: &mut Framebuffer, path: P) -> bool {
+    let frame = match fs::read(path) {
+        Ok(frame) => frame,
+        Err(_) => return false,
+    };
+    let header = header(fb);
+    let len = visible_len(fb);
+    if frame.len() != header.len() + len || !frame.starts_with(&header) {
+        return false;
+    }
+    fb.frame[..len].copy_from_slice(&frame[header.len()..]);
+    true
+}
@@ -0,0 +1,236 @@
+use std::f32::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::buffer::Vect;
+
+const LANCZOS_LOBES: f32 = 3.0;
+
+/// How images are resampled when they don't match the size they're drawn at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Filter {
+    /// Fastest, for slow SoCs, but blocky.
+    Nearest,
+    #[default]
+    Bilinear,
+    /// Sharpest, and the slowest.
+    Lanczos,
+}
+
+impl FromStr for Filter {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nearest" => Ok(Filter::Nearest),
+            "bilinear" => Ok(Filter::Bilinear),
+            "lanczos" => Ok(Filter::Lanczos),
+            _ => Err(format!("unknown scale filter {s}")),
+        }
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Filter::Nearest => write!(f, "nearest"),
+            Filter::Bilinear => write!(f, "bilinear"),
+            Filter::Lanczos => write!(f, "lanczos"),
+        }
+    }
+}
+
+impl Filter {
+    fn support(&self) -> f32 {
+        match self {
+            Filter::Nearest => 0.5,
+            Filter::Bilinear => 1.0,
+            Filter::Lanczos => LANCZOS_LOBES,
+        }
+    }
+
+    fn kernel(&self, x: f32) -> f32 {
+        let x = x.abs();
+        match self {
+            Filter::Nearest => 1.0,
+            Filter::Bilinear => (1.0 - x).max(0.0),
+            Filter::Lanczos if x < LANCZOS_LOBES => sinc(x) * sinc(x / LANCZOS_LOBES),
+            Filter::Lanczos => 0.0,
+        }
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// Resizes RGBA `pixels` of size `from` to `to`.
+pub fn resize(pixels: &[u8], from: Vect, to: Vect, filter: Filter) -> Vec<u8> {
+    if from == to {
+        return pixels.to_vec();
+    }
+    if filter == Filter::Nearest {
+        return nearest(pixels, from, to);
+    }
+    let (from_w, from_h) = (from.0 as usize, from.1 as usize);
+    let (to_w, to_h) = (to.0 as usize, to.1 as usize);
+
+    // rows first into floats, then columns back into bytes
+    let columns = contributions(from_w, to_w, filter);
+    let mut rows = vec![0.0f32; to_w * from_h * 4];
+    for y in 0..from_h {
+        for (x, (start, weights)) in columns.iter().enumerate() {
+            let dst = &mut rows[(y * to_w + x) * 4..][..4];
+            for (i, w) in weights.iter().enumerate() {
+                let src = &pixels[(y * from_w + start + i) * 4..][..4];
+                for c in 0..4 {
+                    dst[c] += src[c] as f32 * w;
+                }
+            }
+        }
+    }
+
+    let lines = contributions(from_h, to_h, filter);
+    let mut out = vec![0u8; to_w * to_h * 4];
+    for (y, (start, weights)) in lines.iter().enumerate() {
+        for x in 0..to_w {
+            let mut sum = [0.0f32; 4];
+            for (i, w) in weights.iter().enumerate() {
+                let src = &rows[((start + i) * to_w + x) * 4..][..4];
+                for c in 0..4 {
+                    sum[c] += src[c] * w;
+                }
+            }
+            let dst = &mut out[(y * to_w + x) * 4..][..4];
+            for c in 0..4 {
+                dst[c] = sum[c].round().clamp(0.0, 255.0) as u8;
+            }
+        }
+    }
+    out
+}
+
+fn nearest(pixels: &[u8], from: Vect, to: Vect) -> Vec<u8> {
+    let mut out = Vec::with_capacity((to.0 * to.1 * 4) as usize);
+    for y in 0..to.1 {
+        let sy = (y as u64 * from.1 as u64 / to.1 as u64) as u32;
+        for x in 0..to.0 {
+            let sx = (x as u64 * from.0 as u64 / to.0 as u64) as u32;
+            let i = ((sy * from.0 + sx) * 4) as usize;
+            out.extend_from_slice(&pixels[i..i + 4]);
+        }
+    }
+    out
+}
+
+/// For every destination pixel along one axis, the first source pixel it's
+/// made of and the normalized weights of it and the following ones.
+fn contributions(from: usize, to: usize, filter: Filter) -> Vec<(usize, Vec<f32>)> {
+    let scale = from as f32 / to as f32;
+    // when shrinking, the kernel is stretched so every source pixel counts
+    let stretch = scale.max(1.0);
+    let radius = filter.support() * stretch;
+    (0..to)
+        .map(|i| {
+            let center = (i as f32 + 0.5) * scale;
+            let start = ((center - radius).floor().max(0.0) as usize).min(from - 1);
+            let end = ((center + radius).ceil() as usize).clamp(start + 1, from);
+            let mut weights = (start..end)
+                .map(|j| filter.kernel((j as f32 + 0.5 - center) / stretch))
+                .collect::<Vec<_>>();
+            let total: f32 = weights.iter().sum();
+            if total != 0.0 {
+                weights.iter_mut().for_each(|w| *w /= total);
+            }
+            (start, weights)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLACK: [u8; 4] = [0, 0, 0, 0xFF];
+    const WHITE: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xFF];
+    const FILTERS: [Filter; 3] = [Filter::Nearest, Filter::Bilinear, Filter::Lanczos];
+
+    fn image(pixels: &[[u8; 4]]) -> Vec<u8> {
+        pixels.concat()
+    }
+
+    /// The red channel of each pixel.
+    fn reds(pixels: &[u8]) -> Vec<u8> {
+        pixels.chunks(4).map(|pixel| pixel[0]).collect()
+    }
+
+    #[test]
+    fn same_size_is_unchanged() {
+        let pixels = image(&[BLACK, WHITE, [1, 2, 3, 4], [5, 6, 7, 8]]);
+        for filter in FILTERS {
+            assert_eq!(resize(&pixels, (2, 2), (2, 2), filter), pixels, "{filter}");
+        }
+    }
+
+    #[test]
+    fn nearest_doubles_each_pixel() {
+        let (a, b, c, d) = ([1, 1, 1, 1], [2, 2, 2, 2], [3, 3, 3, 3], [4, 4, 4, 4]);
+        let pixels = image(&[a, b, c, d]);
+        let expected = image(&[a, a, b, b, a, a, b, b, c, c, d, d, c, c, d, d]);
+        assert_eq!(resize(&pixels, (2, 2), (4, 4), Filter::Nearest), expected);
+    }
+
+    #[test]
+    fn nearest_halves_to_every_other_pixel() {
+        let pixels = (0..16).flat_map(|i| [i, i, i, 0xFF]).collect::<Vec<_>>();
+        let halved = resize(&pixels, (4, 4), (2, 2), Filter::Nearest);
+        assert_eq!(reds(&halved), [0, 2, 8, 10]);
+    }
+
+    #[test]
+    fn bilinear_doubles_into_a_ramp() {
+        let doubled = resize(&image(&[BLACK, WHITE]), (2, 1), (4, 1), Filter::Bilinear);
+        assert_eq!(reds(&doubled), [0, 64, 191, 255]);
+        // alpha is resampled too, and opaque stays opaque
+        assert!(doubled.chunks(4).all(|pixel| pixel[3] == 0xFF));
+    }
+
+    #[test]
+    fn bilinear_halves_by_averaging_neighbors() {
+        let pixels = image(&[BLACK, BLACK, WHITE, WHITE]);
+        let halved = resize(&pixels, (4, 1), (2, 1), Filter::Bilinear);
+        assert_eq!(reds(&halved), [36, 219]);
+    }
+
+    #[test]
+    fn flat_images_stay_flat() {
+        let gray = [0x80, 0x40, 0x20, 0xFF];
+        let pixels = image(&[gray; 36]);
+        for filter in FILTERS {
+            for to in [(3, 3), (12, 12), (5, 9)] {
+                let resized = resize(&pixels, (6, 6), to, filter);
+                assert!(
+                    resized.chunks(4).all(|pixel| pixel == gray),
+                    "{} {:?}",
+                    filter,
+                    to
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn lanczos_weights_sum_to_one_at_the_edges() {
+        for (from, to) in [(10, 25), (25, 10), (7, 7), (1920, 1366)] {
+            let columns = contributions(from, to, Filter::Lanczos);
+            for (start, weights) in [&columns[0], &columns[to - 1]] {
+                let total: f32 = weights.iter().sum();
+                assert!((total - 1.0).abs() < 1e-5, "{} to {}: {}", from, to, total);
+                assert!(start + weights.len() <= from);
+            }
+        }
+    }
+}
@@ -0,0 +1,255 @@
+use std::fs;
+
+use chrono::{Datelike, Duration, Local, NaiveDate, NaiveDateTime, TimeZone, Utc, Weekday};
+
+/// A booking from an iCalendar file, with its recurrence if it repeats.
+#[derive(Debug, Clone, Default)]
+pub struct Event {
+    pub summary: String,
+    pub location: Option<String>,
+    /// In local time.
+    pub start: Option<NaiveDateTime>,
+    pub end: Option<NaiveDateTime>,
+    pub all_day: bool,
+    rule: Option<Rule>,
+    exdates: Vec<NaiveDate>,
+    cancelled: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Freq {
+    Daily,
+    Weekly,
+}
+
+/// The part of an `RRULE` class timetables use.
+#[derive(Debug, Clone)]
+struct Rule {
+    freq: Freq,
+    interval: i64,
+    until: Option<NaiveDate>,
+    count: Option<i64>,
+    by_day: Vec<Weekday>,
+}
+
+impl Event {
+    /// Whether the event takes place on `date`.
+    pub fn occurs_on(&self, date: NaiveDate) -> bool {
+        let first = match self.start {
+            Some(start) if !self.cancelled => start.date(),
+            _ => return false,
+        };
+        if date < first || self.exdates.contains(&date) {
+            return false;
+        }
+        let rule = match &self.rule {
+            Some(rule) => rule,
+            None => return date == first,
+        };
+        if rule.until.is_some_and(|until| date > until) || !rule.matches(first, date) {
+            return false;
+        }
+        match rule.count {
+            // the occurrences up to and including this one
+            Some(count) => {
+                let days = (date - first).num_days();
+                let n = (0..=days)
+                    .map(|i| first + Duration::days(i))
+                    .filter(|&day| rule.matches(first, day))
+                    .count() as i64;
+                n <= count
+            }
+            None => true,
+        }
+    }
+}
+
+impl Rule {
+    fn matches(&self, first: NaiveDate, date: NaiveDate) -> bool {
+        match self.freq {
+            Freq::Daily => (date - first).num_days() % self.interval == 0,
+            Freq::Weekly => {
+                let weekday = date.weekday();
+                let on_day = if self.by_day.is_empty() {
+                    weekday == first.weekday()
+                } else {
+                    self.by_day.contains(&weekday)
+                };
+                let week =
+                    |d: NaiveDate| d - Duration::days(d.weekday().num_days_from_monday() as i64);
+                let weeks = (week(date) - week(first)).num_days() / 7;
+                on_day && weeks % self.interval == 0
+            }
+        }
+    }
+}
+
+/// Reads the events of the iCalendar file at `path` taking place on `date`,
+/// ordered by when they start, warning if it can't be read.
+pub fn events_on(path: &str, date: NaiveDate) -> Vec<Event> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) => {
+            eprintln!("unable to read schedule {path}: {err}");
+            return Vec::new();
+        }
+    };
+    let mut events = parse(&content)
+        .into_iter()
+        .filter(|event| event.occurs_on(date))
+        .collect::<Vec<_>>();
+    events.sort_by_key(|event| (!event.all_day, event.start.map(|start| start.time())));
+    events
+}
+
+/// The `VEVENT`s of an iCalendar document. Times with a `TZID` are taken to
+/// be in the local time zone.
+pub fn parse(content: &str) -> Vec<Event> {
+    let mut events = Vec::new();
+    let mut event: Option<Event> = None;
+    for line in unfold(content) {
+        let (name, value) = match split_property(&line) {
+            Some(property) => property,
+            None => continue,
+        };
+        match (name.as_str(), event.as_mut()) {
+            ("BEGIN", _) if value == "VEVENT" => event = Some(Event::default()),
+            ("END", Some(_)) if value == "VEVENT" => events.extend(event.take()),
+            ("SUMMARY", Some(event)) => event.summary = unescape(value),
+            ("LOCATION", Some(event)) => event.location = Some(unescape(value)),
+            ("STATUS", Some(event)) => event.cancelled = value == "CANCELLED",
+            ("DTSTART", Some(event)) => {
+                event.all_day = !value.contains('T');
+                event.start = parse_time(value);
+            }
+            ("DTEND", Some(event)) => event.end = parse_time(value),
+            ("RRULE", Some(event)) => event.rule = parse_rule(value),
+            ("EXDATE", Some(event)) => event.exdates.extend(
+                value
+                    .split(',')
+                    .filter_map(parse_time)
+                    .map(|time| time.date()),
+            ),
+            _ => {}
+        }
+    }
+    events
+}
+
+/// Joins lines continued on the next one, which starts with a space or tab.
+fn unfold(content: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in content.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+/// Splits `NAME;PARAM=x:VALUE` into its name and value. Colons in quoted
+/// parameters don't end them.
+fn split_property(line: &str) -> Option<(String, &str)> {
+    let mut quoted = false;
+    let colon = line.char_indices().find_map(|(i, c)| match c {
+        '"' => {
+            quoted = !quoted;
+            None
+        }
+        ':' if !quoted => Some(i),
+        _ => None,
+    })?;
+    let (head, value) = (&line[..colon], &line[colon + 1..]);
+    let name = head.split(';').next().unwrap_or(head);
+    Some((name.to_ascii_uppercase(), value))
+}
+
+/// Parses a `DATE` or a `DATE-TIME`, in UTC if it ends in `Z`, into local
+/// time.
+fn parse_time(value: &str) -> Option<NaiveDateTime> {
+    let value = value.trim();
+    if let Some(utc) = value.strip_suffix('Z') {
+        let time = NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").ok()?;
+        return Some(
+            Utc.from_utc_datetime(&time)
+                .with_timezone(&Local)
+                .naive_local(),
+        );
+    }
+    NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+        .ok()
+        .or_else(|| {
+            let date = NaiveDate::parse_from_str(value, "%Y%m%d").ok()?;
+            date.and_hms_opt(0, 0, 0)
+        })
+}
+
+fn parse_rule(value: &str) -> Option<Rule> {
+    let mut freq = None;
+    let mut rule = Rule {
+        freq: Freq::Daily,
+        interval: 1,
+        until: None,
+        count: None,
+        by_day: Vec::new(),
+    };
+    for part in value.split(';') {
+        match part.split_once('=') {
+            Some(("FREQ", "DAILY")) => freq = Some(Freq::Daily),
+            Some(("FREQ", "WEEKLY")) => freq = Some(Freq::Weekly),
+            Some(("INTERVAL", n)) => rule.interval = n.parse().ok().filter(|&n| n > 0)?,
+            Some(("UNTIL", until)) => rule.until = Some(parse_time(until)?.date()),
+            Some(("COUNT", n)) => rule.count = Some(n.parse().ok()?),
+            Some(("BYDAY", days)) => {
+                // without the ordinals monthly rules put in front
+                for day in days.split(',') {
+                    let day = day
+                        .trim_start_matches(|c: char| c == '+' || c == '-' || c.is_ascii_digit());
+                    rule.by_day.push(weekday(day)?);
+                }
+            }
+            _ => {}
+        }
+    }
+    match freq {
+        Some(freq) => {
+            rule.freq = freq;
+            Some(rule)
+        }
+        None => {
+            eprintln!("unsupported schedule recurrence {value}, only daily and weekly ones are");
+            None
+        }
+    }
+}
+
+fn weekday(day: &str) -> Option<Weekday> {
+    Some(match day {
+        "MO" => Weekday::Mon,
+        "TU" => Weekday::Tue,
+        "WE" => Weekday::Wed,
+        "TH" => Weekday::Thu,
+        "FR" => Weekday::Fri,
+        "SA" => Weekday::Sat,
+        "SU" => Weekday::Sun,
+        _ => return None,
+    })
+}
+
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => out.push(' '),
+            Some(c) => out.push(c),
+            None => {}
+        }
+    }
+    out
+}
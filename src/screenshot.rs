@@ -0,0 +1,57 @@
+use std::fs;
+use std::io;
+
+use chrono::Local;
+
+use crate::buffer::Vect;
+use crate::format::PixelFormat;
+
+/// Saves `frame`, the screen of `size` in `format` with rows `stride` bytes
+/// apart, as a PNG named after the current time in `dir`, returning its path.
+pub fn save(
+    dir: &str,
+    frame: &[u8],
+    size: Vect,
+    stride: u32,
+    format: &PixelFormat,
+) -> io::Result<String> {
+    fs::create_dir_all(dir)?;
+    let path = format!("{dir}/ddlm-{}.png", Local::now().format("%Y%m%d-%H%M%S"));
+    save_as(&path, frame, size, stride, format)?;
+    Ok(path)
+}
+
+/// Saves `frame`, as `save` takes it, as a PNG at `path`.
+pub fn save_as(
+    path: &str,
+    frame: &[u8],
+    size: Vect,
+    stride: u32,
+    format: &PixelFormat,
+) -> io::Result<()> {
+    let bpp = format.bytes_per_pixel as usize;
+    let row = size.0 as usize * bpp;
+    let pixels = frame
+        .chunks(stride as usize)
+        .take(size.1 as usize)
+        .flat_map(|line| line[..row].chunks_exact(bpp))
+        .flat_map(|pixel| format.unpack_rgba8(pixel))
+        .collect::<Vec<_>>();
+    encode(path, &pixels, size)
+}
+
+#[cfg(feature = "screenshot")]
+fn encode(path: &str, pixels: &[u8], size: Vect) -> io::Result<()> {
+    let file = io::BufWriter::new(fs::File::create(path)?);
+    let mut encoder = png::Encoder::new(file, size.0, size.1);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(io::Error::other)?;
+    writer.write_image_data(pixels).map_err(io::Error::other)?;
+    writer.finish().map_err(io::Error::other)
+}
+
+#[cfg(not(feature = "screenshot"))]
+fn encode(_path: &str, _pixels: &[u8], _size: Vect) -> io::Result<()> {
+    Err(io::Error::other("built without the screenshot feature"))
+}
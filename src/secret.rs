@@ -0,0 +1,65 @@
+use std::fmt;
+use std::ops::Deref;
+
+use zeroize::Zeroize;
+
+/// Text typed in secret, such as a password. Its memory is wiped when it's
+/// cleared or dropped, and when it grows out of its allocation, and it can't
+/// be cloned, so no stray copies are left behind.
+#[derive(Default)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self(String::with_capacity(capacity))
+    }
+
+    pub fn push(&mut self, c: char) {
+        let len = self.0.len() + c.len_utf8();
+        if len > self.0.capacity() {
+            // moved by hand, as growing in place would leave the old
+            // allocation behind unwiped
+            let mut grown = String::with_capacity(len.max(self.0.capacity() * 2));
+            grown.push_str(&self.0);
+            self.0.zeroize();
+            self.0 = grown;
+        }
+        self.0.push(c);
+    }
+
+    pub fn pop(&mut self) -> Option<char> {
+        let c = self.0.chars().next_back()?;
+        let len = self.0.len() - c.len_utf8();
+        // SAFETY: the bytes of the whole last character are zeroed, and a
+        // run of NULs is valid UTF-8
+        unsafe {
+            let bytes = self.0.as_mut_vec();
+            bytes[len..].zeroize();
+            bytes.truncate(len);
+        }
+        Some(c)
+    }
+
+    pub fn clear(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl Deref for Secret {
+    type Target = str;
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret(..)")
+    }
+}
@@ -0,0 +1,147 @@
+use std::io;
+use std::os::unix::io::RawFd;
+
+use crate::dbus::{Bus, Call};
+
+/// The name the greeter takes on the system bus.
+pub const NAME: &str = "org.ddlm.Greeter";
+/// The error a request that couldn't be carried out is answered with.
+pub const FAILED: &str = "org.ddlm.Greeter.Error.Failed";
+/// The error calls by anyone but root are answered with.
+pub const ACCESS_DENIED: &str = "org.freedesktop.DBus.Error.AccessDenied";
+
+const PATH: &str = "/org/ddlm/Greeter";
+const INTERFACE: &str = "org.ddlm.Greeter";
+const INTROSPECTABLE: &str = "org.freedesktop.DBus.Introspectable";
+const UNKNOWN_METHOD: &str = "org.freedesktop.DBus.Error.UnknownMethod";
+const INTROSPECTION: &str = r#"<!DOCTYPE node PUBLIC "-//freedesktop//DTD D-BUS Object Introspection 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/introspect.dtd">
+<node>
+  <interface name="org.ddlm.Greeter">
+    <method name="Screenshot">
+      <arg name="path" type="s" direction="out"/>
+    </method>
+  </interface>
+  <interface name="org.freedesktop.DBus.Introspectable">
+    <method name="Introspect">
+      <arg name="data" type="s" direction="out"/>
+    </method>
+  </interface>
+</node>
+"#;
+
+/// What another client asked of the greeter, to be answered with `answer`.
+pub enum Request {
+    Screenshot(Call),
+}
+
+/// The greeter's object on the system bus, through which other clients can
+/// have it save screenshots.
+pub struct Service {
+    bus: Bus,
+}
+
+impl Service {
+    /// Connects to the system bus and takes the greeter's name.
+    pub fn start() -> Result<Self, String> {
+        let mut bus =
+            Bus::system().map_err(|err| format!("unable to connect to the system bus: {err}"))?;
+        match bus.request_name(NAME) {
+            Ok(true) => Ok(Self { bus }),
+            Ok(false) => Err(format!("{NAME} is already taken on the system bus")),
+            Err(err) => Err(format!("unable to take {NAME} on the system bus: {err}")),
+        }
+    }
+
+    /// The connection to wait on.
+    pub fn fd(&self) -> RawFd {
+        self.bus.fd()
+    }
+
+    /// The requests made since last asked, calls that aren't any, or that
+    /// weren't made by root, answered right away.
+    pub fn take(&mut self) -> io::Result<Vec<Request>> {
+        let mut requests = Vec::new();
+        for call in self.bus.take_calls()? {
+            let interface = call.interface.as_str();
+            let answer = match (call.path.as_str(), call.member.as_str()) {
+                (PATH, "Screenshot") if matches!(interface, INTERFACE | "") => {
+                    if self.by_root(&call) {
+                        requests.push(Request::Screenshot(call));
+                        continue;
+                    }
+                    Err((ACCESS_DENIED, "Only root can take screenshots"))
+                }
+                (PATH, "Introspect") if matches!(interface, INTROSPECTABLE | "") => {
+                    Ok(INTROSPECTION)
+                }
+                _ => Err((UNKNOWN_METHOD, "No such method")),
+            };
+            self.answer(&call, answer);
+        }
+        Ok(requests)
+    }
+
+    /// Whether `call` was made by root, which the bus policy doesn't ensure
+    /// if it's more lenient than the one ddlm ships.
+    fn by_root(&mut self, call: &Call) -> bool {
+        let Some(sender) = &call.sender else {
+            return false;
+        };
+        match self.bus.unix_user(sender) {
+            Ok(uid) => uid == 0,
+            Err(err) => {
+                eprintln!("unable to tell who called on the system bus: {err}");
+                false
+            }
+        }
+    }
+
+    /// Answers `call` with a string, or else with an error's name and
+    /// message.
+    pub fn answer(&mut self, call: &Call, result: Result<&str, (&str, &str)>) {
+        if let Err(err) = self.bus.reply(call, result) {
+            eprintln!("unable to answer on the system bus: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::os::unix::net::UnixStream;
+    use std::thread;
+
+    use super::*;
+    use crate::dbus::{wire, Arg};
+
+    fn screenshot_by(uid: u32) -> (Vec<Request>, UnixStream) {
+        let (ours, mut theirs) = UnixStream::pair().unwrap();
+        let mut service = Service {
+            bus: Bus::new(ours).unwrap(),
+        };
+        let call = wire::call(2, PATH, "Screenshot", ":1.5");
+        theirs.write_all(&call).unwrap();
+        let bus = thread::spawn(move || {
+            // the greeter asking who ":1.5" is, its first call
+            assert_eq!(wire::name_of_next(&mut theirs), "GetConnectionUnixUser");
+            theirs.write_all(&wire::reply(1, Arg::U32(uid))).unwrap();
+            theirs
+        });
+        let requests = service.take().unwrap();
+        (requests, bus.join().unwrap())
+    }
+
+    #[test]
+    fn screenshots_are_taken_for_root() {
+        let (requests, _) = screenshot_by(0);
+        assert_eq!(requests.len(), 1);
+    }
+
+    #[test]
+    fn screenshots_are_refused_to_others() {
+        let (requests, mut bus) = screenshot_by(1000);
+        assert!(requests.is_empty());
+        assert_eq!(wire::name_of_next(&mut bus), ACCESS_DENIED);
+    }
+}
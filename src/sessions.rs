@@ -0,0 +1,168 @@
+use std::env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+pub const WAYLAND_SESSIONS: &str = "/usr/share/wayland-sessions";
+pub const X11_SESSIONS: &str = "/usr/share/xsessions";
+
+const DEFAULT_PATH: &str = "/usr/local/bin:/usr/bin:/bin";
+
+// X11 sessions need a server started for them
+const X11_WRAPPER: [&str; 2] = ["startx", "/usr/bin/env"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SessionKind {
+    /// Given with `--session`.
+    Command,
+    Wayland,
+    X11,
+}
+
+impl SessionKind {
+    /// Header of the kind's group in the session list.
+    pub fn header(&self) -> &'static str {
+        match self {
+            SessionKind::Command => "Configured",
+            SessionKind::Wayland => "Wayland",
+            SessionKind::X11 => "X11",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Session {
+    /// The desktop entry's file name without extension.
+    pub id: String,
+    pub name: String,
+    pub command: Vec<String>,
+    pub kind: SessionKind,
+    /// The desktop entry's `Icon`, a name or an absolute path.
+    pub icon: Option<String>,
+}
+
+impl Session {
+    /// The programs that have to exist for the session to start.
+    fn programs(&self) -> impl Iterator<Item = &str> {
+        let (wrapper, skip) = match self.kind {
+            SessionKind::X11 => (Some(X11_WRAPPER[0]), X11_WRAPPER.len()),
+            _ => (None, 0),
+        };
+        wrapper
+            .into_iter()
+            .chain(self.command.get(skip).map(String::as_str))
+    }
+
+    /// Checks that the session's programs can be run, so that a broken
+    /// session doesn't start and instantly die.
+    pub fn check(&self) -> Result<(), String> {
+        match self.programs().find(|p| find_executable(p).is_none()) {
+            Some(program) => Err(format!("Session command not found: {program}")),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Looks `program` up like a shell would, in `PATH` unless it has a slash.
+fn find_executable(program: &str) -> Option<PathBuf> {
+    let is_executable = |path: &Path| {
+        fs::metadata(path).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+    };
+    if program.contains('/') {
+        let path = PathBuf::from(program);
+        return is_executable(&path).then_some(path);
+    }
+    let path = env::var("PATH").unwrap_or_else(|_| DEFAULT_PATH.to_string());
+    path.split(':')
+        .map(|dir| Path::new(dir).join(program))
+        .find(|path| is_executable(path))
+}
+
+/// Which scanned sessions to offer, by id or name. An empty allow list allows
+/// everything that isn't denied.
+#[derive(Debug, Clone, Default)]
+pub struct SessionFilter {
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+}
+
+impl SessionFilter {
+    fn matches(list: &[String], session: &Session) -> bool {
+        list.iter().any(|s| *s == session.id || *s == session.name)
+    }
+
+    pub fn allows(&self, session: &Session) -> bool {
+        (self.allow.is_empty() || Self::matches(&self.allow, session))
+            && !Self::matches(&self.deny, session)
+    }
+}
+
+/// The installed sessions the filter allows, Wayland ones first and each
+/// group sorted by name.
+pub fn scan(filter: &SessionFilter) -> Vec<Session> {
+    let mut sessions = Vec::new();
+    for (dir, kind) in [
+        (WAYLAND_SESSIONS, SessionKind::Wayland),
+        (X11_SESSIONS, SessionKind::X11),
+    ] {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.filter_map(Result::ok) {
+            let path = entry.path();
+            if path.extension().is_none_or(|ext| ext != "desktop") {
+                continue;
+            }
+            if let Some(session) = read_entry(&path, kind).filter(|s| filter.allows(s)) {
+                sessions.push(session);
+            }
+        }
+    }
+    sessions.sort_by(|a, b| (a.kind, &a.name).cmp(&(b.kind, &b.name)));
+    sessions
+}
+
+/// Reads a session desktop entry, skipping hidden ones.
+fn read_entry(path: &Path, kind: SessionKind) -> Option<Session> {
+    let content = fs::read_to_string(path).ok()?;
+    let mut name = None;
+    let mut exec = None;
+    let mut icon = None;
+    let mut in_entry = false;
+    for line in content.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            in_entry = line == "[Desktop Entry]";
+            continue;
+        }
+        if !in_entry {
+            continue;
+        }
+        match line.split_once('=') {
+            Some(("Name", value)) => name = Some(value.to_string()),
+            Some(("Exec", value)) => exec = Some(value.to_string()),
+            Some(("Icon", value)) if !value.is_empty() => icon = Some(value.to_string()),
+            Some(("Hidden", "true")) | Some(("NoDisplay", "true")) => return None,
+            _ => {}
+        }
+    }
+    let id = path.file_stem()?.to_string_lossy().into_owned();
+    let mut command: Vec<String> = match kind {
+        SessionKind::X11 => X11_WRAPPER.iter().map(|s| s.to_string()).collect(),
+        _ => Vec::new(),
+    };
+    // quoted like in the shell, close enough to the desktop entry spec
+    let exec = exec?;
+    let words = shell_words::split(&exec)
+        .unwrap_or_else(|_| exec.split_whitespace().map(str::to_string).collect());
+    // field codes like %f make no sense for a session
+    command.extend(words.into_iter().filter(|arg| !arg.starts_with('%')));
+    Some(Session {
+        name: name.unwrap_or_else(|| id.clone()),
+        id,
+        command,
+        kind,
+        icon,
+    })
+}
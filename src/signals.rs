@@ -0,0 +1,105 @@
+use std::convert::TryFrom;
+use std::os::unix::io::RawFd;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::thread;
+
+use nix::fcntl::OFlag;
+use nix::sys::signal::{SigSet, Signal};
+use nix::unistd::{pipe2, read, write};
+
+use crate::exit::{self, Failure};
+
+// the end of the pipe VT switch requests are read from
+static REQUESTS: AtomicI32 = AtomicI32::new(-1);
+// the signal the greeter was asked to exit by, 0 until then
+static TERMINATED: AtomicI32 = AtomicI32::new(0);
+
+/// What the kernel asks of the greeter's VT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VtRequest {
+    /// Stop drawing, another VT is to be shown.
+    Release,
+    /// The VT is shown again.
+    Acquire,
+}
+
+/// Waits for signals in a thread of its own, rather than being interrupted
+/// by them wherever: SIGTERM, SIGINT and SIGHUP ask the greeter to exit, a
+/// second one exiting right away, and SIGUSR1 and SIGUSR2 are passed on as
+/// VT requests. Either wakes whoever waits on `fd`. Meant to run before other
+/// threads are started, as they're only blocked in those started after.
+pub fn install() {
+    let (read_end, write_end) = match pipe2(OFlag::O_NONBLOCK | OFlag::O_CLOEXEC) {
+        Ok(pipe) => pipe,
+        Err(err) => {
+            eprintln!("unable to handle signals: {err}");
+            return;
+        }
+    };
+    let mut set = SigSet::empty();
+    for signal in [
+        Signal::SIGTERM,
+        Signal::SIGINT,
+        Signal::SIGHUP,
+        Signal::SIGUSR1,
+        Signal::SIGUSR2,
+    ] {
+        set.add(signal);
+    }
+    if let Err(err) = set.thread_block() {
+        eprintln!("unable to handle signals: {err}");
+        return;
+    }
+    REQUESTS.store(read_end, Ordering::Relaxed);
+    thread::spawn(move || loop {
+        let request = match set.wait() {
+            Ok(Signal::SIGUSR1) => b'r',
+            Ok(Signal::SIGUSR2) => b'a',
+            // in case the main thread is stuck, e.g. on greetd
+            Ok(signal) if terminated().is_some() => exit::fail(
+                Failure::Cancelled,
+                format!("terminated by {}", signal.as_str()),
+            ),
+            Ok(signal) => {
+                TERMINATED.store(signal as i32, Ordering::Relaxed);
+                b't'
+            }
+            Err(_) => continue,
+        };
+        let _ = write(write_end, &[request]);
+    });
+}
+
+/// The signal the greeter was asked to exit by, if any.
+pub fn terminated() -> Option<Signal> {
+    match TERMINATED.load(Ordering::Relaxed) {
+        0 => None,
+        signal => Signal::try_from(signal).ok(),
+    }
+}
+
+/// The descriptor that becomes readable on VT requests and when asked to
+/// exit.
+pub fn fd() -> Option<RawFd> {
+    let fd = REQUESTS.load(Ordering::Relaxed);
+    (fd >= 0).then_some(fd)
+}
+
+/// The VT requests since last asked, oldest first.
+pub fn take() -> Vec<VtRequest> {
+    let fd = match fd() {
+        Some(fd) => fd,
+        None => return Vec::new(),
+    };
+    let mut requests = Vec::new();
+    let mut buf = [0u8; 16];
+    while let Ok(n @ 1..) = read(fd, &mut buf) {
+        requests.extend(buf[..n].iter().filter_map(|&request| match request {
+            b'r' => Some(VtRequest::Release),
+            b'a' => Some(VtRequest::Acquire),
+            // only there to wake the reader
+            _ => None,
+        }));
+    }
+    requests
+}
@@ -0,0 +1,213 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub const STATE_DIR: &str = "/var/cache/ndlm";
+
+pub const LAST_USER: &str = "lastuser";
+// the users who logged in last, most recent first, one per line
+pub const RECENT_USERS: &str = "recentusers";
+pub const LAST_SESSION: &str = "lastsession";
+// the session each user last logged in to, kept by `UserSessions`
+pub const USER_SESSIONS: &str = "usersessions";
+// when the last session was started, to tell on the next start whether it
+// died right away
+pub const SESSION_START: &str = "sessionstart";
+// set once the first run help was dismissed
+pub const HELP_SEEN: &str = "helpseen";
+// set while colors are inverted
+pub const INVERT: &str = "invert";
+
+/// Small values the greeter keeps between runs, such as the last user.
+pub trait StateStore {
+    fn get(&self, key: &str) -> Option<String>;
+    fn set(&mut self, key: &str, value: &str) -> io::Result<()>;
+    fn remove(&mut self, key: &str) -> io::Result<()>;
+
+    /// Whether values are kept between runs at all.
+    fn is_persistent(&self) -> bool {
+        true
+    }
+}
+
+/// Keeps every value in a file named after its key.
+pub struct FileStore {
+    dir: PathBuf,
+}
+
+impl FileStore {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into() }
+    }
+}
+
+impl StateStore for FileStore {
+    fn get(&self, key: &str) -> Option<String> {
+        fs::read_to_string(self.dir.join(key)).ok()
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        write_atomic(&self.dir.join(key), value)
+    }
+
+    fn remove(&mut self, key: &str) -> io::Result<()> {
+        match fs::remove_file(self.dir.join(key)) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            res => res,
+        }
+    }
+}
+
+/// The session each user last logged in to, as `user<TAB>session` lines in
+/// a file, the most recent login first.
+pub struct UserSessions {
+    path: PathBuf,
+    sessions: Vec<(String, String)>,
+}
+
+impl UserSessions {
+    /// How many users are remembered, the rest forgotten oldest first.
+    const MAX: usize = 64;
+
+    /// Reads the sessions in `path`, none if it doesn't exist yet.
+    pub fn load<P: Into<PathBuf>>(path: P) -> Self {
+        let path = path.into();
+        let sessions = match fs::read_to_string(&path) {
+            Ok(content) => content
+                .lines()
+                .filter_map(|line| line.split_once('\t'))
+                .map(|(user, session)| (user.to_string(), session.to_string()))
+                .collect(),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => {
+                eprintln!("unable to read {}: {err}", path.display());
+                Vec::new()
+            }
+        };
+        Self { path, sessions }
+    }
+
+    /// The session `user` last logged in to.
+    pub fn get(&self, user: &str) -> Option<&str> {
+        self.sessions
+            .iter()
+            .find(|(u, _)| u == user)
+            .map(|(_, session)| session.as_str())
+    }
+
+    /// Remembers that `user` logged in to `session` and writes the file.
+    pub fn set(&mut self, user: &str, session: &str) -> io::Result<()> {
+        self.sessions.retain(|(u, _)| u != user);
+        self.sessions
+            .insert(0, (user.to_string(), session.to_string()));
+        self.sessions.truncate(Self::MAX);
+        let content = self
+            .sessions
+            .iter()
+            .map(|(user, session)| format!("{user}\t{session}\n"))
+            .collect::<String>();
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        write_atomic(&self.path, &content)
+    }
+}
+
+/// Writes `path` through a temporary file renamed over it, so it's never
+/// left half written.
+fn write_atomic(path: &Path, content: &str) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    fs::write(&tmp, content)?;
+    fs::rename(&tmp, path)
+}
+
+/// Remembers nothing, for when there is nowhere to keep state.
+pub struct NoStore;
+
+impl StateStore for NoStore {
+    fn get(&self, _key: &str) -> Option<String> {
+        None
+    }
+
+    fn set(&mut self, _key: &str, _value: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn remove(&mut self, _key: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn is_persistent(&self) -> bool {
+        false
+    }
+}
+
+/// A file store in `dir`, or a store that remembers nothing if `dir` can't
+/// be created. A read-only filesystem is expected on immutable systems and
+/// not worth a warning.
+pub fn open(dir: &str) -> Box<dyn StateStore> {
+    match fs::create_dir_all(dir) {
+        Ok(()) => Box::new(FileStore::new(dir)),
+        Err(err) if err.kind() == io::ErrorKind::ReadOnlyFilesystem => Box::new(NoStore),
+        Err(err) => {
+            eprintln!("not keeping state, unable to create {dir}: {err}");
+            Box::new(NoStore)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    /// A path in a directory of its own for `test`, which is removed first.
+    fn scratch(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ddlm-{}-{test}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir.join(USER_SESSIONS)
+    }
+
+    #[test]
+    fn user_sessions_read_back_most_recent_first() {
+        let path = scratch("round-trip");
+        let mut sessions = UserSessions::load(&path);
+        assert_eq!(sessions.get("alice"), None);
+        sessions.set("alice", "sway").unwrap();
+        sessions.set("bob", "GNOME on Xorg").unwrap();
+        sessions.set("alice", "plasma").unwrap();
+        let content = fs::read_to_string(&path).unwrap();
+        assert_eq!(content, "alice\tplasma\nbob\tGNOME on Xorg\n");
+        let loaded = UserSessions::load(&path);
+        assert_eq!(loaded.get("alice"), Some("plasma"));
+        assert_eq!(loaded.get("bob"), Some("GNOME on Xorg"));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn user_sessions_forget_the_oldest_users() {
+        let path = scratch("trim");
+        let mut sessions = UserSessions::load(&path);
+        for i in 0..=UserSessions::MAX {
+            sessions.set(&format!("user{i}"), "sway").unwrap();
+        }
+        let loaded = UserSessions::load(&path);
+        assert_eq!(loaded.sessions.len(), UserSessions::MAX);
+        assert_eq!(loaded.get("user0"), None);
+        assert_eq!(loaded.get("user1"), Some("sway"));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn user_sessions_skip_lines_without_a_tab() {
+        let path = scratch("malformed");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "alice\tsway\ngarbage\n").unwrap();
+        let loaded = UserSessions::load(&path);
+        assert_eq!(loaded.sessions.len(), 1);
+        assert_eq!(loaded.get("alice"), Some("sway"));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,257 @@
+use std::fmt;
+use std::io::{self, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::process;
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::thread;
+use std::time::Duration;
+
+use chrono::{DateTime, Local, SecondsFormat, TimeZone};
+
+use crate::dump::quote;
+
+const TIMEOUT: Duration = Duration::from_secs(2);
+// records past this many waiting to be sent are dropped, rather than piling
+// up behind an unreachable server
+const QUEUE: usize = 16;
+
+// facility authpriv
+const FACILITY: u8 = 10;
+const SEVERITY_WARNING: u8 = 4;
+const SEVERITY_NOTICE: u8 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    Udp,
+    /// With octet counting framing, as in RFC 6587.
+    Tcp,
+}
+
+/// Where auth attempts are sent, written as `udp://host:port` or
+/// `tcp://host:port`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    transport: Transport,
+    addr: String,
+}
+
+impl FromStr for Target {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (transport, addr) = match s.split_once("://") {
+            Some(("udp", addr)) => (Transport::Udp, addr),
+            Some(("tcp", addr)) => (Transport::Tcp, addr),
+            _ => {
+                return Err(format!(
+                    "expected udp://HOST:PORT or tcp://HOST:PORT, got {s}"
+                ))
+            }
+        };
+        if !addr.contains(':') {
+            return Err(format!("missing port in syslog target {s}"));
+        }
+        Ok(Self {
+            transport,
+            addr: addr.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.transport {
+            Transport::Udp => write!(f, "udp://{}", self.addr),
+            Transport::Tcp => write!(f, "tcp://{}", self.addr),
+        }
+    }
+}
+
+/// Sends RFC 5424 records of login attempts to a remote syslog server,
+/// from a thread of its own so that a slow DNS server or an unreachable
+/// server doesn't hold up the greeter.
+pub struct Syslog {
+    target: Target,
+    hostname: String,
+    queue: Option<SyncSender<String>>,
+    // told once the thread has sent everything queued
+    done: Receiver<()>,
+}
+
+impl Syslog {
+    /// Starts the thread sending to `target`, which it resolves once.
+    pub fn new(target: Target) -> Self {
+        let hostname = hostname::get()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let (queue, records) = mpsc::sync_channel::<String>(QUEUE);
+        let (finished, done) = mpsc::channel();
+        let sender = target.clone();
+        thread::spawn(move || {
+            let mut addr = None;
+            for record in records {
+                // tried again with the next record if the name didn't resolve
+                if addr.is_none() {
+                    addr = resolve(&sender)
+                        .map_err(|err| eprintln!("unable to resolve syslog {sender}: {err}"))
+                        .ok();
+                }
+                if let Some(addr) = addr {
+                    if let Err(err) = send(sender.transport, addr, &record) {
+                        eprintln!("unable to send to syslog {sender}: {err}");
+                    }
+                }
+            }
+            let _ = finished.send(());
+        });
+        Self {
+            target,
+            hostname,
+            queue: Some(queue),
+            done,
+        }
+    }
+
+    /// Records a login attempt by `user`, with the reason it failed, if it did.
+    pub fn auth_attempt(&self, user: &str, failure: Option<&str>) {
+        let user = quote(user);
+        let (severity, msg) = match failure {
+            None => (SEVERITY_NOTICE, format!("login succeeded for {user}")),
+            Some(reason) => (
+                SEVERITY_WARNING,
+                format!("login failed for {user}: {reason}"),
+            ),
+        };
+        let record = record(&self.hostname, severity, "auth", &msg, &Local::now());
+        let queued = match &self.queue {
+            Some(queue) => queue.try_send(record),
+            None => return,
+        };
+        if let Err(TrySendError::Full(_)) = queued {
+            eprintln!("syslog {} is behind, dropping a record", self.target);
+        }
+    }
+}
+
+impl Drop for Syslog {
+    /// Gives the thread as long as one send may take to finish the records
+    /// queued, such as the login that ends the greeter.
+    fn drop(&mut self) {
+        self.queue = None;
+        let _ = self.done.recv_timeout(TIMEOUT);
+    }
+}
+
+/// An RFC 5424 record from ddlm on `hostname` at `time`.
+fn record<Tz: TimeZone>(
+    hostname: &str,
+    severity: u8,
+    msg_id: &str,
+    msg: &str,
+    time: &DateTime<Tz>,
+) -> String
+where
+    Tz::Offset: fmt::Display,
+{
+    format!(
+        "<{}>1 {} {} ddlm {} {msg_id} - {msg}",
+        FACILITY * 8 + severity,
+        time.to_rfc3339_opts(SecondsFormat::Millis, false),
+        header_field(hostname),
+        process::id(),
+    )
+}
+
+/// `record` framed by octet counting, for TCP.
+fn frame(record: &str) -> String {
+    format!("{} {record}", record.len())
+}
+
+fn send(transport: Transport, addr: SocketAddr, record: &str) -> io::Result<()> {
+    match transport {
+        Transport::Udp => {
+            let local: SocketAddr = if addr.is_ipv4() {
+                ([0, 0, 0, 0], 0).into()
+            } else {
+                ([0u16; 8], 0).into()
+            };
+            UdpSocket::bind(local)?.send_to(record.as_bytes(), addr)?;
+        }
+        Transport::Tcp => {
+            let mut stream = TcpStream::connect_timeout(&addr, TIMEOUT)?;
+            stream.set_write_timeout(Some(TIMEOUT))?;
+            stream.write_all(frame(record).as_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+fn resolve(target: &Target) -> io::Result<SocketAddr> {
+    target
+        .addr
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address for syslog server"))
+}
+
+/// Header fields are printable ASCII without spaces, `-` if empty.
+fn header_field(s: &str) -> String {
+    let field = s
+        .chars()
+        .filter(|c| c.is_ascii_graphic())
+        .collect::<String>();
+    if field.is_empty() {
+        "-".to_string()
+    } else {
+        field
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn targets_parse_and_display() {
+        for target in ["udp://logs.example.com:514", "tcp://[::1]:6514"] {
+            assert_eq!(target.parse::<Target>().unwrap().to_string(), target);
+        }
+        let target: Target = "tcp://10.0.0.1:601".parse().unwrap();
+        assert_eq!(target.transport, Transport::Tcp);
+        assert_eq!(target.addr, "10.0.0.1:601");
+    }
+
+    #[test]
+    fn targets_need_a_transport_and_port() {
+        assert!("logs.example.com:514".parse::<Target>().is_err());
+        assert!("http://logs.example.com:514".parse::<Target>().is_err());
+        assert!("udp://logs.example.com".parse::<Target>().is_err());
+    }
+
+    #[test]
+    fn records_follow_rfc_5424() {
+        let time = Utc.with_ymd_and_hms(2026, 10, 17, 8, 30, 5).unwrap();
+        let record = record("gate way", SEVERITY_WARNING, "auth", "login failed", &time);
+        // authpriv.warning, version 1, no structured data
+        let expected = format!(
+            "<84>1 2026-10-17T08:30:05.000+00:00 gateway ddlm {} auth - login failed",
+            process::id()
+        );
+        assert_eq!(record, expected);
+    }
+
+    #[test]
+    fn empty_hostnames_are_nil() {
+        let time = Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap();
+        let record = record("", SEVERITY_NOTICE, "auth", "hi", &time);
+        assert!(record.starts_with("<85>1 2026-01-01T00:00:00.000+00:00 - ddlm "));
+    }
+
+    #[test]
+    fn tcp_frames_count_octets() {
+        assert_eq!(frame("<85>1 x"), "7 <85>1 x");
+        // bytes, not characters
+        assert_eq!(frame("é"), "2 é");
+    }
+}
@@ -0,0 +1,36 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// The password field's label while asking for the secret, unless
+/// `--unlock-prompt` says otherwise.
+pub const DEFAULT_PROMPT: &str = "Passphrase:";
+
+/// Runs `command` through `sh -c` with `secret` on its standard input, without
+/// a trailing newline, and the username in `DDLM_USER`. The error is the last
+/// line the command wrote to its standard error, if any.
+pub fn run(command: &str, user: &str, secret: &str) -> Result<(), String> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("DDLM_USER", user)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| format!("Unable to run the unlock command: {err}"))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // it may exit without reading, which isn't an error of its own
+        let _ = stdin.write_all(secret.as_bytes());
+    }
+    let output = child
+        .wait_with_output()
+        .map_err(|err| format!("Unable to run the unlock command: {err}"))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    match stderr.lines().rev().map(str::trim).find(|l| !l.is_empty()) {
+        Some(line) => Err(format!("Unlock failed: {line}")),
+        None => Err(format!("Unlock failed: {}", output.status)),
+    }
+}
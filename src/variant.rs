@@ -0,0 +1,101 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use chrono::{Local, Timelike};
+
+/// Which palette of the theme to draw with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Variant {
+    Dark,
+    Light,
+    /// Dark during the configured dark hours, light otherwise.
+    #[default]
+    Auto,
+}
+
+impl FromStr for Variant {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dark" => Ok(Variant::Dark),
+            "light" => Ok(Variant::Light),
+            "auto" => Ok(Variant::Auto),
+            _ => Err(format!("unknown theme variant {s}")),
+        }
+    }
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Variant::Dark => write!(f, "dark"),
+            Variant::Light => write!(f, "light"),
+            Variant::Auto => write!(f, "auto"),
+        }
+    }
+}
+
+impl Variant {
+    pub fn is_dark(&self, hours: DarkHours) -> bool {
+        match self {
+            Variant::Dark => true,
+            Variant::Light => false,
+            Variant::Auto => hours.contains(Local::now().hour()),
+        }
+    }
+
+    /// When the variant might change next, at the start of the next hour.
+    pub fn next_switch(&self) -> Option<Instant> {
+        if *self != Variant::Auto {
+            return None;
+        }
+        let now = Local::now();
+        let into_hour = now.minute() * 60 + now.second();
+        Some(Instant::now() + Duration::from_secs((3600 - into_hour) as u64))
+    }
+}
+
+/// The hours of the day, from `start` up to but not including `end`, during
+/// which the dark variant is used. Wraps around midnight if `start > end`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DarkHours {
+    start: u32,
+    end: u32,
+}
+
+impl Default for DarkHours {
+    fn default() -> Self {
+        Self { start: 19, end: 7 }
+    }
+}
+
+impl FromStr for DarkHours {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || format!("expected START-END hours, got {s}");
+        let (start, end) = s.split_once('-').ok_or_else(err)?;
+        let start = start.parse().map_err(|_| err())?;
+        let end = end.parse().map_err(|_| err())?;
+        if start > 23 || end > 23 {
+            return Err(err());
+        }
+        Ok(Self { start, end })
+    }
+}
+
+impl fmt::Display for DarkHours {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
+
+impl DarkHours {
+    pub fn contains(&self, hour: u32) -> bool {
+        if self.start <= self.end {
+            (self.start..self.end).contains(&hour)
+        } else {
+            hour >= self.start || hour < self.end
+        }
+    }
+}
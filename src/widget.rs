@@ -0,0 +1,403 @@
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::{Local, TimeZone, Timelike};
+
+use crate::buffer::{Buffer, Rect};
+use crate::color::Color;
+use crate::draw::Font;
+use crate::locale::TimeLocale;
+use crate::schedule;
+use crate::Error;
+
+/// A part of the screen that can redraw itself on a timer without the rest of
+/// the greeter being redrawn.
+pub trait Widget {
+    /// The rectangle the widget draws into, in screen coordinates.
+    fn bounds(&self) -> Rect;
+
+    /// When the widget needs to be redrawn next, if ever.
+    fn next_tick(&self) -> Option<Instant>;
+
+    /// Draws the widget into `buf`, which covers exactly its bounds and was
+    /// already cleared to the background.
+    fn draw(&mut self, buf: &mut Buffer<'_>, bg: &Color) -> Result<(), Error>;
+}
+
+/// The current time, redrawn at the start of every minute, or every second if
+/// the format shows seconds.
+pub struct Clock {
+    font: Font,
+    color: Color,
+    format: String,
+    locale: TimeLocale,
+    seconds: bool,
+    bounds: Rect,
+    next_tick: Instant,
+}
+
+impl Clock {
+    const MARGIN: u32 = 16;
+
+    /// A clock placed by `alignment` within the margins of a screen of
+    /// `screen_size`, written with the strftime `format`, or the locale's
+    /// hours and minutes.
+    pub fn new(
+        font: Font,
+        color: Color,
+        screen_size: (u32, u32),
+        alignment: (f32, f32),
+        format: Option<String>,
+        locale: TimeLocale,
+    ) -> Self {
+        let format = format.unwrap_or_else(|| locale.clock_format().to_string());
+        // wide enough for every month and weekday name
+        let width = (1..=12)
+            .flat_map(|month| (1..=7).map(move |day| (month, day)))
+            .flat_map(|(month, day)| [(0, 0), (8, 8), (12, 58), (23, 59)].map(|t| (month, day, t)))
+            .filter_map(|(month, day, (hour, min))| {
+                Local
+                    .with_ymd_and_hms(2024, month, day, hour, min, 0)
+                    .earliest()
+            })
+            .map(|time| font.text_width(&locale.format(&time, &format)))
+            .max()
+            .unwrap_or(0);
+        let second = Local.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).earliest();
+        let seconds = second.is_some_and(|time| {
+            locale.format(&time, &format)
+                != locale.format(&(time + chrono::Duration::seconds(1)), &format)
+        });
+        let height = font.size() as u32;
+        let place = |free: u32, align: f32| Self::MARGIN + (free as f32 * align) as u32;
+        let x = place(
+            screen_size.0.saturating_sub(width + 2 * Self::MARGIN),
+            alignment.0,
+        );
+        let y = place(
+            screen_size.1.saturating_sub(height + 2 * Self::MARGIN),
+            alignment.1,
+        );
+        Self {
+            font,
+            color,
+            format,
+            locale,
+            seconds,
+            bounds: (x, y, width, height),
+            next_tick: Instant::now(),
+        }
+    }
+}
+
+impl Widget for Clock {
+    fn bounds(&self) -> Rect {
+        self.bounds
+    }
+
+    fn next_tick(&self) -> Option<Instant> {
+        Some(self.next_tick)
+    }
+
+    fn draw(&mut self, buf: &mut Buffer<'_>, bg: &Color) -> Result<(), Error> {
+        let now = Local::now();
+        let until_next_tick = if self.seconds {
+            Duration::from_nanos(1_000_000_000 - now.nanosecond().min(999_999_999) as u64)
+        } else {
+            Duration::from_secs(60 - now.second() as u64)
+        };
+        self.next_tick = Instant::now() + until_next_tick;
+        let text = self.locale.format(&now, &self.format);
+        // right aligned, as the names' widths vary
+        let x = self.bounds.2.saturating_sub(self.font.text_width(&text));
+        self.font
+            .auto_draw_text(&mut buf.offset((x, 0))?, bg, &self.color, &text)?;
+        Ok(())
+    }
+}
+
+/// Today's bookings from an iCalendar file, as on lab machines, re-read every
+/// hour and when the day changes.
+pub struct Schedule {
+    path: String,
+    font: Font,
+    color: Color,
+    bounds: Rect,
+    next_tick: Instant,
+}
+
+impl Schedule {
+    const REFRESH: Duration = Duration::from_secs(60 * 60);
+    const LINES: u32 = 8;
+    const MARGIN: u32 = 16;
+
+    /// A panel with its top left corner at `pos`, as wide as the screen of
+    /// `screen_size` allows.
+    pub fn new(
+        path: String,
+        font: Font,
+        color: Color,
+        pos: (u32, u32),
+        screen_size: (u32, u32),
+    ) -> Self {
+        let x = pos.0.min(screen_size.0);
+        let width = screen_size.0.saturating_sub(x + Self::MARGIN);
+        let height =
+            ((Self::LINES + 1) * Self::line_height(&font)).min(screen_size.1.saturating_sub(pos.1));
+        Self {
+            path,
+            font,
+            color,
+            bounds: (x, pos.1, width, height),
+            next_tick: Instant::now(),
+        }
+    }
+
+    fn line_height(font: &Font) -> u32 {
+        font.size() as u32 + 4
+    }
+}
+
+impl Widget for Schedule {
+    fn bounds(&self) -> Rect {
+        self.bounds
+    }
+
+    fn next_tick(&self) -> Option<Instant> {
+        Some(self.next_tick)
+    }
+
+    fn draw(&mut self, buf: &mut Buffer<'_>, bg: &Color) -> Result<(), Error> {
+        let now = Local::now().naive_local();
+        let today = now.date();
+        let until_tomorrow = (today + chrono::Duration::days(1))
+            .and_hms_opt(0, 0, 0)
+            .and_then(|midnight| (midnight - now).to_std().ok())
+            .unwrap_or(Self::REFRESH);
+        self.next_tick = Instant::now() + Self::REFRESH.min(until_tomorrow);
+
+        let events = schedule::events_on(&self.path, today);
+        let mut lines = vec!["Today".to_string()];
+        if events.is_empty() {
+            lines.push("No bookings".to_string());
+        }
+        let shown = if events.len() > Self::LINES as usize {
+            Self::LINES as usize - 1
+        } else {
+            events.len()
+        };
+        for event in &events[..shown] {
+            let when = match (event.all_day, event.start, event.end) {
+                (true, ..) => "All day".to_string(),
+                (false, Some(start), Some(end)) => {
+                    format!("{}-{}", start.format("%H:%M"), end.format("%H:%M"))
+                }
+                (false, Some(start), None) => start.format("%H:%M").to_string(),
+                (false, None, _) => String::new(),
+            };
+            let mut line = format!("{when}  {}", event.summary);
+            if let Some(location) = &event.location {
+                line.push_str(&format!(", {location}"));
+            }
+            lines.push(line);
+        }
+        if shown < events.len() {
+            lines.push(format!("and {} more", events.len() - shown));
+        }
+
+        let line_height = Self::line_height(&self.font);
+        for (i, line) in lines.iter().enumerate() {
+            let y = i as u32 * line_height;
+            if y + line_height > self.bounds.3 {
+                break;
+            }
+            let color = if i == 0 { Color::YELLOW } else { self.color };
+            self.font
+                .auto_draw_text(&mut buf.offset((0, y))?, bg, &color, line)?;
+        }
+        Ok(())
+    }
+}
+
+/// The hostname, shown as the title. It's re-read periodically, as with DHCP
+/// it may only be set after the greeter started.
+pub struct Hostname {
+    font: Font,
+    color: Color,
+    alignment: (f32, f32),
+    bounds: Rect,
+    next_tick: Instant,
+}
+
+impl Hostname {
+    const REFRESH: Duration = Duration::from_secs(30);
+    const MARGIN: u32 = 16;
+
+    /// A title centered on `alignment`, given as fractions of `screen_size`,
+    /// on a row across the whole screen so a longer name still fits.
+    pub fn new(font: Font, color: Color, alignment: (f32, f32), screen_size: (u32, u32)) -> Self {
+        let height = font.size() as u32;
+        let y = (screen_size.1 as f32 * alignment.1) as u32;
+        let y = y
+            .min(screen_size.1.saturating_sub(height))
+            .max(Self::MARGIN.min(screen_size.1.saturating_sub(height)));
+        Self {
+            font,
+            color,
+            alignment,
+            bounds: (0, y, screen_size.0, height),
+            next_tick: Instant::now(),
+        }
+    }
+}
+
+impl Widget for Hostname {
+    fn bounds(&self) -> Rect {
+        self.bounds
+    }
+
+    fn next_tick(&self) -> Option<Instant> {
+        Some(self.next_tick)
+    }
+
+    fn draw(&mut self, buf: &mut Buffer<'_>, bg: &Color) -> Result<(), Error> {
+        self.next_tick = Instant::now() + Self::REFRESH;
+        let name = match hostname::get() {
+            Ok(name) => name.to_string_lossy().into_owned(),
+            Err(err) => {
+                eprintln!("unable to read hostname: {err}");
+                return Ok(());
+            }
+        };
+        let width = self.font.text_width(&name);
+        let center = (self.bounds.2 as f32 * self.alignment.0) as u32;
+        let x = center
+            .saturating_sub(width / 2)
+            .min(self.bounds.2.saturating_sub(width + Self::MARGIN))
+            .max(Self::MARGIN);
+        self.font
+            .draw_text(&mut buf.offset((x, 0))?, bg, &self.color, &name)?;
+        Ok(())
+    }
+}
+
+/// What a command run at startup says needs attention, such as pending
+/// updates or a reboot, as a badge in the top left corner. The command runs
+/// in the background, and nothing is shown if it prints nothing.
+pub struct StatusBadge {
+    font: Font,
+    result: Option<Receiver<Option<String>>>,
+    text: Option<String>,
+    bounds: Rect,
+    next_tick: Instant,
+}
+
+impl StatusBadge {
+    const MARGIN: u32 = 16;
+    const PADDING: u32 = 4;
+    // how often to look for the command's output until it's there
+    const POLL: Duration = Duration::from_millis(500);
+
+    /// Starts `command` through `sh -c`, to show the first line it prints.
+    pub fn new(font: Font, command: String, screen_size: (u32, u32)) -> Self {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let output = Command::new("sh")
+                .arg("-c")
+                .arg(&command)
+                .stdin(Stdio::null())
+                .stderr(Stdio::inherit())
+                .output();
+            let line = match output {
+                Ok(output) => {
+                    if !output.status.success() {
+                        eprintln!("status command exited with {}", output.status);
+                    }
+                    String::from_utf8_lossy(&output.stdout)
+                        .lines()
+                        .map(str::trim)
+                        .find(|line| !line.is_empty())
+                        .map(str::to_string)
+                }
+                Err(err) => {
+                    eprintln!("unable to run the status command: {err}");
+                    None
+                }
+            };
+            let _ = tx.send(line);
+        });
+        let height = font.size() as u32 + 2 * Self::PADDING;
+        Self {
+            font,
+            result: Some(rx),
+            text: None,
+            bounds: (Self::MARGIN, Self::MARGIN, screen_size.0 / 2, height),
+            next_tick: Instant::now() + Self::POLL,
+        }
+    }
+}
+
+impl Widget for StatusBadge {
+    fn bounds(&self) -> Rect {
+        self.bounds
+    }
+
+    fn next_tick(&self) -> Option<Instant> {
+        self.result.as_ref().map(|_| self.next_tick)
+    }
+
+    fn draw(&mut self, buf: &mut Buffer<'_>, _bg: &Color) -> Result<(), Error> {
+        self.next_tick = Instant::now() + Self::POLL;
+        if let Some(rx) = &self.result {
+            match rx.try_recv() {
+                Ok(text) => {
+                    self.text = text;
+                    self.result = None;
+                }
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => self.result = None,
+            }
+        }
+        let text = match &self.text {
+            Some(text) => text,
+            None => return Ok(()),
+        };
+        let width = (self.font.text_width(text) + 2 * Self::PADDING).min(self.bounds.2);
+        let mut badge = buf.subdimensions((0, 0, width, self.bounds.3))?;
+        badge.memset(&Color::YELLOW);
+        let mut inner = badge.offset((Self::PADDING, Self::PADDING))?;
+        self.font
+            .auto_draw_text(&mut inner, &Color::YELLOW, &Color::BLACK, text)?;
+        Ok(())
+    }
+}
+
+/// An image drawn once, such as the distribution's logo.
+pub struct Logo {
+    pixels: Vec<u8>,
+    bounds: Rect,
+}
+
+impl Logo {
+    /// `pixels` is RGBA of the size of `bounds`.
+    pub fn new(pixels: Vec<u8>, bounds: Rect) -> Self {
+        Self { pixels, bounds }
+    }
+}
+
+impl Widget for Logo {
+    fn bounds(&self) -> Rect {
+        self.bounds
+    }
+
+    fn next_tick(&self) -> Option<Instant> {
+        None
+    }
+
+    fn draw(&mut self, buf: &mut Buffer<'_>, bg: &Color) -> Result<(), Error> {
+        let dim = (self.bounds.2, self.bounds.3);
+        Ok(buf.blend_rgba(&self.pixels, dim, bg)?)
+    }
+}
@@ -0,0 +1,240 @@
+use std::convert::TryFrom;
+use std::env;
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::ptr;
+
+use xkbcommon_dl::{
+    xkb_compose_compile_flags, xkb_compose_state, xkb_compose_state_flags, xkb_compose_status,
+    xkb_compose_table, xkb_context, xkb_context_flags, xkb_key_direction, xkb_keymap,
+    xkb_keymap_compile_flags, xkb_rule_names, xkb_state, xkb_state_component, XkbCommon,
+    XkbCommonCompose,
+};
+
+// XKB key codes are the kernel's, shifted for X11's sake
+const KEYCODE_OFFSET: u32 = 8;
+
+/// A keyboard layout compiled by libxkbcommon, loaded at runtime, with the
+/// state of its modifiers and dead keys.
+pub struct Xkb {
+    lib: &'static XkbCommon,
+    context: *mut xkb_context,
+    keymap: *mut xkb_keymap,
+    state: *mut xkb_state,
+    compose: Option<Compose>,
+    // as the layout names them, by index
+    layouts: Vec<String>,
+}
+
+/// Dead key and compose sequences, from the locale's compose table.
+struct Compose {
+    lib: &'static XkbCommonCompose,
+    table: *mut xkb_compose_table,
+    state: *mut xkb_compose_state,
+}
+
+impl Xkb {
+    /// Compiles the `layout` and its `variant`, with `options` such as a key
+    /// switching between several layouts, libxkbcommon's defaults if not
+    /// given.
+    pub fn new(
+        layout: Option<&str>,
+        variant: Option<&str>,
+        options: Option<&str>,
+    ) -> Result<Self, String> {
+        let lib = xkbcommon_dl::xkbcommon_option().ok_or("unable to load libxkbcommon")?;
+        let layout_c = layout.map(cstring).transpose()?;
+        let variant_c = variant.map(cstring).transpose()?;
+        let options_c = options.map(cstring).transpose()?;
+        let names = xkb_rule_names {
+            rules: ptr::null(),
+            model: ptr::null(),
+            layout: layout_c.as_ref().map_or(ptr::null(), |s| s.as_ptr()),
+            variant: variant_c.as_ref().map_or(ptr::null(), |s| s.as_ptr()),
+            options: options_c.as_ref().map_or(ptr::null(), |s| s.as_ptr()),
+        };
+        let layouts = layout
+            .unwrap_or("us")
+            .split(',')
+            .map(|name| name.trim().to_string())
+            .collect();
+        // SAFETY: the names outlive the calls, and everything created is
+        // checked and freed on drop
+        unsafe {
+            let context =
+                (lib.xkb_context_new)(xkb_context_flags::XKB_CONTEXT_NO_ENVIRONMENT_NAMES);
+            if context.is_null() {
+                return Err("unable to create an XKB context".to_string());
+            }
+            let keymap = (lib.xkb_keymap_new_from_names)(
+                context,
+                &names,
+                xkb_keymap_compile_flags::XKB_KEYMAP_COMPILE_NO_FLAGS,
+            );
+            if keymap.is_null() {
+                (lib.xkb_context_unref)(context);
+                return Err(format!(
+                    "unknown XKB layout {}{}",
+                    layout.unwrap_or("us"),
+                    variant.map(|v| format!(" ({v})")).unwrap_or_default()
+                ));
+            }
+            let state = (lib.xkb_state_new)(keymap);
+            if state.is_null() {
+                (lib.xkb_keymap_unref)(keymap);
+                (lib.xkb_context_unref)(context);
+                return Err("unable to create an XKB state".to_string());
+            }
+            Ok(Self {
+                lib,
+                context,
+                keymap,
+                state,
+                compose: Compose::new(context),
+                layouts,
+            })
+        }
+    }
+
+    /// Follows the kernel key `code` going down or up, for the modifiers and
+    /// locks it changes.
+    pub fn update(&mut self, code: u16, pressed: bool) {
+        let direction = if pressed {
+            xkb_key_direction::XKB_KEY_DOWN
+        } else {
+            xkb_key_direction::XKB_KEY_UP
+        };
+        // SAFETY: the state is valid until drop
+        unsafe {
+            (self.lib.xkb_state_update_key)(self.state, code as u32 + KEYCODE_OFFSET, direction);
+        }
+    }
+
+    /// The character the kernel key `code` types, once any dead keys before
+    /// it are combined with it.
+    pub fn char(&mut self, code: u16) -> Option<char> {
+        let keycode = code as u32 + KEYCODE_OFFSET;
+        let mut buf = [0 as c_char; 64];
+        // SAFETY: the state is valid until drop, and the buffer's length is
+        // passed along
+        let text = unsafe {
+            let sym = (self.lib.xkb_state_key_get_one_sym)(self.state, keycode);
+            match self.compose.as_ref().map(|compose| compose.feed(sym)) {
+                Some(xkb_compose_status::XKB_COMPOSE_COMPOSING) => return None,
+                Some(xkb_compose_status::XKB_COMPOSE_CANCELLED) => {
+                    self.compose.as_ref()?.reset();
+                    return None;
+                }
+                Some(xkb_compose_status::XKB_COMPOSE_COMPOSED) => {
+                    let compose = self.compose.as_ref()?;
+                    let n = (compose.lib.xkb_compose_state_get_utf8)(
+                        compose.state,
+                        buf.as_mut_ptr(),
+                        buf.len(),
+                    );
+                    compose.reset();
+                    n
+                }
+                _ => (self.lib.xkb_state_key_get_utf8)(
+                    self.state,
+                    keycode,
+                    buf.as_mut_ptr(),
+                    buf.len(),
+                ),
+            }
+        };
+        let len = usize::try_from(text).ok()?.min(buf.len() - 1);
+        let bytes = buf[..len].iter().map(|&b| b as u8).collect::<Vec<_>>();
+        let c = String::from_utf8(bytes).ok()?.chars().next()?;
+        (!c.is_control()).then_some(c)
+    }
+
+    /// The name of the layout keys are typed with, if several are set up.
+    pub fn layout(&self) -> Option<&str> {
+        if self.layouts.len() < 2 {
+            return None;
+        }
+        // SAFETY: the state is valid until drop
+        let index = unsafe {
+            (self.lib.xkb_state_serialize_layout)(
+                self.state,
+                xkb_state_component::XKB_STATE_LAYOUT_EFFECTIVE,
+            )
+        };
+        self.layouts.get(index as usize).map(String::as_str)
+    }
+
+    /// The letter on the kernel key `code` in the current layout, for
+    /// bindings held with Ctrl.
+    pub fn letter(&self, code: u16) -> Option<char> {
+        // SAFETY: the state is valid until drop
+        let sym = unsafe {
+            (self.lib.xkb_state_key_get_one_sym)(self.state, code as u32 + KEYCODE_OFFSET)
+        };
+        // Latin keysyms are their ASCII code
+        let c = char::from_u32(sym).filter(char::is_ascii_alphabetic)?;
+        Some(c.to_ascii_lowercase())
+    }
+}
+
+impl Drop for Xkb {
+    fn drop(&mut self) {
+        self.compose = None;
+        // SAFETY: created in new and not used after this
+        unsafe {
+            (self.lib.xkb_state_unref)(self.state);
+            (self.lib.xkb_keymap_unref)(self.keymap);
+            (self.lib.xkb_context_unref)(self.context);
+        }
+    }
+}
+
+impl Compose {
+    /// The compose table of the locale keys are typed in, if there's one.
+    unsafe fn new(context: *mut xkb_context) -> Option<Self> {
+        let lib = xkbcommon_dl::xkbcommon_compose_option()?;
+        let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+            .iter()
+            .find_map(|var| env::var(var).ok().filter(|value| !value.is_empty()))
+            .unwrap_or_else(|| "C".to_string());
+        let locale = CString::new(locale).ok()?;
+        let table = (lib.xkb_compose_table_new_from_locale)(
+            context,
+            locale.as_ptr(),
+            xkb_compose_compile_flags::XKB_COMPOSE_COMPILE_NO_FLAGS,
+        );
+        if table.is_null() {
+            return None;
+        }
+        let state =
+            (lib.xkb_compose_state_new)(table, xkb_compose_state_flags::XKB_COMPOSE_STATE_NO_FLAGS);
+        if state.is_null() {
+            (lib.xkb_compose_table_unref)(table);
+            return None;
+        }
+        Some(Self { lib, table, state })
+    }
+
+    unsafe fn feed(&self, sym: u32) -> xkb_compose_status {
+        (self.lib.xkb_compose_state_feed)(self.state, sym);
+        (self.lib.xkb_compose_state_get_status)(self.state)
+    }
+
+    unsafe fn reset(&self) {
+        (self.lib.xkb_compose_state_reset)(self.state);
+    }
+}
+
+impl Drop for Compose {
+    fn drop(&mut self) {
+        // SAFETY: created in new and not used after this
+        unsafe {
+            (self.lib.xkb_compose_state_unref)(self.state);
+            (self.lib.xkb_compose_table_unref)(self.table);
+        }
+    }
+}
+
+fn cstring(s: &str) -> Result<CString, String> {
+    CString::new(s).map_err(|_| format!("invalid XKB name {s}"))
+}